@@ -1,5 +1,52 @@
 //! Blocking serial API
 
+/// Read half of a serial interface (blocking variant)
+pub trait Read<Word> {
+    /// The type of error that can occur when reading
+    type Error;
+
+    /// Reads words into a slice, blocking until at least one word has been read
+    ///
+    /// Returns the number of words read. Words already received are read without
+    /// blocking again, so the count may be less than the length of `buffer`; an
+    /// empty `buffer` returns `Ok(0)` immediately.
+    fn read(&mut self, buffer: &mut [Word]) -> Result<usize, Self::Error>;
+}
+
+/// Read half of a serial interface, giving up after a timeout (blocking variant)
+pub trait ReadTimeout<Word> {
+    /// The type of error that can occur when reading
+    type Error;
+
+    /// Reads words into a slice, blocking until at least one word has been read
+    /// or `timer` has counted down `timeout`
+    ///
+    /// Returns the number of words read, as [`Read::read`] does, or
+    /// [`TimeoutError::TimedOut`] if no word was received in time.
+    ///
+    /// [`Read::read`]: trait.Read.html#tymethod.read
+    /// [`TimeoutError::TimedOut`]: enum.TimeoutError.html#variant.TimedOut
+    fn read_timeout<T>(
+        &mut self,
+        buffer: &mut [Word],
+        timer: &mut T,
+        timeout: T::Time,
+    ) -> Result<usize, TimeoutError<Self::Error, T::Error>>
+    where
+        T: crate::nb::timer::CountDown;
+}
+
+/// Error of a serial read with timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutError<SE, TE> {
+    /// Serial interface error
+    Serial(SE),
+    /// Timer error
+    Timer(TE),
+    /// No word was received before the timer counted down
+    TimedOut,
+}
+
 /// Write half of a serial interface (blocking variant)
 pub trait Write<Word> {
     /// The type of error that can occur when writing
@@ -19,6 +66,226 @@ pub trait Write<Word> {
     fn flush(&mut self) -> Result<(), Self::Error>;
 }
 
+/// Number of words [`WriteProgress::write_all_with`] writes between progress reports
+///
+/// [`WriteProgress::write_all_with`]: trait.WriteProgress.html#tymethod.write_all_with
+pub const WRITE_CHUNK_LEN: usize = 64;
+
+/// Extension of the blocking serial [`Write`] reporting progress of long writes
+///
+/// Implemented for every serial interface implementing [`Write`].
+///
+/// [`Write`]: trait.Write.html
+pub trait WriteProgress<Word>: Write<Word> {
+    /// Writes a slice in chunks of [`WRITE_CHUNK_LEN`] words, blocking until everything has been written
+    ///
+    /// After each chunk, `on_progress` is called with the number of words written so far,
+    /// so that a transfer taking a long time can report its progress. Each chunk is
+    /// written by a single call of [`Write::write`], which an implementation may hand
+    /// to DMA as a whole.
+    ///
+    /// On error, [`PartialWrite::written`] counts the words of the chunks written before
+    /// the failing one; the transfer is resumed by writing `&buffer[written..]`. Words of
+    /// the failing chunk may have been sent already, and are then sent again.
+    ///
+    /// [`WRITE_CHUNK_LEN`]: constant.WRITE_CHUNK_LEN.html
+    /// [`Write::write`]: trait.Write.html#tymethod.write
+    /// [`PartialWrite::written`]: struct.PartialWrite.html#structfield.written
+    fn write_all_with<F>(
+        &mut self,
+        buffer: &[Word],
+        on_progress: F,
+    ) -> Result<(), PartialWrite<Self::Error>>
+    where
+        F: FnMut(usize);
+}
+
+/// Error of a serial write reporting progress, with the number of words known to be written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialWrite<E> {
+    /// Number of words written before the error
+    pub written: usize,
+    /// Serial interface error
+    pub error: E,
+}
+
+impl<S, Word> WriteProgress<Word> for S
+where
+    S: Write<Word>,
+{
+    fn write_all_with<F>(
+        &mut self,
+        buffer: &[Word],
+        mut on_progress: F,
+    ) -> Result<(), PartialWrite<Self::Error>>
+    where
+        F: FnMut(usize),
+    {
+        let mut written = 0;
+        for chunk in buffer.chunks(WRITE_CHUNK_LEN) {
+            self.write(chunk)
+                .map_err(|error| PartialWrite { written, error })?;
+            written += chunk.len();
+            on_progress(written);
+        }
+
+        Ok(())
+    }
+}
+
+/// Adapter implementing [`core::fmt::Write`] over a blocking serial [`Write`] of bytes
+///
+/// Formatted output, such as `write!` and `writeln!`, is written to the serial
+/// interface as UTF-8 bytes, each string slice with a single call of [`Write::write`].
+/// Serial errors are reported as [`core::fmt::Error`]; the serial interface is
+/// not flushed.
+///
+/// [`Write`]: trait.Write.html
+/// [`Write::write`]: trait.Write.html#tymethod.write
+pub struct FmtWrite<S> {
+    serial: S,
+}
+
+impl<S> FmtWrite<S> {
+    /// Wraps a serial interface
+    pub const fn new(serial: S) -> Self {
+        FmtWrite { serial }
+    }
+
+    /// Returns a mutable reference to the serial interface, for example to flush it
+    pub fn serial_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Releases the serial interface
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+impl<S> core::fmt::Write for FmtWrite<S>
+where
+    S: Write<u8>,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.serial
+            .write(s.as_bytes())
+            .map_err(|_| core::fmt::Error)
+    }
+}
+
+/// Buffered implementation of the blocking serial [`Write`] over a non-blocking serial interface
+///
+/// Words are queued into a ring buffer in caller-provided storage, and sent by
+/// [`drain`], typically called from the transmit or timer interrupt, so that
+/// writing does not wait for the serial interface. Only when the buffer is full
+/// does [`Write::write`] wait, sending the oldest words itself until there is
+/// room. [`Write::flush`] sends every queued word and flushes the interface.
+///
+/// [`Write`]: trait.Write.html
+/// [`Write::write`]: trait.Write.html#tymethod.write
+/// [`Write::flush`]: trait.Write.html#tymethod.flush
+/// [`drain`]: #method.drain
+pub struct BufferedWrite<'a, S, Word> {
+    serial: S,
+    buffer: &'a mut [Word],
+    // index of the oldest queued word
+    head: usize,
+    len: usize,
+}
+
+impl<'a, S, Word> BufferedWrite<'a, S, Word>
+where
+    S: crate::nb::serial::Write<Word>,
+    Word: Copy,
+{
+    /// Wraps a serial interface, queueing words into `buffer`
+    ///
+    /// With an empty `buffer`, every word is written to the serial interface directly.
+    pub fn new(serial: S, buffer: &'a mut [Word]) -> Self {
+        BufferedWrite {
+            serial,
+            buffer,
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of words waiting to be sent
+    pub fn queued(&self) -> usize {
+        self.len
+    }
+
+    /// Sends queued words until the serial interface would block, without waiting
+    ///
+    /// Returns the number of words sent. Call this function whenever the serial
+    /// interface is ready to take more words, such as in its transmit interrupt.
+    pub fn drain(&mut self) -> Result<usize, S::Error> {
+        let mut count = 0;
+        while self.len != 0 {
+            match self.serial.write(self.buffer[self.head]) {
+                Ok(()) => {}
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+            self.head = (self.head + 1) % self.buffer.len();
+            self.len -= 1;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Releases the serial interface, with the words still queued
+    ///
+    /// Call [`Write::flush`] first to send them.
+    ///
+    /// [`Write::flush`]: trait.Write.html#tymethod.flush
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+// the blocking `Write` comes from `write::Default`, which retries until these calls make progress
+impl<'a, S, Word> crate::nb::serial::Write<Word> for BufferedWrite<'a, S, Word>
+where
+    S: crate::nb::serial::Write<Word>,
+    Word: Copy,
+{
+    type Error = S::Error;
+
+    fn write(&mut self, word: Word) -> nb::Result<(), Self::Error> {
+        if self.buffer.is_empty() {
+            return self.serial.write(word);
+        }
+        if self.len == self.buffer.len() {
+            self.drain().map_err(nb::Error::Other)?;
+            if self.len == self.buffer.len() {
+                return Err(nb::Error::WouldBlock);
+            }
+        }
+        let tail = (self.head + self.len) % self.buffer.len();
+        self.buffer[tail] = word;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.drain().map_err(nb::Error::Other)?;
+        if self.len != 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.serial.flush()
+    }
+}
+
+impl<'a, S, Word> write::Default<Word> for BufferedWrite<'a, S, Word>
+where
+    S: crate::nb::serial::Write<Word>,
+    Word: Copy,
+{
+}
+
 /// Blocking serial write
 pub mod write {
     /// Marker trait to opt into default blocking write implementation
@@ -52,3 +319,94 @@ pub mod write {
         }
     }
 }
+
+/// Blocking serial read
+pub mod read {
+    /// Marker trait to opt into default blocking read implementation
+    ///
+    /// Implementers of [`nonblocking::serial::Read`] can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`blocking::serial::Read`] for the type.
+    ///
+    /// [`nonblocking::serial::Read`]: ../../nonblocking/serial/trait.Read.html
+    /// [`blocking::serial::Read`]: ../trait.Read.html
+    pub trait Default<Word>: crate::nb::serial::Read<Word> {}
+
+    impl<S, Word> crate::blocking::serial::Read<Word> for S
+    where
+        S: Default<Word>,
+    {
+        type Error = S::Error;
+
+        fn read(&mut self, buffer: &mut [Word]) -> Result<usize, Self::Error> {
+            let (first, rest) = match buffer.split_first_mut() {
+                Some(split) => split,
+                None => return Ok(0),
+            };
+            *first = nb::block!(self.read())?;
+            let mut count = 1;
+            for word in rest {
+                match self.read() {
+                    Ok(w) => *word = w,
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(e)) => return Err(e),
+                }
+                count += 1;
+            }
+
+            Ok(count)
+        }
+    }
+
+    impl<S, Word> crate::blocking::serial::ReadTimeout<Word> for S
+    where
+        S: Default<Word>,
+    {
+        type Error = S::Error;
+
+        fn read_timeout<T>(
+            &mut self,
+            buffer: &mut [Word],
+            timer: &mut T,
+            timeout: T::Time,
+        ) -> Result<usize, super::TimeoutError<Self::Error, T::Error>>
+        where
+            T: crate::nb::timer::CountDown,
+        {
+            use super::TimeoutError;
+
+            let (first, _) = match buffer.split_first_mut() {
+                Some(split) => split,
+                None => return Ok(0),
+            };
+            timer.start(timeout).map_err(TimeoutError::Timer)?;
+            loop {
+                match self.read() {
+                    Ok(w) => {
+                        *first = w;
+                        break;
+                    }
+                    Err(nb::Error::WouldBlock) => {}
+                    Err(nb::Error::Other(e)) => return Err(TimeoutError::Serial(e)),
+                }
+                match timer.wait() {
+                    Ok(()) => return Err(TimeoutError::TimedOut),
+                    Err(nb::Error::WouldBlock) => {}
+                    Err(nb::Error::Other(e)) => return Err(TimeoutError::Timer(e)),
+                }
+            }
+            // the first word has arrived, the rest is read as far as already received
+            let mut count = 1;
+            for word in buffer[1..].iter_mut() {
+                match self.read() {
+                    Ok(w) => *word = w,
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(e)) => return Err(TimeoutError::Serial(e)),
+                }
+                count += 1;
+            }
+
+            Ok(count)
+        }
+    }
+}
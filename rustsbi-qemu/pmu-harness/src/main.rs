@@ -0,0 +1,409 @@
+use std::{
+    fmt, fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{self, Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+// 测试内核完成自检并开始接受请求时输出的行
+const CONSOLE_READY: &str = "<< PMU-test: Console ready";
+// 测试内核失败时输出的行以此开头
+const TEST_FAILURE: &str = "!! PMU-test:";
+// 自检耗时较长，等待控制台就绪的时间
+const READY_TIMEOUT: Duration = Duration::from_secs(120);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const USAGE: &str = "\
+usage: pmu-harness --dist <DIR> [--qemu <QEMU>] [--cpu <CPU>] [--thresholds <FILE>]
+
+  --dist <DIR>         directory of rustsbi-qemu.bin and pmu-test-kernel.bin, built with the console feature
+  --qemu <QEMU>        QEMU system emulator, qemu-system-riscv64 by default
+  --cpu <CPU>          QEMU CPU model, rv64,sscofpmf=true by default
+  --thresholds <FILE>  events and bounds of their counts, pmu-harness/thresholds.txt by default";
+
+fn main() {
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("pmu-harness: {}", e);
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    });
+    let thresholds_path = args.thresholds;
+    let thresholds = fs::read_to_string(&thresholds_path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse_thresholds(&text))
+        .unwrap_or_else(|e| {
+            eprintln!("pmu-harness: {}: {}", thresholds_path.display(), e);
+            process::exit(2);
+        });
+    let mut command = Command::new(&args.qemu);
+    command
+        .current_dir(&args.dist)
+        .args(&["-machine", "virt"])
+        .args(&["-cpu", &args.cpu])
+        .args(&["-smp", "2"])
+        .args(&["-icount", "shift=0"])
+        .args(&["-bios", "rustsbi-qemu.bin"])
+        .args(&["-kernel", "pmu-test-kernel.bin"])
+        .arg("-nographic");
+    let mut console = Console::spawn(command).unwrap_or_else(|e| {
+        eprintln!("pmu-harness: {}", e);
+        process::exit(2);
+    });
+    if let Err(e) = console.wait_ready() {
+        console.dump_log();
+        eprintln!("pmu-harness: {}", e);
+        console.kill();
+        process::exit(2);
+    }
+    let outcomes: Vec<Outcome> = thresholds
+        .iter()
+        .map(|threshold| measure(&mut console, threshold))
+        .collect();
+    if let Err(e) = console.quit() {
+        eprintln!("pmu-harness: {}", e);
+        console.kill();
+    }
+
+    println!("pmu-harness: {} events measured", outcomes.len());
+    for (threshold, outcome) in thresholds.iter().zip(&outcomes) {
+        println!("{} {:<20} {}", outcome.verdict(), threshold.name, outcome);
+    }
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.verdict() != "PASS")
+        .count();
+    if failed != 0 {
+        println!(
+            "pmu-harness: {} of {} events failed",
+            failed,
+            outcomes.len()
+        );
+        process::exit(1);
+    }
+    println!("pmu-harness: all events passed");
+}
+
+struct Args {
+    dist: PathBuf,
+    qemu: String,
+    cpu: String,
+    thresholds: PathBuf,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut dist = None;
+        let mut parsed = Args {
+            dist: PathBuf::new(),
+            qemu: "qemu-system-riscv64".to_string(),
+            cpu: "rv64,sscofpmf=true".to_string(),
+            thresholds: Path::new(env!("CARGO_MANIFEST_DIR")).join("thresholds.txt"),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} takes a value", arg));
+            match arg.as_str() {
+                "--dist" => dist = Some(PathBuf::from(value()?)),
+                "--qemu" => parsed.qemu = value()?,
+                "--cpu" => parsed.cpu = value()?,
+                "--thresholds" => parsed.thresholds = PathBuf::from(value()?),
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        parsed.dist = dist.ok_or("--dist is required")?;
+        Ok(parsed)
+    }
+}
+
+// an event to measure, and the bounds of its count
+struct Threshold {
+    name: String,
+    event_idx: u64,
+    event_data: Option<u64>,
+    min: u64,
+    max: u64,
+}
+
+// `<name> <event_idx> [<event_data>] <min> <max>` on each line; `#` starts a comment
+fn parse_thresholds(text: &str) -> Result<Vec<Threshold>, String> {
+    let mut thresholds = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let numbers = words[1..]
+            .iter()
+            .map(|word| {
+                parse_number(word).ok_or_else(|| format!("line {}: bad number {}", i + 1, word))
+            })
+            .collect::<Result<Vec<u64>, String>>()?;
+        let (event_idx, event_data, min, max) = match numbers[..] {
+            [event_idx, min, max] => (event_idx, None, min, max),
+            [event_idx, event_data, min, max] => (event_idx, Some(event_data), min, max),
+            _ => {
+                return Err(format!(
+                    "line {}: expected <name> <event_idx> [<event_data>] <min> <max>",
+                    i + 1
+                ))
+            }
+        };
+        if min > max {
+            return Err(format!(
+                "line {}: minimum {} is above maximum {}",
+                i + 1,
+                min,
+                max
+            ));
+        }
+        thresholds.push(Threshold {
+            name: words[0].to_string(),
+            event_idx,
+            event_data,
+            min,
+            max,
+        });
+    }
+    Ok(thresholds)
+}
+
+fn parse_number(word: &str) -> Option<u64> {
+    match word.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+enum Outcome {
+    InRange {
+        counter_idx: u64,
+        value: u64,
+        min: u64,
+        max: u64,
+    },
+    Below {
+        counter_idx: u64,
+        value: u64,
+        min: u64,
+    },
+    Above {
+        counter_idx: u64,
+        value: u64,
+        max: u64,
+    },
+    Error(String),
+}
+
+impl Outcome {
+    fn verdict(&self) -> &'static str {
+        match self {
+            Outcome::InRange { .. } => "PASS",
+            Outcome::Below { .. } | Outcome::Above { .. } => "FAIL",
+            Outcome::Error(_) => "ERROR",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::InRange {
+                counter_idx,
+                value,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "counter {:>2}: {} within [{}, {}]",
+                    counter_idx, value, min, max
+                )
+            }
+            Outcome::Below {
+                counter_idx,
+                value,
+                min,
+            } => {
+                write!(
+                    f,
+                    "counter {:>2}: {} below minimum {}, diff -{}",
+                    counter_idx,
+                    value,
+                    min,
+                    min - value
+                )
+            }
+            Outcome::Above {
+                counter_idx,
+                value,
+                max,
+            } => {
+                write!(
+                    f,
+                    "counter {:>2}: {} above maximum {}, diff +{}",
+                    counter_idx,
+                    value,
+                    max,
+                    value - max
+                )
+            }
+            Outcome::Error(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// configure a counter for the event, count from start to read, and release the counter
+fn measure(console: &mut Console, threshold: &Threshold) -> Outcome {
+    let config = match threshold.event_data {
+        Some(event_data) => format!("PMU CONFIG {:#x} {:#x}", threshold.event_idx, event_data),
+        None => format!("PMU CONFIG {:#x}", threshold.event_idx),
+    };
+    let counter_idx = match console.request(&config) {
+        Ok(Some(counter_idx)) => counter_idx,
+        Ok(None) => return Outcome::Error(format!("{}: no counter index in response", config)),
+        Err(e) => return Outcome::Error(e),
+    };
+    let value = console
+        .request(&format!("PMU START {}", counter_idx))
+        .and_then(|_| console.request(&format!("PMU READ {}", counter_idx)));
+    // the counter is released whether or not it was read
+    let released = console.request(&format!("PMU RELEASE {}", counter_idx));
+    let value = match (value, released) {
+        (Ok(Some(value)), Ok(_)) => value,
+        (Ok(None), _) => {
+            return Outcome::Error(format!("PMU READ {}: no value in response", counter_idx))
+        }
+        (Err(e), _) | (_, Err(e)) => return Outcome::Error(e),
+    };
+    let (min, max) = (threshold.min, threshold.max);
+    if value < min {
+        Outcome::Below {
+            counter_idx,
+            value,
+            min,
+        }
+    } else if value > max {
+        Outcome::Above {
+            counter_idx,
+            value,
+            max,
+        }
+    } else {
+        Outcome::InRange {
+            counter_idx,
+            value,
+            min,
+            max,
+        }
+    }
+}
+
+// the serial console of the test kernel, on the standard input and output of QEMU
+struct Console {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    log: Vec<String>,
+}
+
+impl Console {
+    fn spawn(mut command: Command) -> Result<Console, String> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("spawn qemu: {}", e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        // lines are read on a thread, so that a hung QEMU is caught by timeouts
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                // stops when QEMU exits, or when the harness no longer listens
+                match line.map(|line| sender.send(line)) {
+                    Ok(Ok(())) => {}
+                    _ => return,
+                }
+            }
+        });
+        Ok(Console {
+            child,
+            stdin,
+            lines,
+            log: Vec::new(),
+        })
+    }
+
+    fn next_line(&mut self, timeout: Duration) -> Result<String, String> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => {
+                let line = line.trim_end().to_string();
+                self.log.push(line.clone());
+                if line.starts_with(TEST_FAILURE) {
+                    return Err(format!("test kernel failed: {}", line));
+                }
+                Ok(line)
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                Err(format!("no output from the test kernel in {:?}", timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err("QEMU exited".to_string()),
+        }
+    }
+
+    fn wait_ready(&mut self) -> Result<(), String> {
+        while self.next_line(READY_TIMEOUT)? != CONSOLE_READY {}
+        Ok(())
+    }
+
+    // send a request, and return the value of an `OK <value>` response
+    fn request(&mut self, request: &str) -> Result<Option<u64>, String> {
+        writeln!(self.stdin, "{}", request)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("{}: {}", request, e))?;
+        // other harts may print in between
+        loop {
+            let line = self
+                .next_line(RESPONSE_TIMEOUT)
+                .map_err(|e| format!("{}: {}", request, e))?;
+            if line == "OK" {
+                return Ok(None);
+            }
+            if let Some(value) = line.strip_prefix("OK ") {
+                return parse_number(value)
+                    .map(Some)
+                    .ok_or_else(|| format!("{}: bad response {}", request, line));
+            }
+            if let Some(reason) = line.strip_prefix("ERR ") {
+                return Err(format!("{}: error {}", request, reason));
+            }
+        }
+    }
+
+    // end the session; the test kernel shuts QEMU down
+    fn quit(&mut self) -> Result<(), String> {
+        self.request("QUIT")?;
+        let status = self
+            .child
+            .wait()
+            .map_err(|e| format!("wait on qemu: {}", e))?;
+        if !status.success() {
+            return Err(format!("QEMU exited with {}", status));
+        }
+        Ok(())
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    fn dump_log(&self) {
+        for line in &self.log {
+            eprintln!("{}", line);
+        }
+    }
+}
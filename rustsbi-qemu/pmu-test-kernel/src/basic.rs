@@ -0,0 +1,61 @@
+// Go through the whole PMU call sequence with a hardware counter
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing PMU call sequence");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    check!(num_counters > 0, "num_counters returned no counters");
+    println!("<< PMU-test: Number of counters: {}", num_counters);
+    println!("<< PMU-test: {} programmable counters", counter::programmable_counters());
+    // hardware counters come first, each read through a counter CSR of its own, then firmware counters
+    let mut first_firmware = None;
+    let mut csrs = 0u32;
+    for idx in 0..num_counters {
+        let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+        println!(
+            "<< PMU-test: Counter {}: csr = {:#x}, width = {}, firmware = {}",
+            idx, info.csr, info.width, info.firmware
+        );
+        if info.firmware {
+            first_firmware.get_or_insert(idx);
+        } else {
+            check!(first_firmware.is_none(), "hardware counter {} follows firmware counter {:?}", idx, first_firmware);
+            check!((0xC00..0xC20).contains(&info.csr), "counter {} reported csr {:#x}, not a counter CSR", idx, info.csr);
+            check!(csrs & 1 << (info.csr - 0xC00) == 0, "counter {} reported csr {:#x} of another counter", idx, info.csr);
+            csrs |= 1 << (info.csr - 0xC00);
+        }
+        // QEMU implements every hardware counter with 64 bits, which the firmware detects at boot
+        check!(
+            info.firmware || info.width == 64,
+            "counter {} reported width {}, expected 64",
+            idx,
+            info.width
+        );
+    }
+
+    let mask = counter::all_counters(num_counters);
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, sbi::CFG_FLAG_CLEAR_VALUE, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        "counter_config_matching"
+    );
+    check!(idx < num_counters, "counter_config_matching returned counter {} out of range", idx);
+    let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+    check!(!info.firmware, "instructions event matched firmware counter {}", idx);
+    println!("<< PMU-test: Instructions event matched counter {}", idx);
+
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start");
+    let start = counter::read(info.csr);
+    counter::spin(1000);
+    let end = counter::read(info.csr);
+    check!(end > start, "counter {} did not advance while running: {} -> {}", idx, start, end);
+    measurement!(hartid, "instructions", idx, end - start, "Counter {} advanced by {}", idx, end - start);
+
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop");
+    let stopped = counter::read(info.csr);
+    counter::spin(1000);
+    let after = counter::read(info.csr);
+    check!(after == stopped, "counter {} advanced after stop: {} -> {}", idx, stopped, after);
+    println!("<< PMU-test: PMU call sequence passed");
+}
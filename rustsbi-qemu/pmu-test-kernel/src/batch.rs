@@ -0,0 +1,125 @@
+// Reading many counters in one call of RustSBI's firmware specific extension, compared with reading
+// them one by one; the numbers are printed for reference and left out of the golden output
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi::{self, SBI_ERR_FAILED, SBI_ERR_INVALID_ADDRESS, SBI_ERR_INVALID_PARAM};
+use core::ptr::read_volatile;
+
+const ROUNDS: usize = 8;
+
+// one 64-bit value for each bit of the counter mask; the test kernel runs without paging,
+// so the address of this buffer is its physical address
+static mut VALUES: [u64; usize::BITS as usize] = [0; usize::BITS as usize];
+
+pub fn run() {
+    println!(">> PMU-test: Testing batch counter read");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let buf = unsafe { VALUES.as_ptr() as usize };
+
+    // every firmware counter holds a different value, stopped so that it does not change;
+    // multiplexed counters, if any, do not count firmware events and are left out
+    let mut fw_mask: usize = 0;
+    loop {
+        let ret = sbi::pmu_counter_config_matching(
+            fw_base,
+            counter::all_counters(num_counters - fw_base),
+            sbi::CFG_FLAG_CLEAR_VALUE,
+            sbi::EVENT_FW_SET_TIMER,
+            0,
+        );
+        if ret.error == SBI_ERR_FAILED {
+            break;
+        }
+        let idx = check_ok!(ret, "counter_config_matching set_timer");
+        check_ok!(sbi::pmu_counter_start(idx, 1, sbi::START_FLAG_SET_INIT_VALUE, initial_value(idx)), "counter_start");
+        check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop");
+        fw_mask |= 1 << (idx - fw_base);
+    }
+    check!(fw_mask != 0, "no firmware counter to read");
+    let fw_counters = fw_mask.count_ones() as usize;
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let clock_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, counter::all_counters(fw_base), flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let clock = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(clock_idx), "counter_get_info")).csr;
+
+    // values are placed relative to the base, hardware counters included
+    check_ok!(sbi::pmu_counter_stop(clock_idx, 1, 0), "counter_stop cycles");
+    let read = check_ok!(
+        sbi::rustsbi_pmu_counter_read_batch(0, (1 << clock_idx) | (fw_mask << fw_base), buf, 0),
+        "read_batch"
+    );
+    check!(read == 1 + fw_counters, "read_batch read {} counters", read);
+    let value = unsafe { read_volatile(&VALUES[clock_idx]) };
+    check!(
+        value as usize == counter::read(clock),
+        "read_batch returned {} for the cycle counter, which reads {}",
+        value,
+        counter::read(clock)
+    );
+    for idx in (fw_base..num_counters).filter(|idx| fw_mask & (1 << (idx - fw_base)) != 0) {
+        let value = unsafe { read_volatile(&VALUES[idx]) };
+        let fw_value = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+        check!(
+            value == initial_value(idx) && value as usize == fw_value,
+            "read_batch returned {} for counter {}, counter_fw_read returned {}",
+            value,
+            idx,
+            fw_value
+        );
+    }
+    check_ok!(sbi::pmu_counter_start(clock_idx, 1, 0, 0), "counter_start cycles");
+
+    check_err!(
+        sbi::rustsbi_pmu_counter_read_batch(fw_base, fw_mask, buf + 4, 0),
+        SBI_ERR_INVALID_PARAM,
+        "read_batch misaligned buffer"
+    );
+    check_err!(
+        sbi::rustsbi_pmu_counter_read_batch(fw_base, fw_mask, buf, 1),
+        SBI_ERR_INVALID_ADDRESS,
+        "read_batch buffer above XLEN bits"
+    );
+    check_err!(
+        // counter 1 is the `time` counter, which is read with the `time` CSR
+        sbi::rustsbi_pmu_counter_read_batch(0, 1 << 1, buf, 0),
+        SBI_ERR_INVALID_PARAM,
+        "read_batch time counter"
+    );
+    check_err!(
+        sbi::rustsbi_pmu_counter_read_batch(fw_base, 1 << (num_counters - fw_base), buf, 0),
+        SBI_ERR_INVALID_PARAM,
+        "read_batch out of range"
+    );
+
+    let (mut batch, mut one_by_one) = (0, 0);
+    for _ in 0..ROUNDS {
+        let start = counter::read(clock);
+        check_ok!(sbi::rustsbi_pmu_counter_read_batch(fw_base, fw_mask, buf, 0), "read_batch");
+        batch += counter::read(clock).wrapping_sub(start);
+
+        let start = counter::read(clock);
+        for idx in (fw_base..num_counters).filter(|idx| fw_mask & (1 << (idx - fw_base)) != 0) {
+            check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+        }
+        one_by_one += counter::read(clock).wrapping_sub(start);
+    }
+    println!(
+        "<< PMU-test: Reading {} counters takes {} cycles in one call, {} cycles one by one",
+        fw_counters,
+        batch / ROUNDS,
+        one_by_one / ROUNDS
+    );
+
+    // only running counters can be stopped, and unbound from their events at the same time
+    check_ok!(sbi::pmu_counter_start(fw_base, fw_mask, 0, 0), "counter_start");
+    check_ok!(sbi::pmu_counter_stop(fw_base, fw_mask, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    check_ok!(sbi::pmu_counter_stop(clock_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    println!("<< PMU-test: Batch counter read passed");
+}
+
+fn initial_value(counter_idx: usize) -> u64 {
+    1000 + counter_idx as u64
+}
@@ -0,0 +1,107 @@
+// Cycles per PMU call, measured with `rdcycle` around the calls and printed as a table with the `bench` feature;
+// with `bench-check` as well, each call must stay within a tolerance of its baseline in `bench-baseline.txt`
+
+use crate::counter;
+use crate::sbi::{self, SbiRet};
+
+const ITERATIONS: usize = 64;
+const CSR_CYCLE: usize = 0xC00;
+// a call may take this much longer than its baseline, in percent, before it counts as a regression
+#[cfg(feature = "bench-check")]
+const TOLERANCE_PERCENT: usize = 25;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Benchmarking PMU calls");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let fw_mask = counter::all_counters(num_counters - fw_base);
+    // `rdcycle` only counts while the `cycle` counter is started
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let cycle_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, 1, flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    // a firmware counter is the target of the calls, as every platform has one
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, fw_mask, sbi::CFG_FLAG_CLEAR_VALUE, sbi::EVENT_FW_IPI_SENT, 0),
+        "counter_config_matching ipi sent"
+    );
+    // reading `cycle` twice in a row, subtracted from every measurement
+    let overhead = measure(|| {}, || SbiRet { error: sbi::SBI_SUCCESS, value: 0 }, |_| {}).1;
+
+    println!("<< PMU-test: {:<24} {:>8} {:>8}", "call", "average", "minimum");
+    let stop = |_: &SbiRet| {
+        check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop");
+    };
+    let (total, min) = measure(|| {}, || sbi::pmu_counter_start(idx, 1, 0, 0), stop);
+    report(hartid, "counter_start", idx, total, min, overhead);
+    let start = || {
+        check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start");
+    };
+    let (total, min) = measure(start, || sbi::pmu_counter_stop(idx, 1, 0), |_| {});
+    report(hartid, "counter_stop", idx, total, min, overhead);
+    let (total, min) = measure(|| {}, || sbi::pmu_counter_fw_read(idx), |_| {});
+    report(hartid, "counter_fw_read", idx, total, min, overhead);
+    start();
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    // configured counters are started, so that they can be released with a reset between the calls
+    let config = || sbi::pmu_counter_config_matching(fw_base, fw_mask, flags, sbi::EVENT_FW_IPI_SENT, 0);
+    let release = |ret: &SbiRet| {
+        check_ok!(sbi::pmu_counter_stop(ret.value, 1, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    };
+    let (total, min) = measure(|| {}, config, release);
+    report(hartid, "counter_config_matching", fw_base, total, min, overhead);
+
+    check_ok!(sbi::pmu_counter_stop(cycle_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    println!("<< PMU-test: PMU call benchmark finished");
+}
+
+// total and minimum cycles of `ITERATIONS` calls, with `before` and `after` each call left out of the measurement
+fn measure(mut before: impl FnMut(), mut call: impl FnMut() -> SbiRet, mut after: impl FnMut(&SbiRet)) -> (usize, usize) {
+    let (mut total, mut min) = (0, usize::MAX);
+    for _ in 0..ITERATIONS {
+        before();
+        let start = counter::read(CSR_CYCLE);
+        let ret = call();
+        let cycles = counter::read(CSR_CYCLE).wrapping_sub(start);
+        check_ok!(ret, "benchmarked call");
+        after(&ret);
+        total += cycles;
+        min = min.min(cycles);
+    }
+    (total, min)
+}
+
+fn report(hartid: usize, call: &'static str, counter_idx: usize, total: usize, min: usize, overhead: usize) {
+    let average = (total / ITERATIONS).saturating_sub(overhead);
+    let min = min.saturating_sub(overhead);
+    measurement!(hartid, call, counter_idx, average, "{:<24} {:>8} {:>8}", call, average, min);
+    #[cfg(feature = "bench-check")]
+    {
+        let baseline = baseline(call);
+        check!(
+            average <= baseline * (100 + TOLERANCE_PERCENT) / 100,
+            "{} takes {} cycles, more than {}% above its baseline of {} cycles",
+            call,
+            average,
+            TOLERANCE_PERCENT,
+            baseline
+        );
+    }
+}
+
+// `<call> <cycles>` on each line of the baseline file; `#` starts a comment
+#[cfg(feature = "bench-check")]
+fn baseline(call: &str) -> usize {
+    const BASELINE: &str = include_str!("../bench-baseline.txt");
+    for line in BASELINE.lines() {
+        let mut words = line.split('#').next().unwrap().split_ascii_whitespace();
+        if words.next() == Some(call) {
+            if let Some(cycles) = words.next().and_then(|word| word.parse().ok()) {
+                return cycles;
+            }
+        }
+    }
+    check!(false, "no baseline for {} in bench-baseline.txt", call);
+    unreachable!()
+}
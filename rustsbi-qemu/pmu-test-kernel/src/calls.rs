@@ -0,0 +1,46 @@
+// SBI calls serviced for this hart, counted by platform specific firmware events of RustSBI
+
+use crate::counter;
+use crate::sbi;
+
+const BASE_CALLS: usize = 3;
+const PMU_CALLS: usize = 2;
+
+pub fn run() {
+    println!(">> PMU-test: Testing SBI call counting");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let mask = counter::all_counters(num_counters - fw_base);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let sbi_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_SBI_CALLS),
+        "counter_config_matching sbi calls"
+    );
+    let pmu_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_PMU_CALLS),
+        "counter_config_matching pmu calls"
+    );
+
+    // a call is counted before it is handled, so every read counts itself; nothing is printed in between,
+    // as printing makes console calls
+    let sbi_before = check_ok!(sbi::pmu_counter_fw_read(sbi_idx), "counter_fw_read sbi calls");
+    let pmu_before = check_ok!(sbi::pmu_counter_fw_read(pmu_idx), "counter_fw_read pmu calls");
+    for _ in 0..BASE_CALLS {
+        sbi::probe_extension(sbi::EXTENSION_PMU);
+    }
+    for _ in 0..PMU_CALLS {
+        check_ok!(sbi::pmu_num_counters(), "num_counters");
+    }
+    let sbi_after = check_ok!(sbi::pmu_counter_fw_read(sbi_idx), "counter_fw_read sbi calls");
+    let pmu_after = check_ok!(sbi::pmu_counter_fw_read(pmu_idx), "counter_fw_read pmu calls");
+    // the calls above, the read of the PMU call counter before them and the read after them
+    let sbi_calls = sbi_after.wrapping_sub(sbi_before);
+    check!(sbi_calls == BASE_CALLS + PMU_CALLS + 2, "{} SBI calls counted, expected {}", sbi_calls, BASE_CALLS + PMU_CALLS + 2);
+    // base calls are not counted as PMU calls, the two reads of the SBI call counter are
+    let pmu_calls = pmu_after.wrapping_sub(pmu_before);
+    check!(pmu_calls == PMU_CALLS + 2, "{} PMU calls counted, expected {}", pmu_calls, PMU_CALLS + 2);
+
+    check_ok!(sbi::pmu_counter_stop(sbi_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop sbi calls");
+    check_ok!(sbi::pmu_counter_stop(pmu_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop pmu calls");
+    println!("<< PMU-test: SBI call counting passed");
+}
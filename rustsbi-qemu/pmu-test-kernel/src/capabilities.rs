@@ -0,0 +1,29 @@
+// Capabilities of the firmware build, reported by the capabilities call of RustSBI's firmware specific extension
+// so that tests adapt to the firmware instead of probing each capability
+
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM};
+
+// RustSBI-QEMU runs `GenericPmu` and registers the machine timer with the sampler
+const EXPECTED: usize = sbi::RUSTSBI_PMU_CAP_SNAPSHOT
+    | sbi::RUSTSBI_PMU_CAP_FIRMWARE_EVENTS
+    | sbi::RUSTSBI_PMU_CAP_READ_BATCH
+    | sbi::RUSTSBI_PMU_CAP_SAMPLER;
+
+pub fn run() {
+    println!(">> PMU-test: Testing PMU capabilities");
+    let caps = check_ok!(sbi::rustsbi_pmu_capabilities(0), "rustsbi_pmu_capabilities");
+    check!(caps & EXPECTED == EXPECTED, "capabilities {:#x} lack {:#x}", caps, EXPECTED & !caps);
+    println!("<< PMU-test: Sscofpmf {}", if has_sscofpmf() { "reported" } else { "not reported" });
+    // the version is the one supervisor reads on probe
+    let version = check_ok!(sbi::rustsbi_pmu_capabilities(sbi::RUSTSBI_PMU_CAPABILITIES_VERSION), "rustsbi_pmu_capabilities version");
+    let probed = sbi::probe_extension(sbi::EXTENSION_PMU);
+    check!(version == probed, "version {:#x}, but probed {:#x}", version, probed);
+    check_err!(sbi::rustsbi_pmu_capabilities(1 << 1), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_capabilities reserved flags");
+    println!("<< PMU-test: PMU capabilities passed");
+}
+
+// whether hardware counters raise overflow interrupts; firmware calls failing report none
+pub fn has_sscofpmf() -> bool {
+    let ret = sbi::rustsbi_pmu_capabilities(0);
+    ret.error == sbi::SBI_SUCCESS && ret.value & sbi::RUSTSBI_PMU_CAP_SSCOFPMF != 0
+}
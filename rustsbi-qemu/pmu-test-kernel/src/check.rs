@@ -0,0 +1,52 @@
+// Test verdicts; the firmware turns system reset into TEST_PASS or TEST_FAIL on the sifive_test device
+
+use crate::sbi;
+
+// Fail the test if the condition does not hold
+macro_rules! check {
+    ($cond: expr, $fmt: literal $(, $($arg: tt)+)?) => {
+        if !$cond {
+            $crate::console::print(format_args!(concat!("!! PMU-test: ", $fmt, "\n") $(, $($arg)+)?));
+            $crate::check::fail()
+        }
+    };
+}
+
+// Fail the test if the SBI call returned an error, returns `SbiRet.value` otherwise
+macro_rules! check_ok {
+    ($ret: expr, $what: literal) => {{
+        let ret = $ret;
+        check!(
+            ret.error == $crate::sbi::SBI_SUCCESS,
+            "{} returned error {}",
+            $what,
+            ret.error as isize
+        );
+        ret.value
+    }};
+}
+
+// Fail the test if the SBI call did not return the expected error
+macro_rules! check_err {
+    ($ret: expr, $error: expr, $what: literal) => {{
+        let ret = $ret;
+        check!(
+            ret.error == $error,
+            "{} returned error {}, expected {}",
+            $what,
+            ret.error as isize,
+            $error as isize
+        );
+    }};
+}
+
+pub fn pass() -> ! {
+    sbi::system_reset(sbi::RESET_TYPE_SHUTDOWN, sbi::RESET_REASON_NO_REASON);
+    sbi::shutdown()
+}
+
+pub fn fail() -> ! {
+    println!("!! PMU-test: PMU test FAILED");
+    sbi::system_reset(sbi::RESET_TYPE_SHUTDOWN, sbi::RESET_REASON_SYSTEM_FAILURE);
+    sbi::shutdown()
+}
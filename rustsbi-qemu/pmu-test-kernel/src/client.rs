@@ -0,0 +1,45 @@
+// Typed wrappers of the client library against the calls of the test kernel: the same counters and
+// status, and a firmware counter configured, started, read and released through the wrappers
+
+use crate::counter;
+use crate::sbi;
+use sbi_pmu_client::{self as client, events, ConfigFlags, EventIdx, IndexMask, StartFlags, StopFlags};
+
+const ROUNDS: usize = 8;
+const INITIAL_VALUE: usize = 1000;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing PMU client library");
+    let num_counters = check_ok!(client::num_counters(), "client num_counters");
+    check!(num_counters == check_ok!(sbi::pmu_num_counters(), "num_counters"), "client found {} counters", num_counters);
+    for idx in 0..num_counters {
+        let info = check_ok!(client::counter_get_info(idx), "client counter_get_info");
+        check!(info == check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"), "client info of counter {} differs", idx);
+    }
+    let event = EventIdx::from_parts(events::EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_IPI_SENT);
+    check!(event.raw() == sbi::EVENT_FW_IPI_SENT, "client encoded ipi_sent as {:#x}", event.raw());
+
+    let base = counter::first_firmware_counter(num_counters);
+    let firmware = IndexMask::new(base, counter::all_counters(num_counters - base));
+    let flags = ConfigFlags::CLEAR_VALUE | ConfigFlags::AUTO_START;
+    let idx = check_ok!(client::counter_config_matching(firmware, flags, event, 0), "client counter_config_matching");
+    let counter = IndexMask::single(idx);
+    let target = 1 << hartid;
+    for _ in 0..ROUNDS {
+        check_ok!(sbi::send_ipi(&target, 0), "send_ipi");
+    }
+    // the IPIs sent to this hart are left pending, supervisor software interrupt is not used here
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    let value = check_ok!(client::counter_fw_read(idx), "client counter_fw_read");
+    check!(value == ROUNDS, "client read {} IPIs, expected {}", value, ROUNDS);
+    check_ok!(client::counter_stop(counter, StopFlags::empty()), "client counter_stop");
+    check_ok!(client::counter_start(counter, StartFlags::SET_INIT_VALUE, INITIAL_VALUE as u64), "client counter_start");
+    let value = check_ok!(client::counter_fw_read(idx), "client counter_fw_read");
+    check!(value == INITIAL_VALUE, "client read {} after starting from {}", value, INITIAL_VALUE);
+    check_ok!(client::counter_stop(counter, StopFlags::RESET), "client counter_stop reset");
+    check_err!(client::counter_stop(counter, StopFlags::RESET), sbi::SBI_ERR_ALREADY_STOPPED, "client counter_stop released");
+
+    let status = check_ok!(client::rustsbi::pmu_hart_status(hartid), "client pmu_hart_status");
+    check!(status == check_ok!(sbi::rustsbi_pmu_hart_status(hartid), "rustsbi_pmu_hart_status"), "client status {:#x} differs", status);
+    println!("<< PMU-test: PMU client library passed");
+}
@@ -0,0 +1,206 @@
+// Line-based request and response protocol over the console, so that a host-side script can operate
+// counters against the running firmware; built with the `console` feature and run by `cargo xtask console`
+//
+// Requests, one per line, with numbers in decimal or in hexadecimal with a `0x` prefix:
+//
+//   PMU CONFIG <event_idx> [<event_data>]   configure a counter for the event, responds with its index
+//   PMU START <counter_idx>                 start the counter from zero
+//   PMU STOP <counter_idx>                  stop the counter, keeping its value
+//   PMU RELEASE <counter_idx>               stop the counter if running, and unbind it from its event
+//   PMU READ <counter_idx>                  responds with the current value of the counter
+//   QUIT                                    end the session
+//
+// Each request is answered by one line, `OK`, `OK <value>`, or `ERR <reason>`, where the reason of a failed
+// SBI call is its error number.
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi::{self, SbiRet, SBI_ERR_ALREADY_STOPPED, SBI_SUCCESS};
+use core::convert::Infallible;
+use core::fmt::Write as _;
+use embedded_hal::blocking::serial::{read, write, FmtWrite, Read, Write};
+use embedded_hal::nb::serial;
+
+// The legacy console of the firmware as a serial interface, blocking through the default implementations
+pub struct SbiSerial;
+
+impl serial::Read<u8> for SbiSerial {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        match sbi::console_getchar() {
+            usize::MAX => Err(nb::Error::WouldBlock),
+            c => Ok(c as u8),
+        }
+    }
+}
+
+impl serial::Write<u8> for SbiSerial {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Infallible> {
+        sbi::console_putchar(word as usize);
+        Ok(())
+    }
+
+    // every character is out of the firmware once the call returns
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl read::Default<u8> for SbiSerial {}
+impl write::Default<u8> for SbiSerial {}
+
+const MAX_LINE: usize = 80;
+
+pub struct Console<S> {
+    io: FmtWrite<S>,
+}
+
+enum Response {
+    Ok,
+    Value(usize),
+    Error(&'static str),
+    Failed(usize),
+}
+
+impl<S: Read<u8> + Write<u8>> Console<S> {
+    pub fn new(serial: S) -> Self {
+        Console { io: FmtWrite::new(serial) }
+    }
+
+    // answer requests until `QUIT`, or until the console fails
+    pub fn serve(&mut self) {
+        let mut buf = [0u8; MAX_LINE];
+        loop {
+            let line = match self.read_line(&mut buf) {
+                Some(Ok(line)) => line,
+                Some(Err(reason)) => {
+                    self.respond(Response::Error(reason));
+                    continue;
+                }
+                None => return,
+            };
+            let mut words = line.split_ascii_whitespace();
+            let response = match words.next() {
+                None => continue,
+                Some("QUIT") => {
+                    self.respond(Response::Ok);
+                    return;
+                }
+                Some("PMU") => pmu_request(&mut words),
+                Some(_) => Response::Error("unknown request"),
+            };
+            self.respond(response);
+        }
+    }
+
+    // one line without its terminator; `None` if the console fails
+    fn read_line<'a>(&mut self, buf: &'a mut [u8; MAX_LINE]) -> Option<Result<&'a str, &'static str>> {
+        let mut len = 0;
+        let mut overflow = false;
+        loop {
+            let mut byte = [0u8];
+            if self.io.serial_mut().read(&mut byte).ok()? == 0 {
+                continue;
+            }
+            match byte[0] {
+                b'\r' | b'\n' if len == 0 && !overflow => continue,
+                b'\r' | b'\n' => break,
+                _ if len == MAX_LINE => overflow = true,
+                c => {
+                    buf[len] = c;
+                    len += 1;
+                }
+            }
+        }
+        if overflow {
+            return Some(Err("line too long"));
+        }
+        Some(core::str::from_utf8(&buf[..len]).map_err(|_| "not UTF-8"))
+    }
+
+    fn respond(&mut self, response: Response) {
+        let _ = match response {
+            Response::Ok => writeln!(self.io, "OK"),
+            Response::Value(value) => writeln!(self.io, "OK {}", value),
+            Response::Error(reason) => writeln!(self.io, "ERR {}", reason),
+            Response::Failed(error) => writeln!(self.io, "ERR {}", error as isize),
+        };
+        let _ = self.io.serial_mut().flush();
+    }
+}
+
+fn pmu_request<'a>(words: &mut impl Iterator<Item = &'a str>) -> Response {
+    let operation = words.next();
+    let mut args = [0usize; 2];
+    let mut count = 0;
+    for word in words {
+        match (args.get_mut(count), parse(word)) {
+            (Some(arg), Some(value)) => *arg = value,
+            (None, _) => return Response::Error("too many arguments"),
+            (_, None) => return Response::Error("bad number"),
+        }
+        count += 1;
+    }
+    let num_counters = match sbi::pmu_num_counters() {
+        SbiRet { error: SBI_SUCCESS, value } => value,
+        ret => return Response::Failed(ret.error),
+    };
+    let [first, second] = args;
+    match (operation, count) {
+        (Some("CONFIG"), 1) | (Some("CONFIG"), 2) => value_of(sbi::pmu_counter_config_matching(
+            0,
+            counter::all_counters(num_counters),
+            sbi::CFG_FLAG_CLEAR_VALUE,
+            first,
+            second as u64,
+        )),
+        (Some("START"), 1) => ok_of(sbi::pmu_counter_start(first, 1, sbi::START_FLAG_SET_INIT_VALUE, 0)),
+        (Some("STOP"), 1) => ok_of(sbi::pmu_counter_stop(first, 1, 0)),
+        (Some("RELEASE"), 1) => match sbi::pmu_counter_stop(first, 1, sbi::STOP_FLAG_RESET) {
+            // a stopped counter is released all the same
+            SbiRet { error: SBI_ERR_ALREADY_STOPPED, .. } => Response::Ok,
+            ret => ok_of(ret),
+        },
+        (Some("READ"), 1) => read_counter(first),
+        (Some("CONFIG"), _) | (Some("START"), _) | (Some("STOP"), _) | (Some("RELEASE"), _) | (Some("READ"), _) => {
+            Response::Error("wrong number of arguments")
+        }
+        _ => Response::Error("unknown PMU request"),
+    }
+}
+
+// firmware counters are read through the SBI call, hardware counters through their CSRs
+fn read_counter(counter_idx: usize) -> Response {
+    let info = match sbi::pmu_counter_get_info(counter_idx) {
+        SbiRet { error: SBI_SUCCESS, value } => CounterInfo::decode(value),
+        ret => return Response::Failed(ret.error),
+    };
+    if info.firmware {
+        value_of(sbi::pmu_counter_fw_read(counter_idx))
+    } else {
+        Response::Value(counter::read(info.csr))
+    }
+}
+
+fn value_of(ret: SbiRet) -> Response {
+    match ret.error {
+        SBI_SUCCESS => Response::Value(ret.value),
+        error => Response::Failed(error),
+    }
+}
+
+fn ok_of(ret: SbiRet) -> Response {
+    match ret.error {
+        SBI_SUCCESS => Response::Ok,
+        error => Response::Failed(error),
+    }
+}
+
+fn parse(word: &str) -> Option<usize> {
+    match word.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
@@ -0,0 +1,100 @@
+// Read counters from supervisor mode; the firmware must allow it in `mcounteren`
+
+use crate::sbi;
+
+// Decoded `counter_info` returned by `sbi_pmu_counter_get_info`
+#[derive(Clone, Copy, Debug)]
+pub struct CounterInfo {
+    pub csr: usize,
+    pub width: usize,
+    pub firmware: bool,
+}
+
+impl CounterInfo {
+    pub fn decode(info: usize) -> CounterInfo {
+        CounterInfo {
+            csr: info & 0xFFF,
+            width: ((info >> 12) & 0x3F) + 1,
+            firmware: info >> (usize::BITS - 1) != 0,
+        }
+    }
+}
+
+// Read a hardware counter by its CSR number, from `cycle` (0xC00) to `hpmcounter31` (0xC1F)
+pub fn read(csr: usize) -> usize {
+    let value: usize;
+    macro_rules! op {
+        ($i: literal) => {
+            unsafe { asm!("csrr {0}, {csr}", out(reg) value, csr = const 0xC00 + $i) }
+        };
+    }
+    match csr.wrapping_sub(0xC00) {
+        0 => op!(0), 1 => op!(1), 2 => op!(2), 3 => op!(3),
+        4 => op!(4), 5 => op!(5), 6 => op!(6), 7 => op!(7),
+        8 => op!(8), 9 => op!(9), 10 => op!(10), 11 => op!(11),
+        12 => op!(12), 13 => op!(13), 14 => op!(14), 15 => op!(15),
+        16 => op!(16), 17 => op!(17), 18 => op!(18), 19 => op!(19),
+        20 => op!(20), 21 => op!(21), 22 => op!(22), 23 => op!(23),
+        24 => op!(24), 25 => op!(25), 26 => op!(26), 27 => op!(27),
+        28 => op!(28), 29 => op!(29), 30 => op!(30), 31 => op!(31),
+        _ => panic!("not a hardware counter CSR: {:#x}", csr),
+    }
+    value
+}
+
+// Index of the first firmware counter, firmware counters follow the hardware ones
+pub fn first_firmware_counter(num_counters: usize) -> usize {
+    for idx in 0..num_counters {
+        let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+        if info.firmware {
+            return idx;
+        }
+    }
+    check!(false, "no firmware counter among {} counters", num_counters);
+    unreachable!()
+}
+
+// Number of programmable counters, from `hpmcounter3` up to the first firmware counter
+pub fn programmable_counters() -> usize {
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    first_firmware_counter(num_counters) - 3
+}
+
+// Whether the platform has `needed` programmable counters for a test; QEMU implements as many as its
+// `pmu-num` property asks for, tests needing more are skipped
+pub fn enough_programmable(needed: usize) -> bool {
+    let present = programmable_counters();
+    if present < needed {
+        println!("<< PMU-test: Skipped, {} programmable counters needed, {} present", needed, present);
+    }
+    present >= needed
+}
+
+// Mask of all counters starting from counter 0
+pub fn all_counters(num_counters: usize) -> usize {
+    if num_counters >= usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << num_counters) - 1
+    }
+}
+
+// Execute exactly `2 * iterations` instructions, `iterations` must not be zero
+#[inline(always)]
+pub fn fixed_loop(iterations: usize) {
+    unsafe {
+        asm!(
+            "2: addi {0}, {0}, -1",
+            "bnez {0}, 2b",
+            inout(reg) iterations => _,
+        )
+    };
+}
+
+// Busy loop executing a known number of iterations
+#[inline(never)]
+pub fn spin(iterations: usize) {
+    for i in 0..iterations {
+        unsafe { asm!("/* {0} */", in(reg) i) };
+    }
+}
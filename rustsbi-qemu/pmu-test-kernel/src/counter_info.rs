@@ -0,0 +1,53 @@
+// Information of all counters in one call of RustSBI's firmware specific extension, as kernels query it at
+// boot, compared with `sbi_pmu_counter_get_info` for each counter
+
+use crate::sbi::{self, SBI_ERR_INVALID_ADDRESS, SBI_ERR_INVALID_PARAM};
+use core::ptr::read_volatile;
+
+const CAPACITY: usize = 64;
+const UNTOUCHED: usize = 0x5a5a;
+
+// one XLEN-bit `counter_info` for each counter; the test kernel runs without paging, so the address of this
+// buffer is its physical address
+static mut INFOS: [usize; CAPACITY] = [UNTOUCHED; CAPACITY];
+
+fn entry(idx: usize) -> usize {
+    unsafe { read_volatile(&INFOS[idx]) }
+}
+
+pub fn run() {
+    println!(">> PMU-test: Testing bulk counter information read");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    check!(num_counters < CAPACITY, "{} counters do not fit in {} entries", num_counters, CAPACITY);
+    let buf = unsafe { INFOS.as_ptr() as usize };
+
+    let read = check_ok!(sbi::rustsbi_pmu_counter_info_read(buf, 0, CAPACITY, 0), "rustsbi_pmu_counter_info_read");
+    check!(read == num_counters, "rustsbi_pmu_counter_info_read returned {}, num_counters {}", read, num_counters);
+    for idx in 0..num_counters {
+        let info = check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info");
+        check!(entry(idx) == info, "counter {}: bulk info {:#x}, counter_get_info {:#x}", idx, entry(idx), info);
+    }
+    // entries past the last counter are left alone
+    check!(entry(num_counters) == UNTOUCHED, "entry past the last counter written: {:#x}", entry(num_counters));
+
+    // at most as many entries as asked for, and with none, only the number of counters
+    unsafe { INFOS = [UNTOUCHED; CAPACITY] };
+    let read = check_ok!(sbi::rustsbi_pmu_counter_info_read(buf, 0, 2, 0), "rustsbi_pmu_counter_info_read 2 entries");
+    check!(read == num_counters, "rustsbi_pmu_counter_info_read returned {} for 2 entries", read);
+    check!(entry(1) != UNTOUCHED && entry(2) == UNTOUCHED, "2 entries asked for, entries 1 and 2 hold {:#x} and {:#x}", entry(1), entry(2));
+    let read = check_ok!(sbi::rustsbi_pmu_counter_info_read(0, 0, 0, 0), "rustsbi_pmu_counter_info_read no entry");
+    check!(read == num_counters, "rustsbi_pmu_counter_info_read returned {} for no entry", read);
+
+    check_err!(sbi::rustsbi_pmu_counter_info_read(buf, 0, CAPACITY, 1), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_counter_info_read reserved flags");
+    check_err!(
+        sbi::rustsbi_pmu_counter_info_read(buf + 2, 0, CAPACITY, 0),
+        SBI_ERR_INVALID_PARAM,
+        "rustsbi_pmu_counter_info_read misaligned buffer"
+    );
+    check_err!(
+        sbi::rustsbi_pmu_counter_info_read(buf, 1, CAPACITY, 0),
+        SBI_ERR_INVALID_ADDRESS,
+        "rustsbi_pmu_counter_info_read buffer above XLEN bits"
+    );
+    println!("<< PMU-test: Bulk counter information read passed");
+}
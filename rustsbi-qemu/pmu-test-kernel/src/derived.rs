@@ -0,0 +1,38 @@
+// Derived metrics of a loop with a known number of instructions; QEMU counts cycles and instructions,
+// cache and branch events are counted only by the platforms with an event profile for them
+
+use crate::counter;
+use crate::metrics::Milli;
+
+const ITERATIONS: usize = 10000;
+
+pub fn run() {
+    println!(">> PMU-test: Testing derived metrics");
+    let metrics = measure! {
+        counter::fixed_loop(ITERATIONS);
+    };
+    check!(metrics.cycles.is_some() && metrics.instructions.is_some(), "cycles or instructions not counted: {:?}", metrics);
+    let instructions = metrics.instructions.unwrap();
+    check!(instructions >= 2 * ITERATIONS as u64, "{} instructions counted for a loop of {}", instructions, 2 * ITERATIONS);
+    // a cycle is an instruction under icount, other platforms retire at most a few instructions per cycle
+    let ipc = metrics.ipc().unwrap_or(Milli(0));
+    check!(ipc > Milli(0) && ipc <= Milli(8000), "{} instructions per cycle", ipc);
+    println!("<< PMU-test: IPC {}", ipc);
+    match metrics.mpki() {
+        Some(mpki) => {
+            println!("<< PMU-test: MPKI {}", mpki);
+        }
+        None => check!(metrics.cache_misses.is_none(), "cache misses counted without a rate"),
+    }
+    match metrics.branch_mispredict_rate() {
+        Some(rate) => {
+            check!(rate <= Milli(100_000), "{}% of branches mispredicted", rate);
+            println!("<< PMU-test: Branch mispredict rate {}%", rate);
+        }
+        None => check!(metrics.branches.map_or(true, |branches| branches == 0), "branches counted without a mispredict rate"),
+    }
+    // released counters are available again
+    let again = measure! {};
+    check!(again.instructions.is_some(), "instructions not counted again");
+    println!("<< PMU-test: Derived metrics passed");
+}
@@ -0,0 +1,27 @@
+// Print PMU state of this hart through the firmware specific extension of RustSBI;
+// the printed lines are checked against the golden output
+
+use crate::counter;
+use crate::sbi;
+
+pub fn run() {
+    println!(">> PMU-test: Testing PMU state dump");
+    let probed = sbi::probe_extension(sbi::EXTENSION_RUSTSBI);
+    check!(probed != 0, "RustSBI firmware specific extension is not available");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let hw_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, counter::all_counters(fw_base), flags, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        "counter_config_matching instructions"
+    );
+    // no IPI is sent while dumping, the firmware counter stays at zero
+    let fw_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, 1, flags, sbi::EVENT_FW_IPI_SENT, 0),
+        "counter_config_matching ipi_sent"
+    );
+    check_ok!(sbi::rustsbi_pmu_dump(), "rustsbi_pmu_dump");
+    check_ok!(sbi::pmu_counter_stop(hw_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop instructions");
+    check_ok!(sbi::pmu_counter_stop(fw_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop ipi_sent");
+    println!("<< PMU-test: PMU state dump passed");
+}
@@ -0,0 +1,69 @@
+// Querying which events can be counted through `sbi_pmu_event_get_info`, before configuring any counter
+
+use crate::events::{event_idx, EVENT_TYPE_HARDWARE_CACHE};
+use crate::sbi::{self, SBI_ERR_INVALID_ADDRESS, SBI_ERR_INVALID_PARAM};
+use core::ptr::{read_volatile, write_volatile};
+
+// bit 0 of the output field is set for supported events
+const SUPPORTED: u32 = 1;
+// L1 data cache read accesses, which neither QEMU nor the device tree QEMU generates map to any counter
+const EVENT_CACHE_L1D_READ_ACCESS: usize = event_idx(EVENT_TYPE_HARDWARE_CACHE, 0);
+// event type 15 is reserved
+const EVENT_RESERVED: usize = 0xF0000;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EventInfo {
+    event_idx: u32,
+    output: u32,
+    event_data: u64,
+}
+
+// entries are 16 bytes long and the shared memory is aligned to them; the test kernel runs without paging,
+// so the address of this buffer is its physical address
+#[repr(C, align(16))]
+struct Entries([EventInfo; QUERIES.len()]);
+
+// events with their event data, and whether they are supported
+const QUERIES: [(usize, u64, bool); 7] = [
+    (sbi::EVENT_HW_CPU_CYCLES, 0, true),
+    (sbi::EVENT_HW_INSTRUCTIONS, 0, true),
+    (sbi::EVENT_FW_IPI_SENT, 0, true),
+    (sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_ECALL_CYCLES, true),
+    (sbi::EVENT_FW_PLATFORM, 0, false),
+    (EVENT_CACHE_L1D_READ_ACCESS, 0, false),
+    (EVENT_RESERVED, 0, false),
+];
+
+static mut ENTRIES: Entries = Entries([EventInfo { event_idx: 0, output: 0, event_data: 0 }; QUERIES.len()]);
+
+pub fn run() {
+    println!(">> PMU-test: Testing event information query");
+    let shmem = unsafe { ENTRIES.0.as_ptr() as usize };
+    for (i, &(event_idx, event_data, _)) in QUERIES.iter().enumerate() {
+        // the output field is written by SBI whether the event is supported or not
+        let entry = EventInfo { event_idx: event_idx as u32, output: u32::MAX, event_data };
+        unsafe { write_volatile(&mut ENTRIES.0[i], entry) };
+    }
+    check_ok!(sbi::pmu_event_get_info(shmem, 0, QUERIES.len(), 0), "event_get_info");
+    for (i, &(event_idx, event_data, supported)) in QUERIES.iter().enumerate() {
+        let entry = unsafe { read_volatile(&ENTRIES.0[i]) };
+        check!(
+            entry.event_idx as usize == event_idx && entry.event_data == event_data,
+            "event_get_info changed entry {}",
+            i
+        );
+        check!(
+            (entry.output & SUPPORTED != 0) == supported,
+            "event {:#x} with data {:#x} reported as {}supported",
+            event_idx,
+            event_data,
+            if supported { "not " } else { "" }
+        );
+    }
+
+    check_err!(sbi::pmu_event_get_info(shmem + 8, 0, 1, 0), SBI_ERR_INVALID_PARAM, "event_get_info misaligned");
+    check_err!(sbi::pmu_event_get_info(shmem, 1, 1, 0), SBI_ERR_INVALID_ADDRESS, "event_get_info above XLEN bits");
+    check_err!(sbi::pmu_event_get_info(shmem, 0, 1, 1), SBI_ERR_INVALID_PARAM, "event_get_info reserved flags");
+    println!("<< PMU-test: Event information query passed");
+}
@@ -0,0 +1,96 @@
+// Counting IPIs and remote fences sent to all harts with firmware counters
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+use crate::smp;
+
+const ROUNDS: usize = 16;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing firmware counters");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    // firmware counters follow the hardware ones; on RV32 they lie beyond the first XLEN counters,
+    // so the mask starts at the first firmware counter
+    let base = counter::first_firmware_counter(num_counters);
+    let mask = counter::all_counters(num_counters - base);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let ipi_idx = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, flags, sbi::EVENT_FW_IPI_SENT, 0),
+        "counter_config_matching ipi_sent"
+    );
+    let sfence_idx = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, flags, sbi::EVENT_FW_SFENCE_VMA_SENT, 0),
+        "counter_config_matching sfence_vma_sent"
+    );
+    for &idx in &[ipi_idx, sfence_idx] {
+        let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+        check!(info.firmware, "firmware event matched hardware counter {}", idx);
+    }
+
+    // harts that came online later are left out, so that the expected counts are fixed
+    let targets = smp::secondary_harts() | 1 << hartid;
+    let harts = targets.count_ones() as usize;
+    println!("<< PMU-test: Sending {} IPIs and remote fences to {} harts", ROUNDS, harts);
+    for _ in 0..ROUNDS {
+        check_ok!(sbi::send_ipi(&targets, 0), "send_ipi");
+        check_ok!(sbi::remote_sfence_vma(&targets, 0, 0, usize::MAX), "remote_sfence_vma");
+    }
+    // the IPIs sent to this hart are left pending, supervisor software interrupt is not used here
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+
+    let expected = ROUNDS * harts;
+    let ipi_sent = check_ok!(sbi::pmu_counter_fw_read(ipi_idx), "counter_fw_read ipi_sent");
+    check!(ipi_sent == expected, "ipi_sent counted {}, expected {}", ipi_sent, expected);
+    let sfence_sent = check_ok!(sbi::pmu_counter_fw_read(sfence_idx), "counter_fw_read sfence_vma_sent");
+    check!(sfence_sent == expected, "sfence_vma_sent counted {}, expected {}", sfence_sent, expected);
+    // the counts are far below 2^32, so the upper half is zero on RV32 as well as on RV64
+    let ipi_sent_hi = check_ok!(sbi::pmu_counter_fw_read_hi(ipi_idx), "counter_fw_read_hi ipi_sent");
+    check!(ipi_sent_hi == 0, "ipi_sent upper half is {:#x}, expected 0", ipi_sent_hi);
+
+    // reading and clearing in one call starts the next interval at zero while the counter keeps running
+    let interval = check_ok!(sbi::rustsbi_pmu_counter_read_clear(ipi_idx), "counter_read_clear ipi_sent");
+    check!(interval == expected, "counter_read_clear returned {}, expected {}", interval, expected);
+    check_ok!(sbi::send_ipi(&targets, 0), "send_ipi");
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    let interval = check_ok!(sbi::rustsbi_pmu_counter_read_clear(ipi_idx), "counter_read_clear ipi_sent");
+    check!(interval == harts, "counter_read_clear returned {} for the next interval, expected {}", interval, harts);
+    check_err!(
+        sbi::rustsbi_pmu_counter_read_clear(0),
+        sbi::SBI_ERR_INVALID_PARAM,
+        "counter_read_clear hardware counter"
+    );
+
+    // a firmware counter started from an initial value counts on top of it, into the upper half on RV32
+    check_ok!(sbi::pmu_counter_stop(ipi_idx, 1, 0), "counter_stop ipi_sent");
+    let initial_value = u32::MAX as u64;
+    check_ok!(
+        sbi::pmu_counter_start(ipi_idx, 1, sbi::START_FLAG_SET_INIT_VALUE, initial_value),
+        "counter_start ipi_sent"
+    );
+    let ipi_sent = fw_read(ipi_idx);
+    check!(ipi_sent == initial_value, "ipi_sent read {:#x} after start, expected {:#x}", ipi_sent, initial_value);
+    check_ok!(sbi::send_ipi(&targets, 0), "send_ipi");
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    let ipi_sent = fw_read(ipi_idx);
+    let expected = initial_value + harts as u64;
+    check!(ipi_sent == expected, "ipi_sent read {:#x}, expected {:#x}", ipi_sent, expected);
+
+    check_ok!(sbi::pmu_counter_stop(ipi_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop ipi_sent");
+    check_ok!(sbi::pmu_counter_stop(sfence_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop sfence_vma_sent");
+    println!("<< PMU-test: Firmware counters passed");
+}
+
+// full 64-bit value of a firmware counter
+fn fw_read(counter_idx: usize) -> u64 {
+    let lo = check_ok!(sbi::pmu_counter_fw_read(counter_idx), "counter_fw_read");
+    let hi = check_ok!(sbi::pmu_counter_fw_read_hi(counter_idx), "counter_fw_read_hi");
+    match () {
+        #[cfg(target_pointer_width = "32")]
+        () => (hi as u64) << 32 | lo as u64,
+        #[cfg(not(target_pointer_width = "32"))]
+        () => {
+            drop(hi);
+            lo as u64
+        }
+    }
+}
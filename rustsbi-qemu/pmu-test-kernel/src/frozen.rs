@@ -0,0 +1,53 @@
+// Counter CSRs of stopped counters keep the value the counter was stopped with: RustSBI-QEMU traps reads of
+// them and returns that value, even for `cycle`, which only stops virtually and keeps counting in hardware;
+// every emulated read is counted by a platform specific firmware event
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+
+pub fn run() {
+    println!(">> PMU-test: Testing reads of stopped counter CSRs");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let mask = counter::all_counters(num_counters);
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, sbi::CFG_FLAG_CLEAR_VALUE, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching"
+    );
+    let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+    check!(!info.firmware, "cycles event matched firmware counter {}", idx);
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let reads_idx = check_ok!(
+        sbi::pmu_counter_config_matching(
+            fw_base,
+            counter::all_counters(num_counters - fw_base),
+            sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START,
+            sbi::EVENT_FW_PLATFORM,
+            sbi::RUSTSBI_FW_PMU_EMULATED_COUNTER_READS
+        ),
+        "counter_config_matching emulated counter reads"
+    );
+
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start");
+    counter::spin(1000);
+    check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop");
+    let stopped = counter::read(info.csr);
+    counter::spin(1000);
+    let after = counter::read(info.csr);
+    check!(stopped != 0 && after == stopped, "stopped counter {} read {} -> {}", idx, stopped, after);
+    let reads = check_ok!(sbi::pmu_counter_fw_read(reads_idx), "counter_fw_read emulated counter reads");
+    check!(reads == 2, "{} emulated counter reads counted, expected 2", reads);
+    // still read through the counter CSR, not through `sbi_pmu_counter_fw_read`
+    check_err!(sbi::pmu_counter_fw_read(idx), sbi::SBI_ERR_INVALID_PARAM, "counter_fw_read of stopped counter");
+
+    // started again, the counter goes on from the value it was stopped with
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start again");
+    counter::spin(1000);
+    let resumed = counter::read(info.csr);
+    check!(resumed > stopped, "counter {} did not advance after start: {} -> {}", idx, stopped, resumed);
+    // reads of a running counter do not trap
+    let reads = check_ok!(sbi::pmu_counter_fw_read(reads_idx), "counter_fw_read emulated counter reads");
+    check!(reads == 2, "{} emulated counter reads counted after start, expected 2", reads);
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    check_ok!(sbi::pmu_counter_stop(reads_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop emulated counter reads");
+    println!("<< PMU-test: Reads of stopped counter CSRs passed");
+}
@@ -0,0 +1,75 @@
+// Starting and stopping hardware and firmware counters as a group in one call, so that they measure
+// the same interval; skews are printed for reference and left out of the golden output
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+
+const IPIS: usize = 4;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing counter groups");
+    if !counter::enough_programmable(2) {
+        return;
+    }
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    // two programmable counters count the same event, any difference between them is skew
+    let hw_mask = counter::all_counters(fw_base - 3);
+    let first = check_ok!(
+        sbi::pmu_counter_config_matching(3, hw_mask, sbi::CFG_FLAG_CLEAR_VALUE, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let second = check_ok!(
+        sbi::pmu_counter_config_matching(3, hw_mask, sbi::CFG_FLAG_CLEAR_VALUE, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles again"
+    );
+    let ipi_idx = check_ok!(
+        sbi::pmu_counter_config_matching(
+            fw_base,
+            counter::all_counters(num_counters - fw_base),
+            sbi::CFG_FLAG_CLEAR_VALUE,
+            sbi::EVENT_FW_IPI_SENT,
+            0
+        ),
+        "counter_config_matching ipi_sent"
+    );
+    // on RV32 the group must fit in one mask of XLEN counters
+    let base = first.min(second);
+    check!(ipi_idx - base < usize::BITS as usize, "counters {} to {} do not fit in one mask", base, ipi_idx);
+    let group = 1 << (first - base) | 1 << (second - base) | 1 << (ipi_idx - base);
+    let first_csr = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(first), "counter_get_info")).csr;
+    let second_csr = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(second), "counter_get_info")).csr;
+    let skew = || (counter::read(first_csr) as isize).wrapping_sub(counter::read(second_csr) as isize).unsigned_abs();
+
+    // started by separate calls, the counters are apart by the cost of an SBI call
+    check_ok!(sbi::pmu_counter_start(first, 1, sbi::START_FLAG_SET_INIT_VALUE, 0), "counter_start first");
+    check_ok!(sbi::pmu_counter_start(second, 1, sbi::START_FLAG_SET_INIT_VALUE, 0), "counter_start second");
+    check_ok!(sbi::pmu_counter_stop(base, group & !(1 << (ipi_idx - base)), 0), "counter_stop cycles");
+    let separate = skew();
+
+    // started and stopped as a group, they count the same interval as the firmware counter
+    let target = 1 << hartid;
+    check_ok!(sbi::send_ipi(&target, 0), "send_ipi before the group starts");
+    check_ok!(sbi::pmu_counter_start(base, group, sbi::START_FLAG_SET_INIT_VALUE, 0), "counter_start group");
+    for _ in 0..IPIS {
+        check_ok!(sbi::send_ipi(&target, 0), "send_ipi");
+    }
+    check_ok!(sbi::pmu_counter_stop(base, group, 0), "counter_stop group");
+    check_ok!(sbi::send_ipi(&target, 0), "send_ipi after the group stops");
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    let grouped = skew();
+    measurement!(hartid, "group_skew_cycles", first, grouped, "Skew of {} cycles started as a group", grouped);
+    measurement!(hartid, "separate_skew_cycles", first, separate, "Skew of {} cycles started one by one", separate);
+    check!(
+        grouped < separate,
+        "grouped counters are {} cycles apart, no closer than {} cycles when started one by one",
+        grouped,
+        separate
+    );
+    let ipi_sent = check_ok!(sbi::pmu_counter_fw_read(ipi_idx), "counter_fw_read ipi_sent");
+    check!(ipi_sent == IPIS, "ipi_sent counted {} in the group, expected {}", ipi_sent, IPIS);
+
+    check_ok!(sbi::pmu_counter_start(base, group, 0, 0), "counter_start group");
+    check_ok!(sbi::pmu_counter_stop(base, group, sbi::STOP_FLAG_RESET), "counter_stop group reset");
+    println!("<< PMU-test: Counter groups passed");
+}
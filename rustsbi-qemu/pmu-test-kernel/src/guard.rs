@@ -0,0 +1,31 @@
+// Counting a block of code with `CounterGuard` of the client library: the values are written when the
+// guard is dropped, and the counters are released for the next guard
+
+use crate::counter;
+use sbi_pmu_client::{CounterGuard, EVENT_HW_CPU_CYCLES, EVENT_HW_INSTRUCTIONS, MAX_EVENTS, SBI_ERR_INVALID_PARAM};
+
+const ITERATIONS: usize = 1000;
+
+pub fn run() {
+    println!(">> PMU-test: Testing scoped counter guard");
+    let events = [EVENT_HW_CPU_CYCLES, EVENT_HW_INSTRUCTIONS];
+    for _ in 0..4 {
+        let mut values = [0; 2];
+        {
+            let guard = CounterGuard::new(&events, &mut values);
+            check!(guard.is_ok(), "guard returned error {}", guard.as_ref().err().map_or(0, |&error| error as isize));
+            counter::fixed_loop(ITERATIONS);
+        }
+        let (cycles, instructions) = (values[0], values[1]);
+        check!(cycles > 0, "no cycles counted by the guard");
+        // the configuration and start calls of the guard are outside of the block
+        let expected = 2 * ITERATIONS as u64;
+        check!(instructions >= expected && instructions < 10 * expected, "{} instructions counted for a loop of {}", instructions, expected);
+    }
+    let mut values = [0; 1];
+    check!(CounterGuard::new(&events, &mut values).err() == Some(SBI_ERR_INVALID_PARAM), "guard accepted too few values");
+    let mut values = [0; MAX_EVENTS + 1];
+    let events = [EVENT_HW_INSTRUCTIONS; MAX_EVENTS + 1];
+    check!(CounterGuard::new(&events, &mut values).err() == Some(SBI_ERR_INVALID_PARAM), "guard accepted too many events");
+    println!("<< PMU-test: Scoped counter guard passed");
+}
@@ -0,0 +1,95 @@
+// Counter summaries of this hart and of another hart, queried through the firmware specific extension
+// of RustSBI; runs after the isolation test, when the partner hart has released its counters
+
+use crate::counter;
+use crate::sbi::{self, SbiRet, SBI_ERR_INVALID_PARAM};
+use crate::smp;
+
+// hart id no QEMU machine has
+const ABSENT_HART: usize = 0x7fff;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Summary {
+    started: usize,
+    bound: usize,
+    overflow_pending: bool,
+}
+
+impl Summary {
+    fn decode(value: usize) -> Summary {
+        Summary {
+            started: value & 0xff,
+            bound: (value >> 8) & 0xff,
+            overflow_pending: value & (1 << 16) != 0,
+        }
+    }
+}
+
+fn summary(hartid: usize) -> Summary {
+    Summary::decode(check_ok!(sbi::rustsbi_pmu_hart_status(hartid), "rustsbi_pmu_hart_status"))
+}
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing hart PMU status query");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let base = summary(hartid);
+
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let hw_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, counter::all_counters(fw_base), flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let fw_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, 1, sbi::CFG_FLAG_CLEAR_VALUE, sbi::EVENT_FW_SET_TIMER, 0),
+        "counter_config_matching set_timer"
+    );
+    let now = summary(hartid);
+    check!(
+        now.started == base.started + 1 && now.bound == base.bound + 2,
+        "status {:?} with a started and a stopped counter, was {:?}",
+        now,
+        base
+    );
+    check_ok!(sbi::pmu_counter_start(fw_idx, 1, 0, 0), "counter_start set_timer");
+    check_ok!(sbi::pmu_counter_stop(hw_idx, 1, 0), "counter_stop cycles");
+    let now = summary(hartid);
+    check!(
+        now.started == base.started + 1 && now.bound == base.bound + 2,
+        "status {:?} after swapping started counters, was {:?}",
+        now,
+        base
+    );
+    check_ok!(sbi::pmu_counter_stop(fw_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop set_timer");
+    let now = summary(hartid);
+    check!(
+        now.started == base.started && now.bound == base.bound + 1,
+        "status {:?} after releasing the firmware counter, was {:?}",
+        now,
+        base
+    );
+    // a counter is released by stopping it, so the stopped cycle counter is started once more
+    check_ok!(sbi::pmu_counter_start(hw_idx, 1, 0, 0), "counter_start cycles");
+    check_ok!(sbi::pmu_counter_stop(hw_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    let now = summary(hartid);
+    check!(now == base, "status {:?} after releasing counters, was {:?}", now, base);
+
+    // the partner of the isolation test reset its counter when it finished
+    let secondary_harts = smp::secondary_harts();
+    if secondary_harts != 0 {
+        let partner = secondary_harts.trailing_zeros() as usize;
+        let remote = summary(partner);
+        check!(remote.started == 0 && remote.bound == 0, "hart {} reports {:?} when idle", partner, remote);
+    }
+
+    // rejected when the platform knows its harts through the HSM extension, empty otherwise
+    let SbiRet { error, value } = sbi::rustsbi_pmu_hart_status(ABSENT_HART);
+    check!(
+        error == SBI_ERR_INVALID_PARAM || (error == 0 && value == 0),
+        "status of absent hart {:#x} returned error {:#x}, value {:#x}",
+        ABSENT_HART,
+        error,
+        value
+    );
+    println!("<< PMU-test: Hart PMU status query passed");
+}
@@ -0,0 +1,74 @@
+// VS-mode and VU-mode filter hints: this kernel runs in HS-mode when the hypervisor extension is present,
+// so a counter inhibited in VS-mode and VU-mode must still count here
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+
+pub fn run() {
+    println!(">> PMU-test: Testing VS/VU-mode event filtering");
+    if !counter::enough_programmable(1) {
+        return;
+    }
+    let hypervisor = probe_hypervisor();
+    // without the hypervisor extension the hints are ignored, and the counter counts all the same
+    println!(
+        "<< PMU-test: Hypervisor extension {}",
+        if hypervisor { "present" } else { "absent" }
+    );
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    // filter bits are only in `mhpmevent` of programmable counters
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE
+        | sbi::CFG_FLAG_AUTO_START
+        | sbi::CFG_FLAG_SET_VSINH
+        | sbi::CFG_FLAG_SET_VUINH;
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(3, counter::all_counters(fw_base - 3), flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+    let before = counter::read(info.csr);
+    counter::fixed_loop(0x1000);
+    let after = counter::read(info.csr);
+    check!(
+        after > before,
+        "counter {} inhibited in VS/VU-mode did not count in {}-mode: {} -> {}",
+        idx,
+        if hypervisor { "HS" } else { "S" },
+        before,
+        after
+    );
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop");
+    println!("<< PMU-test: VS/VU-mode event filtering passed");
+}
+
+// Supervisor cannot read `misa`; reading `hstatus` is an illegal instruction without the hypervisor
+// extension, and the firmware forwards the exception to `stvec`, where it is skipped
+fn probe_hypervisor() -> bool {
+    let present: usize;
+    unsafe {
+        let stvec: usize;
+        asm!("csrr {}, stvec", out(reg) stvec);
+        // `stvec` must be 4-byte aligned, the handler code starts at the next aligned address
+        asm!("csrw stvec, {}", in(reg) (skip_instruction as usize + 3) & !3);
+        asm!("li a0, 1", "csrr zero, 0x600", out("a0") present);
+        asm!("csrw stvec, {}", in(reg) stvec);
+    }
+    present != 0
+}
+
+// Skip the trapping instruction and clear `a0`; only the 4-byte `csrr` above traps into here
+#[naked]
+unsafe extern "C" fn skip_instruction() -> ! {
+    asm!("
+    .align  2
+    csrw    sscratch, t0
+    csrr    t0, sepc
+    addi    t0, t0, 4
+    csrw    sepc, t0
+    csrr    t0, sscratch
+    li      a0, 0
+    sret
+    ",
+    options(noreturn))
+}
@@ -0,0 +1,85 @@
+// Two harts binding different events to the same counter index must not see each other's state
+
+use crate::counter;
+use crate::sbi;
+use crate::smp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// `hpmcounter3`, the first programmable counter
+const COUNTER_IDX: usize = 3;
+const COUNTER_CSR: usize = 0xC00 + COUNTER_IDX;
+// QEMU selectors of instructions and cycles, bound as raw events so that any programmable counter matches
+const RAW_INSTRUCTIONS: u64 = 0x2;
+const RAW_CYCLES: u64 = 0x1;
+const ITERATIONS: usize = 10000;
+const INSTRET_SLACK: usize = 16;
+
+// hart id of the secondary hart taking part in the test, `usize::MAX` if none
+static PARTNER: AtomicUsize = AtomicUsize::new(usize::MAX);
+static BARRIER: AtomicUsize = AtomicUsize::new(0);
+static PARTNER_DONE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing counter isolation between harts");
+    if !counter::enough_programmable(1) {
+        return;
+    }
+    let secondary_harts = smp::secondary_harts();
+    check!(secondary_harts != 0, "counter isolation needs at least two harts");
+    let partner = secondary_harts.trailing_zeros() as usize;
+    println!("<< PMU-test: Hart {} and hart {} share counter {}", hartid, partner, COUNTER_IDX);
+    PARTNER.store(partner, Ordering::Release);
+
+    let instructions = count_on_shared_counter(RAW_INSTRUCTIONS);
+    check!(
+        instructions >= 2 * ITERATIONS && instructions <= 2 * ITERATIONS + INSTRET_SLACK,
+        "counter {} counted {} instructions on hart {}, expected {} to {}",
+        COUNTER_IDX,
+        instructions,
+        hartid,
+        2 * ITERATIONS,
+        2 * ITERATIONS + INSTRET_SLACK
+    );
+    while PARTNER_DONE.load(Ordering::Acquire) == 0 {
+        core::hint::spin_loop();
+    }
+    println!("<< PMU-test: Counter isolation between harts passed");
+}
+
+pub fn requested(hartid: usize) -> bool {
+    PARTNER.load(Ordering::Acquire) == hartid && PARTNER_DONE.load(Ordering::Acquire) == 0
+}
+
+pub fn run_secondary(hartid: usize) {
+    let cycles = count_on_shared_counter(RAW_CYCLES);
+    check!(cycles > 0, "counter {} did not count cycles on hart {}", COUNTER_IDX, hartid);
+    PARTNER_DONE.store(1, Ordering::Release);
+}
+
+// bind the event to the shared counter while the other hart holds it too, then count over the fixed loop
+fn count_on_shared_counter(raw_event: u64) -> usize {
+    // without `SKIP_MATCH` the call fails if the counter is taken, by this hart or any other
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(
+            COUNTER_IDX,
+            1,
+            sbi::CFG_FLAG_CLEAR_VALUE,
+            sbi::EVENT_HW_RAW,
+            raw_event
+        ),
+        "counter_config_matching on shared counter"
+    );
+    check!(idx == COUNTER_IDX, "counter_config_matching returned counter {}", idx);
+    smp::barrier(&BARRIER, 2, 1);
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start on shared counter");
+    let start = counter::read(COUNTER_CSR);
+    counter::fixed_loop(ITERATIONS);
+    let end = counter::read(COUNTER_CSR);
+    // both harts hold the counter until both have read it
+    smp::barrier(&BARRIER, 2, 2);
+    check_ok!(
+        sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET),
+        "counter_stop on shared counter"
+    );
+    end.wrapping_sub(start)
+}
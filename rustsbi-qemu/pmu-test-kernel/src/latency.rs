@@ -0,0 +1,60 @@
+// Measuring the latency of stopping many hardware counters at once and one by one; the numbers
+// are printed for reference, they change with the QEMU version and are left out of the golden output
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi::{self, SBI_ERR_FAILED};
+
+const ROUNDS: usize = 8;
+// QEMU counts DTLB read misses with this selector; raw events match any programmable counter
+const RAW_EVENT_DATA: u64 = 0x10019;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Measuring counter stop latency");
+    if !counter::enough_programmable(1) {
+        return;
+    }
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    // the clock of the measurement
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let clock_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, counter::all_counters(fw_base), flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let clock = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(clock_idx), "counter_get_info")).csr;
+    // every programmable counter left is measured
+    let mut measured = 0;
+    loop {
+        let ret = sbi::pmu_counter_config_matching(3, counter::all_counters(fw_base - 3), 0, sbi::EVENT_HW_RAW, RAW_EVENT_DATA);
+        if ret.error == SBI_ERR_FAILED {
+            break;
+        }
+        measured |= 1 << check_ok!(ret, "counter_config_matching raw event");
+    }
+    check!(measured != 0, "no programmable counter to measure");
+
+    let (mut at_once, mut one_by_one) = (0, 0);
+    for _ in 0..ROUNDS {
+        check_ok!(sbi::pmu_counter_start(0, measured, 0, 0), "counter_start");
+        let start = counter::read(clock);
+        check_ok!(sbi::pmu_counter_stop(0, measured, 0), "counter_stop at once");
+        at_once += counter::read(clock).wrapping_sub(start);
+
+        check_ok!(sbi::pmu_counter_start(0, measured, 0, 0), "counter_start");
+        let start = counter::read(clock);
+        for idx in (0..usize::BITS as usize).filter(|idx| measured & (1 << idx) != 0) {
+            check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop one by one");
+        }
+        one_by_one += counter::read(clock).wrapping_sub(start);
+    }
+    let count = measured.count_ones();
+    let (at_once, one_by_one) = (at_once / ROUNDS, one_by_one / ROUNDS);
+    measurement!(hartid, "stop_at_once_cycles", clock_idx, at_once, "Stopping {} counters takes {} cycles at once", count, at_once);
+    measurement!(hartid, "stop_one_by_one_cycles", clock_idx, one_by_one, "Stopping {} counters takes {} cycles one by one", count, one_by_one);
+
+    // only running counters can be stopped, and unbound from their events at the same time
+    check_ok!(sbi::pmu_counter_start(0, measured, 0, 0), "counter_start");
+    check_ok!(sbi::pmu_counter_stop(0, measured, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    check_ok!(sbi::pmu_counter_stop(clock_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    println!("<< PMU-test: Counter stop latency measured");
+}
@@ -0,0 +1,44 @@
+// Leak check of RustSBI's firmware specific extension: counters configured in a session and still bound to
+// an event are reported, released ones are not, and a new session forgets them. Firmware built without the
+// `leak-check` feature does not support the call, and the test is skipped
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED};
+
+pub fn run() {
+    println!(">> PMU-test: Testing counter leak check");
+    // counters left bound by earlier tests are printed by the firmware, and left out of the new session
+    let ret = sbi::rustsbi_pmu_leak_check(0, sbi::RUSTSBI_PMU_LEAK_CHECK_END_SESSION);
+    if ret.error == SBI_ERR_NOT_SUPPORTED {
+        println!("<< PMU-test: Leak check not built into the firmware, skipped");
+        println!("<< PMU-test: Counter leak check passed");
+        return;
+    }
+    check_ok!(ret, "rustsbi_pmu_leak_check end session");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let fw_mask = counter::all_counters(num_counters - fw_base);
+    let leaked = check_ok!(sbi::rustsbi_pmu_leak_check(fw_base, 0), "rustsbi_pmu_leak_check");
+    check!(leaked == 0, "new session leaked counters {:#x}", leaked);
+
+    // one counter is released, the other one is leaked
+    let kept = check_ok!(sbi::pmu_counter_config_matching(fw_base, fw_mask, 0, sbi::EVENT_FW_SET_TIMER, 0), "counter_config_matching set_timer");
+    let released = check_ok!(sbi::pmu_counter_config_matching(fw_base, fw_mask, 0, sbi::EVENT_FW_IPI_SENT, 0), "counter_config_matching ipi_sent");
+    check_ok!(sbi::pmu_counter_start(released, 1, 0, 0), "counter_start");
+    check_ok!(sbi::pmu_counter_stop(released, 1, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    let leaked = check_ok!(sbi::rustsbi_pmu_leak_check(fw_base, 0), "rustsbi_pmu_leak_check");
+    check!(leaked == 1 << (kept - fw_base), "leaked counters {:#x}, expected counter {}", leaked, kept);
+    let leaked = check_ok!(sbi::rustsbi_pmu_leak_check(kept, 0), "rustsbi_pmu_leak_check from the leaked counter");
+    check!(leaked == 1, "leaked counters {:#x} from counter {}", leaked, kept);
+    check_err!(sbi::rustsbi_pmu_leak_check(num_counters, 0), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_leak_check beyond counters");
+    check_err!(sbi::rustsbi_pmu_leak_check(0, 1 << 1), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_leak_check reserved flag");
+
+    // ending the session reports the leak once more, then forgets it; the counter stays bound
+    let leaked = check_ok!(sbi::rustsbi_pmu_leak_check(fw_base, sbi::RUSTSBI_PMU_LEAK_CHECK_END_SESSION), "rustsbi_pmu_leak_check end session");
+    check!(leaked == 1 << (kept - fw_base), "session ended with leaked counters {:#x}", leaked);
+    let leaked = check_ok!(sbi::rustsbi_pmu_leak_check(fw_base, 0), "rustsbi_pmu_leak_check");
+    check!(leaked == 0, "new session leaked counters {:#x}", leaked);
+    check_ok!(sbi::pmu_counter_start(kept, 1, 0, 0), "counter_start");
+    check_ok!(sbi::pmu_counter_stop(kept, 1, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    println!("<< PMU-test: Counter leak check passed");
+}
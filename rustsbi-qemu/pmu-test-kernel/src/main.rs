@@ -0,0 +1,158 @@
+// A supervisor-mode payload testing the PMU extension of RustSBI
+#![feature(naked_functions, asm)]
+#![no_std]
+#![no_main]
+
+#[macro_use]
+mod console;
+#[macro_use]
+mod check;
+#[macro_use]
+mod report;
+#[macro_use]
+mod metrics;
+mod basic;
+mod batch;
+#[cfg(feature = "bench")]
+mod bench;
+mod calls;
+mod capabilities;
+mod client;
+#[cfg(feature = "console")]
+mod command;
+mod counter;
+mod counter_info;
+mod derived;
+mod dump;
+mod event_info;
+// event encodings are shared with RustSBI; the file depends only on `core`
+#[allow(unused)]
+#[path = "../../../rustsbi/src/pmu/events.rs"]
+mod events;
+mod firmware;
+mod frozen;
+mod group;
+mod guard;
+mod hart_status;
+mod hypervisor;
+mod isolation;
+mod latency;
+mod leak;
+mod negative;
+mod nested;
+mod overflow;
+mod overhead;
+#[cfg(feature = "perf-export")]
+mod perf;
+mod protect;
+mod reclaim;
+mod registers;
+mod remote;
+mod replay;
+mod sampler;
+mod sanity;
+mod sbi;
+mod selftest;
+mod selfprof;
+mod smp;
+mod snapshot;
+mod stats;
+mod stress;
+mod total;
+mod watch;
+
+pub extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
+    if hartid != 0 {
+        smp::secondary_main(hartid)
+    }
+    println!(
+        "<< PMU-test: Hart id = {}, DTB physical address = {:#x}",
+        hartid, dtb_pa
+    );
+    let pmu_version = sbi::probe_extension(sbi::EXTENSION_PMU);
+    check!(pmu_version != 0, "PMU extension is not available");
+    println!("<< PMU-test: PMU extension probed: {:#x}", pmu_version);
+    // before any test configures a counter, to see that the firmware left none bound
+    selftest::run();
+    basic::run(hartid);
+    negative::run();
+    sanity::run();
+    event_info::run();
+    firmware::run(hartid);
+    group::run(hartid);
+    nested::run(hartid);
+    dump::run();
+    overhead::run(hartid);
+    selfprof::run(hartid);
+    batch::run();
+    protect::run();
+    sampler::run(hartid);
+    snapshot::run(hartid);
+    overflow::run();
+    hypervisor::run();
+    stress::run();
+    latency::run(hartid);
+    isolation::run(hartid);
+    hart_status::run(hartid);
+    remote::run(hartid);
+    total::run(hartid);
+    derived::run();
+    guard::run();
+    client::run(hartid);
+    registers::run();
+    leak::run();
+    reclaim::run(hartid);
+    stats::run();
+    replay::run();
+    watch::run(hartid);
+    counter_info::run();
+    frozen::run();
+    capabilities::run();
+    calls::run();
+    #[cfg(feature = "bench")]
+    bench::run(hartid);
+    #[cfg(feature = "console")]
+    {
+        println!("<< PMU-test: Console ready");
+        command::Console::new(command::SbiSerial).serve();
+    }
+    println!("<< PMU-test: PMU test SUCCESS, shutdown");
+    check::pass()
+}
+
+use core::panic::PanicInfo;
+
+#[cfg_attr(not(test), panic_handler)]
+#[allow(unused)]
+fn panic(info: &PanicInfo) -> ! {
+    println!("!! PMU-test: {}", info);
+    println!("!! PMU-test: PMU test FAILED due to panic");
+    check::fail()
+}
+
+const BOOT_STACK_SIZE: usize = 4096 * 4 * 8;
+
+static mut BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
+
+#[naked]
+#[link_section = ".text.entry"]
+#[export_name = "_start"]
+unsafe extern "C" fn entry() -> ! {
+    asm!("
+    # 1. set sp
+    # sp = bootstack + (hartid + 1) * 0x10000
+    add     t0, a0, 1
+    slli    t0, t0, 14
+1:  auipc   sp, %pcrel_hi({boot_stack})
+    addi    sp, sp, %pcrel_lo(1b)
+    add     sp, sp, t0
+
+    # 2. jump to rust_main (absolute address)
+1:  auipc   t0, %pcrel_hi({rust_main})
+    addi    t0, t0, %pcrel_lo(1b)
+    jr      t0
+    ", 
+    boot_stack = sym BOOT_STACK, 
+    rust_main = sym rust_main,
+    options(noreturn))
+}
@@ -0,0 +1,118 @@
+// Derived metrics combining raw counters: instructions per cycle, cache misses per thousand instructions and
+// the branch mispredict rate
+//
+// The module depends only on the SBI calls and counter reads, so that any supervisor can use it. Events the
+// platform cannot count are left out of the measurement, and the metrics needing them are `None`.
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi::{self, SBI_SUCCESS};
+use core::fmt;
+
+// Count events while running `$body`, and return the `Metrics` of it
+macro_rules! measure {
+    ($($body: tt)*) => {{
+        let scope = $crate::metrics::Scope::start();
+        {
+            $($body)*
+        };
+        scope.stop()
+    }};
+}
+
+// events measured by a scope, in the order of the fields of `Metrics`
+const EVENTS: [usize; 5] = [
+    sbi::EVENT_HW_CPU_CYCLES,
+    sbi::EVENT_HW_INSTRUCTIONS,
+    sbi::EVENT_HW_CACHE_MISSES,
+    sbi::EVENT_HW_BRANCH_INSTRUCTIONS,
+    sbi::EVENT_HW_BRANCH_MISSES,
+];
+
+// Raw event counts of a measurement, `None` for events the platform cannot count
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub cycles: Option<u64>,
+    pub instructions: Option<u64>,
+    pub cache_misses: Option<u64>,
+    pub branches: Option<u64>,
+    pub branch_misses: Option<u64>,
+}
+
+impl Metrics {
+    // Instructions per cycle
+    pub fn ipc(&self) -> Option<Milli> {
+        ratio(self.instructions?, self.cycles?, 1)
+    }
+
+    // Cache misses per thousand instructions
+    pub fn mpki(&self) -> Option<Milli> {
+        ratio(self.cache_misses?, self.instructions?, 1000)
+    }
+
+    // Mispredicted branches in percent of all branches
+    pub fn branch_mispredict_rate(&self) -> Option<Milli> {
+        ratio(self.branch_misses?, self.branches?, 100)
+    }
+}
+
+// `scale * numerator / denominator`, or `None` when nothing was counted in the denominator
+fn ratio(numerator: u64, denominator: u64, scale: u128) -> Option<Milli> {
+    if denominator == 0 {
+        return None;
+    }
+    Some(Milli((numerator as u128 * scale * 1000 / denominator as u128) as u64))
+}
+
+// A fixed-point number in thousandths, without floating point in supervisor mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Milli(pub u64);
+
+impl fmt::Display for Milli {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:03}", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+// Counters of the events of a measurement, started together by `start` and released by `stop`
+pub struct Scope {
+    csr: [Option<usize>; EVENTS.len()],
+    mask: usize,
+}
+
+impl Scope {
+    pub fn start() -> Scope {
+        let num_counters = sbi::pmu_num_counters().value;
+        // hardware events only match hardware counters, which come first
+        let all = counter::all_counters(num_counters.min(usize::BITS as usize));
+        let mut scope = Scope { csr: [None; EVENTS.len()], mask: 0 };
+        for (csr, &event) in scope.csr.iter_mut().zip(EVENTS.iter()) {
+            let ret = sbi::pmu_counter_config_matching(0, all, sbi::CFG_FLAG_CLEAR_VALUE, event, 0);
+            if ret.error != SBI_SUCCESS {
+                continue;
+            }
+            let info = sbi::pmu_counter_get_info(ret.value);
+            if info.error == SBI_SUCCESS {
+                *csr = Some(CounterInfo::decode(info.value).csr);
+            }
+            scope.mask |= 1 << ret.value;
+        }
+        // started in one call, so that the counters cover the same instructions
+        if scope.mask != 0 && sbi::pmu_counter_start(0, scope.mask, 0, 0).error != SBI_SUCCESS {
+            scope.csr = [None; EVENTS.len()];
+        }
+        scope
+    }
+
+    // Read the counters before stopping them, leaving the stop call out of the measurement
+    pub fn stop(self) -> Metrics {
+        let mut value = [None; EVENTS.len()];
+        for (value, csr) in value.iter_mut().zip(self.csr.iter()) {
+            *value = csr.map(|csr| counter::read(csr) as u64);
+        }
+        if self.mask != 0 {
+            sbi::pmu_counter_stop(0, self.mask, sbi::STOP_FLAG_RESET);
+        }
+        let [cycles, instructions, cache_misses, branches, branch_misses] = value;
+        Metrics { cycles, instructions, cache_misses, branches, branch_misses }
+    }
+}
@@ -0,0 +1,103 @@
+// Invalid calls must fail with the error codes required by the specification
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi::{
+    self, SBI_ERR_ALREADY_STARTED, SBI_ERR_ALREADY_STOPPED, SBI_ERR_FAILED, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED,
+};
+
+// Counter index of the `time` counter, which can never monitor events
+const COUNTER_TIME: usize = 1;
+// Event type 4 is reserved
+const EVENT_RESERVED_TYPE: usize = 0x4_0000;
+// Hardware general event with an undefined event code, which no counter can monitor
+const EVENT_HW_UNDEFINED: usize = 0x0_00FF;
+// QEMU counts DTLB read misses with this selector; raw events match any programmable counter
+const RAW_EVENT_DATA: u64 = 0x10019;
+
+pub fn run() {
+    println!(">> PMU-test: Testing invalid PMU calls");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let mask = counter::all_counters(num_counters);
+
+    check_err!(sbi::pmu_counter_get_info(num_counters), SBI_ERR_INVALID_PARAM, "counter_get_info out of range");
+
+    check_err!(
+        sbi::pmu_counter_config_matching(0, 0, 0, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        SBI_ERR_INVALID_PARAM,
+        "counter_config_matching with empty mask"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(num_counters, 1, 0, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        SBI_ERR_INVALID_PARAM,
+        "counter_config_matching with base out of range"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(0, mask, 1 << 8, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        SBI_ERR_INVALID_PARAM,
+        "counter_config_matching with reserved flags"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(0, mask, 0, EVENT_RESERVED_TYPE, 0),
+        SBI_ERR_INVALID_PARAM,
+        "counter_config_matching with reserved event type"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(0, mask, 0, 1 << 20, 0),
+        SBI_ERR_INVALID_PARAM,
+        "counter_config_matching with event_idx wider than 20 bits"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(COUNTER_TIME, 1, sbi::CFG_FLAG_SKIP_MATCH, sbi::EVENT_HW_CPU_CYCLES, 0),
+        SBI_ERR_INVALID_PARAM,
+        "counter_config_matching on time counter"
+    );
+    // `time` is read through its CSR, emulated by RustSBI-QEMU, not through the firmware
+    let time = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(COUNTER_TIME), "counter_get_info time"));
+    check!(!time.firmware && time.csr == 0xC01 && time.width == 64, "time counter reported as {:?}", time);
+    check_err!(sbi::pmu_counter_fw_read(COUNTER_TIME), SBI_ERR_INVALID_PARAM, "counter_fw_read on time counter");
+
+    // an event no counter in the set can monitor is not supported, an event whose counters are busy fails
+    check_err!(
+        sbi::pmu_counter_config_matching(0, mask, 0, EVENT_HW_UNDEFINED, 0),
+        SBI_ERR_NOT_SUPPORTED,
+        "counter_config_matching with undefined event"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(0, 1, 0, sbi::EVENT_HW_RAW, RAW_EVENT_DATA),
+        SBI_ERR_NOT_SUPPORTED,
+        "counter_config_matching raw event on the cycle counter"
+    );
+    let cycle_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, 1, sbi::CFG_FLAG_AUTO_START, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    check_err!(
+        sbi::pmu_counter_config_matching(0, 1, 0, sbi::EVENT_HW_CPU_CYCLES, 0),
+        SBI_ERR_FAILED,
+        "counter_config_matching cycles on a busy counter"
+    );
+    check_ok!(sbi::pmu_counter_stop(cycle_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, 0, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        "counter_config_matching"
+    );
+    let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(idx), "counter_get_info"));
+    if !info.firmware {
+        // RustSBI-QEMU lets supervisor read hardware counters through counter CSRs, so the firmware does not read them
+        check_err!(sbi::pmu_counter_fw_read(idx), SBI_ERR_INVALID_PARAM, "counter_fw_read on hardware counter");
+    }
+    check_err!(sbi::pmu_counter_stop(idx, 1, 0), SBI_ERR_ALREADY_STOPPED, "counter_stop before start");
+    check_err!(sbi::pmu_counter_start(idx, 1, 1 << 2, 0), SBI_ERR_INVALID_PARAM, "counter_start with reserved flags");
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start");
+    check_err!(sbi::pmu_counter_start(idx, 1, 0, 0), SBI_ERR_ALREADY_STARTED, "counter_start twice");
+    check_err!(sbi::pmu_counter_stop(idx, 1, 1 << 2), SBI_ERR_INVALID_PARAM, "counter_stop with reserved flags");
+    // a frozen counter keeps its event and is started again, and is released without being started
+    check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop");
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start after stop");
+    check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop again");
+    check_err!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), SBI_ERR_ALREADY_STOPPED, "counter_stop reset of stopped counter");
+    check_err!(sbi::pmu_counter_stop(idx, 1, 0), SBI_ERR_INVALID_PARAM, "counter_stop after reset");
+    check_err!(sbi::pmu_counter_start(idx, 1, 0, 0), SBI_ERR_INVALID_PARAM, "counter_start without event");
+    println!("<< PMU-test: Invalid PMU calls rejected");
+}
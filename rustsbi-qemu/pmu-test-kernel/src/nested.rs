@@ -0,0 +1,76 @@
+// Counting firmware events in the machine software interrupt handler while other PMU calls of the
+// same hart update and read firmware counters
+
+use crate::counter;
+use crate::sbi;
+
+const ROUNDS: usize = 256;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing firmware counters under nested interrupts");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let base = counter::first_firmware_counter(num_counters);
+    let mask = counter::all_counters(num_counters - base);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let sent_idx = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, flags, sbi::EVENT_FW_IPI_SENT, 0),
+        "counter_config_matching ipi_sent"
+    );
+    let received_idx = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, flags, sbi::EVENT_FW_IPI_RECEIVED, 0),
+        "counter_config_matching ipi_received"
+    );
+    // restarted with a new value in every round, so that counters are updated under the exclusive lock
+    // while IPIs are being received
+    let timer_idx = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, flags, sbi::EVENT_FW_SET_TIMER, 0),
+        "counter_config_matching set_timer"
+    );
+
+    // the firmware handles an IPI sent to this hart in its machine software interrupt handler,
+    // which counts it into `ipi_received` as soon as the sending call returns
+    let target = 1 << hartid;
+    let mut last_received = 0;
+    for round in 0..ROUNDS {
+        check_ok!(sbi::send_ipi(&target, 0), "send_ipi");
+        check_ok!(sbi::pmu_counter_stop(timer_idx, 1, 0), "counter_stop set_timer");
+        // the upper half is set on RV32, a torn update would show up in the value read back
+        let initial_value = (round as u64) << 32 | u32::MAX as u64;
+        check_ok!(
+            sbi::pmu_counter_start(timer_idx, 1, sbi::START_FLAG_SET_INIT_VALUE, initial_value),
+            "counter_start set_timer"
+        );
+        let received = check_ok!(sbi::pmu_counter_fw_read(received_idx), "counter_fw_read ipi_received");
+        check!(
+            received >= last_received && received <= round + 1,
+            "round {}: ipi_received read {} after {}",
+            round,
+            received,
+            last_received
+        );
+        last_received = received;
+        let timer_lo = check_ok!(sbi::pmu_counter_fw_read(timer_idx), "counter_fw_read set_timer");
+        let timer_hi = check_ok!(sbi::pmu_counter_fw_read_hi(timer_idx), "counter_fw_read_hi set_timer");
+        let timer = match () {
+            #[cfg(target_pointer_width = "32")]
+            () => (timer_hi as u64) << 32 | timer_lo as u64,
+            #[cfg(not(target_pointer_width = "32"))]
+            () => {
+                drop(timer_hi);
+                timer_lo as u64
+            }
+        };
+        check!(timer == initial_value, "round {}: set_timer read {:#x}, expected {:#x}", round, timer, initial_value);
+    }
+    // the IPIs are left pending, supervisor software interrupt is not used here
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+
+    let sent = check_ok!(sbi::pmu_counter_fw_read(sent_idx), "counter_fw_read ipi_sent");
+    check!(sent == ROUNDS, "ipi_sent counted {}, expected {}", sent, ROUNDS);
+    let received = check_ok!(sbi::pmu_counter_fw_read(received_idx), "counter_fw_read ipi_received");
+    check!(received == ROUNDS, "ipi_received counted {}, expected {}", received, ROUNDS);
+
+    let stop_mask = 1 << (sent_idx - base) | 1 << (received_idx - base) | 1 << (timer_idx - base);
+    check_ok!(sbi::pmu_counter_stop(base, stop_mask, sbi::STOP_FLAG_RESET), "counter_stop");
+    println!("<< PMU-test: Firmware counters under nested interrupts passed");
+}
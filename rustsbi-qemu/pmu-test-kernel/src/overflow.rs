@@ -0,0 +1,68 @@
+// Sampling path of Sscofpmf: a counter started close to its maximum value overflows and raises LCOFI
+
+use crate::capabilities;
+use crate::counter;
+use crate::sbi;
+
+// local counter overflow interrupt, bit 13 of `sie` and `sip`
+const LCOFI: usize = 1 << 13;
+// the counter starts this many cycles before it overflows
+const MARGIN: u64 = 0x1000;
+const MAX_ROUNDS: usize = 1000;
+
+pub fn run() {
+    println!(">> PMU-test: Testing counter overflow interrupt");
+    if !counter::enough_programmable(1) {
+        return;
+    }
+    if !capabilities::has_sscofpmf() {
+        println!("<< PMU-test: Sscofpmf not reported by the firmware, skipped");
+        println!("<< PMU-test: Counter overflow interrupt passed");
+        return;
+    }
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    // only programmable counters have an overflow bit in `mhpmevent`
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(3, counter::all_counters(fw_base - 3), 0, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    // the interrupt is left pending, `sstatus.SIE` stays clear so that it is never taken
+    unsafe { asm!("csrs sie, {}", in(reg) LCOFI) };
+    check_ok!(
+        sbi::pmu_counter_start(idx, 1, sbi::START_FLAG_SET_INIT_VALUE, u64::MAX - MARGIN),
+        "counter_start near overflow"
+    );
+    let mut rounds = 0;
+    while read_sip() & LCOFI == 0 {
+        check!(rounds < MAX_ROUNDS, "counter {} did not raise LCOFI", idx);
+        counter::fixed_loop(0x100);
+        rounds += 1;
+    }
+    let scountovf = read_scountovf();
+    check!(
+        scountovf & (1 << idx) != 0,
+        "overflow bit of counter {} is not set, scountovf = {:#x}",
+        idx,
+        scountovf
+    );
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop");
+    unsafe {
+        asm!("csrc sip, {}", in(reg) LCOFI);
+        asm!("csrc sie, {}", in(reg) LCOFI);
+    }
+    println!("<< PMU-test: Counter overflow interrupt passed");
+}
+
+fn read_sip() -> usize {
+    let bits: usize;
+    unsafe { asm!("csrr {}, sip", out(reg) bits) };
+    bits
+}
+
+// overflow bits of all hardware counters, readable by supervisor with Sscofpmf
+fn read_scountovf() -> usize {
+    let bits: usize;
+    unsafe { asm!("csrr {}, 0xDA0", out(reg) bits) };
+    bits
+}
@@ -0,0 +1,51 @@
+// Cycles spent by the firmware handling PMU calls, counted by a platform specific firmware event of RustSBI
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_NOT_SUPPORTED};
+
+const CALLS: usize = 64;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing PMU call overhead counting");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    // the firmware measures with `mcycle`, which only runs while the `cycle` counter is started
+    let cycle_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, 1, flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let mask = counter::all_counters(num_counters - fw_base);
+    let ret = sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, 0);
+    check!(
+        ret.error == SBI_ERR_NOT_SUPPORTED,
+        "platform event with unknown event_data returned {:?}, expected SBI_ERR_NOT_SUPPORTED",
+        ret
+    );
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_ECALL_CYCLES),
+        "counter_config_matching pmu ecall cycles"
+    );
+
+    // cycles of a call are counted after it returns its result, so the read below does not count itself
+    let before = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+    for _ in 0..CALLS {
+        check_ok!(sbi::pmu_num_counters(), "num_counters");
+    }
+    let after = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+    let cycles = after.wrapping_sub(before);
+    check!(cycles > 0, "no cycles counted in {} PMU calls", CALLS);
+    measurement!(
+        hartid,
+        "pmu_ecall_cycles",
+        idx,
+        cycles / CALLS,
+        "{} PMU calls took {} cycles in the firmware on average",
+        CALLS,
+        cycles / CALLS
+    );
+
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop pmu ecall cycles");
+    check_ok!(sbi::pmu_counter_stop(cycle_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    println!("<< PMU-test: PMU call overhead counting passed");
+}
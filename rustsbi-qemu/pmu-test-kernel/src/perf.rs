@@ -0,0 +1,136 @@
+// Export of counter samples as a perf.data stream, so that they can be read by `perf report` and `perf script`
+//
+// The stream uses the pipe mode of perf.data, which needs no file offsets: a pipe header, one attribute record
+// for each sampled event, then one sample record for each event in each sample. It is printed in hexadecimal
+// on lines starting with `PERF`, between `PERF BEGIN` and `PERF END`; `cargo xtask perf` writes the bytes
+// back into a file, and `cargo xtask test --perf-data <FILE>` does so for the test run.
+
+const PERF_MAGIC: &[u8; 8] = b"PERFILE2";
+const PIPE_HEADER_SIZE: u64 = 16;
+
+const PERF_RECORD_SAMPLE: u32 = 9;
+const PERF_RECORD_HEADER_ATTR: u32 = 64;
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+// size of the first published `perf_event_attr`, accepted by every perf version
+const PERF_ATTR_SIZE_VER0: u32 = 64;
+
+const PERF_SAMPLE_IP: u64 = 1 << 0;
+const PERF_SAMPLE_TID: u64 = 1 << 1;
+const PERF_SAMPLE_TIME: u64 = 1 << 2;
+const PERF_SAMPLE_CPU: u64 = 1 << 7;
+const PERF_SAMPLE_PERIOD: u64 = 1 << 8;
+const PERF_SAMPLE_IDENTIFIER: u64 = 1 << 16;
+const SAMPLE_TYPE: u64 = PERF_SAMPLE_IDENTIFIER | PERF_SAMPLE_IP | PERF_SAMPLE_TID | PERF_SAMPLE_TIME | PERF_SAMPLE_CPU | PERF_SAMPLE_PERIOD;
+// identifier, ip, pid and tid, time, cpu and reserved, period
+const SAMPLE_SIZE: u16 = 8 + 6 * 8;
+
+// the `time` CSR of QEMU virt counts at 10 MHz
+const TIME_NS_PER_TICK: u64 = 100;
+
+// bytes printed on each line
+const LINE_LEN: usize = 32;
+
+// Print a perf.data stream of `count` samples of counters measuring generic hardware events `event_idx`.
+// `value(s, 0)` returns the time of sample `s`, oldest first, and `value(s, 1 + i)` the value of the counter
+// of `event_idx[i]` in it. The period of a sample is the increase of the counter since the previous sample,
+// or its value for the first sample.
+pub fn export(hartid: usize, event_idx: &[usize], count: usize, value: impl Fn(usize, usize) -> u64) {
+    let mut out = HexLines::new();
+    println!("PERF BEGIN");
+    out.put(PERF_MAGIC);
+    out.put(&PIPE_HEADER_SIZE.to_le_bytes());
+    for (i, &event) in event_idx.iter().enumerate() {
+        attr_record(&mut out, event, id(i));
+    }
+    for s in 0..count {
+        let time = value(s, 0) * TIME_NS_PER_TICK;
+        for i in 0..event_idx.len() {
+            let last = if s == 0 { 0 } else { value(s - 1, 1 + i) };
+            sample_record(&mut out, id(i), hartid, time, value(s, 1 + i).wrapping_sub(last));
+        }
+    }
+    out.finish();
+    println!("PERF END");
+}
+
+// identifiers of events, only used within the stream
+fn id(i: usize) -> u64 {
+    1 + i as u64
+}
+
+fn attr_record(out: &mut HexLines, event_idx: usize, id: u64) {
+    header(out, PERF_RECORD_HEADER_ATTR, 8 + PERF_ATTR_SIZE_VER0 as u16 + 8);
+    out.put(&PERF_TYPE_HARDWARE.to_le_bytes());
+    out.put(&PERF_ATTR_SIZE_VER0.to_le_bytes());
+    // generic hardware events of SBI follow those of perf, starting from 1 instead of 0
+    out.put(&(event_idx as u64 - 1).to_le_bytes());
+    // sample period, sample type, read format, flags
+    out.put(&0u64.to_le_bytes());
+    out.put(&SAMPLE_TYPE.to_le_bytes());
+    out.put(&0u64.to_le_bytes());
+    out.put(&0u64.to_le_bytes());
+    // wakeup events, breakpoint type, config1
+    out.put(&0u32.to_le_bytes());
+    out.put(&0u32.to_le_bytes());
+    out.put(&0u64.to_le_bytes());
+    // identifiers of the event
+    out.put(&id.to_le_bytes());
+}
+
+fn sample_record(out: &mut HexLines, id: u64, hartid: usize, time: u64, period: u64) {
+    header(out, PERF_RECORD_SAMPLE, SAMPLE_SIZE);
+    out.put(&id.to_le_bytes());
+    // the instruction pointer is not sampled
+    out.put(&0u64.to_le_bytes());
+    // pid and tid
+    out.put(&0u32.to_le_bytes());
+    out.put(&0u32.to_le_bytes());
+    out.put(&time.to_le_bytes());
+    out.put(&(hartid as u32).to_le_bytes());
+    out.put(&0u32.to_le_bytes());
+    out.put(&period.to_le_bytes());
+}
+
+fn header(out: &mut HexLines, record_type: u32, size: u16) {
+    out.put(&record_type.to_le_bytes());
+    // misc
+    out.put(&0u16.to_le_bytes());
+    out.put(&size.to_le_bytes());
+}
+
+struct HexLines {
+    buf: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl HexLines {
+    fn new() -> Self {
+        HexLines { buf: [0; LINE_LEN], len: 0 }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.len == LINE_LEN {
+                self.finish();
+            }
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    // print the bytes of an incomplete line
+    fn finish(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut hex = [0u8; 2 * LINE_LEN];
+        for (i, byte) in self.buf[..self.len].iter().enumerate() {
+            hex[2 * i] = DIGITS[(byte >> 4) as usize];
+            hex[2 * i + 1] = DIGITS[(byte & 0xf) as usize];
+        }
+        println!("PERF {}", core::str::from_utf8(&hex[..2 * self.len]).unwrap());
+        self.len = 0;
+    }
+}
@@ -0,0 +1,70 @@
+// Counter values of events protected against timing side channels, coarsened by the firmware
+// through the event protection call of RustSBI's firmware specific extension
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM};
+use core::ptr::read_volatile;
+
+const QUANTUM: usize = 16;
+const JITTER: usize = 8;
+// not a multiple of the quantum, and larger than anything counted while the test runs
+const INITIAL_VALUE: u64 = 1037;
+const ROUNDED: usize = 1024;
+
+static mut VALUES: [u64; usize::BITS as usize] = [0; usize::BITS as usize];
+
+pub fn run() {
+    println!(">> PMU-test: Testing counter value protection");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    // no other test counts remote fence.i, so that protecting it changes nothing else
+    check_ok!(sbi::rustsbi_pmu_protect_event(sbi::EVENT_FW_FENCE_I_SENT, QUANTUM, 0), "protect_event");
+    check_err!(sbi::rustsbi_pmu_protect_event(1 << 20, QUANTUM, 0), SBI_ERR_INVALID_PARAM, "protect_event invalid event");
+
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(
+            fw_base,
+            counter::all_counters(num_counters - fw_base),
+            sbi::CFG_FLAG_CLEAR_VALUE,
+            sbi::EVENT_FW_FENCE_I_SENT,
+            0
+        ),
+        "counter_config_matching fence.i sent"
+    );
+    check_ok!(sbi::pmu_counter_start(idx, 1, sbi::START_FLAG_SET_INIT_VALUE, INITIAL_VALUE), "counter_start");
+    check_ok!(sbi::pmu_counter_stop(idx, 1, 0), "counter_stop");
+    let value = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+    check!(value == ROUNDED, "counter_fw_read returned {}, expected {} rounded down to {}", value, INITIAL_VALUE, ROUNDED);
+
+    // a smaller quantum does not weaken protection
+    check_ok!(sbi::rustsbi_pmu_protect_event(sbi::EVENT_FW_FENCE_I_SENT, 4, 0), "protect_event smaller quantum");
+    let value = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+    check!(value == ROUNDED, "counter_fw_read returned {} after a smaller quantum, expected {}", value, ROUNDED);
+
+    check_ok!(sbi::rustsbi_pmu_protect_event(sbi::EVENT_FW_FENCE_I_SENT, 0, JITTER), "protect_event jitter");
+    let buf = unsafe { VALUES.as_ptr() as usize };
+    for _ in 0..16 {
+        let value = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read");
+        check!(
+            (ROUNDED..ROUNDED + JITTER).contains(&value),
+            "counter_fw_read returned {}, expected {} plus jitter below {}",
+            value,
+            ROUNDED,
+            JITTER
+        );
+        check_ok!(sbi::rustsbi_pmu_counter_read_batch(idx, 1, buf, 0), "read_batch");
+        let value = unsafe { read_volatile(&VALUES[0]) } as usize;
+        check!(
+            (ROUNDED..ROUNDED + JITTER).contains(&value),
+            "read_batch returned {}, expected {} plus jitter below {}",
+            value,
+            ROUNDED,
+            JITTER
+        );
+    }
+
+    // only running counters can be stopped, and unbound from their events at the same time
+    check_ok!(sbi::pmu_counter_start(idx, 1, 0, 0), "counter_start");
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop reset");
+    println!("<< PMU-test: Counter value protection passed");
+}
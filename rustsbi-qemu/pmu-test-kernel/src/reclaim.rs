@@ -0,0 +1,57 @@
+// Idle counters and reclaiming through RustSBI's firmware specific extension: a started counter no SBI call
+// used for longer than the idle timeout of the firmware is reported by the hart status call until it is read
+// again, and the reclaim call frees every counter of the hart, as a kernel started after a crash would
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM};
+
+// idle timeout rustsbi-qemu sets, in ticks of the `time` counter; 100 milliseconds on QEMU
+const IDLE_TIMEOUT: usize = 1_000_000;
+// the `time` CSR, emulated by the firmware
+const CSR_TIME: usize = 0xC01;
+
+// started and bound counters of the hart status, bits 0..16
+fn in_use(hartid: usize) -> usize {
+    check_ok!(sbi::rustsbi_pmu_hart_status(hartid), "rustsbi_pmu_hart_status") & 0xffff
+}
+
+fn idle(hartid: usize) -> usize {
+    (check_ok!(sbi::rustsbi_pmu_hart_status(hartid), "rustsbi_pmu_hart_status") >> 17) & 0xff
+}
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing idle counter reclaim");
+    // whatever earlier tests left bound is freed first
+    check_ok!(sbi::rustsbi_pmu_reclaim(), "rustsbi_pmu_reclaim");
+    check!(in_use(hartid) == 0, "status {:#x} after reclaim", in_use(hartid));
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let fw_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, counter::all_counters(num_counters - fw_base), flags, sbi::EVENT_FW_SET_TIMER, 0),
+        "counter_config_matching set_timer"
+    );
+    check!(idle(hartid) == 0, "{} idle counters right after configuring", idle(hartid));
+
+    // reading the `time` counter meanwhile is no use of the firmware counter, only SBI calls are
+    let start = counter::read(CSR_TIME);
+    while counter::read(CSR_TIME).wrapping_sub(start) <= 2 * IDLE_TIMEOUT {}
+    check!(idle(hartid) == 1, "{} idle counters after twice the idle timeout", idle(hartid));
+    check_ok!(sbi::pmu_counter_fw_read(fw_idx), "counter_fw_read");
+    check!(idle(hartid) == 0, "{} idle counters after reading the counter", idle(hartid));
+
+    // counters left running and bound, as by a crashed kernel, are stopped and unbound
+    check_ok!(
+        sbi::pmu_counter_config_matching(0, counter::all_counters(fw_base), flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let reclaimed = check_ok!(sbi::rustsbi_pmu_reclaim(), "rustsbi_pmu_reclaim");
+    check!(reclaimed == 2, "reclaimed {} counters, 2 were bound", reclaimed);
+    check!(in_use(hartid) == 0, "status {:#x} after reclaim", in_use(hartid));
+    check_err!(sbi::pmu_counter_stop(fw_idx, 1, 0), SBI_ERR_INVALID_PARAM, "counter_stop reclaimed counter");
+    let value = check_ok!(sbi::pmu_counter_fw_read(fw_idx), "counter_fw_read reclaimed counter");
+    check!(value == 0, "reclaimed counter kept value {}", value);
+    let reclaimed = check_ok!(sbi::rustsbi_pmu_reclaim(), "rustsbi_pmu_reclaim");
+    check!(reclaimed == 0, "reclaimed {} counters twice", reclaimed);
+    println!("<< PMU-test: Idle counter reclaim passed");
+}
@@ -0,0 +1,57 @@
+// Arguments of the client library stubs against the dispatcher of RustSBI: 64-bit values and physical
+// addresses whose halves differ, which only come back right if each half lands in the argument register
+// RustSBI reads it from, on RV32 as well as RV64
+
+use crate::counter;
+use crate::sbi;
+use sbi_pmu_client::{self as client, events, rustsbi, ConfigFlags, EventIdx, IndexMask, StartFlags, StopFlags};
+
+// the upper half comes back from `counter_fw_read_hi` on RV32, and is lost if it is not passed in a4
+const INITIAL_VALUE: u64 = 0x0000_0003_0000_0005;
+
+// one 64-bit value for the single counter read in a batch; the test kernel runs without paging,
+// so the address of this buffer is its physical address
+static mut VALUES: [u64; 1] = [0];
+
+pub fn run() {
+    println!(">> PMU-test: Testing ecall argument registers");
+    let num_counters = check_ok!(client::num_counters(), "num_counters");
+    let base = counter::first_firmware_counter(num_counters);
+    let firmware = IndexMask::new(base, counter::all_counters(num_counters - base));
+
+    // event_data is read from a4, with its upper half from a5 on RV32; a value differing in the upper
+    // half only is another, unsupported platform event
+    let event = EventIdx::from_parts(events::EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_PLATFORM);
+    let event_data = sbi::RUSTSBI_FW_PMU_ECALL_CYCLES | 1 << 32;
+    check_err!(client::counter_config_matching(firmware, ConfigFlags::CLEAR_VALUE, event, event_data), sbi::SBI_ERR_NOT_SUPPORTED, "config_matching upper half of event_data");
+
+    // initial_value is read from a3, with its upper half from a4 on RV32
+    let event = EventIdx::from_parts(events::EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_IPI_SENT);
+    let idx = check_ok!(client::counter_config_matching(firmware, ConfigFlags::CLEAR_VALUE, event, 0), "counter_config_matching ipi_sent");
+    let counter = IndexMask::single(idx);
+    check_ok!(client::counter_start(counter, StartFlags::SET_INIT_VALUE, INITIAL_VALUE), "counter_start");
+    check_ok!(client::counter_stop(counter, StopFlags::empty()), "counter_stop");
+    let lo = check_ok!(client::counter_fw_read(idx), "counter_fw_read");
+    let hi = check_ok!(client::counter_fw_read_hi(idx), "counter_fw_read_hi");
+    let expected_hi = if usize::BITS == 32 { (INITIAL_VALUE >> 32) as usize } else { 0 };
+    check!(lo == INITIAL_VALUE as usize && hi == expected_hi, "started from {:#x}, read {:#x} and upper half {:#x}", INITIAL_VALUE, lo, hi);
+
+    // the counter and the buffer in a0 to a3, with a zero upper half of the address on RV64
+    let buf = unsafe { VALUES.as_ptr() as u64 };
+    let read = check_ok!(rustsbi::pmu_counter_read_batch(counter, buf), "pmu_counter_read_batch");
+    let value = unsafe { core::ptr::read_volatile(VALUES.as_ptr()) };
+    check!(read == 1 && value == INITIAL_VALUE, "read {} counters in a batch, value {:#x}", read, value);
+
+    // physical addresses above XLEN bits have their upper half in a register of its own on RV32
+    #[cfg(target_pointer_width = "32")]
+    {
+        check_err!(rustsbi::pmu_counter_read_batch(counter, buf | 1 << 32), sbi::SBI_ERR_INVALID_ADDRESS, "pmu_counter_read_batch above 4 GiB");
+        check_err!(client::snapshot_set_shm(1 << 32), sbi::SBI_ERR_INVALID_ADDRESS, "snapshot_set_shm above 4 GiB");
+    }
+    // and the all-ones address disabling the snapshot is all ones in both registers on both XLENs
+    check_ok!(client::snapshot_disable(), "snapshot_disable");
+
+    check_ok!(client::counter_start(counter, StartFlags::empty(), 0), "counter_start");
+    check_ok!(client::counter_stop(counter, StopFlags::RESET), "counter_stop reset");
+    println!("<< PMU-test: Ecall argument registers passed");
+}
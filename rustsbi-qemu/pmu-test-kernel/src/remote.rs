@@ -0,0 +1,75 @@
+// Configuring, starting and stopping counters of both harts from this hart through the firmware specific
+// extension of RustSBI, checked with the hart status call
+
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM};
+use crate::smp;
+
+pub const OPERATION_CONFIG: u64 = 0;
+pub const OPERATION_START: u64 = 1;
+pub const OPERATION_STOP: u64 = 2;
+
+// the cycle event matches counter 0 on every hart
+const COUNTER_IDX: usize = 0;
+
+// operation, counter_idx_base, counter_idx_mask, flags, event_idx, and event data or initial value;
+// the test kernel runs without paging, so the address of this request is its physical address
+static mut REQUEST: [u64; 6] = [0; 6];
+
+// perform an operation on one counter of every hart in the mask
+pub fn control_counter(hart_mask: usize, operation: u64, counter_idx: usize, flags: usize, event_idx: usize) -> sbi::SbiRet {
+    let request = unsafe {
+        REQUEST = [operation, counter_idx as u64, 1, flags as u64, event_idx as u64, 0];
+        REQUEST.as_ptr() as usize
+    };
+    sbi::rustsbi_pmu_remote_control(hart_mask, 0, request, 0)
+}
+
+fn control(hart_mask: usize, operation: u64, flags: usize, event_idx: usize) -> sbi::SbiRet {
+    control_counter(hart_mask, operation, COUNTER_IDX, flags, event_idx)
+}
+
+// started and bound counters of a hart, from the hart status call
+fn counts(hartid: usize) -> (usize, usize) {
+    let value = check_ok!(sbi::rustsbi_pmu_hart_status(hartid), "rustsbi_pmu_hart_status");
+    (value & 0xff, (value >> 8) & 0xff)
+}
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing remote counter control");
+    let secondary_harts = smp::secondary_harts();
+    check!(secondary_harts != 0, "remote counter control needs at least two harts");
+    let partner = secondary_harts.trailing_zeros() as usize;
+    let both = (1 << hartid) | (1 << partner);
+    let (started, bound) = counts(hartid);
+
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let harts = check_ok!(control(both, OPERATION_CONFIG, flags, sbi::EVENT_HW_CPU_CYCLES), "remote config");
+    check!(harts == 2, "remote config reached {} harts", harts);
+    check!(counts(hartid) == (started + 1, bound + 1), "local counter not configured and started");
+    check!(counts(partner) == (1, 1), "counter of hart {} not configured and started", partner);
+
+    check_ok!(control(1 << partner, OPERATION_STOP, 0, 0), "remote stop");
+    check!(counts(partner) == (0, 1), "counter of hart {} not stopped", partner);
+    check_ok!(control(1 << partner, OPERATION_START, 0, 0), "remote start");
+    check!(counts(partner) == (1, 1), "counter of hart {} not started again", partner);
+    check_ok!(control(both, OPERATION_STOP, sbi::STOP_FLAG_RESET, 0), "remote stop and reset");
+    check!(counts(hartid) == (started, bound), "local counter not released");
+    check!(counts(partner) == (0, 0), "counter of hart {} not released", partner);
+
+    // the failing hart is reported in the value
+    let ret = control(1 << partner, OPERATION_START, 0, 0);
+    check!(
+        ret.error == SBI_ERR_INVALID_PARAM && ret.value == partner,
+        "remote start of a free counter returned error {}, value {}",
+        ret.error as isize,
+        ret.value
+    );
+    check_err!(control(both, 3, 0, 0), SBI_ERR_INVALID_PARAM, "remote control with an unknown operation");
+    let misaligned = unsafe { REQUEST.as_ptr() as usize } + 4;
+    check_err!(
+        sbi::rustsbi_pmu_remote_control(both, 0, misaligned, 0),
+        SBI_ERR_INVALID_PARAM,
+        "remote control with a misaligned request"
+    );
+    println!("<< PMU-test: Remote counter control passed");
+}
@@ -0,0 +1,130 @@
+// Record and replay of PMU calls: the firmware records the calls of a run, supervisor reads them out through
+// the trace read call of RustSBI's firmware specific extension and issues them again, and the same counters
+// must be allocated and the same errors returned. Firmware built without the `trace-record` feature returns
+// no records, and the test is skipped
+
+use crate::counter;
+use crate::sbi::{self, SbiRet, SBI_ERR_NOT_SUPPORTED};
+
+const CAPACITY: usize = 32;
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+const FUNCTION_PMU_COUNTER_GET_INFO: usize = 0x1;
+const FUNCTION_PMU_COUNTER_CONFIG_MATCHING: usize = 0x2;
+
+// a recorded call, as the firmware copies it out
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TraceRecord {
+    extension: usize,
+    function: usize,
+    param: [usize; 6],
+    error: usize,
+    value: usize,
+}
+
+const EMPTY: TraceRecord = TraceRecord { extension: 0, function: 0, param: [0; 6], error: 0, value: 0 };
+
+static mut RECORDED: [TraceRecord; CAPACITY] = [EMPTY; CAPACITY];
+static mut REPLAYED: [TraceRecord; CAPACITY] = [EMPTY; CAPACITY];
+
+// binds, starts, stops and releases counters, with calls failing on busy, started and stopped counters in
+// between; every counter bound is released at the end. Returns the number of calls made
+fn workload(fw_base: usize, fw_mask: usize) -> usize {
+    let mut calls = 0;
+    let mut call = |ret: SbiRet| {
+        calls += 1;
+        ret
+    };
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE;
+    let cycles = call(sbi::pmu_counter_config_matching(0, 1, flags | sbi::CFG_FLAG_AUTO_START, sbi::EVENT_HW_CPU_CYCLES, 0)).value;
+    call(sbi::pmu_counter_config_matching(0, 1, flags, sbi::EVENT_HW_CPU_CYCLES, 0));
+    let timer_a = call(sbi::pmu_counter_config_matching(fw_base, fw_mask, flags, sbi::EVENT_FW_SET_TIMER, 0)).value;
+    let timer_b = call(sbi::pmu_counter_config_matching(fw_base, fw_mask, flags, sbi::EVENT_FW_SET_TIMER, 0)).value;
+    let ipi = call(sbi::pmu_counter_config_matching(fw_base, fw_mask, flags, sbi::EVENT_FW_IPI_SENT, 0)).value;
+    call(sbi::pmu_counter_start(timer_a, 1, 0, 0));
+    call(sbi::pmu_counter_start(timer_a, 1, 0, 0));
+    call(sbi::pmu_counter_stop(timer_b, 1, sbi::STOP_FLAG_RESET));
+    call(sbi::pmu_counter_start(timer_b, 1, 0, 0));
+    call(sbi::pmu_counter_stop(timer_b, 1, sbi::STOP_FLAG_RESET));
+    call(sbi::pmu_counter_stop(timer_a, 1, sbi::STOP_FLAG_RESET));
+    // takes one of the counters just released
+    let again = call(sbi::pmu_counter_config_matching(fw_base, fw_mask, flags, sbi::EVENT_FW_IPI_SENT, 0)).value;
+    call(sbi::pmu_counter_start(ipi, 1, 0, 0));
+    call(sbi::pmu_counter_start(again, 1, 0, 0));
+    call(sbi::pmu_counter_stop(ipi, 1, sbi::STOP_FLAG_RESET));
+    call(sbi::pmu_counter_stop(again, 1, sbi::STOP_FLAG_RESET));
+    call(sbi::pmu_counter_fw_read(again));
+    call(sbi::pmu_counter_stop(cycles, 1, sbi::STOP_FLAG_RESET));
+    calls
+}
+
+pub fn run() {
+    println!(">> PMU-test: Testing PMU call replay");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let fw_mask = counter::all_counters(num_counters - fw_base);
+    let (recorded, replayed) = unsafe { (RECORDED.as_ptr() as usize, REPLAYED.as_ptr() as usize) };
+    // calls made by earlier tests are read out first
+    loop {
+        let ret = sbi::rustsbi_trace_read(recorded, 0, CAPACITY);
+        if ret.error == SBI_ERR_NOT_SUPPORTED {
+            println!("<< PMU-test: Call tracing not built into the firmware, skipped");
+            println!("<< PMU-test: PMU call replay passed");
+            return;
+        }
+        if check_ok!(ret, "rustsbi_trace_read earlier calls") < CAPACITY {
+            break;
+        }
+    }
+
+    let calls = workload(fw_base, fw_mask);
+    let len = check_ok!(sbi::rustsbi_trace_read(recorded, 0, CAPACITY), "rustsbi_trace_read");
+    if len == 0 {
+        println!("<< PMU-test: Call recorder not installed in the firmware, skipped");
+        println!("<< PMU-test: PMU call replay passed");
+        return;
+    }
+    check!(len == calls, "{} calls recorded, {} made", len, calls);
+    let records = unsafe { RECORDED };
+    for record in &records[..len] {
+        check!(record.extension == sbi::EXTENSION_PMU, "call of extension {:#x} recorded", record.extension);
+    }
+
+    // the workload released every counter it bound, so the replay starts from the same state
+    for (idx, record) in records[..len].iter().enumerate() {
+        let ret = sbi::ecall(record.extension, record.function, record.param);
+        check!(
+            ret.error == record.error,
+            "call {} of function {} returned error {} on replay, {} when recorded",
+            idx,
+            record.function,
+            ret.error as isize,
+            record.error as isize
+        );
+        let deterministic = matches!(
+            record.function,
+            FUNCTION_PMU_NUM_COUNTERS | FUNCTION_PMU_COUNTER_GET_INFO | FUNCTION_PMU_COUNTER_CONFIG_MATCHING
+        );
+        check!(
+            !deterministic || ret.value == record.value,
+            "call {} of function {} returned {} on replay, {} when recorded",
+            idx,
+            record.function,
+            ret.value,
+            record.value
+        );
+    }
+
+    // the replay is recorded as the very same sequence
+    let len_again = check_ok!(sbi::rustsbi_trace_read(replayed, 0, CAPACITY), "rustsbi_trace_read replay");
+    check!(len_again == len, "{} calls recorded on replay, {} replayed", len_again, len);
+    let replays = unsafe { REPLAYED };
+    for (idx, (record, replay)) in records[..len].iter().zip(&replays[..len]).enumerate() {
+        check!(
+            replay.function == record.function && replay.param == record.param && replay.error == record.error,
+            "call {} recorded differently on replay",
+            idx
+        );
+    }
+    println!("<< PMU-test: PMU call replay passed");
+}
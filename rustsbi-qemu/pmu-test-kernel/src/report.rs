@@ -0,0 +1,41 @@
+// Measurements of the tests, printed as text, or with the `json` feature as one JSON object per line:
+//
+//   {"event":"instructions","counter_idx":3,"value":2001,"hart":0,"timestamp":1234567}
+//
+// where the timestamp is the `time` counter when the measurement is reported; text lines are left out of
+// the golden output as their values change, JSON lines all start with `{"event":`
+
+// the `time` CSR, emulated by the firmware
+#[cfg(feature = "json")]
+const CSR_TIME: usize = 0xC01;
+
+// Report a measurement of `event` by counter `counter_idx` on hart `hartid`, described by `$fmt` in text
+macro_rules! measurement {
+    ($hartid: expr, $event: expr, $counter_idx: expr, $value: expr, $fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::report::measurement(
+            $hartid,
+            $event,
+            $counter_idx,
+            $value as u64,
+            format_args!($fmt $(, $($arg)+)?),
+        )
+    };
+}
+
+#[cfg(not(feature = "json"))]
+pub fn measurement(_hartid: usize, _event: &str, _counter_idx: usize, _value: u64, text: core::fmt::Arguments) {
+    println!("<< PMU-test: {}", text);
+}
+
+#[cfg(feature = "json")]
+pub fn measurement(hartid: usize, event: &str, counter_idx: usize, value: u64, _text: core::fmt::Arguments) {
+    // event names are identifiers of the tests, with nothing to escape
+    println!(
+        "{{\"event\":\"{}\",\"counter_idx\":{},\"value\":{},\"hart\":{},\"timestamp\":{}}}",
+        event,
+        counter_idx,
+        value,
+        hartid,
+        crate::counter::read(CSR_TIME)
+    );
+}
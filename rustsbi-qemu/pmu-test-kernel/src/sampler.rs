@@ -0,0 +1,98 @@
+// Periodic sampling of counters by the firmware, from the machine timer interrupt, through
+// the sampler calls of RustSBI's firmware specific extension
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi::{self, SBI_ERR_ALREADY_STARTED, SBI_ERR_ALREADY_STOPPED, SBI_ERR_INVALID_PARAM};
+use core::ptr::read_volatile;
+
+// ticks of the `time` counter, 1 millisecond on QEMU
+const PERIOD: usize = 10_000;
+const SAMPLES: u64 = 4;
+// the `time` CSR, emulated by the firmware
+const CSR_TIME: usize = 0xC01;
+
+// count of samples written, then records of the sample time, the cycle counter and the instret counter
+const RECORD_LEN: usize = 3;
+const CAPACITY: usize = 8;
+static mut RING: [u64; 1 + CAPACITY * RECORD_LEN] = [0; 1 + CAPACITY * RECORD_LEN];
+
+// the hart id is only exported with the samples
+#[cfg_attr(not(feature = "perf-export"), allow(unused_variables))]
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing periodic counter sampling");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let mask = counter::all_counters(fw_base);
+    let cycle_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let instret_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, flags, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        "counter_config_matching instructions"
+    );
+    // every sample is taken in a machine timer interrupt, which the firmware counts by itself
+    let timer_idx = check_ok!(
+        sbi::pmu_counter_config_matching(
+            fw_base,
+            counter::all_counters(num_counters - fw_base),
+            flags,
+            sbi::EVENT_FW_PLATFORM,
+            sbi::RUSTSBI_FW_PMU_MTIMER_INTERRUPTS
+        ),
+        "counter_config_matching machine timer interrupts"
+    );
+    let set = (1 << cycle_idx) | (1 << instret_idx);
+    let buf = unsafe { RING.as_ptr() as usize };
+    let size = core::mem::size_of_val(unsafe { &RING });
+
+    check_err!(sbi::rustsbi_pmu_sampler_start(0, set, 0, buf, 0, size), SBI_ERR_INVALID_PARAM, "sampler_start zero period");
+    check_err!(sbi::rustsbi_pmu_sampler_start(0, set, PERIOD, buf + 4, 0, size), SBI_ERR_INVALID_PARAM, "sampler_start misaligned buffer");
+    check_err!(sbi::rustsbi_pmu_sampler_start(0, set, PERIOD, buf, 0, 8), SBI_ERR_INVALID_PARAM, "sampler_start buffer too small");
+    check_err!(sbi::rustsbi_pmu_sampler_stop(), SBI_ERR_ALREADY_STOPPED, "sampler_stop before start");
+    let capacity = check_ok!(sbi::rustsbi_pmu_sampler_start(0, set, PERIOD, buf, 0, size), "sampler_start");
+    check!(capacity == CAPACITY, "sampler_start returned capacity {}, expected {}", capacity, CAPACITY);
+    check_err!(sbi::rustsbi_pmu_sampler_start(0, set, PERIOD, buf, 0, size), SBI_ERR_ALREADY_STARTED, "sampler_start twice");
+
+    // samples are taken in the machine timer interrupt while this loop runs in supervisor mode
+    let start = counter::read(CSR_TIME);
+    while unsafe { read_volatile(&RING[0]) } < SAMPLES {
+        check!(
+            counter::read(CSR_TIME).wrapping_sub(start) < 100 * PERIOD,
+            "only {} samples taken in 100 periods",
+            unsafe { read_volatile(&RING[0]) }
+        );
+    }
+    let taken = check_ok!(sbi::rustsbi_pmu_sampler_stop(), "sampler_stop") as u64;
+    check!(taken >= SAMPLES, "sampler_stop returned {} samples, at least {} were written", taken, SAMPLES);
+    let interrupts = check_ok!(sbi::pmu_counter_fw_read(timer_idx), "counter_fw_read machine timer interrupts") as u64;
+    check!(interrupts >= taken, "{} machine timer interrupts counted for {} samples", interrupts, taken);
+
+    // values in a record follow the order of counter indices
+    let (cycle_at, instret_at) = if cycle_idx < instret_idx { (1, 2) } else { (2, 1) };
+    let record = |i: usize| unsafe { [0, cycle_at, instret_at].map(|j| read_volatile(&RING[1 + i * RECORD_LEN + j])) };
+    for i in 1..SAMPLES as usize {
+        let (prev, this) = (record(i - 1), record(i));
+        check!(this[0] >= prev[0] + PERIOD as u64, "sample {} taken at {}, {} ticks after the last one", i, this[0], this[0] - prev[0]);
+        check!(this[1] > prev[1] && this[2] > prev[2], "cycles or instructions did not increase in sample {}", i);
+    }
+    let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(cycle_idx), "counter_get_info"));
+    check!(record(0)[1] <= counter::read(info.csr) as u64, "sampled cycles are later than the cycle counter");
+    println!("<< PMU-test: {} samples taken, {} ticks apart", taken, PERIOD);
+    #[cfg(feature = "perf-export")]
+    {
+        // the oldest records are overwritten once the ring is full
+        let count = core::cmp::min(taken as usize, CAPACITY);
+        let oldest = taken as usize - count;
+        let column = [0, cycle_at, instret_at];
+        crate::perf::export(hartid, &[sbi::EVENT_HW_CPU_CYCLES, sbi::EVENT_HW_INSTRUCTIONS], count, |s, j| unsafe {
+            read_volatile(&RING[1 + (oldest + s) % CAPACITY * RECORD_LEN + column[j]])
+        });
+    }
+
+    check_ok!(sbi::pmu_counter_stop(cycle_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    check_ok!(sbi::pmu_counter_stop(instret_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop instructions");
+    check_ok!(sbi::pmu_counter_stop(timer_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop machine timer interrupts");
+    println!("<< PMU-test: Periodic counter sampling passed");
+}
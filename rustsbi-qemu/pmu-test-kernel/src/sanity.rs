@@ -0,0 +1,52 @@
+// Counting cycles and instructions over a loop of known length
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+
+const ITERATIONS: usize = 10000;
+// Instructions executed by reading the counter CSRs around the loop
+const INSTRET_SLACK: usize = 16;
+
+pub fn run() {
+    println!(">> PMU-test: Testing cycle and instret counters");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let mask = counter::all_counters(num_counters);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let cycle_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let instret_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, mask, flags, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        "counter_config_matching instructions"
+    );
+    let cycle = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(cycle_idx), "counter_get_info"));
+    let instret = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(instret_idx), "counter_get_info"));
+    check!(!cycle.firmware && !instret.firmware, "cycles or instructions matched a firmware counter");
+
+    let cycle_start = counter::read(cycle.csr);
+    let instret_start = counter::read(instret.csr);
+    counter::fixed_loop(ITERATIONS);
+    let instret_end = counter::read(instret.csr);
+    let cycle_end = counter::read(cycle.csr);
+
+    let instructions = instret_end.wrapping_sub(instret_start);
+    let cycles = cycle_end.wrapping_sub(cycle_start);
+    println!(
+        "<< PMU-test: {} iterations took {} instructions, {} cycles",
+        ITERATIONS, instructions, cycles
+    );
+    // each iteration is one `addi` and one `bnez`
+    check!(
+        instructions >= 2 * ITERATIONS && instructions <= 2 * ITERATIONS + INSTRET_SLACK,
+        "instret advanced by {}, expected {} to {}",
+        instructions,
+        2 * ITERATIONS,
+        2 * ITERATIONS + INSTRET_SLACK
+    );
+    check!(cycles > 0, "cycle did not advance");
+
+    check_ok!(sbi::pmu_counter_stop(cycle_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    check_ok!(sbi::pmu_counter_stop(instret_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop instructions");
+    println!("<< PMU-test: Cycle and instret counters passed");
+}
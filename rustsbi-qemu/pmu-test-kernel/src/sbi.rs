@@ -0,0 +1,423 @@
+#![allow(unused)]
+
+use crate::events::{
+    event_idx, EVENT_TYPE_FIRMWARE, EVENT_TYPE_HARDWARE_GENERAL, EVENT_TYPE_HARDWARE_RAW, SBI_PMU_FW_FENCE_I_SENT,
+    SBI_PMU_FW_IPI_RECEIVED, SBI_PMU_FW_IPI_SENT, SBI_PMU_FW_PLATFORM, SBI_PMU_FW_SET_TIMER,
+    SBI_PMU_FW_SFENCE_VMA_SENT, SBI_PMU_HW_BRANCH_INSTRUCTIONS, SBI_PMU_HW_BRANCH_MISSES, SBI_PMU_HW_CACHE_MISSES,
+    SBI_PMU_HW_CPU_CYCLES, SBI_PMU_HW_INSTRUCTIONS,
+};
+
+pub const EXTENSION_BASE: usize = 0x10;
+pub const EXTENSION_SRST: usize = 0x53525354;
+pub const EXTENSION_IPI: usize = 0x735049;
+pub const EXTENSION_RFENCE: usize = 0x52464E43;
+pub const EXTENSION_PMU: usize = 0x504D55;
+pub const EXTENSION_RUSTSBI: usize = 0x0A000004;
+
+const FUNCTION_BASE_PROBE_EXTENSION: usize = 0x3;
+
+const FUNCTION_SRST_SYSTEM_RESET: usize = 0x0;
+
+const FUNCTION_IPI_SEND_IPI: usize = 0x0;
+
+const FUNCTION_RFENCE_REMOTE_SFENCE_VMA: usize = 0x1;
+
+pub const RESET_TYPE_SHUTDOWN: usize = 0x0000_0000;
+pub const RESET_REASON_NO_REASON: usize = 0x0000_0000;
+pub const RESET_REASON_SYSTEM_FAILURE: usize = 0x0000_0001;
+
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+const FUNCTION_PMU_COUNTER_GET_INFO: usize = 0x1;
+const FUNCTION_PMU_COUNTER_CONFIG_MATCHING: usize = 0x2;
+const FUNCTION_PMU_COUNTER_START: usize = 0x3;
+const FUNCTION_PMU_COUNTER_STOP: usize = 0x4;
+const FUNCTION_PMU_COUNTER_FW_READ: usize = 0x5;
+const FUNCTION_PMU_COUNTER_FW_READ_HI: usize = 0x6;
+const FUNCTION_PMU_SNAPSHOT_SET_SHM: usize = 0x7;
+const FUNCTION_PMU_EVENT_GET_INFO: usize = 0x8;
+
+const FUNCTION_RUSTSBI_PMU_DUMP: usize = 0x0;
+const FUNCTION_RUSTSBI_TRACE_READ: usize = 0x1;
+const FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH: usize = 0x2;
+const FUNCTION_RUSTSBI_PMU_PROTECT_EVENT: usize = 0x3;
+const FUNCTION_RUSTSBI_PMU_SAMPLER_START: usize = 0x4;
+const FUNCTION_RUSTSBI_PMU_SAMPLER_STOP: usize = 0x5;
+const FUNCTION_RUSTSBI_PMU_COUNTER_READ_CLEAR: usize = 0x6;
+const FUNCTION_RUSTSBI_PMU_HART_STATUS: usize = 0x7;
+const FUNCTION_RUSTSBI_PMU_REMOTE_CONTROL: usize = 0x8;
+const FUNCTION_RUSTSBI_PMU_EVENT_TOTAL: usize = 0x9;
+const FUNCTION_RUSTSBI_PMU_LEAK_CHECK: usize = 0xA;
+const FUNCTION_RUSTSBI_PMU_RECLAIM: usize = 0xB;
+const FUNCTION_RUSTSBI_STATS_READ: usize = 0xC;
+const FUNCTION_RUSTSBI_PMU_COUNTER_WATCH: usize = 0xD;
+const FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ: usize = 0xE;
+const FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT: usize = 0xF;
+const FUNCTION_RUSTSBI_PMU_CAPABILITIES: usize = 0x10;
+
+pub const SBI_SUCCESS: usize = 0;
+pub const SBI_ERR_FAILED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-1));
+pub const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
+pub const SBI_ERR_INVALID_PARAM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-3));
+pub const SBI_ERR_DENIED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-4));
+pub const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
+pub const SBI_ERR_ALREADY_AVAILABLE: usize = usize::from_ne_bytes(isize::to_ne_bytes(-6));
+pub const SBI_ERR_ALREADY_STARTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-7));
+pub const SBI_ERR_ALREADY_STOPPED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-8));
+pub const SBI_ERR_NO_SHMEM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-9));
+
+pub const CFG_FLAG_SKIP_MATCH: usize = 1 << 0;
+pub const CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+pub const CFG_FLAG_AUTO_START: usize = 1 << 2;
+pub const CFG_FLAG_SET_VUINH: usize = 1 << 3;
+pub const CFG_FLAG_SET_VSINH: usize = 1 << 4;
+pub const START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+pub const START_FLAG_INIT_SNAPSHOT: usize = 1 << 1;
+pub const STOP_FLAG_RESET: usize = 1 << 0;
+pub const STOP_FLAG_TAKE_SNAPSHOT: usize = 1 << 1;
+pub const RUSTSBI_STATS_READ_CLEAR: usize = 1 << 0;
+
+pub const EVENT_HW_CPU_CYCLES: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CPU_CYCLES);
+pub const EVENT_HW_INSTRUCTIONS: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_INSTRUCTIONS);
+pub const EVENT_HW_CACHE_MISSES: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CACHE_MISSES);
+pub const EVENT_HW_BRANCH_INSTRUCTIONS: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_BRANCH_INSTRUCTIONS);
+pub const EVENT_HW_BRANCH_MISSES: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_BRANCH_MISSES);
+pub const EVENT_HW_RAW: usize = event_idx(EVENT_TYPE_HARDWARE_RAW, 0);
+pub const EVENT_FW_SET_TIMER: usize = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_SET_TIMER);
+pub const EVENT_FW_IPI_SENT: usize = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_IPI_SENT);
+pub const EVENT_FW_IPI_RECEIVED: usize = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_IPI_RECEIVED);
+pub const EVENT_FW_SFENCE_VMA_SENT: usize = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_SFENCE_VMA_SENT);
+pub const EVENT_FW_FENCE_I_SENT: usize = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_FENCE_I_SENT);
+pub const EVENT_FW_PLATFORM: usize = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_PLATFORM);
+pub use crate::events::{
+    RUSTSBI_FW_PMU_CONSOLE_CYCLES, RUSTSBI_FW_PMU_ECALL_CYCLES, RUSTSBI_FW_PMU_EMULATED_COUNTER_READS, RUSTSBI_FW_PMU_IPI_CYCLES,
+    RUSTSBI_FW_PMU_MSOFT_INTERRUPTS, RUSTSBI_FW_PMU_MTIMER_INTERRUPTS, RUSTSBI_FW_PMU_PMU_CALLS, RUSTSBI_FW_PMU_SBI_CALLS,
+    RUSTSBI_FW_PMU_TRAP_CYCLES, RUSTSBI_PMU_CAPABILITIES_VERSION, RUSTSBI_PMU_CAP_FIRMWARE_EVENTS, RUSTSBI_PMU_CAP_READ_BATCH,
+    RUSTSBI_PMU_CAP_SAMPLER, RUSTSBI_PMU_CAP_SNAPSHOT, RUSTSBI_PMU_CAP_SSCOFPMF, RUSTSBI_PMU_LEAK_CHECK_END_SESSION,
+    RUSTSBI_PMU_SELF_TEST_PASSED, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SbiRet {
+    /// Error number
+    pub error: usize,
+    /// Result value
+    pub value: usize,
+}
+
+#[inline(always)]
+fn sbi_call(extension: usize, function: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize, arg5: usize) -> SbiRet {
+    let (error, value);
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => unsafe {
+            asm!(
+                "ecall",
+                in("a0") arg0, in("a1") arg1, in("a2") arg2, in("a3") arg3, in("a4") arg4, in("a5") arg5,
+                in("a6") function, in("a7") extension,
+                lateout("a0") error, lateout("a1") value,
+            )
+        },
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {
+            drop((extension, function, arg0, arg1, arg2, arg3, arg4, arg5));
+            unimplemented!("not RISC-V instruction set architecture")
+        }
+    };
+    SbiRet { error, value }
+}
+
+// issue a call given by its extension and function IDs and parameters, as recorded by the firmware
+#[inline]
+pub fn ecall(extension: usize, function: usize, param: [usize; 6]) -> SbiRet {
+    sbi_call(extension, function, param[0], param[1], param[2], param[3], param[4], param[5])
+}
+
+#[inline]
+pub fn probe_extension(extension_id: usize) -> usize {
+    sbi_call(EXTENSION_BASE, FUNCTION_BASE_PROBE_EXTENSION, extension_id, 0, 0, 0, 0, 0).value
+}
+
+#[inline]
+pub fn system_reset(reset_type: usize, reset_reason: usize) -> SbiRet {
+    sbi_call(EXTENSION_SRST, FUNCTION_SRST_SYSTEM_RESET, reset_type, reset_reason, 0, 0, 0, 0)
+}
+
+// RustSBI reads the hart mask from memory, so it is passed by reference
+#[inline]
+pub fn send_ipi(hart_mask: &usize, hart_mask_base: usize) -> SbiRet {
+    sbi_call(EXTENSION_IPI, FUNCTION_IPI_SEND_IPI, hart_mask as *const usize as usize, hart_mask_base, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn remote_sfence_vma(hart_mask: &usize, hart_mask_base: usize, start_addr: usize, size: usize) -> SbiRet {
+    sbi_call(
+        EXTENSION_RFENCE,
+        FUNCTION_RFENCE_REMOTE_SFENCE_VMA,
+        hart_mask as *const usize as usize,
+        hart_mask_base,
+        start_addr,
+        size,
+        0,
+        0,
+    )
+}
+
+#[inline]
+pub fn pmu_num_counters() -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_NUM_COUNTERS, 0, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn pmu_counter_get_info(counter_idx: usize) -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_GET_INFO, counter_idx, 0, 0, 0, 0, 0)
+}
+
+// 64-bit arguments take two registers on RV32, lower half first
+#[inline]
+fn split_u64(value: u64) -> (usize, usize) {
+    match () {
+        #[cfg(target_pointer_width = "32")]
+        () => (value as usize, (value >> 32) as usize),
+        #[cfg(not(target_pointer_width = "32"))]
+        () => (value as usize, 0),
+    }
+}
+
+#[inline]
+pub fn pmu_counter_config_matching(counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+    let (event_data_lo, event_data_hi) = split_u64(event_data);
+    sbi_call(
+        EXTENSION_PMU,
+        FUNCTION_PMU_COUNTER_CONFIG_MATCHING,
+        counter_idx_base,
+        counter_idx_mask,
+        config_flags,
+        event_idx,
+        event_data_lo,
+        event_data_hi,
+    )
+}
+
+#[inline]
+pub fn pmu_counter_start(counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+    let (initial_value_lo, initial_value_hi) = split_u64(initial_value);
+    sbi_call(
+        EXTENSION_PMU,
+        FUNCTION_PMU_COUNTER_START,
+        counter_idx_base,
+        counter_idx_mask,
+        start_flags,
+        initial_value_lo,
+        initial_value_hi,
+        0,
+    )
+}
+
+#[inline]
+pub fn pmu_counter_stop(counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_STOP, counter_idx_base, counter_idx_mask, stop_flags, 0, 0, 0)
+}
+
+#[inline]
+pub fn pmu_counter_fw_read(counter_idx: usize) -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_FW_READ, counter_idx, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn pmu_counter_fw_read_hi(counter_idx: usize) -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_FW_READ_HI, counter_idx, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn pmu_snapshot_set_shm(shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_SNAPSHOT_SET_SHM, shmem_phys_lo, shmem_phys_hi, flags, 0, 0, 0)
+}
+
+#[inline]
+pub fn pmu_event_get_info(shmem_phys_lo: usize, shmem_phys_hi: usize, num_entries: usize, flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_PMU, FUNCTION_PMU_EVENT_GET_INFO, shmem_phys_lo, shmem_phys_hi, num_entries, flags, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_dump() -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_DUMP, 0, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_counter_read_batch(counter_idx_base: usize, counter_idx_mask: usize, buf_phys_lo: usize, buf_phys_hi: usize) -> SbiRet {
+    sbi_call(
+        EXTENSION_RUSTSBI,
+        FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH,
+        counter_idx_base,
+        counter_idx_mask,
+        buf_phys_lo,
+        buf_phys_hi,
+        0,
+        0,
+    )
+}
+
+#[inline]
+pub fn rustsbi_pmu_protect_event(event_idx: usize, quantum: usize, jitter: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_PROTECT_EVENT, event_idx, quantum, jitter, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_sampler_start(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    period: usize,
+    buf_phys_lo: usize,
+    buf_phys_hi: usize,
+    size: usize,
+) -> SbiRet {
+    sbi_call(
+        EXTENSION_RUSTSBI,
+        FUNCTION_RUSTSBI_PMU_SAMPLER_START,
+        counter_idx_base,
+        counter_idx_mask,
+        period,
+        buf_phys_lo,
+        buf_phys_hi,
+        size,
+    )
+}
+
+#[inline]
+pub fn rustsbi_pmu_sampler_stop() -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_SAMPLER_STOP, 0, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_counter_read_clear(counter_idx: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_COUNTER_READ_CLEAR, counter_idx, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_hart_status(hartid: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_HART_STATUS, hartid, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_remote_control(hart_mask: usize, hart_mask_base: usize, request_phys_lo: usize, request_phys_hi: usize) -> SbiRet {
+    sbi_call(
+        EXTENSION_RUSTSBI,
+        FUNCTION_RUSTSBI_PMU_REMOTE_CONTROL,
+        hart_mask,
+        hart_mask_base,
+        request_phys_lo,
+        request_phys_hi,
+        0,
+        0,
+    )
+}
+
+#[inline]
+pub fn rustsbi_pmu_firmware_event_total(event_idx: usize, buf_phys_lo: usize, buf_phys_hi: usize, num_harts: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_EVENT_TOTAL, event_idx, buf_phys_lo, buf_phys_hi, num_harts, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_leak_check(counter_idx_base: usize, flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_LEAK_CHECK, counter_idx_base, flags, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_self_test_result(counter_idx_base: usize, flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT, counter_idx_base, flags, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_capabilities(flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_CAPABILITIES, flags, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_reclaim() -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_RECLAIM, 0, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_trace_read(buf_phys_lo: usize, buf_phys_hi: usize, count: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_TRACE_READ, buf_phys_lo, buf_phys_hi, count, 0, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_stats_read(buf_phys_lo: usize, buf_phys_hi: usize, count: usize, flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_STATS_READ, buf_phys_lo, buf_phys_hi, count, flags, 0, 0)
+}
+
+#[inline]
+pub fn rustsbi_pmu_counter_info_read(buf_phys_lo: usize, buf_phys_hi: usize, num_entries: usize, flags: usize) -> SbiRet {
+    sbi_call(EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, buf_phys_lo, buf_phys_hi, num_entries, flags, 0, 0)
+}
+
+// the threshold takes a1 on RV64, and a1 and a2 on RV32, moving the other parameters up by one register
+#[inline]
+pub fn rustsbi_pmu_counter_watch(counter_idx: usize, threshold: u64, flags: usize, shmem_phys_lo: usize, shmem_phys_hi: usize) -> SbiRet {
+    match () {
+        #[cfg(target_pointer_width = "32")]
+        () => sbi_call(
+            EXTENSION_RUSTSBI,
+            FUNCTION_RUSTSBI_PMU_COUNTER_WATCH,
+            counter_idx,
+            threshold as usize,
+            (threshold >> 32) as usize,
+            flags,
+            shmem_phys_lo,
+            shmem_phys_hi,
+        ),
+        #[cfg(not(target_pointer_width = "32"))]
+        () => sbi_call(
+            EXTENSION_RUSTSBI,
+            FUNCTION_RUSTSBI_PMU_COUNTER_WATCH,
+            counter_idx,
+            threshold as usize,
+            flags,
+            shmem_phys_lo,
+            shmem_phys_hi,
+            0,
+        ),
+    }
+}
+
+#[inline(always)]
+fn sbi_call_legacy(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
+    let ret;
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => unsafe {
+            asm!(
+                "ecall",
+                in("a0") arg0, in("a1") arg1, in("a2") arg2,
+                in("a7") which,
+                lateout("a0") ret,
+            )
+        },
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {
+            drop((which, arg0, arg1, arg2));
+            unimplemented!("not RISC-V instruction set architecture")
+        }
+    };
+    ret
+}
+
+const SBI_CONSOLE_PUTCHAR: usize = 1;
+const SBI_CONSOLE_GETCHAR: usize = 2;
+const SBI_SHUTDOWN: usize = 8;
+
+pub fn console_putchar(c: usize) {
+    sbi_call_legacy(SBI_CONSOLE_PUTCHAR, c, 0, 0);
+}
+
+// Returns `usize::MAX` if no character is available
+pub fn console_getchar() -> usize {
+    sbi_call_legacy(SBI_CONSOLE_GETCHAR, 0, 0, 0)
+}
+
+pub fn shutdown() -> ! {
+    sbi_call_legacy(SBI_SHUTDOWN, 0, 0, 0);
+    unreachable!()
+}
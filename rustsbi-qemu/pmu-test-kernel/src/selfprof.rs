@@ -0,0 +1,84 @@
+// Cycles the firmware spends in its own hot paths, the trap handler, inter-processor interrupts and console
+// output, counted by platform specific firmware events of RustSBI next to the PMU call overhead, along with
+// the machine interrupts the firmware takes
+
+use crate::counter;
+use crate::sbi;
+
+const CALLS: usize = 64;
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing firmware self-profiling");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let mask = counter::all_counters(num_counters - fw_base);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    // the firmware measures with `mcycle`, which only runs while the `cycle` counter is started
+    let cycle_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, 1, flags, sbi::EVENT_HW_CPU_CYCLES, 0),
+        "counter_config_matching cycles"
+    );
+    let ecall_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_ECALL_CYCLES),
+        "counter_config_matching pmu ecall cycles"
+    );
+    let trap_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_TRAP_CYCLES),
+        "counter_config_matching trap cycles"
+    );
+    let ipi_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_IPI_CYCLES),
+        "counter_config_matching ipi cycles"
+    );
+    let console_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_CONSOLE_CYCLES),
+        "counter_config_matching console cycles"
+    );
+    let soft_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, mask, flags, sbi::EVENT_FW_PLATFORM, sbi::RUSTSBI_FW_PMU_MSOFT_INTERRUPTS),
+        "counter_config_matching machine software interrupts"
+    );
+
+    // the trap handler takes every PMU call, so it spends at least the cycles of the PMU extension handler;
+    // each read is counted after it returns, the trap counter covers the calls between the two ecall reads
+    let ecall_before = check_ok!(sbi::pmu_counter_fw_read(ecall_idx), "counter_fw_read pmu ecall cycles");
+    let trap_before = check_ok!(sbi::pmu_counter_fw_read(trap_idx), "counter_fw_read trap cycles");
+    for _ in 0..CALLS {
+        check_ok!(sbi::pmu_num_counters(), "num_counters");
+    }
+    let ecall_after = check_ok!(sbi::pmu_counter_fw_read(ecall_idx), "counter_fw_read pmu ecall cycles");
+    let trap_after = check_ok!(sbi::pmu_counter_fw_read(trap_idx), "counter_fw_read trap cycles");
+    let (ecall, trap) = (ecall_after.wrapping_sub(ecall_before), trap_after.wrapping_sub(trap_before));
+    check!(trap >= ecall && ecall > 0, "trap handler counted {} cycles, PMU calls {}", trap, ecall);
+
+    // the firmware handles an IPI sent to this hart in its machine software interrupt handler
+    let target = 1 << hartid;
+    check_ok!(sbi::send_ipi(&target, 0), "send_ipi");
+    // the IPI is left pending, supervisor software interrupt is not used here
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    let ipi = check_ok!(sbi::pmu_counter_fw_read(ipi_idx), "counter_fw_read ipi cycles");
+    check!(ipi > 0, "no cycles counted handling an IPI");
+    let soft = check_ok!(sbi::pmu_counter_fw_read(soft_idx), "counter_fw_read machine software interrupts");
+    check!(soft == 1, "{} machine software interrupts counted for one IPI", soft);
+
+    // every character printed goes through the legacy console call
+    let console_before = check_ok!(sbi::pmu_counter_fw_read(console_idx), "counter_fw_read console cycles");
+    measurement!(
+        hartid,
+        "trap_cycles",
+        trap_idx,
+        trap / CALLS,
+        "{} PMU calls took {} cycles in the trap handler on average",
+        CALLS,
+        trap / CALLS
+    );
+    let console = check_ok!(sbi::pmu_counter_fw_read(console_idx), "counter_fw_read console cycles");
+    check!(console > console_before, "no cycles counted writing console output");
+
+    let stop_mask = [ecall_idx, trap_idx, ipi_idx, console_idx, soft_idx]
+        .iter()
+        .fold(0, |mask, idx| mask | 1 << (idx - fw_base));
+    check_ok!(sbi::pmu_counter_stop(fw_base, stop_mask, sbi::STOP_FLAG_RESET), "counter_stop");
+    check_ok!(sbi::pmu_counter_stop(cycle_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop cycles");
+    println!("<< PMU-test: Firmware self-profiling passed");
+}
@@ -0,0 +1,35 @@
+// Boot-time self-test of RustSBI: the firmware exercised every counter before the kernel booted, and reports
+// the counters which failed and passed through its firmware specific extension. Firmware built without the
+// `pmu-self-test` feature does not run it, and the test is skipped
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED};
+
+// the cycle and instret counters
+const FIXED: usize = 0b101;
+
+pub fn run() {
+    println!(">> PMU-test: Testing boot-time self-test result");
+    let ret = sbi::rustsbi_pmu_self_test_result(0, 0);
+    if ret.error == SBI_ERR_NOT_SUPPORTED {
+        println!("<< PMU-test: Self-test not built into the firmware, skipped");
+        println!("<< PMU-test: Boot-time self-test result passed");
+        return;
+    }
+    let failed = check_ok!(ret, "rustsbi_pmu_self_test_result");
+    check!(failed == 0, "counters {:#x} failed the self-test", failed);
+    let passed = check_ok!(sbi::rustsbi_pmu_self_test_result(0, sbi::RUSTSBI_PMU_SELF_TEST_PASSED), "rustsbi_pmu_self_test_result passed");
+    check!(passed & FIXED == FIXED, "cycle and instret did not pass the self-test, passed counters {:#x}", passed);
+    // firmware counters count firmware events the self-test raised
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let fw_passed = check_ok!(sbi::rustsbi_pmu_self_test_result(fw_base, sbi::RUSTSBI_PMU_SELF_TEST_PASSED), "rustsbi_pmu_self_test_result firmware");
+    check!(fw_passed & 1 != 0, "firmware counter {} did not pass the self-test, passed counters {:#x}", fw_base, fw_passed);
+
+    // counters are released after the self-test
+    let bound = check_ok!(sbi::rustsbi_pmu_reclaim(), "rustsbi_pmu_reclaim");
+    check!(bound == 0, "{} counters left bound", bound);
+    check_err!(sbi::rustsbi_pmu_self_test_result(num_counters, 0), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_self_test_result past the last counter");
+    check_err!(sbi::rustsbi_pmu_self_test_result(0, 1 << 1), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_self_test_result reserved flags");
+    println!("<< PMU-test: Boot-time self-test result passed");
+}
@@ -0,0 +1,30 @@
+// Harts other than the boot hart wait here for tests that need more than one hart
+
+use crate::isolation;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static SECONDARY_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn secondary_main(hartid: usize) -> ! {
+    SECONDARY_HARTS.fetch_or(1 << hartid, Ordering::AcqRel);
+    // IPIs and remote fences sent to this hart are handled by the firmware while spinning
+    loop {
+        if isolation::requested(hartid) {
+            isolation::run_secondary(hartid);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+// Bitmap of secondary harts online so far
+pub fn secondary_harts() -> usize {
+    SECONDARY_HARTS.load(Ordering::Acquire)
+}
+
+// Wait until `harts` harts have arrived at each of the barriers numbered up to `stage`
+pub fn barrier(counter: &AtomicUsize, harts: usize, stage: usize) {
+    counter.fetch_add(1, Ordering::AcqRel);
+    while counter.load(Ordering::Acquire) < harts * stage {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,134 @@
+// Registering the snapshot shared memory and checking what the firmware writes into it on stop
+
+use crate::counter::{self, CounterInfo};
+use crate::sbi;
+use core::ptr::{read_volatile, write_volatile};
+
+const IPIS: usize = 8;
+// value a counter is started from through the shared memory
+const SNAPSHOT_START: u64 = 1000;
+// layout of the shared memory in 64-bit words: overflow bitmap, counter values relative to `counter_idx_base`,
+// then `mtime` at the time of the snapshot, written by RustSBI
+const SHMEM_WORDS: usize = 4096 / 8;
+const OVERFLOW_BITMAP: usize = 0;
+const COUNTER_VALUES: usize = 1;
+const MTIME: usize = COUNTER_VALUES + 64;
+// the `time` CSR, emulated by the firmware
+const CSR_TIME: usize = 0xC01;
+// addresses supervisor must not register: the firmware image at the start of RAM, and the boot ROM of QEMU
+const FIRMWARE_BASE: usize = 0x8000_0000;
+const NOT_RAM: usize = 0x1000;
+
+#[repr(C, align(4096))]
+struct Shmem([u64; SHMEM_WORDS]);
+
+// the test kernel runs without paging, so the address of this page is its physical address
+static mut SHMEM: Shmem = Shmem([0; SHMEM_WORDS]);
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing snapshot shared memory");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_base = counter::first_firmware_counter(num_counters);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let instret_idx = check_ok!(
+        sbi::pmu_counter_config_matching(0, counter::all_counters(fw_base), flags, sbi::EVENT_HW_INSTRUCTIONS, 0),
+        "counter_config_matching instructions"
+    );
+    let ipi_idx = check_ok!(
+        sbi::pmu_counter_config_matching(
+            fw_base,
+            counter::all_counters(num_counters - fw_base),
+            flags,
+            sbi::EVENT_FW_IPI_SENT,
+            0
+        ),
+        "counter_config_matching ipi_sent"
+    );
+
+    check_err!(
+        sbi::pmu_counter_stop(ipi_idx, 1, sbi::STOP_FLAG_TAKE_SNAPSHOT),
+        sbi::SBI_ERR_NO_SHMEM,
+        "counter_stop with snapshot before shared memory is set"
+    );
+    check_err!(
+        sbi::pmu_counter_start(ipi_idx, 1, sbi::START_FLAG_INIT_SNAPSHOT, 0),
+        sbi::SBI_ERR_NO_SHMEM,
+        "counter_start from snapshot before shared memory is set"
+    );
+    let shmem = unsafe { SHMEM.0.as_ptr() } as usize;
+    check_err!(sbi::pmu_snapshot_set_shm(shmem + 8, 0, 0), sbi::SBI_ERR_INVALID_PARAM, "snapshot_set_shm unaligned");
+    check_err!(sbi::pmu_snapshot_set_shm(shmem, 1, 0), sbi::SBI_ERR_INVALID_ADDRESS, "snapshot_set_shm above XLEN bits");
+    check_err!(
+        sbi::pmu_snapshot_set_shm(FIRMWARE_BASE, 0, 0),
+        sbi::SBI_ERR_INVALID_ADDRESS,
+        "snapshot_set_shm in firmware memory"
+    );
+    check_err!(sbi::pmu_snapshot_set_shm(NOT_RAM, 0, 0), sbi::SBI_ERR_INVALID_ADDRESS, "snapshot_set_shm outside RAM");
+    check_ok!(sbi::pmu_snapshot_set_shm(shmem, 0, 0), "snapshot_set_shm");
+
+    let target = 1 << hartid;
+    for _ in 0..IPIS {
+        check_ok!(sbi::send_ipi(&target, 0), "send_ipi");
+    }
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    counter::fixed_loop(1000);
+
+    // every stop writes its own counters from index 0 of `counter_values`; reset them at the same time
+    let stop_flags = sbi::STOP_FLAG_TAKE_SNAPSHOT | sbi::STOP_FLAG_RESET;
+    let before = counter::read(CSR_TIME) as u64;
+    check_ok!(sbi::pmu_counter_stop(ipi_idx, 1, stop_flags), "counter_stop ipi_sent");
+    let after = counter::read(CSR_TIME) as u64;
+    let mtime = unsafe { read_volatile(&SHMEM.0[MTIME]) };
+    // on RV32 only the low XLEN bits of `time` are read, compare as elapsed ticks from `before`
+    check!(
+        mtime.wrapping_sub(before) as usize <= after.wrapping_sub(before) as usize,
+        "snapshot mtime is {}, expected between {} and {}",
+        mtime,
+        before,
+        after
+    );
+    let ipi_sent = check_ok!(sbi::pmu_counter_fw_read(ipi_idx), "counter_fw_read ipi_sent");
+    check!(ipi_sent == IPIS, "ipi_sent counted {}, expected {}", ipi_sent, IPIS);
+    check_snapshot(ipi_sent, "ipi_sent");
+
+    check_ok!(sbi::pmu_counter_stop(instret_idx, 1, stop_flags), "counter_stop instructions");
+    let info = CounterInfo::decode(check_ok!(sbi::pmu_counter_get_info(instret_idx), "counter_get_info"));
+    // the counter is stopped, reading it directly gives the value at the time of the snapshot
+    check_snapshot(counter::read(info.csr), "instructions");
+
+    // a counter started from the snapshot takes its value from `counter_values`, not from `initial_value`
+    let start_idx = check_ok!(
+        sbi::pmu_counter_config_matching(fw_base, counter::all_counters(num_counters - fw_base), 0, sbi::EVENT_FW_IPI_SENT, 0),
+        "counter_config_matching ipi_sent from snapshot"
+    );
+    unsafe { write_volatile(&mut SHMEM.0[COUNTER_VALUES], SNAPSHOT_START) };
+    let start_flags = sbi::START_FLAG_INIT_SNAPSHOT | sbi::START_FLAG_SET_INIT_VALUE;
+    check_ok!(sbi::pmu_counter_start(start_idx, 1, start_flags, 7), "counter_start from snapshot");
+    let value = check_ok!(sbi::pmu_counter_fw_read(start_idx), "counter_fw_read started from snapshot");
+    check!(value as u64 == SNAPSHOT_START, "counter started from snapshot reads {}, expected {}", value, SNAPSHOT_START);
+    check_ok!(sbi::pmu_counter_stop(start_idx, 1, sbi::STOP_FLAG_RESET), "counter_stop started from snapshot");
+
+    check_ok!(
+        sbi::pmu_snapshot_set_shm(usize::MAX, usize::MAX, 0),
+        "snapshot_set_shm disable"
+    );
+    println!("<< PMU-test: Snapshot shared memory passed");
+}
+
+// neither counter can overflow here; only the low XLEN bits are compared on RV32
+fn check_snapshot(expected: usize, what: &str) {
+    let (overflow, value) = unsafe {
+        (
+            read_volatile(&SHMEM.0[OVERFLOW_BITMAP]),
+            read_volatile(&SHMEM.0[COUNTER_VALUES]),
+        )
+    };
+    check!(overflow == 0, "{} snapshot overflow bitmap is {:#x}, expected 0", what, overflow);
+    check!(
+        value as usize == expected,
+        "{} snapshot value is {}, expected {}",
+        what,
+        value,
+        expected
+    );
+}
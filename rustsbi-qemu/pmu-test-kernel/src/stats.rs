@@ -0,0 +1,64 @@
+// SBI call statistics of RustSBI's firmware specific extension: every call is counted by its extension and
+// function IDs, and supervisor copies the table out, optionally clearing it. Firmware built without the
+// `call-stats` feature does not support the call, and the test is skipped
+
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED};
+
+const CALLS: u64 = 16;
+const CAPACITY: usize = 8;
+// FID of the call statistics read call, which counts itself
+const FUNCTION_STATS_READ: usize = 0xC;
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct StatsEntry {
+    extension: usize,
+    function: usize,
+    count: u64,
+}
+
+static mut TABLE: [StatsEntry; CAPACITY] = [StatsEntry { extension: 0, function: 0, count: 0 }; CAPACITY];
+
+// calls of a function in the first `len` entries, zero if it has no entry
+fn calls(len: usize, extension: usize, function: usize) -> u64 {
+    let table = unsafe { &TABLE[..len] };
+    table
+        .iter()
+        .find(|entry| entry.extension == extension && entry.function == function)
+        .map_or(0, |entry| entry.count)
+}
+
+pub fn run() {
+    println!(">> PMU-test: Testing SBI call statistics");
+    let buf = unsafe { TABLE.as_ptr() as usize };
+    // calls made by earlier tests are cleared first; printing would count console calls, so nothing is
+    // printed until the table is checked
+    let ret = sbi::rustsbi_stats_read(buf, 0, CAPACITY, sbi::RUSTSBI_STATS_READ_CLEAR);
+    if ret.error == SBI_ERR_NOT_SUPPORTED {
+        println!("<< PMU-test: Call statistics not built into the firmware, skipped");
+        println!("<< PMU-test: SBI call statistics passed");
+        return;
+    }
+    check_ok!(ret, "rustsbi_stats_read clear");
+    for _ in 0..CALLS {
+        check_ok!(sbi::pmu_num_counters(), "num_counters");
+    }
+    check_err!(sbi::rustsbi_stats_read(buf + 4, 0, CAPACITY, 0), SBI_ERR_INVALID_PARAM, "rustsbi_stats_read misaligned buffer");
+    check_err!(sbi::rustsbi_stats_read(buf, 0, CAPACITY, 1 << 1), SBI_ERR_INVALID_PARAM, "rustsbi_stats_read reserved flags");
+
+    // rejected calls are counted as well, and so is the read itself
+    let len = check_ok!(sbi::rustsbi_stats_read(buf, 0, CAPACITY, sbi::RUSTSBI_STATS_READ_CLEAR), "rustsbi_stats_read");
+    check!(len == 2, "{} functions called since the table was cleared, expected 2", len);
+    let num_counters = calls(len, sbi::EXTENSION_PMU, FUNCTION_PMU_NUM_COUNTERS);
+    check!(num_counters == CALLS, "num_counters counted {} times, expected {}", num_counters, CALLS);
+    let reads = calls(len, sbi::EXTENSION_RUSTSBI, FUNCTION_STATS_READ);
+    check!(reads == 3, "rustsbi_stats_read counted {} times, expected 3", reads);
+
+    // only the read itself is counted after clearing
+    let len = check_ok!(sbi::rustsbi_stats_read(buf, 0, CAPACITY, 0), "rustsbi_stats_read after clearing");
+    check!(len == 1, "{} functions called after clearing, expected 1", len);
+    let reads = calls(len, sbi::EXTENSION_RUSTSBI, FUNCTION_STATS_READ);
+    check!(reads == 1, "rustsbi_stats_read counted {} times after clearing, expected 1", reads);
+    println!("<< PMU-test: SBI call statistics passed");
+}
@@ -0,0 +1,66 @@
+// Allocating every counter until none is left, then freeing and allocating them again
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_FAILED, SBI_ERR_NOT_SUPPORTED};
+
+const ROUNDS: usize = 4;
+// QEMU counts DTLB read misses with this selector; raw events match any programmable counter
+const RAW_EVENT_DATA: u64 = 0x10019;
+
+// events that together can occupy every counter except `time`: cycles and instructions take the fixed
+// counters, raw events the programmable ones and a firmware event the firmware counters
+const EVENTS: &[(usize, u64)] = &[
+    (sbi::EVENT_HW_CPU_CYCLES, 0),
+    (sbi::EVENT_HW_INSTRUCTIONS, 0),
+    (sbi::EVENT_HW_RAW, RAW_EVENT_DATA),
+    (sbi::EVENT_FW_SET_TIMER, 0),
+];
+
+pub fn run() {
+    println!(">> PMU-test: Testing counter exhaustion and reallocation");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    for round in 0..ROUNDS {
+        let mut count = 0;
+        // `counter_idx_mask` is only XLEN bits wide, on RV32 the counters do not fit in a single mask
+        for base in (0..num_counters).step_by(usize::BITS as usize) {
+            let allocated = allocate_all(base, counter::all_counters(num_counters - base));
+            count += allocated.count_ones() as usize;
+            check_ok!(
+                sbi::pmu_counter_stop(base, allocated, sbi::STOP_FLAG_RESET),
+                "counter_stop all allocated counters"
+            );
+        }
+        // the `time` counter can never be allocated
+        check!(
+            count == num_counters - 1,
+            "round {}: allocated {} counters, expected {}",
+            round,
+            count,
+            num_counters - 1
+        );
+    }
+    println!(
+        "<< PMU-test: Allocated and freed {} counters {} times",
+        num_counters - 1,
+        ROUNDS
+    );
+}
+
+// config-match every event until no counter is left for it, returns the bitmap of allocated counters
+// relative to `base`
+fn allocate_all(base: usize, mask: usize) -> usize {
+    let mut allocated = 0;
+    for &(event_idx, event_data) in EVENTS {
+        loop {
+            let ret = sbi::pmu_counter_config_matching(base, mask, sbi::CFG_FLAG_AUTO_START, event_idx, event_data);
+            // counters for the event are all in use, or none of them is in the set
+            if ret.error == SBI_ERR_FAILED || ret.error == SBI_ERR_NOT_SUPPORTED {
+                break;
+            }
+            let idx = check_ok!(ret, "counter_config_matching") - base;
+            check!(allocated & (1 << idx) == 0, "counter {} allocated twice", base + idx);
+            allocated |= 1 << idx;
+        }
+    }
+    allocated
+}
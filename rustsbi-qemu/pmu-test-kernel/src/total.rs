@@ -0,0 +1,77 @@
+// Counting a firmware event over all harts in one call of RustSBI's firmware specific extension,
+// with counters on both harts configured through the remote control call
+
+use crate::counter;
+use crate::remote::{self, OPERATION_CONFIG, OPERATION_STOP};
+use crate::sbi::{self, SBI_ERR_INVALID_PARAM};
+use crate::smp;
+use core::ptr::read_volatile;
+
+const ROUNDS: usize = 8;
+const MAX_HARTS: usize = 8;
+
+// one 64-bit value for each hart; the test kernel runs without paging, so the address of this buffer
+// is its physical address
+static mut VALUES: [u64; MAX_HARTS] = [0; MAX_HARTS];
+
+fn total() -> (usize, [u64; MAX_HARTS]) {
+    let buf = unsafe { VALUES.as_ptr() as usize };
+    let total = check_ok!(
+        sbi::rustsbi_pmu_firmware_event_total(sbi::EVENT_FW_IPI_RECEIVED, buf, 0, MAX_HARTS),
+        "firmware_event_total ipi_received"
+    );
+    (total, unsafe { read_volatile(&VALUES) })
+}
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing firmware event total over harts");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let fw_idx = counter::first_firmware_counter(num_counters);
+    let partner = smp::secondary_harts().trailing_zeros() as usize;
+    let both = (1 << hartid) | (1 << partner);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    check_ok!(
+        remote::control_counter(both, OPERATION_CONFIG, fw_idx, flags, sbi::EVENT_FW_IPI_RECEIVED),
+        "remote config ipi_received"
+    );
+
+    // IPIs pending on a hart are received once, so each round waits until both harts have received theirs
+    for round in 1..=ROUNDS {
+        check_ok!(sbi::send_ipi(&both, 0), "send_ipi");
+        loop {
+            let (_, values) = total();
+            if values[hartid] == round as u64 && values[partner] == round as u64 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    // the IPIs sent to this hart are left pending, supervisor software interrupt is not used here
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+    let (sum, values) = total();
+    check!(sum == 2 * ROUNDS, "total of ipi_received is {}, expected {}", sum, 2 * ROUNDS);
+    for (i, &value) in values.iter().enumerate() {
+        let expected = if both & (1 << i) != 0 { ROUNDS as u64 } else { 0 };
+        check!(value == expected, "hart {} received {} IPIs, expected {}", i, value, expected);
+    }
+    // without a buffer only the total is returned
+    let sum = check_ok!(sbi::rustsbi_pmu_firmware_event_total(sbi::EVENT_FW_IPI_RECEIVED, 0, 0, 0), "firmware_event_total");
+    check!(sum == 2 * ROUNDS, "total without a buffer is {}, expected {}", sum, 2 * ROUNDS);
+
+    check_err!(
+        sbi::rustsbi_pmu_firmware_event_total(sbi::EVENT_HW_CPU_CYCLES, 0, 0, 0),
+        SBI_ERR_INVALID_PARAM,
+        "firmware_event_total of a hardware event"
+    );
+    let misaligned = unsafe { VALUES.as_ptr() as usize } + 4;
+    check_err!(
+        sbi::rustsbi_pmu_firmware_event_total(sbi::EVENT_FW_IPI_RECEIVED, misaligned, 0, 1),
+        SBI_ERR_INVALID_PARAM,
+        "firmware_event_total with a misaligned buffer"
+    );
+    check_ok!(
+        remote::control_counter(both, OPERATION_STOP, fw_idx, sbi::STOP_FLAG_RESET, 0),
+        "remote stop ipi_received"
+    );
+    println!("<< PMU-test: Firmware event total over harts passed");
+}
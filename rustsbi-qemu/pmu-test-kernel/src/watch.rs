@@ -0,0 +1,97 @@
+// Watchpoints on firmware counters through the counter watch call of RustSBI's firmware specific extension:
+// once the counter reaches the threshold, the firmware raises a supervisor software interrupt, or writes the
+// counter value into shared memory, and disarms the watchpoint
+
+use crate::counter;
+use crate::sbi::{self, SBI_ERR_DENIED, SBI_ERR_INVALID_PARAM};
+use core::ptr::read_volatile;
+
+const THRESHOLD: usize = 4;
+const SIP_SSIP: usize = 1 << 1;
+
+static mut NOTIFIED: [u64; 1] = [0];
+
+// supervisor software interrupts stay disabled, the pending bit is read from `sip`
+fn ssip_pending() -> bool {
+    let sip: usize;
+    unsafe { asm!("csrr {}, sip", out(reg) sip) };
+    sip & SIP_SSIP != 0
+}
+
+fn clear_ssip() {
+    unsafe { asm!("csrc sip, {}", in(reg) SIP_SSIP) };
+}
+
+fn notified() -> u64 {
+    unsafe { read_volatile(NOTIFIED.as_ptr()) }
+}
+
+pub fn run(hartid: usize) {
+    println!(">> PMU-test: Testing counter watchpoints");
+    let num_counters = check_ok!(sbi::pmu_num_counters(), "num_counters");
+    let base = counter::first_firmware_counter(num_counters);
+    let mask = counter::all_counters(num_counters - base);
+    let flags = sbi::CFG_FLAG_CLEAR_VALUE | sbi::CFG_FLAG_AUTO_START;
+    let idx = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, flags, sbi::EVENT_FW_SFENCE_VMA_SENT, 0),
+        "counter_config_matching sfence_vma_sent"
+    );
+    // a remote fence sent to this hart alone is counted once, and raises no supervisor software interrupt itself
+    let target = 1 << hartid;
+    let fence = || {
+        check_ok!(sbi::remote_sfence_vma(&target, 0, 0, usize::MAX), "remote_sfence_vma");
+    };
+
+    clear_ssip();
+    check_ok!(sbi::rustsbi_pmu_counter_watch(idx, THRESHOLD as u64, 0, 0, 0), "rustsbi_pmu_counter_watch");
+    for count in 1..THRESHOLD {
+        fence();
+        check!(!ssip_pending(), "supervisor software interrupt raised at count {}, threshold {}", count, THRESHOLD);
+    }
+    fence();
+    check!(ssip_pending(), "no supervisor software interrupt at threshold {}", THRESHOLD);
+    clear_ssip();
+    // the watchpoint is disarmed once reached
+    fence();
+    check!(!ssip_pending(), "supervisor software interrupt raised again past the threshold");
+
+    // through shared memory, with the counter value when the threshold is reached
+    let shmem = unsafe { NOTIFIED.as_ptr() as usize };
+    let threshold = check_ok!(sbi::pmu_counter_fw_read(idx), "counter_fw_read sfence_vma_sent") as u64 + 2;
+    check_ok!(
+        sbi::rustsbi_pmu_counter_watch(idx, threshold, sbi::RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, shmem, 0),
+        "rustsbi_pmu_counter_watch shared memory"
+    );
+    fence();
+    check!(notified() == 0, "shared memory notified with {} below threshold {}", notified(), threshold);
+    fence();
+    check!(notified() == threshold, "shared memory notified with {}, expected {}", notified(), threshold);
+    check!(!ssip_pending(), "supervisor software interrupt raised for a shared memory watchpoint");
+
+    // a threshold of zero disarms the watchpoint
+    check_ok!(sbi::rustsbi_pmu_counter_watch(idx, threshold + 1, 0, 0, 0), "rustsbi_pmu_counter_watch");
+    check_ok!(sbi::rustsbi_pmu_counter_watch(idx, 0, 0, 0, 0), "rustsbi_pmu_counter_watch disarm");
+    fence();
+    fence();
+    check!(!ssip_pending(), "supervisor software interrupt raised by a disarmed watchpoint");
+
+    check_err!(sbi::rustsbi_pmu_counter_watch(0, 1, 0, 0, 0), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_counter_watch hardware counter");
+    check_err!(sbi::rustsbi_pmu_counter_watch(idx, 1, 1 << 1, 0, 0), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_counter_watch reserved flag");
+    check_err!(
+        sbi::rustsbi_pmu_counter_watch(idx, 1, sbi::RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, shmem + 4, 0),
+        SBI_ERR_INVALID_PARAM,
+        "rustsbi_pmu_counter_watch misaligned shared memory"
+    );
+    check_ok!(sbi::pmu_counter_stop(idx, 1, sbi::STOP_FLAG_RESET), "counter_stop sfence_vma_sent");
+    check_err!(sbi::rustsbi_pmu_counter_watch(idx, 1, 0, 0, 0), SBI_ERR_INVALID_PARAM, "rustsbi_pmu_counter_watch unbound counter");
+
+    // the protection test coarsened `fence_i_sent`, whose exact values a notification would give away
+    let protected = check_ok!(
+        sbi::pmu_counter_config_matching(base, mask, 0, sbi::EVENT_FW_FENCE_I_SENT, 0),
+        "counter_config_matching fence_i_sent"
+    );
+    check_err!(sbi::rustsbi_pmu_counter_watch(protected, 1, 0, 0, 0), SBI_ERR_DENIED, "rustsbi_pmu_counter_watch protected event");
+    check_ok!(sbi::pmu_counter_start(protected, 1, 0, 0), "counter_start fence_i_sent");
+    check_ok!(sbi::pmu_counter_stop(protected, 1, sbi::STOP_FLAG_RESET), "counter_stop fence_i_sent");
+    println!("<< PMU-test: Counter watchpoints passed");
+}
@@ -1,8 +1,42 @@
 #![allow(dead_code)]
 
 // 这部分其实是运行时提供的，不应该做到实现库里面
+use core::sync::atomic::{AtomicUsize, Ordering};
 use rustsbi::SbiRet;
 
+// 核间请求；发送方在目标核的请求位图里置位，再触发目标核的机器软件中断
+const REQUEST_IPI: usize = 1 << 0;
+const REQUEST_FENCE_I: usize = 1 << 1;
+const REQUEST_SFENCE_VMA: usize = 1 << 2;
+const REQUEST_SFENCE_VMA_ASID: usize = 1 << 3;
+// 其它核要求本核配置、启动或停止计数器，请求内容在RustSBI的信箱里
+const REQUEST_PMU: usize = 1 << 4;
+
+// 每个核等待处理的请求，最多8个核
+static REQUESTS: [AtomicUsize; 8] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+// 机器时钟由S层的定时器和PMU采样共用；每个核记录两个截止时间，mtimecmp取较早的一个
+struct Deadlines {
+    supervisor: u64,
+    sample: u64,
+}
+
+static DEADLINES: spin::Mutex<[Deadlines; 8]> = spin::Mutex::new([NO_DEADLINES; 8]);
+
+const NO_DEADLINES: Deadlines = Deadlines {
+    supervisor: u64::MAX,
+    sample: u64::MAX,
+};
+
 pub struct Clint {
     base: usize,
 }
@@ -28,6 +62,27 @@ impl Clint {
         }
     }
 
+    // 按本核较早的截止时间设置mtimecmp；两个截止时间都没有时关闭机器时钟中断
+    fn program_timer(&mut self, hart_id: usize, deadlines: &Deadlines) {
+        let deadline = deadlines.supervisor.min(deadlines.sample);
+        self.set_timer(hart_id, deadline);
+        if deadline == u64::MAX {
+            unsafe { riscv::register::mie::clear_mtimer() };
+        }
+    }
+
+    // 处理本核的机器时钟中断，在PMU采样之后调用；S层的截止时间到了才转发为S层的时钟中断
+    pub fn handle_timer_interrupt(&mut self) {
+        let hart_id = riscv::register::mhartid::read();
+        let mut deadlines = DEADLINES.lock();
+        let deadlines = &mut deadlines[hart_id];
+        if self.get_mtime() >= deadlines.supervisor {
+            unsafe { riscv::register::mip::set_stimer() };
+            deadlines.supervisor = u64::MAX;
+        }
+        self.program_timer(hart_id, deadlines);
+    }
+
     pub fn send_soft(&mut self, hart_id: usize) {
         unsafe {
             let base = self.base as *mut u8;
@@ -41,9 +96,57 @@ impl Clint {
             core::ptr::write_volatile((base as *mut u32).add(hart_id), 0);
         }
     }
+
+    // 处理本核收到的核间请求，在机器软件中断里调用
+    pub fn handle_soft_interrupt(&mut self) {
+        use rustsbi::pmu::{
+            count_firmware_event, SBI_PMU_FW_FENCE_I_RECEIVED, SBI_PMU_FW_IPI_RECEIVED,
+            SBI_PMU_FW_SFENCE_VMA_ASID_RECEIVED, SBI_PMU_FW_SFENCE_VMA_RECEIVED,
+        };
+        let hart_id = riscv::register::mhartid::read();
+        // 先清除软件中断，之后到达的请求会再次触发中断
+        self.clear_soft(hart_id);
+        let requests = REQUESTS[hart_id].load(Ordering::Acquire);
+        if requests & REQUEST_FENCE_I != 0 {
+            unsafe { asm!("fence.i") };
+            count_firmware_event(SBI_PMU_FW_FENCE_I_RECEIVED);
+        }
+        // 不区分地址范围和ASID，全部刷新
+        if requests & (REQUEST_SFENCE_VMA | REQUEST_SFENCE_VMA_ASID) != 0 {
+            unsafe { asm!("sfence.vma") };
+        }
+        if requests & REQUEST_SFENCE_VMA != 0 {
+            count_firmware_event(SBI_PMU_FW_SFENCE_VMA_RECEIVED);
+        }
+        if requests & REQUEST_SFENCE_VMA_ASID != 0 {
+            count_firmware_event(SBI_PMU_FW_SFENCE_VMA_ASID_RECEIVED);
+        }
+        if requests & REQUEST_PMU != 0 {
+            rustsbi::pmu::handle_pmu_ipi();
+        }
+        if requests & REQUEST_IPI != 0 {
+            // 转发给S层，表现为S层的软件中断
+            unsafe { riscv::register::mip::set_ssoft() };
+            count_firmware_event(SBI_PMU_FW_IPI_RECEIVED);
+        }
+        REQUESTS[hart_id].fetch_and(!requests, Ordering::AcqRel);
+    }
+
+    // 向hart_mask里的核发送请求
+    //
+    // 不等待目标核处理完成：RustSBI调用远程栅栏时持有锁，其它核如果同时在等这把锁，就没有机会响应请求，会造成死锁。
+    // 目标核运行在S态时会立即响应机器软件中断，发给本核的请求在mret之后立即处理
+    fn send_request(&mut self, hart_mask: HartMask, request: usize) {
+        for i in 0..=self.max_hart_id() {
+            if hart_mask.has_bit(i) {
+                REQUESTS[i].fetch_or(request, Ordering::AcqRel);
+                self.send_soft(i);
+            }
+        }
+    }
 }
 
-use rustsbi::{HartMask, Ipi, Timer};
+use rustsbi::{Fence, HartMask, Ipi, Timer};
 
 impl Ipi for Clint {
     fn max_hart_id(&self) -> usize {
@@ -52,11 +155,26 @@ impl Ipi for Clint {
     }
 
     fn send_ipi_many(&mut self, hart_mask: HartMask) -> SbiRet {
-        for i in 0..=self.max_hart_id() {
-            if hart_mask.has_bit(i) {
-                self.send_soft(i);
-            }
-        }
+        self.send_request(hart_mask, REQUEST_IPI);
+        SbiRet::ok(0)
+    }
+}
+
+impl Fence for Clint {
+    fn remote_fence_i(&mut self, hart_mask: HartMask) -> SbiRet {
+        self.send_request(hart_mask, REQUEST_FENCE_I);
+        SbiRet::ok(0)
+    }
+
+    fn remote_sfence_vma(&mut self, hart_mask: HartMask, start_addr: usize, size: usize) -> SbiRet {
+        drop((start_addr, size));
+        self.send_request(hart_mask, REQUEST_SFENCE_VMA);
+        SbiRet::ok(0)
+    }
+
+    fn remote_sfence_vma_asid(&mut self, hart_mask: HartMask, start_addr: usize, size: usize, asid: usize) -> SbiRet {
+        drop((start_addr, size, asid));
+        self.send_request(hart_mask, REQUEST_SFENCE_VMA_ASID);
         SbiRet::ok(0)
     }
 }
@@ -64,6 +182,37 @@ impl Ipi for Clint {
 impl Timer for Clint {
     fn set_timer(&mut self, time_value: u64) {
         let this_mhartid = riscv::register::mhartid::read();
-        self.set_timer(this_mhartid, time_value);
+        let mut deadlines = DEADLINES.lock();
+        deadlines[this_mhartid].supervisor = time_value;
+        self.program_timer(this_mhartid, &deadlines[this_mhartid]);
+    }
+}
+
+impl rustsbi::pmu::PmuIpi for Clint {
+    // MAX_HART_ID存的是设备树里核的数量；发给不存在的核的请求永远等不到结果，这里不能多算一个
+    fn max_hart_id(&self) -> usize {
+        crate::count_harts::MAX_HART_ID.lock().saturating_sub(1)
+    }
+
+    // 发送方会等待目标核处理完成，目标核在机器软件中断里处理
+    fn send_pmu_ipi(&mut self, hartid: usize) {
+        REQUESTS[hartid].fetch_or(REQUEST_PMU, Ordering::AcqRel);
+        self.send_soft(hartid);
+    }
+}
+
+impl rustsbi::pmu::SampleTimer for Clint {
+    fn now(&self) -> u64 {
+        self.get_mtime()
+    }
+
+    fn set_sample_deadline(&mut self, deadline: Option<u64>) {
+        let this_mhartid = riscv::register::mhartid::read();
+        let mut deadlines = DEADLINES.lock();
+        deadlines[this_mhartid].sample = deadline.unwrap_or(u64::MAX);
+        self.program_timer(this_mhartid, &deadlines[this_mhartid]);
+        if deadline.is_some() {
+            unsafe { riscv::register::mie::set_mtimer() };
+        }
     }
 }
@@ -4,15 +4,22 @@ use core::{
     ops::{Generator, GeneratorState},
     pin::Pin,
 };
-use riscv::register::{mie, mip};
+use riscv::register::mcycle;
+use rustsbi::pmu::{
+    count_machine_interrupt, RUSTSBI_FW_PMU_IPI_CYCLES, RUSTSBI_FW_PMU_MEXT_INTERRUPTS,
+    RUSTSBI_FW_PMU_MSOFT_INTERRUPTS, RUSTSBI_FW_PMU_MTIMER_INTERRUPTS, RUSTSBI_FW_PMU_TRAP_CYCLES,
+};
 
 pub fn execute_supervisor(supervisor_mepc: usize, a0: usize, a1: usize) -> ! {
     let mut rt = Runtime::new_sbi_supervisor(supervisor_mepc, a0, a1);
     loop {
-        match Pin::new(&mut rt).resume(()) {
+        let trap = Pin::new(&mut rt).resume(());
+        // 从陷入M层到返回S层，处理这次陷入花费的周期数计入固件平台事件
+        let start = mcycle::read64();
+        match trap {
             GeneratorState::Yielded(MachineTrap::SbiCall()) => {
                 let ctx = rt.context_mut();
-                let param = [ctx.a0, ctx.a1, ctx.a2, ctx.a3, ctx.a4];
+                let param = [ctx.a0, ctx.a1, ctx.a2, ctx.a3, ctx.a4, ctx.a5];
                 let ans = rustsbi::ecall(ctx.a7, ctx.a6, param);
                 ctx.a0 = ans.error;
                 ctx.a1 = ans.value;
@@ -32,10 +39,28 @@ pub fn execute_supervisor(supervisor_mepc: usize, a0: usize, a1: usize) -> ! {
                     }
                 }
             }
-            GeneratorState::Yielded(MachineTrap::MachineTimer()) => unsafe {
-                mip::set_stimer();
-                mie::clear_mtimer();
-            },
+            GeneratorState::Yielded(MachineTrap::MachineTimer()) => {
+                // 先计数再处理，中断风暴时即使处理出错也能在计数器上看到
+                count_machine_interrupt(RUSTSBI_FW_PMU_MTIMER_INTERRUPTS);
+                // 借助时钟中断定期检测计数器回绕，并轮换分时复用的事件
+                rustsbi::pmu::poll_pmu_overflow();
+                rustsbi::pmu::rotate_pmu_multiplex();
+                // 时钟中断可能是PMU采样的截止时间到了，也可能是S层的
+                rustsbi::pmu::pmu_sample_tick();
+                crate::clint::Clint::new(0x2000000 as *mut u8).handle_timer_interrupt();
+            }
+            GeneratorState::Yielded(MachineTrap::MachineSoft()) => {
+                // 其它核发来的IPI和远程栅栏请求
+                count_machine_interrupt(RUSTSBI_FW_PMU_MSOFT_INTERRUPTS);
+                let ipi_start = mcycle::read64();
+                crate::clint::Clint::new(0x2000000 as *mut u8).handle_soft_interrupt();
+                rustsbi::pmu::count_firmware_cycles(RUSTSBI_FW_PMU_IPI_CYCLES, mcycle::read64().wrapping_sub(ipi_start));
+            }
+            GeneratorState::Yielded(MachineTrap::MachineExternal()) => {
+                count_machine_interrupt(RUSTSBI_FW_PMU_MEXT_INTERRUPTS);
+                // 本固件不处理任何M层外部中断源，屏蔽它，以免未认领的中断反复陷入
+                unsafe { riscv::register::mie::clear_mext() };
+            }
             GeneratorState::Complete(()) => {
                 use rustsbi::Reset;
                 crate::test_device::Reset.system_reset(
@@ -44,12 +69,14 @@ pub fn execute_supervisor(supervisor_mepc: usize, a0: usize, a1: usize) -> ! {
                 );
             }
         }
+        rustsbi::pmu::count_firmware_cycles(RUSTSBI_FW_PMU_TRAP_CYCLES, mcycle::read64().wrapping_sub(start));
     }
 }
 
 #[inline]
 unsafe fn get_vaddr_u32(vaddr: usize) -> u32 {
     let mut ans: u32;
+    #[cfg(target_pointer_width = "64")]
     asm!("
         li      {tmp}, (1 << 17)
         csrrs   {tmp}, mstatus, {tmp}
@@ -60,6 +87,18 @@ unsafe fn get_vaddr_u32(vaddr: usize) -> u32 {
         vaddr = in(reg) vaddr,
         ans = lateout(reg) ans
     );
+    // RV32没有lwu指令，lw读到的就是完整的32位
+    #[cfg(target_pointer_width = "32")]
+    asm!("
+        li      {tmp}, (1 << 17)
+        csrrs   {tmp}, mstatus, {tmp}
+        lw      {ans}, 0({vaddr})
+        csrw    mstatus, {tmp}
+        ",
+        tmp = out(reg) _,
+        vaddr = in(reg) vaddr,
+        ans = lateout(reg) ans
+    );
     ans
 }
 
@@ -67,6 +106,12 @@ fn emulate_illegal_instruction(ctx: &mut SupervisorContext, ins: usize) -> bool
     if feature::emulate_rdtime(ctx, ins) {
         return true;
     }
+    if feature::emulate_scountovf(ctx, ins) {
+        return true;
+    }
+    if feature::emulate_hpmcounter(ctx, ins) {
+        return true;
+    }
     false
 }
 
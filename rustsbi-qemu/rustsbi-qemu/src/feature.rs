@@ -1,3 +1,20 @@
+mod emulate_hpmcounter;
 mod emulate_rdtime;
+mod emulate_scountovf;
 
+pub use emulate_hpmcounter::emulate_hpmcounter;
 pub use emulate_rdtime::emulate_rdtime;
+pub use emulate_scountovf::emulate_scountovf;
+
+use crate::runtime::SupervisorContext;
+
+#[inline]
+fn set_register_xi(ctx: &mut SupervisorContext, i: u8, data: usize) {
+    let registers = unsafe { &mut *(ctx as *mut _ as *mut [usize; 31]) };
+    assert!(i <= 31, "i should be valid register target");
+    if i == 0 {
+        // x0, don't modify
+        return;
+    }
+    registers[(i - 1) as usize] = data;
+}
@@ -0,0 +1,31 @@
+use super::set_register_xi;
+use crate::runtime::SupervisorContext;
+
+// mcounteren里的位被清除时，S态读cycle、instret或hpmcounter会触发非法指令异常：计数器停止后GenericPmu可能清除它的位，
+// 这里返回计数器停止时的值；固件不允许S态直接读取的计数器，返回和sbi_pmu_counter_fw_read相同的值，让直接读取计数器的
+// 旧内核也能工作。RV32上读高32位的cycleh、instreth和hpmcounterh同样处理。每次模拟都计入固件事件
+#[inline]
+pub fn emulate_hpmcounter(ctx: &mut SupervisorContext, ins: usize) -> bool {
+    // csrrs rd, csr, x0
+    if ins & 0x000FF07F != 0x00002073 {
+        return false;
+    }
+    let csr = ins >> 20;
+    let is_counter = match csr {
+        0xC00..=0xC1F => true,
+        0xC80..=0xC9F => usize::BITS == 32,
+        _ => false,
+    };
+    if !is_counter {
+        return false;
+    }
+    match rustsbi::pmu::emulate_counter_csr_read(csr) {
+        Some(value) => {
+            let rd = ((ins >> 7) & 0b1_1111) as u8;
+            set_register_xi(ctx, rd, value);
+            ctx.mepc = ctx.mepc.wrapping_add(4); // skip csrr instruction
+            true
+        }
+        None => false, // not emulated by the PMU, leave the exception to supervisor
+    }
+}
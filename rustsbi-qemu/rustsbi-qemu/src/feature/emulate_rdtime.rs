@@ -1,3 +1,4 @@
+use super::set_register_xi;
 use crate::clint;
 use crate::runtime::SupervisorContext;
 
@@ -10,18 +11,16 @@ pub fn emulate_rdtime(ctx: &mut SupervisorContext, ins: usize) -> bool {
         set_register_xi(ctx, rd, time_usize);
         ctx.mepc = ctx.mepc.wrapping_add(4); // skip rdtime instruction
         return true;
-    } else {
-        return false; // is not a rdtime instruction
     }
-}
-
-#[inline]
-fn set_register_xi(ctx: &mut SupervisorContext, i: u8, data: usize) {
-    let registers = unsafe { &mut *(ctx as *mut _ as *mut [usize; 31]) };
-    assert!(i <= 31, "i should be valid register target");
-    if i == 0 {
-        // x0, don't modify
-        return;
+    // RV32上还需要模拟rdtimeh，读取mtime的高32位
+    #[cfg(target_pointer_width = "32")]
+    if ins & 0xFFFFF07F == 0xC8102073 {
+        let rd = ((ins >> 7) & 0b1_1111) as u8;
+        let clint = clint::Clint::new(0x2000000 as *mut u8);
+        let time_hi = (clint.get_mtime() >> 32) as usize;
+        set_register_xi(ctx, rd, time_hi);
+        ctx.mepc = ctx.mepc.wrapping_add(4); // skip rdtimeh instruction
+        return true;
     }
-    registers[(i - 1) as usize] = data;
+    false // is not a rdtime instruction
 }
@@ -0,0 +1,17 @@
+use super::set_register_xi;
+use crate::runtime::SupervisorContext;
+
+// 没有Sscofpmf扩展时，读scountovf会触发非法指令异常，这里返回固件检测到的溢出位图
+#[inline]
+pub fn emulate_scountovf(ctx: &mut SupervisorContext, ins: usize) -> bool {
+    if ins & 0xFFFFF07F == 0xDA002073 {
+        // csrrs rd, scountovf, x0
+        let rd = ((ins >> 7) & 0b1_1111) as u8;
+        let bitmap = rustsbi::pmu::poll_pmu_overflow();
+        set_register_xi(ctx, rd, bitmap);
+        ctx.mepc = ctx.mepc.wrapping_add(4); // skip csrr instruction
+        return true;
+    } else {
+        return false; // is not a scountovf read instruction
+    }
+}
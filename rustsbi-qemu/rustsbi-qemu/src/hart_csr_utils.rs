@@ -115,7 +115,6 @@ fn print_medeleg() {
     );
 }
 
-#[cfg(target_pointer_width = "64")]
 #[inline]
 fn print_pmp() {
     let pmps = unsafe { pmps::<16>() };
@@ -14,6 +14,7 @@ mod feature;
 mod hart_csr_utils;
 mod ns16550a;
 mod runtime;
+mod shmem;
 mod test_device;
 mod pmu;
 
@@ -33,6 +34,19 @@ static mut HEAP_SPACE: [u8; SBI_HEAP_SIZE] = [0; SBI_HEAP_SIZE];
 #[global_allocator]
 static SBI_HEAP: LockedHeap<32> = LockedHeap::empty();
 
+// 调用记录器最多保存的记录数，每条记录占10个XLEN位的字
+#[cfg(feature = "trace-record")]
+const TRACE_RECORDS: usize = 128;
+
+// 计数器空闲的时限，QEMU上mtime的频率为10MHz，即100毫秒
+const IDLE_TIMEOUT: usize = 1_000_000;
+
+// QEMU把-kernel指定的镜像加载到这个地址；RV32和RV64的加载地址不同
+#[cfg(target_pointer_width = "64")]
+const SUPERVISOR_ENTRY: usize = 0x80200000;
+#[cfg(target_pointer_width = "32")]
+const SUPERVISOR_ENTRY: usize = 0x80400000;
+
 #[cfg_attr(not(test), panic_handler)]
 #[allow(unused)]
 fn panic(info: &PanicInfo) -> ! {
@@ -55,7 +69,11 @@ extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
         init_legacy_stdio();
         init_clint();
         init_test_device();
-        init_pmu();
+        init_pmu(dtb_pa);
+        #[cfg(feature = "trace-ring")]
+        init_trace_ring();
+        #[cfg(feature = "trace-record")]
+        init_trace_recorder();
         println!("[rustsbi] RustSBI version {}", rustsbi::VERSION);
         println!("{}", rustsbi::LOGO);
         println!(
@@ -66,11 +84,14 @@ extern "C" fn rust_main(hartid: usize, dtb_pa: usize) -> ! {
     }
     delegate_interrupt_exception();
     set_pmp();
+    set_mcounteren();
+    // 清除上次启动留下的计数器状态，并按平台设置mcounteren.TM
+    rustsbi::pmu::reset_pmu_hart();
     if hartid == 0 {
         hart_csr_utils::print_hart_csrs();
-        println!("[rustsbi] enter supervisor 0x80200000");
+        println!("[rustsbi] enter supervisor {:#x}", SUPERVISOR_ENTRY);
     }
-    execute::execute_supervisor(0x80200000, hartid, dtb_pa);
+    execute::execute_supervisor(SUPERVISOR_ENTRY, hartid, dtb_pa);
 }
 
 fn init_heap() {
@@ -94,6 +115,15 @@ fn init_clint() {
     let clint = clint::Clint::new(0x2000000 as *mut u8);
     use rustsbi::init_timer;
     init_timer(clint);
+    let clint = clint::Clint::new(0x2000000 as *mut u8);
+    use rustsbi::init_remote_fence;
+    init_remote_fence(clint);
+    // 没有Sscofpmf时，S层可以让固件借助机器时钟定期采样计数器
+    let clint = clint::Clint::new(0x2000000 as *mut u8);
+    rustsbi::pmu::init_pmu_sampler(clint);
+    // 性能分析工具可以在一个核上控制其它核的计数器
+    let clint = clint::Clint::new(0x2000000 as *mut u8);
+    rustsbi::pmu::init_pmu_ipi(clint);
 }
 
 fn init_test_device() {
@@ -101,8 +131,42 @@ fn init_test_device() {
     init_reset(test_device::Reset);
 }
 
-fn init_pmu() {
-    rustsbi::init_pmu(pmu::Pmu);
+fn init_pmu(dtb_pa: usize) {
+    let info = unsafe { pmu::fdt::parse(dtb_pa) };
+    // 在HS态运行虚拟机时，S层可以要求不计VS和VU态的事件
+    let hypervisor = riscv::register::misa::read().map_or(false, |misa| misa.has_extension('H'));
+    let profile = pmu::profile::select(&info.compatible);
+    println!("[rustsbi] PMU event profile: {}", profile.name);
+    (profile.enable)();
+    // S层传入的缓冲区地址在固件写入前都要检查，防止S层借固件改写固件自身或S层无权访问的内存
+    rustsbi::init_shared_memory(shmem::SupervisorMemory::new(info.memory));
+    let hardware = pmu::Hardware::new(info.sscofpmf, hypervisor, info.events, profile);
+    // S层可以通过RustSBI的固件扩展要求粗化指定事件的计数值，防御计时侧信道
+    let seed = riscv::register::mcycle::read();
+    // 已启动、但超过IDLE_TIMEOUT没有被S层通过SBI调用使用的计数器记为空闲；S层崩溃后留下的计数器可以一次性回收
+    // 停止的计数器在S态直接读取时保持停止时的值：cycle和instret由固件虚拟停止，硬件上仍在计数
+    let config = rustsbi::pmu::PmuConfig::new()
+        .idle_timeout(IDLE_TIMEOUT)
+        .stopped_read(rustsbi::pmu::StoppedRead::Frozen)
+        // 不允许S态直接读取的计数器，旧内核直接读取时由固件模拟
+        .hidden_read(rustsbi::pmu::HiddenRead::Emulate)
+        .armed(seed);
+    rustsbi::pmu::init_generic_pmu(hardware, config);
+    // 在S层启动前检查每个计数器能否计数，及早发现有问题的平台；自检后计数器都已释放
+    #[cfg(feature = "pmu-self-test")]
+    rustsbi::pmu::run_pmu_self_test();
+}
+
+// 跟踪记录保存在内存里，由S层通过RustSBI的固件扩展读出，避免串口输出干扰计数
+#[cfg(feature = "trace-ring")]
+fn init_trace_ring() {
+    rustsbi::trace::init_trace_sink(rustsbi::trace::TraceRing::new());
+}
+
+// 按调用顺序记录PMU调用，S层读出后依次重新发起，复现计数器分配的问题；记录放在堆上
+#[cfg(feature = "trace-record")]
+fn init_trace_recorder() {
+    rustsbi::trace::init_trace_sink(rustsbi::trace::TraceRecorder::new(TRACE_RECORDS));
 }
 
 // 委托终端；把S的中断全部委托给S层
@@ -124,6 +188,8 @@ fn delegate_interrupt_exception() {
         medeleg::set_instruction_fault();
         medeleg::set_load_fault();
         medeleg::set_store_fault();
+        // Sscofpmf的计数器溢出中断（LCOFI）交给S层处理；没有这个扩展时这一位是只读的零
+        asm!("csrs mideleg, {}", in(reg) 1 << 13);
         mie::set_mext();
         // 不打开mie::set_mtimer
         mie::set_msoft();
@@ -146,6 +212,18 @@ fn set_pmp() {
     };
 }
 
+// 允许S态直接读取cycle、instret和hpmcounter3到hpmcounter31；time仍然由固件模拟
+#[cfg(not(feature = "hide-hpmcounters"))]
+fn set_mcounteren() {
+    unsafe { asm!("csrw mcounteren, {}", in(reg) 0xFFFF_FFFDusize) };
+}
+
+// 只允许S态直接读取cycle和instret，读hpmcounter由固件模拟
+#[cfg(feature = "hide-hpmcounters")]
+fn set_mcounteren() {
+    unsafe { asm!("csrw mcounteren, {}", in(reg) 0b101usize) };
+}
+
 #[naked]
 #[link_section = ".text.entry"]
 #[export_name = "_start"]
@@ -1,53 +1,401 @@
-pub struct Pmu;
-
-const TEST_FAIL: u32 = 0x3333;
-const TEST_PASS: u32 = 0x5555;
-const TEST_RESET: u32 = 0x7777;
-
-impl rustsbi::Pmu for Pmu {
-    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value:u64) -> rustsbi::SbiRet{
-        rustsbi::SbiRet {
-            error: 0,
-            value: 0,
-        }
-    }
-    /// Stop or disable a set of counters on the calling HART. The `counter_idx_base`
-    ///and `counter_idx_mask` parameters represent the set of counters. The bit
-    ///definitions of the `stop_flags` parameter are shown in the below table.
-    /// 
-    /// # Flags
-    /// | Flag Name               | Bits       | Description
-    /// | SBI_PMU_STOP_FLAG_RESET | 0:0        | Reset the counter to event mapping.
-    /// | *RESERVED*              | 1:(XLEN-1) | All non-zero values are reserved
-    ///     
-    /// # Errors
-    /// 
-    /// | Error code              | Description
-    /// | SBI_SUCCESS             | counter stopped successfully.
-    /// | SBI_ERR_INVALID_PARAM   | some of the counters specified in parameters
-    ///                             are invalid.
-    /// | SBI_ERR_ALREADY_STOPPED | some of the counters specified in parameters
-    ///                             are already stopped.
-    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> rustsbi::SbiRet{
-        rustsbi::SbiRet {
-            error: 0,
-            value: 0,
-        }
-    }
-    /// | Function Name                   | SBI Version | FID | EID
-    /// | sbi_pmu_num_counters            | 0.3         | 0   | 0x504D55
-    /// | sbi_pmu_counter_get_info        | 0.3         | 1   | 0x504D55
-    /// | sbi_pmu_counter_config_matching | 0.3         | 2   | 0x504D55
-    /// | sbi_pmu_counter_start           | 0.3         | 3   | 0x504D55
-    /// | sbi_pmu_counter_stop            | 0.3         | 4   | 0x504D55
-    /// | sbi_pmu_counter_fw_read         | 0.3         | 5   | 0x504D55
-    /// Low bits is SBI implementation ID. The firmware specific SBI extensions are
-    /// for SBI implementations. It provides firmware specific SBI functions which
-    /// are defined in the external firmware specification.
-    fn pmu_counter_fw_read(&self, counter_idx: usize) -> rustsbi::SbiRet{
-        rustsbi::SbiRet {
-            error: 0,
-            value: 0,
+pub mod fdt;
+pub mod profile;
+
+use fdt::EventMap;
+use profile::Profile;
+use rustsbi::pmu::events::{event_idx, SBI_PMU_HW_CPU_CYCLES, SBI_PMU_HW_INSTRUCTIONS};
+use rustsbi::pmu::{
+    detect_counter_width, PmuPlatform, TimeCsr, COUNTER_CYCLE, COUNTER_INSTRET, COUNTER_TIME, EVENT_TYPE_HARDWARE_GENERAL,
+    EVENT_TYPE_HARDWARE_RAW, EVENT_TYPE_HARDWARE_RAW_V2, FIRST_HPM_COUNTER, RAW_EVENT_MASK,
+};
+
+// 通用硬件事件，event_idx的type为0
+const EVENT_HW_CPU_CYCLES: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CPU_CYCLES);
+const EVENT_HW_INSTRUCTIONS: usize = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_INSTRUCTIONS);
+
+// QEMU的PMU硬件描述；计数器的状态由rustsbi::pmu::GenericPmu管理
+//
+// 逻辑计数器编号和CSR编号一一对应：0是cycle，1是time，2是instret，3到31是hpmcounter3到hpmcounter31
+pub struct Hardware {
+    // 是否实现了Sscofpmf扩展；只有实现了这个扩展，mhpmevent里的特权级过滤位才有意义
+    sscofpmf: bool,
+    // 是否实现了H扩展；没有这个扩展时不存在VS和VU态，mhpmevent的VSINH和VUINH位是保留位
+    hypervisor: bool,
+    // 设备树描述的事件映射
+    event_map: EventMap,
+    // 平台的事件编码，设备树没有描述的事件按它查找
+    profile: &'static Profile,
+    // 实现了的计数器个数：cycle、time、instret，以及从hpmcounter3开始连续实现的可编程计数器
+    num_counters: usize,
+    // 每个计数器实际实现的位数，初始化时探测
+    widths: [u32; 32],
+}
+
+impl Hardware {
+    pub fn new(sscofpmf: bool, hypervisor: bool, event_map: EventMap, profile: &'static Profile) -> Hardware {
+        let num_counters = FIRST_HPM_COUNTER + detect_hpm_counters(&event_map);
+        let mut hardware = Hardware {
+            sscofpmf,
+            hypervisor,
+            event_map,
+            profile,
+            num_counters,
+            widths: [64; 32],
+        };
+        // 写入全1再读回，得到计数器的位数；time由固件模拟，不能写入；没有实现的计数器不能访问
+        for idx in (0..num_counters).filter(|&idx| idx != COUNTER_TIME) {
+            hardware.widths[idx] = detect_counter_width(&hardware, idx);
+        }
+        hardware
+    }
+
+    // 原始事件按照设备树的riscv,raw-event-to-mhpmcounters属性匹配计数器
+    fn raw_counter_can_monitor(&self, counter_idx: usize, raw_event: u64) -> bool {
+        match self.event_map.counters_for_raw_event(raw_event) {
+            Some(bitmap) => bitmap & (1 << counter_idx) != 0,
+            None => self.event_map.raw_event_to_mhpmcounters.is_empty(),
+        }
+    }
+}
+
+impl PmuPlatform for Hardware {
+    fn num_counters(&self) -> usize {
+        self.num_counters
+    }
+
+    fn has_sscofpmf(&self) -> bool {
+        self.sscofpmf
+    }
+
+    fn has_hypervisor(&self) -> bool {
+        self.hypervisor
+    }
+
+    // cycle和instret是固定功能的计数器，time不能用于事件计数
+    fn counter_can_monitor(&self, counter_idx: usize, event_idx: usize, event_data: u64) -> bool {
+        match counter_idx {
+            COUNTER_CYCLE => event_idx == EVENT_HW_CPU_CYCLES,
+            COUNTER_TIME => false,
+            COUNTER_INSTRET => event_idx == EVENT_HW_INSTRUCTIONS,
+            _ if event_idx >> 16 == EVENT_TYPE_HARDWARE_RAW => self.raw_counter_can_monitor(counter_idx, event_data & RAW_EVENT_MASK),
+            _ if event_idx >> 16 == EVENT_TYPE_HARDWARE_RAW_V2 => self.raw_counter_can_monitor(counter_idx, event_data),
+            _ => match self.event_map.counters_for_event(event_idx) {
+                Some(bitmap) => bitmap & (1 << counter_idx) != 0,
+                // 设备树没有描述任何映射时，所有hpmcounter都可以监测平台编码里有的任意事件
+                None => self.event_map.event_to_mhpmcounters.is_empty() && self.profile.mhpmevent(event_idx).is_some(),
+            },
+        }
+    }
+
+    // 优先使用设备树给出的取值，其次是平台的事件编码；都没有时使用QEMU的约定，直接以event_idx作为事件编号
+    fn mhpmevent_value(&self, event_idx: usize, event_data: u64) -> u64 {
+        drop(event_data);
+        self.event_map
+            .mhpmevent(event_idx)
+            .or_else(|| self.profile.mhpmevent(event_idx))
+            .unwrap_or(event_idx as u64)
+    }
+
+    // QEMU直接把mhpmevent的值作为事件编号，写入完整的event_data也没有问题
+    fn raw_event_v2(&self) -> bool {
+        true
+    }
+
+    fn hart_id(&self) -> usize {
+        riscv::register::mhartid::read()
+    }
+
+    fn read_mcounteren(&self) -> usize {
+        csr::read_mcounteren()
+    }
+
+    unsafe fn set_mcounteren(&self, bits: usize) {
+        csr::set_mcounteren(bits)
+    }
+
+    unsafe fn clear_mcounteren(&self, bits: usize) {
+        csr::clear_mcounteren(bits)
+    }
+
+    // QEMU的time由固件模拟：S态读time触发非法指令异常，由emulate_rdtime返回CLINT的mtime
+    fn time_csr(&self) -> TimeCsr {
+        TimeCsr::Emulated
+    }
+
+    // 快照里的时间戳取自CLINT的mtime，和S层读到的time是同一个时钟
+    fn read_mtime(&self) -> u64 {
+        crate::clint::Clint::new(0x2000000 as *mut u8).get_mtime()
+    }
+
+    fn read_mcountinhibit(&self) -> usize {
+        csr::read_mcountinhibit()
+    }
+
+    unsafe fn set_mcountinhibit(&self, bits: usize) {
+        csr::set_mcountinhibit(bits)
+    }
+
+    unsafe fn clear_mcountinhibit(&self, bits: usize) {
+        csr::clear_mcountinhibit(bits)
+    }
+
+    fn counter_width(&self, counter_idx: usize) -> u32 {
+        self.widths[counter_idx]
+    }
+
+    fn read_counter(&self, counter_idx: usize) -> u64 {
+        csr::read_mhpmcounter(counter_idx)
+    }
+
+    unsafe fn write_counter(&self, counter_idx: usize, value: u64) {
+        csr::write_mhpmcounter(counter_idx, value)
+    }
+
+    fn read_mhpmevent(&self, counter_idx: usize) -> u64 {
+        let value = csr::read_mhpmevent(counter_idx) as u64;
+        // RV32上Sscofpmf的溢出和过滤位在mhpmeventh里，没有这个扩展时mhpmeventh不存在
+        #[cfg(target_pointer_width = "32")]
+        if self.sscofpmf {
+            return value | (csr::read_mhpmeventh(counter_idx) as u64) << 32;
+        }
+        value
+    }
+
+    unsafe fn write_mhpmevent(&self, counter_idx: usize, value: u64) {
+        csr::write_mhpmevent(counter_idx, value as usize);
+        #[cfg(target_pointer_width = "32")]
+        if self.sscofpmf {
+            csr::write_mhpmeventh(counter_idx, (value >> 32) as usize);
+        }
+    }
+}
+
+// 探测从hpmcounter3开始连续实现了多少个可编程计数器，0到29个
+//
+// QEMU 7.2以后可以用pmu-num属性配置可编程计数器的个数，访问没有实现的计数器会产生非法指令异常；
+// 其它平台上没有实现的mhpmevent是只读的零。写入后能读回非零值的mhpmevent才算实现了；
+// 设备树描述了事件映射时，还要求计数器出现在映射里
+fn detect_hpm_counters(event_map: &EventMap) -> usize {
+    let described = event_map.all_counters().unwrap_or(u32::MAX);
+    (FIRST_HPM_COUNTER..32)
+        .take_while(|&idx| described & (1 << idx) != 0 && csr::probe_mhpmevent(idx))
+        .count()
+}
+
+mod csr {
+    // mhpmcounter和mhpmevent的编号必须在编译时确定，这里为每个计数器编号生成一个分支
+    macro_rules! for_counter_idx {
+        ($idx: expr, $op: ident, $($i: literal)+) => {
+            match $idx {
+                $($i => $op!($i),)+
+                _ => unreachable!("invalid counter index {}", $idx),
+            }
+        };
+    }
+
+    #[inline]
+    pub fn read_mcounteren() -> usize {
+        let bits: usize;
+        unsafe { asm!("csrr {}, mcounteren", out(reg) bits) };
+        bits
+    }
+
+    // 计数器停止和重新启动时，由GenericPmu设置或清除S态读取它们的权限
+    #[inline]
+    pub unsafe fn set_mcounteren(bits: usize) {
+        asm!("csrs mcounteren, {}", in(reg) bits);
+    }
+
+    #[inline]
+    pub unsafe fn clear_mcounteren(bits: usize) {
+        asm!("csrc mcounteren, {}", in(reg) bits);
+    }
+
+    #[inline]
+    pub fn read_mcountinhibit() -> usize {
+        let bits: usize;
+        unsafe { asm!("csrr {}, 0x320", out(reg) bits) };
+        bits
+    }
+
+    // 一条csrs指令同时停止位图里的所有计数器
+    #[inline]
+    pub unsafe fn set_mcountinhibit(bits: usize) {
+        asm!("csrs 0x320, {}", in(reg) bits);
+    }
+
+    #[inline]
+    pub unsafe fn clear_mcountinhibit(bits: usize) {
+        asm!("csrc 0x320, {}", in(reg) bits);
+    }
+
+    // 平头哥扩展的mxstatus，编号为0x7C0；只有平头哥的核心实现了这个CSR
+    #[inline]
+    pub unsafe fn clear_thead_mxstatus(bits: usize) {
+        asm!("csrc 0x7C0, {}", in(reg) bits);
+    }
+
+    // 写mhpmevent3到mhpmevent31，编号为0x323到0x33F；RV32上只写入低32位
+    #[inline]
+    pub unsafe fn write_mhpmevent(idx: usize, value: usize) {
+        macro_rules! op {
+            ($i: literal) => {
+                asm!("csrw {csr}, {0}", in(reg) value, csr = const 0x320 + $i)
+            };
+        }
+        for_counter_idx!(idx, op, 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+    }
+
+    // 写入再读回mhpmevent，随后清零；访问产生非法指令异常时由临时的异常处理跳过，
+    // 并恢复异常改变的mstatus和mepc。写入的是QEMU的周期事件编号，合法的取值才不会被WARL字段丢弃
+    pub fn probe_mhpmevent(idx: usize) -> bool {
+        let mut value: usize = 1;
+        macro_rules! op {
+            ($i: literal) => {
+                riscv::interrupt::free(|_| unsafe {
+                    asm!(
+                        "csrr   {mstatus}, mstatus",
+                        "csrr   {mepc}, mepc",
+                        "la     {tmp}, 2f",
+                        "csrrw  {mtvec}, mtvec, {tmp}",
+                        "csrw   {csr}, {value}",
+                        "csrr   {value}, {csr}",
+                        "csrw   {csr}, zero",
+                        "j      3f",
+                        // mtvec必须4字节对齐
+                        ".align 2",
+                        "2:",
+                        "la     {tmp}, 3f",
+                        "csrw   mepc, {tmp}",
+                        "li     {value}, 0",
+                        "mret",
+                        "3:",
+                        "csrw   mtvec, {mtvec}",
+                        "csrw   mepc, {mepc}",
+                        "csrw   mstatus, {mstatus}",
+                        value = inout(reg) value,
+                        tmp = out(reg) _,
+                        mtvec = out(reg) _,
+                        mepc = out(reg) _,
+                        mstatus = out(reg) _,
+                        csr = const 0x320 + $i,
+                    )
+                })
+            };
+        }
+        for_counter_idx!(idx, op, 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+        value != 0
+    }
+
+    // 读mhpmevent3到mhpmevent31，编号为0x323到0x33F；RV32上只读出低32位
+    #[inline]
+    pub fn read_mhpmevent(idx: usize) -> usize {
+        let value: usize;
+        macro_rules! op {
+            ($i: literal) => {
+                unsafe { asm!("csrr {0}, {csr}", out(reg) value, csr = const 0x320 + $i) }
+            };
+        }
+        for_counter_idx!(idx, op, 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+        value
+    }
+
+    // 写mhpmeventh3到mhpmeventh31，编号为0x723到0x73F；只有RV32上实现了Sscofpmf扩展时才存在
+    #[cfg(target_pointer_width = "32")]
+    #[inline]
+    pub unsafe fn write_mhpmeventh(idx: usize, value: usize) {
+        macro_rules! op {
+            ($i: literal) => {
+                asm!("csrw {csr}, {0}", in(reg) value, csr = const 0x720 + $i)
+            };
+        }
+        for_counter_idx!(idx, op, 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+    }
+
+    // 读mhpmeventh3到mhpmeventh31，编号为0x723到0x73F；只有RV32上实现了Sscofpmf扩展时才存在
+    #[cfg(target_pointer_width = "32")]
+    #[inline]
+    pub fn read_mhpmeventh(idx: usize) -> usize {
+        let value: usize;
+        macro_rules! op {
+            ($i: literal) => {
+                unsafe { asm!("csrr {0}, {csr}", out(reg) value, csr = const 0x720 + $i) }
+            };
+        }
+        for_counter_idx!(idx, op, 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+        value
+    }
+
+    // 读mcycle、minstret和mhpmcounter3到mhpmcounter31，编号为0xB00到0xB1F
+    #[cfg(target_pointer_width = "64")]
+    #[inline]
+    pub fn read_mhpmcounter(idx: usize) -> u64 {
+        let value: usize;
+        macro_rules! op {
+            ($i: literal) => {
+                unsafe { asm!("csrr {0}, {csr}", out(reg) value, csr = const 0xB00 + $i) }
+            };
+        }
+        for_counter_idx!(idx, op, 0 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+        value as u64
+    }
+
+    // RV32上高32位在mcycleh、minstreth和mhpmcounter3h到mhpmcounter31h，编号为0xB80到0xB9F；
+    // 两次读到的高位不同说明读的过程中低位发生了进位，需要重新读
+    #[cfg(target_pointer_width = "32")]
+    #[inline]
+    pub fn read_mhpmcounter(idx: usize) -> u64 {
+        let (mut hi, mut lo, mut hi2): (usize, usize, usize);
+        macro_rules! op {
+            ($i: literal) => {
+                unsafe {
+                    asm!(
+                        "csrr {hi}, {csrh}",
+                        "csrr {lo}, {csr}",
+                        "csrr {hi2}, {csrh}",
+                        hi = out(reg) hi, lo = out(reg) lo, hi2 = out(reg) hi2,
+                        csr = const 0xB00 + $i, csrh = const 0xB80 + $i,
+                    )
+                }
+            };
+        }
+        loop {
+            for_counter_idx!(idx, op, 0 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+            if hi == hi2 {
+                return ((hi as u64) << 32) | lo as u64;
+            }
+        }
+    }
+
+    // 写mcycle、minstret和mhpmcounter3到mhpmcounter31，编号为0xB00到0xB1F；mtime不能通过CSR写入
+    #[cfg(target_pointer_width = "64")]
+    #[inline]
+    pub unsafe fn write_mhpmcounter(idx: usize, value: u64) {
+        let value = value as usize;
+        macro_rules! op {
+            ($i: literal) => {
+                asm!("csrw {csr}, {0}", in(reg) value, csr = const 0xB00 + $i)
+            };
+        }
+        for_counter_idx!(idx, op, 0 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+    }
+
+    // RV32上先把低32位清零再写高32位，避免写入过程中低位进位到高位
+    #[cfg(target_pointer_width = "32")]
+    #[inline]
+    pub unsafe fn write_mhpmcounter(idx: usize, value: u64) {
+        let (hi, lo) = ((value >> 32) as usize, value as usize);
+        macro_rules! op {
+            ($i: literal) => {
+                asm!(
+                    "csrw {csr}, zero",
+                    "csrw {csrh}, {hi}",
+                    "csrw {csr}, {lo}",
+                    hi = in(reg) hi, lo = in(reg) lo,
+                    csr = const 0xB00 + $i, csrh = const 0xB80 + $i,
+                )
+            };
         }
+        for_counter_idx!(idx, op, 0 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
     }
 }
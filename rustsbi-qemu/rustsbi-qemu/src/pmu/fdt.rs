@@ -0,0 +1,170 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use device_tree::{DeviceTree, Node};
+use rustsbi::println;
+
+const DEVICE_TREE_MAGIC: u32 = 0xD00DFEED;
+
+#[repr(C)]
+struct DtbHeader {
+    magic: u32,
+    size: u32,
+}
+
+// 从设备树中读到的PMU信息
+pub struct PmuInfo {
+    // 是否实现了Sscofpmf扩展
+    pub sscofpmf: bool,
+    // pmu节点描述的事件映射
+    pub events: EventMap,
+    // 根节点和cpu节点的compatible属性，用于选择平台的事件编码
+    pub compatible: Vec<String>,
+    // memory节点描述的内存区域：<起始地址 大小>；S层登记的共享内存必须位于其中
+    pub memory: Vec<(u64, u64)>,
+}
+
+// 事件到mhpmevent取值和可用计数器的映射，属性的格式和OpenSBI的fdt_pmu一致；
+// 设备树中没有对应属性时为空，这时使用QEMU的默认约定
+#[derive(Default)]
+pub struct EventMap {
+    // riscv,event-to-mhpmevent：<事件编号 mhpmevent高32位 mhpmevent低32位>
+    pub event_to_mhpmevent: Vec<(u32, u64)>,
+    // riscv,event-to-mhpmcounters：<起始事件编号 结束事件编号 计数器位图>
+    pub event_to_mhpmcounters: Vec<(u32, u32, u32)>,
+    // riscv,raw-event-to-mhpmcounters：<选择值高32位 选择值低32位 掩码高32位 掩码低32位 计数器位图>
+    pub raw_event_to_mhpmcounters: Vec<(u64, u64, u32)>,
+}
+
+impl EventMap {
+    // 事件对应的mhpmevent取值
+    pub fn mhpmevent(&self, event_idx: usize) -> Option<u64> {
+        self.event_to_mhpmevent
+            .iter()
+            .find(|&&(idx, _)| idx as usize == event_idx)
+            .map(|&(_, value)| value)
+    }
+
+    // 能够监测事件的计数器位图；没有任何一项匹配时返回None
+    pub fn counters_for_event(&self, event_idx: usize) -> Option<u32> {
+        self.event_to_mhpmcounters
+            .iter()
+            .filter(|&&(start, end, _)| start as usize <= event_idx && event_idx <= end as usize)
+            .map(|&(_, _, bitmap)| bitmap)
+            .fold(None, |acc, bitmap| Some(acc.unwrap_or(0) | bitmap))
+    }
+
+    // 设备树提到的所有计数器的位图；设备树没有描述任何映射时返回None
+    pub fn all_counters(&self) -> Option<u32> {
+        self.event_to_mhpmcounters
+            .iter()
+            .map(|&(_, _, bitmap)| bitmap)
+            .chain(self.raw_event_to_mhpmcounters.iter().map(|&(_, _, bitmap)| bitmap))
+            .fold(None, |acc, bitmap| Some(acc.unwrap_or(0) | bitmap))
+    }
+
+    // 能够监测原始事件的计数器位图；没有任何一项匹配时返回None
+    pub fn counters_for_raw_event(&self, raw_event: u64) -> Option<u32> {
+        self.raw_event_to_mhpmcounters
+            .iter()
+            .filter(|&&(select, mask, _)| raw_event & mask == select)
+            .map(|&(_, _, bitmap)| bitmap)
+            .fold(None, |acc, bitmap| Some(acc.unwrap_or(0) | bitmap))
+    }
+}
+
+// 解析设备树，读取Sscofpmf扩展是否存在，以及/pmu节点的事件映射
+pub unsafe fn parse(dtb_pa: usize) -> PmuInfo {
+    let mut info = PmuInfo {
+        sscofpmf: false,
+        events: EventMap::default(),
+        compatible: Vec::new(),
+        memory: Vec::new(),
+    };
+    let header = &*(dtb_pa as *const DtbHeader);
+    if u32::from_be(header.magic) != DEVICE_TREE_MAGIC {
+        return info;
+    }
+    let size = u32::from_be(header.size);
+    let data = core::slice::from_raw_parts(dtb_pa as *const u8, size as usize);
+    if let Ok(dt) = DeviceTree::load(data) {
+        info.compatible.extend(strings(&dt.root, "compatible"));
+        info.memory = parse_memory(&dt.root);
+        if let Some(cpu) = dt.find("/cpus/cpu@0") {
+            info.compatible.extend(strings(cpu, "compatible"));
+            if let Ok(isa) = cpu.prop_str("riscv,isa") {
+                // 多字母扩展以下划线分隔，例如rv64imafdc_zicsr_sscofpmf
+                info.sscofpmf = isa.split('_').any(|ext| ext == "sscofpmf");
+            }
+        }
+        if let Some(pmu) = dt.find("/pmu") {
+            info.events = parse_event_map(pmu);
+            println!(
+                "[rustsbi-dtb] PMU: {} event-to-mhpmevent, {} event-to-mhpmcounters, {} raw-event-to-mhpmcounters entries",
+                info.events.event_to_mhpmevent.len(),
+                info.events.event_to_mhpmcounters.len(),
+                info.events.raw_event_to_mhpmcounters.len()
+            );
+        }
+    }
+    info
+}
+
+fn parse_event_map(pmu: &Node) -> EventMap {
+    let mut map = EventMap::default();
+    for c in cells(pmu, "riscv,event-to-mhpmevent").chunks_exact(3) {
+        map.event_to_mhpmevent.push((c[0], join(c[1], c[2])));
+    }
+    for c in cells(pmu, "riscv,event-to-mhpmcounters").chunks_exact(3) {
+        map.event_to_mhpmcounters.push((c[0], c[1], c[2]));
+    }
+    for c in cells(pmu, "riscv,raw-event-to-mhpmcounters").chunks_exact(5) {
+        map.raw_event_to_mhpmcounters.push((join(c[0], c[1]), join(c[2], c[3]), c[4]));
+    }
+    map
+}
+
+// 根节点下所有memory节点的reg属性；地址和大小的单元数由根节点的#address-cells和#size-cells给出，默认各为2
+fn parse_memory(root: &Node) -> Vec<(u64, u64)> {
+    let address_cells = cells(root, "#address-cells").first().copied().unwrap_or(2) as usize;
+    let size_cells = cells(root, "#size-cells").first().copied().unwrap_or(2) as usize;
+    let value = |c: &[u32]| c.iter().fold(0u64, |acc, &cell| acc << 32 | cell as u64);
+    root.children
+        .iter()
+        .filter(|node| node.name == "memory" || node.name.starts_with("memory@"))
+        .flat_map(|node| {
+            cells(node, "reg")
+                .chunks_exact(address_cells + size_cells)
+                .map(|c| (value(&c[..address_cells]), value(&c[address_cells..])))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// 把属性按大端序拆成32位的单元；属性不存在时返回空
+fn cells(node: &Node, name: &str) -> Vec<u32> {
+    match node.prop_raw(name) {
+        Some(raw) => raw
+            .chunks_exact(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// 把字符串列表属性按NUL拆开；属性不存在时返回空
+fn strings(node: &Node, name: &str) -> Vec<String> {
+    match node.prop_raw(name) {
+        Some(raw) => raw
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| core::str::from_utf8(s).ok())
+            .map(String::from)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[inline]
+fn join(hi: u32, lo: u32) -> u64 {
+    ((hi as u64) << 32) | lo as u64
+}
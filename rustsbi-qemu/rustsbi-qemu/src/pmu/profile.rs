@@ -0,0 +1,157 @@
+use alloc::string::String;
+use rustsbi::pmu::events::{
+    event_idx, SBI_PMU_HW_BRANCH_INSTRUCTIONS, SBI_PMU_HW_BRANCH_MISSES, SBI_PMU_HW_CACHE_MISSES,
+};
+use rustsbi::pmu::{
+    CacheEvent, CacheId, CacheOp, CacheResult, EventIdx, EVENT_TYPE_HARDWARE_CACHE, EVENT_TYPE_HARDWARE_GENERAL,
+};
+
+// 平台的事件编码：硬件事件到mhpmevent取值的映射；设备树没有描述的事件按平台的编码查找
+pub struct Profile {
+    pub name: &'static str,
+    // 设备树根节点或cpu节点的compatible属性含有其中之一时选用这个编码
+    pub compatible: &'static [&'static str],
+    // 通用硬件事件的event_idx到mhpmevent取值
+    pub general_events: &'static [(usize, u64)],
+    // 缓存事件到mhpmevent取值
+    pub cache_events: &'static [(CacheEvent, u64)],
+    // 表里没有的通用事件是否直接以event_idx作为mhpmevent取值，这是QEMU的约定
+    pub event_idx_as_value: bool,
+    // 初始化PMU前调用，写入计数需要的厂商CSR
+    pub enable: fn(),
+}
+
+impl Profile {
+    // 事件对应的mhpmevent取值；平台不支持这个事件时返回None
+    pub fn mhpmevent(&self, event_idx: usize) -> Option<u64> {
+        if let Some(&(_, value)) = self.general_events.iter().find(|&&(idx, _)| idx == event_idx) {
+            return Some(value);
+        }
+        if event_idx >> 16 == EVENT_TYPE_HARDWARE_CACHE {
+            let event = CacheEvent::decode(EventIdx::new(event_idx)?)?;
+            return self.cache_events.iter().find(|(e, _)| *e == event).map(|&(_, value)| value);
+        }
+        if self.event_idx_as_value {
+            Some(event_idx as u64)
+        } else {
+            None
+        }
+    }
+}
+
+const fn general(code: usize) -> usize {
+    event_idx(EVENT_TYPE_HARDWARE_GENERAL, code)
+}
+
+const fn cache_event(id: CacheId, op: CacheOp, result: CacheResult) -> CacheEvent {
+    CacheEvent { id, op, result }
+}
+
+// 只使用标准CSR的平台不需要额外的设置
+fn no_vendor_csr() {}
+
+// QEMU的mhpmevent取值就是事件的event_idx，只实现了下面几个缓存事件
+pub static QEMU: Profile = Profile {
+    name: "qemu",
+    compatible: &[],
+    general_events: &[],
+    cache_events: &[
+        (cache_event(CacheId::Dtlb, CacheOp::Read, CacheResult::Miss), 0x10019),
+        (cache_event(CacheId::Dtlb, CacheOp::Write, CacheResult::Miss), 0x1001B),
+        (cache_event(CacheId::Itlb, CacheOp::Read, CacheResult::Miss), 0x10021),
+    ],
+    event_idx_as_value: true,
+    enable: no_vendor_csr,
+};
+
+// SiFive U74核心，例如VisionFive 2上的JH7110；只有hpmcounter3和hpmcounter4两个可编程计数器
+//
+// mhpmevent的低8位是事件类别，从第8位开始是类别内事件的掩码，多个事件位同时置位时计数它们的和：
+// 类别0是指令提交事件，类别1是微架构事件，类别2是存储系统事件
+const U74_COMMIT: u64 = 0;
+const U74_MICROARCH: u64 = 1;
+const U74_MEMORY: u64 = 2;
+
+const fn u74(class: u64, events: u64) -> u64 {
+    events << 8 | class
+}
+
+pub static SIFIVE_U74: Profile = Profile {
+    name: "sifive-u74",
+    compatible: &["starfive,jh7110", "sifive,u74-mc", "sifive,u74"],
+    general_events: &[
+        // 条件分支、JAL和JALR提交
+        (general(SBI_PMU_HW_BRANCH_INSTRUCTIONS), u74(U74_COMMIT, 0b111 << 6)),
+        // 分支方向预测错误和跳转目标预测错误
+        (general(SBI_PMU_HW_BRANCH_MISSES), u74(U74_MICROARCH, 0b11 << 5)),
+        // 指令缓存和数据缓存缺失
+        (general(SBI_PMU_HW_CACHE_MISSES), u74(U74_MEMORY, 0b11)),
+    ],
+    cache_events: &[
+        // 整数和浮点加载提交
+        (cache_event(CacheId::L1d, CacheOp::Read, CacheResult::Access), u74(U74_COMMIT, 1 << 1 | 1 << 11)),
+        // 整数和浮点存储提交
+        (cache_event(CacheId::L1d, CacheOp::Write, CacheResult::Access), u74(U74_COMMIT, 1 << 2 | 1 << 12)),
+        // 数据缓存缺失不区分读写
+        (cache_event(CacheId::L1d, CacheOp::Read, CacheResult::Miss), u74(U74_MEMORY, 1 << 1)),
+        (cache_event(CacheId::L1d, CacheOp::Write, CacheResult::Miss), u74(U74_MEMORY, 1 << 1)),
+        (cache_event(CacheId::L1i, CacheOp::Read, CacheResult::Miss), u74(U74_MEMORY, 1 << 0)),
+        (cache_event(CacheId::Itlb, CacheOp::Read, CacheResult::Miss), u74(U74_MEMORY, 1 << 3)),
+        (cache_event(CacheId::Dtlb, CacheOp::Read, CacheResult::Miss), u74(U74_MEMORY, 1 << 4)),
+        (cache_event(CacheId::Dtlb, CacheOp::Write, CacheResult::Miss), u74(U74_MEMORY, 1 << 4)),
+    ],
+    event_idx_as_value: false,
+    enable: no_vendor_csr,
+};
+
+// 平头哥C906和C910核心，例如全志D1；mhpmevent的取值是厂商定义的事件编号，
+// 可编程计数器可能多于标准的前几个，由启动时的探测确定。没有Sscofpmf扩展，溢出中断和特权级过滤都不可用
+pub static THEAD_C9XX: Profile = Profile {
+    name: "thead-c9xx",
+    compatible: &["allwinner,sun20i-d1", "thead,c906", "thead,c910"],
+    general_events: &[
+        (general(SBI_PMU_HW_BRANCH_INSTRUCTIONS), 0x7),
+        (general(SBI_PMU_HW_BRANCH_MISSES), 0x6),
+    ],
+    cache_events: &[
+        (cache_event(CacheId::L1d, CacheOp::Read, CacheResult::Access), 0xc),
+        (cache_event(CacheId::L1d, CacheOp::Read, CacheResult::Miss), 0xd),
+        (cache_event(CacheId::L1d, CacheOp::Write, CacheResult::Access), 0xe),
+        (cache_event(CacheId::L1d, CacheOp::Write, CacheResult::Miss), 0xf),
+        (cache_event(CacheId::L1i, CacheOp::Read, CacheResult::Access), 0x1),
+        (cache_event(CacheId::L1i, CacheOp::Read, CacheResult::Miss), 0x2),
+        // I-uTLB和D-uTLB缺失
+        (cache_event(CacheId::Itlb, CacheOp::Read, CacheResult::Miss), 0x3),
+        (cache_event(CacheId::Dtlb, CacheOp::Read, CacheResult::Miss), 0x4),
+        (cache_event(CacheId::Dtlb, CacheOp::Write, CacheResult::Miss), 0x4),
+    ],
+    event_idx_as_value: false,
+    enable: enable_thead_c9xx,
+};
+
+// mxstatus的PMDU、PMDS和PMDM位置位时，U、S和M态不计数；复位值由实现决定，需要全部清零
+fn enable_thead_c9xx() {
+    const PMDU: usize = 1 << 10;
+    const PMDS: usize = 1 << 11;
+    const PMDM: usize = 1 << 13;
+    unsafe { super::csr::clear_thead_mxstatus(PMDU | PMDS | PMDM) };
+}
+
+// 按设备树选择的平台编码
+static PROFILES: &[&Profile] = &[&SIFIVE_U74, &THEAD_C9XX];
+
+// 选择平台的事件编码：开启sifive-u74或thead-c9xx特性时固定使用对应的编码，否则按设备树的compatible属性匹配，
+// 都不匹配时使用QEMU的约定
+pub fn select(compatible: &[String]) -> &'static Profile {
+    if cfg!(feature = "sifive-u74") {
+        return &SIFIVE_U74;
+    }
+    if cfg!(feature = "thead-c9xx") {
+        return &THEAD_C9XX;
+    }
+    PROFILES
+        .iter()
+        .find(|profile| profile.compatible.iter().any(|c| compatible.iter().any(|s| s == c)))
+        .copied()
+        .unwrap_or(&QEMU)
+}
@@ -34,7 +34,7 @@ impl Runtime {
     fn reset(&mut self) {
         unsafe { mstatus::set_mpp(MPP::Supervisor) };
         self.context.mstatus = mstatus::read();
-        self.context.machine_stack = 0x2333333366666666; // 将会被resume函数覆盖
+        self.context.machine_stack = 0x2333333366666666u64 as usize; // 将会被resume函数覆盖
     }
 
     // 在处理异常的时候，使用context_mut得到运行时当前用户的上下文，可以改变上下文的内容
@@ -58,6 +58,8 @@ impl Generator for Runtime {
             Trap::Exception(Exception::SupervisorEnvCall) => MachineTrap::SbiCall(),
             Trap::Exception(Exception::IllegalInstruction) => MachineTrap::IllegalInstruction(),
             Trap::Interrupt(Interrupt::MachineTimer) => MachineTrap::MachineTimer(),
+            Trap::Interrupt(Interrupt::MachineSoft) => MachineTrap::MachineSoft(),
+            Trap::Interrupt(Interrupt::MachineExternal) => MachineTrap::MachineExternal(),
             e => panic!(
                 "unhandled exception: {:?}! mtval: {:#x?}, ctx: {:#x?}",
                 e, mtval, self.context
@@ -72,6 +74,8 @@ pub enum MachineTrap {
     SbiCall(),
     IllegalInstruction(),
     MachineTimer(),
+    MachineSoft(),
+    MachineExternal(),
 }
 
 #[derive(Debug)]
@@ -113,6 +117,58 @@ pub struct SupervisorContext {
     pub machine_stack: usize, // 33
 }
 
+// RV32和RV64的上下文只有寄存器宽度不同，用宏生成对应宽度的保存和恢复指令
+#[cfg(target_pointer_width = "64")]
+macro_rules! xlenb {
+    () => {
+        "8"
+    };
+}
+#[cfg(target_pointer_width = "32")]
+macro_rules! xlenb {
+    () => {
+        "4"
+    };
+}
+#[cfg(target_pointer_width = "64")]
+macro_rules! sx {
+    () => {
+        "sd"
+    };
+}
+#[cfg(target_pointer_width = "32")]
+macro_rules! sx {
+    () => {
+        "sw"
+    };
+}
+#[cfg(target_pointer_width = "64")]
+macro_rules! lx {
+    () => {
+        "ld"
+    };
+}
+#[cfg(target_pointer_width = "32")]
+macro_rules! lx {
+    () => {
+        "lw"
+    };
+}
+
+// 把寄存器保存到base+idx*XLENB的位置
+macro_rules! store {
+    ($base: ident; $($reg: ident $idx: literal),+) => {
+        concat!($(sx!(), " ", stringify!($reg), ", ", $idx, "*", xlenb!(), "(", stringify!($base), ")\n"),+)
+    };
+}
+
+// 从base+idx*XLENB的位置恢复寄存器
+macro_rules! load {
+    ($base: ident; $($reg: ident $idx: literal),+) => {
+        concat!($(lx!(), " ", stringify!($reg), ", ", $idx, "*", xlenb!(), "(", stringify!($base), ")\n"),+)
+    };
+}
+
 #[naked]
 #[link_section = ".text"]
 unsafe extern "C" fn do_resume(_supervisor_context: *mut SupervisorContext) {
@@ -123,23 +179,9 @@ unsafe extern "C" fn do_resume(_supervisor_context: *mut SupervisorContext) {
 #[link_section = ".text"]
 unsafe extern "C" fn from_machine_save(_supervisor_context: *mut SupervisorContext) -> ! {
     asm!( // sp:机器栈顶
-        "addi   sp, sp, -15*8", // sp:机器栈顶
+        concat!("addi   sp, sp, -15*", xlenb!()), // sp:机器栈顶
         // 进入函数之前，已经保存了调用者寄存器，应当保存被调用者寄存器
-        "sd     ra, 0*8(sp)
-        sd      gp, 1*8(sp)
-        sd      tp, 2*8(sp)
-        sd      s0, 3*8(sp)
-        sd      s1, 4*8(sp)
-        sd      s2, 5*8(sp)
-        sd      s3, 6*8(sp)
-        sd      s4, 7*8(sp)
-        sd      s5, 8*8(sp)
-        sd      s6, 9*8(sp)
-        sd      s7, 10*8(sp)
-        sd      s8, 11*8(sp)
-        sd      s9, 12*8(sp)
-        sd      s10, 13*8(sp)
-        sd      s11, 14*8(sp)", 
+        store!(sp; ra 0, gp 1, tp 2, s0 3, s1 4, s2 5, s3 6, s4 7, s5 8, s6 9, s7 10, s8 11, s9 12, s10 13, s11 14),
         // a0:特权级上下文
         "j      {to_supervisor_restore}",
         to_supervisor_restore = sym to_supervisor_restore,
@@ -152,45 +194,16 @@ unsafe extern "C" fn from_machine_save(_supervisor_context: *mut SupervisorConte
 pub unsafe extern "C" fn to_supervisor_restore(_supervisor_context: *mut SupervisorContext) -> ! {
     asm!(
         // a0:特权级上下文
-        "sd     sp, 33*8(a0)", // 机器栈顶放进特权级上下文
+        store!(a0; sp 33), // 机器栈顶放进特权级上下文
         "csrw   mscratch, a0", // 新mscratch:特权级上下文
         // mscratch:特权级上下文
         "mv     sp, a0", // 新sp:特权级上下文
-        "ld     t0, 31*8(sp)
-        ld      t1, 32*8(sp)
-        csrw    mstatus, t0
+        load!(sp; t0 31, t1 32),
+        "csrw   mstatus, t0
         csrw    mepc, t1",
-        "ld     ra, 0*8(sp)
-        ld      gp, 2*8(sp)
-        ld      tp, 3*8(sp)
-        ld      t0, 4*8(sp)
-        ld      t1, 5*8(sp)
-        ld      t2, 6*8(sp)
-        ld      s0, 7*8(sp)
-        ld      s1, 8*8(sp)
-        ld      a0, 9*8(sp)
-        ld      a1, 10*8(sp)
-        ld      a2, 11*8(sp)
-        ld      a3, 12*8(sp)
-        ld      a4, 13*8(sp)
-        ld      a5, 14*8(sp)
-        ld      a6, 15*8(sp)
-        ld      a7, 16*8(sp)
-        ld      s2, 17*8(sp)
-        ld      s3, 18*8(sp)
-        ld      s4, 19*8(sp)
-        ld      s5, 20*8(sp)
-        ld      s6, 21*8(sp)
-        ld      s7, 22*8(sp)
-        ld      s8, 23*8(sp)
-        ld      s9, 24*8(sp)
-        ld     s10, 25*8(sp)
-        ld     s11, 26*8(sp)
-        ld      t3, 27*8(sp)
-        ld      t4, 28*8(sp)
-        ld      t5, 29*8(sp)
-        ld      t6, 30*8(sp)",
-        "ld     sp, 1*8(sp)", // 新sp:特权级栈
+        load!(sp; ra 0, gp 2, tp 3, t0 4, t1 5, t2 6, s0 7, s1 8, a0 9, a1 10, a2 11, a3 12, a4 13, a5 14, a6 15, a7 16,
+            s2 17, s3 18, s4 19, s5 20, s6 21, s7 22, s8 23, s9 24, s10 25, s11 26, t3 27, t4 28, t5 29, t6 30),
+        load!(sp; sp 1), // 新sp:特权级栈
         // sp:特权级栈, mscratch:特权级上下文
         "mret",
         options(noreturn)
@@ -205,43 +218,15 @@ pub unsafe extern "C" fn from_supervisor_save() -> ! {
     asm!( // sp:特权级栈,mscratch:特权级上下文
         ".p2align 2",
         "csrrw  sp, mscratch, sp", // 新mscratch:特权级栈, 新sp:特权级上下文
-        "sd     ra, 0*8(sp)
-        sd      gp, 2*8(sp)
-        sd      tp, 3*8(sp)
-        sd      t0, 4*8(sp)
-        sd      t1, 5*8(sp)
-        sd      t2, 6*8(sp)
-        sd      s0, 7*8(sp)
-        sd      s1, 8*8(sp)
-        sd      a0, 9*8(sp)
-        sd      a1, 10*8(sp)
-        sd      a2, 11*8(sp)
-        sd      a3, 12*8(sp)
-        sd      a4, 13*8(sp)
-        sd      a5, 14*8(sp)
-        sd      a6, 15*8(sp)
-        sd      a7, 16*8(sp)
-        sd      s2, 17*8(sp)
-        sd      s3, 18*8(sp)
-        sd      s4, 19*8(sp)
-        sd      s5, 20*8(sp)
-        sd      s6, 21*8(sp)
-        sd      s7, 22*8(sp)
-        sd      s8, 23*8(sp)
-        sd      s9, 24*8(sp)
-        sd     s10, 25*8(sp)
-        sd     s11, 26*8(sp)
-        sd      t3, 27*8(sp)
-        sd      t4, 28*8(sp)
-        sd      t5, 29*8(sp)
-        sd      t6, 30*8(sp)",
-        "csrr   t0, mstatus
-        sd      t0, 31*8(sp)",
-        "csrr   t1, mepc
-        sd      t1, 32*8(sp)",
+        store!(sp; ra 0, gp 2, tp 3, t0 4, t1 5, t2 6, s0 7, s1 8, a0 9, a1 10, a2 11, a3 12, a4 13, a5 14, a6 15, a7 16,
+            s2 17, s3 18, s4 19, s5 20, s6 21, s7 22, s8 23, s9 24, s10 25, s11 26, t3 27, t4 28, t5 29, t6 30),
+        "csrr   t0, mstatus",
+        store!(sp; t0 31),
+        "csrr   t1, mepc",
+        store!(sp; t1 32),
         // mscratch:特权级栈,sp:特权级上下文
         "csrrw  t2, mscratch, sp", // 新mscratch:特权级上下文,t2:特权级栈
-        "sd     t2, 1*8(sp)", // 保存特权级栈
+        store!(sp; t2 1), // 保存特权级栈
         "j      {to_machine_restore}",
         to_machine_restore = sym to_machine_restore,
         options(noreturn)
@@ -254,23 +239,9 @@ unsafe extern "C" fn to_machine_restore() -> ! {
     asm!(
         // mscratch:特权级上下文
         "csrr   sp, mscratch", // sp:特权级上下文
-        "ld     sp, 33*8(sp)", // sp:机器栈
-        "ld     ra, 0*8(sp)
-        ld      gp, 1*8(sp)
-        ld      tp, 2*8(sp)
-        ld      s0, 3*8(sp)
-        ld      s1, 4*8(sp)
-        ld      s2, 5*8(sp)
-        ld      s3, 6*8(sp)
-        ld      s4, 7*8(sp)
-        ld      s5, 8*8(sp)
-        ld      s6, 9*8(sp)
-        ld      s7, 10*8(sp)
-        ld      s8, 11*8(sp)
-        ld      s9, 12*8(sp)
-        ld      s10, 13*8(sp)
-        ld      s11, 14*8(sp)",
-        "addi   sp, sp, 15*8", // sp:机器栈顶
+        load!(sp; sp 33), // sp:机器栈
+        load!(sp; ra 0, gp 1, tp 2, s0 3, s1 4, s2 5, s3 6, s4 7, s5 8, s6 9, s7 10, s8 11, s9 12, s10 13, s11 14),
+        concat!("addi   sp, sp, 15*", xlenb!()), // sp:机器栈顶
         "jr     ra",           // 其实就是ret
         options(noreturn)
     )
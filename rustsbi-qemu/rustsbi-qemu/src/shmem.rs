@@ -0,0 +1,30 @@
+use alloc::vec::Vec;
+
+// S层和固件共享的内存：必须位于设备树描述的内存中，不能和固件自身的内存重叠，并且S态按当前的PMP配置可以读写；
+// 设备树没有描述内存时不检查第一项
+pub struct SupervisorMemory {
+    // 设备树描述的内存区域：<起始地址 大小>
+    memory: Vec<(u64, u64)>,
+}
+
+impl SupervisorMemory {
+    pub fn new(memory: Vec<(u64, u64)>) -> SupervisorMemory {
+        SupervisorMemory { memory }
+    }
+}
+
+impl rustsbi::SharedMemory for SupervisorMemory {
+    fn accessible(&self, phys: usize, size: usize) -> bool {
+        let (start, end) = (phys as u64, phys as u64 + size as u64);
+        let in_memory = self.memory.is_empty() || self.memory.iter().any(|&(base, len)| base <= start && end <= base + len);
+        let (firmware_start, firmware_end) = (skernel as usize as u64, ekernel as usize as u64);
+        let in_firmware = start < firmware_end && firmware_start < end;
+        in_memory && !in_firmware && rustsbi::shmem::pmp_allows_supervisor(phys, size)
+    }
+}
+
+// 链接脚本给出的固件镜像的起止地址，包括栈和堆
+extern "C" {
+    fn skernel();
+    fn ekernel();
+}
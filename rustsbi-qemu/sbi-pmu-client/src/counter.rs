@@ -0,0 +1,46 @@
+// Reading hardware counters from supervisor mode; the firmware must allow it in `mcounteren`
+
+// Value of a hardware counter by its CSR number, from `cycle` (0xC00) to `hpmcounter31` (0xC1F)
+pub fn read(csr: usize) -> u64 {
+    match () {
+        #[cfg(target_pointer_width = "32")]
+        () => loop {
+            // the upper half is read again, in case the lower half wrapped around in between
+            let hi = read_csr(csr + 0x80);
+            let lo = read_csr(csr);
+            if read_csr(csr + 0x80) == hi {
+                break (hi as u64) << 32 | lo as u64;
+            }
+        },
+        #[cfg(not(target_pointer_width = "32"))]
+        () => read_csr(csr) as u64,
+    }
+}
+
+fn read_csr(csr: usize) -> usize {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => {
+            let value: usize;
+            macro_rules! op {
+                ($csr: literal, $($i: literal)*) => {
+                    match csr.wrapping_sub($csr) {
+                        $($i => unsafe { asm!("csrr {0}, {csr}", out(reg) value, csr = const $csr + $i) },)*
+                        _ => panic!("not a hardware counter CSR: {:#x}", csr),
+                    }
+                };
+            }
+            if csr < 0xC80 {
+                op!(0xC00, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+            } else {
+                op!(0xC80, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+            }
+            value
+        }
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {
+            let _ = csr;
+            unimplemented!("not RISC-V instruction set architecture")
+        }
+    }
+}
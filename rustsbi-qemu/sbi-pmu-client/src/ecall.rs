@@ -0,0 +1,199 @@
+/// Completed successfully.
+pub const SBI_SUCCESS: usize = 0;
+/// Failed.
+pub const SBI_ERR_FAILED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-1));
+/// Not supported.
+pub const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
+/// Invalid parameter(s).
+pub const SBI_ERR_INVALID_PARAM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-3));
+/// Denied or not allowed.
+pub const SBI_ERR_DENIED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-4));
+/// Invalid address(s).
+pub const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
+/// Already available.
+pub const SBI_ERR_ALREADY_AVAILABLE: usize = usize::from_ne_bytes(isize::to_ne_bytes(-6));
+/// Already started.
+pub const SBI_ERR_ALREADY_STARTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-7));
+/// Already stopped.
+pub const SBI_ERR_ALREADY_STOPPED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-8));
+/// Shared memory not available.
+pub const SBI_ERR_NO_SHMEM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-9));
+
+/// Result of an SBI call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SbiRet {
+    /// Error number
+    pub error: usize,
+    /// Result value
+    pub value: usize,
+}
+
+impl SbiRet {
+    /// `Ok` with the value if the call succeeded, `Err` with the error number otherwise.
+    #[inline]
+    pub fn into_result(self) -> Result<usize, usize> {
+        if self.error == SBI_SUCCESS {
+            Ok(self.value)
+        } else {
+            Err(self.error)
+        }
+    }
+}
+
+#[inline(always)]
+pub(crate) fn sbi_call(extension: usize, function: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize, arg5: usize) -> SbiRet {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => {
+            let (error, value);
+            unsafe {
+                asm!(
+                    "ecall",
+                    in("a0") arg0, in("a1") arg1, in("a2") arg2, in("a3") arg3, in("a4") arg4, in("a5") arg5,
+                    in("a6") function, in("a7") extension,
+                    lateout("a0") error, lateout("a1") value,
+                )
+            };
+            SbiRet { error, value }
+        }
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {
+            let _ = (extension, function, arg0, arg1, arg2, arg3, arg4, arg5);
+            unimplemented!("not RISC-V instruction set architecture")
+        }
+    }
+}
+
+// Physical address passed as its lower and upper XLEN bits, in two argument registers on both RV32 and RV64
+#[derive(Clone, Copy)]
+pub(crate) struct Phys {
+    lo: usize,
+    hi: usize,
+}
+
+impl Phys {
+    // all-ones address of both halves, which disables a shared memory
+    pub(crate) const ALL_ONES: Phys = Phys { lo: usize::MAX, hi: usize::MAX };
+
+    #[inline]
+    pub(crate) fn new(address: u64) -> Phys {
+        let (lo, hi) = split_u64(address);
+        Phys { lo, hi }
+    }
+}
+
+// An argument of an SBI call, stored into the argument registers from `a0` to `a5`
+pub(crate) trait Argument {
+    // number of argument registers taken by the argument
+    const REGISTERS: usize;
+    // store the argument from register `at`, returns the register of the next argument
+    fn store(self, registers: &mut [usize; 6], at: usize) -> usize;
+}
+
+impl Argument for usize {
+    const REGISTERS: usize = 1;
+    #[inline]
+    fn store(self, registers: &mut [usize; 6], at: usize) -> usize {
+        registers[at] = self;
+        at + 1
+    }
+}
+
+// 64-bit arguments take two registers on RV32, lower half first
+impl Argument for u64 {
+    const REGISTERS: usize = if usize::BITS == 32 { 2 } else { 1 };
+    #[inline]
+    fn store(self, registers: &mut [usize; 6], at: usize) -> usize {
+        let (lo, hi) = split_u64(self);
+        registers[at] = lo;
+        if Self::REGISTERS == 2 {
+            registers[at + 1] = hi;
+        }
+        at + Self::REGISTERS
+    }
+}
+
+impl Argument for Phys {
+    const REGISTERS: usize = 2;
+    #[inline]
+    fn store(self, registers: &mut [usize; 6], at: usize) -> usize {
+        registers[at] = self.lo;
+        registers[at + 1] = self.hi;
+        at + 2
+    }
+}
+
+#[inline]
+fn split_u64(value: u64) -> (usize, usize) {
+    match () {
+        #[cfg(target_pointer_width = "32")]
+        () => (value as usize, (value >> 32) as usize),
+        #[cfg(not(target_pointer_width = "32"))]
+        () => (value as usize, 0),
+    }
+}
+
+// First argument register of each argument, given the number of registers each of them takes
+pub(crate) const fn first_registers<const N: usize>(widths: [usize; N]) -> [usize; N] {
+    let mut registers = [0; N];
+    let mut i = 1;
+    while i < N {
+        registers[i] = registers[i - 1] + widths[i - 1];
+        i += 1;
+    }
+    registers
+}
+
+// Number of argument registers taken by all arguments
+pub(crate) const fn total_registers<const N: usize>(widths: [usize; N]) -> usize {
+    let mut total = 0;
+    let mut i = 0;
+    while i < N {
+        total += widths[i];
+        i += 1;
+    }
+    total
+}
+
+// Fails to compile if the condition does not hold for the XLEN being built
+macro_rules! const_assert {
+    ($cond: expr) => {
+        const _: [(); 0 - !{ $cond } as usize] = [];
+    };
+}
+
+// Generate a stub for each SBI call, storing its arguments into registers for the XLEN being built. The
+// registers taken by the arguments of each call are kept in `layout`, checked against the dispatcher of
+// the calls in RustSBI by `const_assert!`; a call with arguments beyond `a5` fails to compile
+macro_rules! ecall {
+    ($($(#[$doc: meta])* fn $name: ident($($arg: ident: $ty: ty),*) = $extension: expr, $function: expr;)*) => {
+        $(
+            $(#[$doc])*
+            #[inline]
+            pub(crate) fn $name($($arg: $ty),*) -> $crate::ecall::SbiRet {
+                #[allow(unused_mut)]
+                let mut registers = [0; 6];
+                let at = 0;
+                $(let at = $crate::ecall::Argument::store($arg, &mut registers, at);)*
+                let _ = at;
+                let [a0, a1, a2, a3, a4, a5] = registers;
+                $crate::ecall::sbi_call($extension, $function, a0, a1, a2, a3, a4, a5)
+            }
+        )*
+
+        // first argument register of each argument of the stubs, and the number of registers taken
+        #[allow(dead_code, non_upper_case_globals)]
+        pub(crate) mod layout {
+            use super::*;
+            use $crate::ecall::{first_registers, total_registers, Argument};
+            $(
+                pub(crate) const $name: (&[usize], usize) = (
+                    &first_registers([$(<$ty as Argument>::REGISTERS),*]),
+                    total_registers([$(<$ty as Argument>::REGISTERS),*]),
+                );
+                const_assert!($name.1 <= 6);
+            )*
+        }
+    };
+}
@@ -0,0 +1,115 @@
+use crate::counter;
+use crate::ecall::{SbiRet, SBI_ERR_FAILED, SBI_ERR_INVALID_PARAM, SBI_ERR_NOT_SUPPORTED, SBI_SUCCESS};
+use crate::events::EventIdx;
+use crate::pmu::{self, ConfigFlags, CounterInfo, IndexMask, StartFlags, StopFlags};
+
+/// Maximum number of events counted by one [`CounterGuard`].
+pub const MAX_EVENTS: usize = 8;
+
+/// Counts events while it is alive.
+///
+/// Constructing the guard configures a counter for each event and starts them; dropping it reads
+/// the counters into the values given to [`CounterGuard::new`], then stops them and releases them
+/// from their events.
+///
+/// Counters are read before they are stopped, so the values include the reads but not the stop
+/// calls. A counter which cannot be read when the guard is dropped leaves its value unchanged.
+pub struct CounterGuard<'a> {
+    counters: [Counter; MAX_EVENTS],
+    values: &'a mut [u64],
+}
+
+#[derive(Clone, Copy)]
+struct Counter {
+    idx: usize,
+    info: CounterInfo,
+}
+
+impl<'a> CounterGuard<'a> {
+    /// Count `events`, with the value of `events[i]` written into `values[i]`
+    /// when the guard is dropped.
+    ///
+    /// Returns the SBI error number if a counter cannot be configured or started for an event, and
+    /// `SBI_ERR_INVALID_PARAM` if the numbers of events and values differ or there are more than
+    /// [`MAX_EVENTS`] events. Counters configured before the error are released.
+    pub fn new(events: &[EventIdx], values: &'a mut [u64]) -> Result<CounterGuard<'a>, usize> {
+        if events.len() != values.len() || events.len() > MAX_EVENTS {
+            return Err(SBI_ERR_INVALID_PARAM);
+        }
+        let num_counters = pmu::num_counters().into_result()?;
+        let mut counters = [Counter { idx: 0, info: CounterInfo::decode(0) }; MAX_EVENTS];
+        // counters are configured first and started together at the end, so that they cover the same code
+        for (i, &event) in events.iter().enumerate() {
+            let idx = match config(num_counters, event) {
+                Ok(idx) => idx,
+                Err(error) => return Err(release(&counters[..i], 0, error)),
+            };
+            counters[i].idx = idx;
+            match pmu::counter_get_info(idx).into_result() {
+                Ok(info) => counters[i].info = CounterInfo::decode(info),
+                Err(error) => return Err(release(&counters[..=i], 0, error)),
+            }
+        }
+        for (i, counter) in counters[..events.len()].iter().enumerate() {
+            if let Err(error) = pmu::counter_start(IndexMask::single(counter.idx), StartFlags::empty(), 0).into_result() {
+                return Err(release(&counters[..events.len()], i, error));
+            }
+        }
+        Ok(CounterGuard { counters, values })
+    }
+}
+
+impl Drop for CounterGuard<'_> {
+    fn drop(&mut self) {
+        let count = self.values.len();
+        for (value, counter) in self.values.iter_mut().zip(self.counters[..count].iter()) {
+            if counter.info.firmware {
+                if let Ok(firmware) = pmu::counter_fw_read(counter.idx).into_result() {
+                    *value = firmware as u64;
+                }
+            } else {
+                *value = counter::read(counter.info.csr);
+            }
+        }
+        for counter in &self.counters[..count] {
+            pmu::counter_stop(IndexMask::single(counter.idx), StopFlags::RESET);
+        }
+    }
+}
+
+// Configure any counter for the event, with its value cleared
+fn config(num_counters: usize, event: EventIdx) -> Result<usize, usize> {
+    // counters beyond the width of a mask are given by a base; one call per window of counters
+    let mut base = 0;
+    let mut error = SBI_ERR_NOT_SUPPORTED;
+    while base < num_counters {
+        let width = (num_counters - base).min(usize::BITS as usize);
+        let mask = if width == usize::BITS as usize {
+            usize::MAX
+        } else {
+            (1 << width) - 1
+        };
+        let SbiRet { error: this, value } = pmu::counter_config_matching(IndexMask::new(base, mask), ConfigFlags::CLEAR_VALUE, event, 0);
+        if this == SBI_SUCCESS {
+            return Ok(value);
+        }
+        // an event which no counter of a window can count may still be counted by another window
+        if error != SBI_ERR_FAILED {
+            error = this;
+        }
+        base += width;
+    }
+    Err(error)
+}
+
+// Release configured counters after `error`, of which the first `started` are running; only a running
+// counter can be stopped and released from its event, so the others are started for the release
+fn release(counters: &[Counter], started: usize, error: usize) -> usize {
+    for (i, counter) in counters.iter().enumerate() {
+        if i >= started {
+            pmu::counter_start(IndexMask::single(counter.idx), StartFlags::empty(), 0);
+        }
+        pmu::counter_stop(IndexMask::single(counter.idx), StopFlags::RESET);
+    }
+    error
+}
@@ -0,0 +1,35 @@
+//! Supervisor-mode client of the SBI PMU extension.
+//!
+//! Wraps the `ecall`s of the PMU extension with typed arguments, so that kernels running under an SBI
+//! implementation need no inline assembly of their own. Event indices and the values of flags come from the
+//! event encodings of RustSBI, shared with the other side of the calls. [`CounterGuard`] counts events over
+//! a block of code:
+//!
+//! ```no_run
+//! use sbi_pmu_client::{CounterGuard, EVENT_HW_CPU_CYCLES, EVENT_HW_INSTRUCTIONS};
+//!
+//! let mut values = [0; 2];
+//! {
+//!     let _guard = CounterGuard::new(&[EVENT_HW_CPU_CYCLES, EVENT_HW_INSTRUCTIONS], &mut values).unwrap();
+//!     // code to measure
+//! }
+//! let (cycles, instructions) = (values[0], values[1]);
+//! ```
+#![no_std]
+#![feature(asm)]
+
+mod counter;
+#[macro_use]
+mod ecall;
+// event encodings are shared with RustSBI; the file depends only on `core`
+#[path = "../../../rustsbi/src/pmu/events.rs"]
+pub mod events;
+mod guard;
+mod pmu;
+pub mod rustsbi;
+mod stub;
+
+pub use ecall::*;
+pub use events::EventIdx;
+pub use guard::{CounterGuard, MAX_EVENTS};
+pub use pmu::*;
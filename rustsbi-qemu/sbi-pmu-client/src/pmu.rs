@@ -0,0 +1,222 @@
+use crate::ecall::{Phys, SbiRet};
+use crate::events::{
+    EventIdx, EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_CFG_FLAG_AUTO_START, SBI_PMU_CFG_FLAG_CLEAR_VALUE, SBI_PMU_CFG_FLAG_SET_MINH,
+    SBI_PMU_CFG_FLAG_SET_SINH, SBI_PMU_CFG_FLAG_SET_UINH, SBI_PMU_CFG_FLAG_SET_VSINH, SBI_PMU_CFG_FLAG_SET_VUINH,
+    SBI_PMU_CFG_FLAG_SKIP_MATCH, SBI_PMU_HW_BRANCH_INSTRUCTIONS, SBI_PMU_HW_BRANCH_MISSES, SBI_PMU_HW_CACHE_MISSES,
+    SBI_PMU_HW_CPU_CYCLES, SBI_PMU_HW_INSTRUCTIONS, SBI_PMU_START_FLAG_SET_INIT_VALUE, SBI_PMU_STOP_FLAG_RESET,
+    SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT,
+};
+use crate::stub;
+use core::ops::BitOr;
+
+/// Extension ID of the SBI PMU extension.
+pub const EXTENSION_PMU: usize = 0x504D55;
+
+
+/// CPU cycles.
+pub const EVENT_HW_CPU_CYCLES: EventIdx = EventIdx::from_parts(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CPU_CYCLES);
+/// Retired instructions.
+pub const EVENT_HW_INSTRUCTIONS: EventIdx = EventIdx::from_parts(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_INSTRUCTIONS);
+/// Cache misses.
+pub const EVENT_HW_CACHE_MISSES: EventIdx = EventIdx::from_parts(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CACHE_MISSES);
+/// Retired branch instructions.
+pub const EVENT_HW_BRANCH_INSTRUCTIONS: EventIdx = EventIdx::from_parts(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_BRANCH_INSTRUCTIONS);
+/// Mispredicted branch instructions.
+pub const EVENT_HW_BRANCH_MISSES: EventIdx = EventIdx::from_parts(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_BRANCH_MISSES);
+
+/// Set of counters or harts selected by a base index and a bit mask.
+///
+/// Bit `i` of the mask selects index `base + i`, the same way as `counter_idx_base` and
+/// `counter_idx_mask` of the PMU calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexMask {
+    base: usize,
+    mask: usize,
+}
+
+impl IndexMask {
+    /// Select index `base + i` for every bit `i` set in `mask`.
+    #[inline]
+    pub const fn new(base: usize, mask: usize) -> IndexMask {
+        IndexMask { base, mask }
+    }
+
+    /// Select only `index`.
+    #[inline]
+    pub const fn single(index: usize) -> IndexMask {
+        IndexMask { base: index, mask: 1 }
+    }
+
+    /// The first index the mask is relative to.
+    #[inline]
+    pub const fn base(&self) -> usize {
+        self.base
+    }
+
+    /// The bit mask, relative to `base`.
+    #[inline]
+    pub const fn mask(&self) -> usize {
+        self.mask
+    }
+}
+
+macro_rules! flags {
+    ($(#[$doc: meta])* $name: ident { $($(#[$flag_doc: meta])* $flag: ident = $value: expr,)* }) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct $name(usize);
+
+        impl $name {
+            $($(#[$flag_doc])* pub const $flag: $name = $name($value);)*
+
+            /// No flag set.
+            #[inline]
+            pub const fn empty() -> $name {
+                $name(0)
+            }
+
+            /// Raw value passed to the SBI call.
+            #[inline]
+            pub const fn bits(self) -> usize {
+                self.0
+            }
+
+            /// Whether all flags of `other` are set.
+            #[inline]
+            pub const fn contains(self, other: $name) -> bool {
+                self.0 & other.0 == other.0
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = $name;
+            #[inline]
+            fn bitor(self, rhs: $name) -> $name {
+                $name(self.0 | rhs.0)
+            }
+        }
+    };
+}
+
+flags! {
+    /// Flags of [`counter_config_matching`].
+    ConfigFlags {
+        /// Skip the counter matching, and use the given counters as they are configured.
+        SKIP_MATCH = SBI_PMU_CFG_FLAG_SKIP_MATCH,
+        /// Clear the value of the counter when it is configured.
+        CLEAR_VALUE = SBI_PMU_CFG_FLAG_CLEAR_VALUE,
+        /// Start the counter when it is configured.
+        AUTO_START = SBI_PMU_CFG_FLAG_AUTO_START,
+        /// Inhibit counting in VU-mode.
+        SET_VUINH = SBI_PMU_CFG_FLAG_SET_VUINH,
+        /// Inhibit counting in VS-mode.
+        SET_VSINH = SBI_PMU_CFG_FLAG_SET_VSINH,
+        /// Inhibit counting in U-mode.
+        SET_UINH = SBI_PMU_CFG_FLAG_SET_UINH,
+        /// Inhibit counting in S-mode.
+        SET_SINH = SBI_PMU_CFG_FLAG_SET_SINH,
+        /// Inhibit counting in M-mode.
+        SET_MINH = SBI_PMU_CFG_FLAG_SET_MINH,
+    }
+}
+
+flags! {
+    /// Flags of [`counter_start`].
+    StartFlags {
+        /// Set the value of the counters to `initial_value`.
+        SET_INIT_VALUE = SBI_PMU_START_FLAG_SET_INIT_VALUE,
+    }
+}
+
+flags! {
+    /// Flags of [`counter_stop`].
+    StopFlags {
+        /// Release the counters from their events.
+        RESET = SBI_PMU_STOP_FLAG_RESET,
+        /// Save the values of the counters into the snapshot shared memory.
+        TAKE_SNAPSHOT = SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT,
+    }
+}
+
+/// Decoded `counter_info` returned by [`counter_get_info`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CounterInfo {
+    /// CSR number of a hardware counter.
+    pub csr: usize,
+    /// Width of a hardware counter in bits.
+    pub width: usize,
+    /// Whether it is a firmware counter, read with [`counter_fw_read`].
+    pub firmware: bool,
+}
+
+impl CounterInfo {
+    /// Decode the `counter_info` value.
+    #[inline]
+    pub fn decode(info: usize) -> CounterInfo {
+        CounterInfo {
+            csr: info & 0xFFF,
+            width: ((info >> 12) & 0x3F) + 1,
+            firmware: info >> (usize::BITS - 1) != 0,
+        }
+    }
+}
+
+/// Number of counters, hardware and firmware.
+#[inline]
+pub fn num_counters() -> SbiRet {
+    stub::pmu_num_counters()
+}
+
+/// Information of a counter, decoded by [`CounterInfo::decode`].
+#[inline]
+pub fn counter_get_info(counter_idx: usize) -> SbiRet {
+    stub::pmu_counter_get_info(counter_idx)
+}
+
+/// Find and configure a counter among `counters` for an event, returns its index.
+#[inline]
+pub fn counter_config_matching(counters: IndexMask, flags: ConfigFlags, event: EventIdx, event_data: u64) -> SbiRet {
+    stub::pmu_counter_config_matching(counters.base(), counters.mask(), flags.bits(), event.raw(), event_data)
+}
+
+/// Start `counters`, from `initial_value` with [`StartFlags::SET_INIT_VALUE`].
+#[inline]
+pub fn counter_start(counters: IndexMask, flags: StartFlags, initial_value: u64) -> SbiRet {
+    stub::pmu_counter_start(counters.base(), counters.mask(), flags.bits(), initial_value)
+}
+
+/// Stop `counters`.
+#[inline]
+pub fn counter_stop(counters: IndexMask, flags: StopFlags) -> SbiRet {
+    stub::pmu_counter_stop(counters.base(), counters.mask(), flags.bits())
+}
+
+/// Value of a firmware counter, the lower half of it on RV32.
+#[inline]
+pub fn counter_fw_read(counter_idx: usize) -> SbiRet {
+    stub::pmu_counter_fw_read(counter_idx)
+}
+
+/// Upper half of the value of a firmware counter on RV32, zero on RV64.
+#[inline]
+pub fn counter_fw_read_hi(counter_idx: usize) -> SbiRet {
+    stub::pmu_counter_fw_read_hi(counter_idx)
+}
+
+/// Set the physical address of the snapshot shared memory, aligned to 4096 bytes.
+#[inline]
+pub fn snapshot_set_shm(shmem_phys: u64) -> SbiRet {
+    stub::pmu_snapshot_set_shm(Phys::new(shmem_phys), 0)
+}
+
+/// Disable the snapshot shared memory.
+#[inline]
+pub fn snapshot_disable() -> SbiRet {
+    stub::pmu_snapshot_set_shm(Phys::ALL_ONES, 0)
+}
+
+/// Query `num_entries` events in the array of `event_info` entries at physical address `shmem_phys`.
+#[inline]
+pub fn event_get_info(shmem_phys: u64, num_entries: usize) -> SbiRet {
+    stub::pmu_event_get_info(Phys::new(shmem_phys), num_entries, 0)
+}
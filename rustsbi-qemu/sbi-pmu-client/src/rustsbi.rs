@@ -0,0 +1,142 @@
+//! PMU calls of the firmware specific extension of RustSBI.
+//!
+//! Other SBI implementations return `SBI_ERR_NOT_SUPPORTED` for these calls, or nothing if they do not
+//! implement the extension; probe it with the base extension before use.
+
+use crate::ecall::{Phys, SbiRet};
+use crate::events::{EventIdx, RUSTSBI_PMU_CAPABILITIES_VERSION, RUSTSBI_PMU_LEAK_CHECK_END_SESSION, RUSTSBI_PMU_SELF_TEST_PASSED};
+use crate::events::RUSTSBI_PMU_WATCH_NOTIFY_SHMEM;
+use crate::pmu::IndexMask;
+use crate::stub;
+
+/// Extension ID of the firmware specific extension of RustSBI.
+pub const EXTENSION_RUSTSBI: usize = 0x0A000004;
+
+// flag of the call statistics read call: clear the statistics after copying them out
+const RUSTSBI_STATS_READ_CLEAR: usize = 1 << 0;
+
+/// Print the counters of the calling hart to the firmware console.
+#[inline]
+pub fn pmu_dump() -> SbiRet {
+    stub::rustsbi_pmu_dump()
+}
+
+/// Read `counters` into the array of 64-bit values at physical address `buf_phys`, one for each bit of the
+/// mask; returns the number of counters read.
+#[inline]
+pub fn pmu_counter_read_batch(counters: IndexMask, buf_phys: u64) -> SbiRet {
+    stub::rustsbi_pmu_counter_read_batch(counters.base(), counters.mask(), Phys::new(buf_phys))
+}
+
+/// Round the values of counters bound to `event` down to a multiple of `quantum` and add a random number
+/// below `jitter`; zero leaves either out.
+#[inline]
+pub fn pmu_protect_event(event: EventIdx, quantum: usize, jitter: usize) -> SbiRet {
+    stub::rustsbi_pmu_protect_event(event.raw(), quantum, jitter)
+}
+
+/// Sample `counters` of the calling hart every `period` ticks of the `time` counter, into the ring buffer
+/// of `size` bytes at physical address `buf_phys`.
+#[inline]
+pub fn pmu_sampler_start(counters: IndexMask, period: usize, buf_phys: u64, size: usize) -> SbiRet {
+    stub::rustsbi_pmu_sampler_start(counters.base(), counters.mask(), period, Phys::new(buf_phys), size)
+}
+
+/// Stop sampling on the calling hart.
+#[inline]
+pub fn pmu_sampler_stop() -> SbiRet {
+    stub::rustsbi_pmu_sampler_stop()
+}
+
+/// Read a firmware counter and reset it to zero in one step.
+#[inline]
+pub fn pmu_counter_read_clear(counter_idx: usize) -> SbiRet {
+    stub::rustsbi_pmu_counter_read_clear(counter_idx)
+}
+
+/// Summary of the counters of hart `hartid`: started counters in bits 7:0, bound counters in bits 15:8,
+/// a pending overflow in bit 16, and idle counters in bits 24:17.
+#[inline]
+pub fn pmu_hart_status(hartid: usize) -> SbiRet {
+    stub::rustsbi_pmu_hart_status(hartid)
+}
+
+/// Perform the request at physical address `request_phys` on every hart of `harts`; returns the number
+/// of harts.
+#[inline]
+pub fn pmu_remote_control(harts: IndexMask, request_phys: u64) -> SbiRet {
+    stub::rustsbi_pmu_remote_control(harts.mask(), harts.base(), Phys::new(request_phys))
+}
+
+/// Total of firmware event `event` over all harts; with `num_harts` other than zero, the value of each
+/// hart is written into the array of 64-bit values at physical address `buf_phys`.
+#[inline]
+pub fn pmu_firmware_event_total(event: EventIdx, buf_phys: u64, num_harts: usize) -> SbiRet {
+    stub::rustsbi_pmu_firmware_event_total(event.raw(), Phys::new(buf_phys), num_harts)
+}
+
+/// Counters of the calling hart configured in the current session and never released, bit `i` for counter
+/// `counter_idx_base + i`; with `end_session`, a new session starts. Firmware reports them only when built
+/// with the `leak-check` feature of RustSBI.
+#[inline]
+pub fn pmu_leak_check(counter_idx_base: usize, end_session: bool) -> SbiRet {
+    let flags = if end_session { RUSTSBI_PMU_LEAK_CHECK_END_SESSION } else { 0 };
+    stub::rustsbi_pmu_leak_check(counter_idx_base, flags)
+}
+
+/// Counters which failed the boot-time self-test of the firmware, or with `passed`, those which passed it, bit `i`
+/// for counter `counter_idx_base + i`. Firmware runs the self-test only when built with the `pmu-self-test`
+/// feature of RustSBI-QEMU.
+#[inline]
+pub fn pmu_self_test_result(counter_idx_base: usize, passed: bool) -> SbiRet {
+    let flags = if passed { RUSTSBI_PMU_SELF_TEST_PASSED } else { 0 };
+    stub::rustsbi_pmu_self_test_result(counter_idx_base, flags)
+}
+
+/// Optional capabilities of the firmware build, as `RUSTSBI_PMU_CAP_*` bits of the `events` module.
+#[inline]
+pub fn pmu_capabilities() -> SbiRet {
+    stub::rustsbi_pmu_capabilities(0)
+}
+
+/// Version of the PMU extension the firmware implements, the same value probing the extension returns.
+#[inline]
+pub fn pmu_extension_version() -> SbiRet {
+    stub::rustsbi_pmu_capabilities(RUSTSBI_PMU_CAPABILITIES_VERSION)
+}
+
+/// Stop all counters of the calling hart and unbind them from their events, whoever configured them;
+/// returns the number of counters which were bound.
+#[inline]
+pub fn pmu_reclaim() -> SbiRet {
+    stub::rustsbi_pmu_reclaim()
+}
+
+/// Notify supervisor once firmware counter `counter_idx` reaches `threshold`, then disarm; zero disarms at once.
+/// The notification is a supervisor software interrupt, or with `notify_phys`, the counter value written as a
+/// 64-bit value at that physical address.
+#[inline]
+pub fn pmu_counter_watch(counter_idx: usize, threshold: u64, notify_phys: Option<u64>) -> SbiRet {
+    match notify_phys {
+        Some(shmem_phys) => stub::rustsbi_pmu_counter_watch(counter_idx, threshold, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, Phys::new(shmem_phys)),
+        None => stub::rustsbi_pmu_counter_watch(counter_idx, threshold, 0, Phys::new(0)),
+    }
+}
+
+/// Write the `counter_info` of counters `0` to `num_entries - 1`, as `counter_get_info` returns it, into the
+/// array of XLEN-bit values at physical address `buf_phys`, stopping at the last counter; returns the number
+/// of counters.
+#[inline]
+pub fn pmu_counter_info_read(buf_phys: u64, num_entries: usize) -> SbiRet {
+    stub::rustsbi_pmu_counter_info_read(Phys::new(buf_phys), num_entries, 0)
+}
+
+/// Copy at most `count` entries of the SBI call statistics into the array at physical address `buf_phys`,
+/// each the extension and function IDs as XLEN-bit words followed by the 64-bit number of calls; returns the
+/// number of entries copied. With `clear`, the statistics start over. Firmware keeps them only when built with
+/// the `call-stats` feature of RustSBI.
+#[inline]
+pub fn call_stats(buf_phys: u64, count: usize, clear: bool) -> SbiRet {
+    let flags = if clear { RUSTSBI_STATS_READ_CLEAR } else { 0 };
+    stub::rustsbi_stats_read(Phys::new(buf_phys), count, flags)
+}
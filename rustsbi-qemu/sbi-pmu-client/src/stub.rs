@@ -0,0 +1,99 @@
+//! Stubs of the SBI calls, taking the arguments in the form the calls define for both RV32 and RV64.
+//!
+//! A `u64` argument takes one register on RV64 and two on RV32, lower half first; a physical address,
+//! `Phys`, always takes two registers, with an upper half of zero on RV64.
+
+use crate::ecall::Phys;
+use crate::pmu::EXTENSION_PMU;
+use crate::rustsbi::EXTENSION_RUSTSBI;
+
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+const FUNCTION_PMU_COUNTER_GET_INFO: usize = 0x1;
+const FUNCTION_PMU_COUNTER_CONFIG_MATCHING: usize = 0x2;
+const FUNCTION_PMU_COUNTER_START: usize = 0x3;
+const FUNCTION_PMU_COUNTER_STOP: usize = 0x4;
+const FUNCTION_PMU_COUNTER_FW_READ: usize = 0x5;
+const FUNCTION_PMU_COUNTER_FW_READ_HI: usize = 0x6;
+const FUNCTION_PMU_SNAPSHOT_SET_SHM: usize = 0x7;
+const FUNCTION_PMU_EVENT_GET_INFO: usize = 0x8;
+
+const FUNCTION_RUSTSBI_PMU_DUMP: usize = 0x0;
+const FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH: usize = 0x2;
+const FUNCTION_RUSTSBI_PMU_PROTECT_EVENT: usize = 0x3;
+const FUNCTION_RUSTSBI_PMU_SAMPLER_START: usize = 0x4;
+const FUNCTION_RUSTSBI_PMU_SAMPLER_STOP: usize = 0x5;
+const FUNCTION_RUSTSBI_PMU_COUNTER_READ_CLEAR: usize = 0x6;
+const FUNCTION_RUSTSBI_PMU_HART_STATUS: usize = 0x7;
+const FUNCTION_RUSTSBI_PMU_REMOTE_CONTROL: usize = 0x8;
+const FUNCTION_RUSTSBI_PMU_EVENT_TOTAL: usize = 0x9;
+const FUNCTION_RUSTSBI_PMU_LEAK_CHECK: usize = 0xA;
+const FUNCTION_RUSTSBI_PMU_RECLAIM: usize = 0xB;
+const FUNCTION_RUSTSBI_STATS_READ: usize = 0xC;
+const FUNCTION_RUSTSBI_PMU_COUNTER_WATCH: usize = 0xD;
+const FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ: usize = 0xE;
+const FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT: usize = 0xF;
+const FUNCTION_RUSTSBI_PMU_CAPABILITIES: usize = 0x10;
+
+ecall! {
+    fn pmu_num_counters() = EXTENSION_PMU, FUNCTION_PMU_NUM_COUNTERS;
+    fn pmu_counter_get_info(counter_idx: usize) = EXTENSION_PMU, FUNCTION_PMU_COUNTER_GET_INFO;
+    fn pmu_counter_config_matching(counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) =
+        EXTENSION_PMU, FUNCTION_PMU_COUNTER_CONFIG_MATCHING;
+    fn pmu_counter_start(counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) =
+        EXTENSION_PMU, FUNCTION_PMU_COUNTER_START;
+    fn pmu_counter_stop(counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) = EXTENSION_PMU, FUNCTION_PMU_COUNTER_STOP;
+    fn pmu_counter_fw_read(counter_idx: usize) = EXTENSION_PMU, FUNCTION_PMU_COUNTER_FW_READ;
+    fn pmu_counter_fw_read_hi(counter_idx: usize) = EXTENSION_PMU, FUNCTION_PMU_COUNTER_FW_READ_HI;
+    fn pmu_snapshot_set_shm(shmem_phys: Phys, flags: usize) = EXTENSION_PMU, FUNCTION_PMU_SNAPSHOT_SET_SHM;
+    fn pmu_event_get_info(shmem_phys: Phys, num_entries: usize, flags: usize) = EXTENSION_PMU, FUNCTION_PMU_EVENT_GET_INFO;
+
+    fn rustsbi_pmu_dump() = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_DUMP;
+    fn rustsbi_pmu_counter_read_batch(counter_idx_base: usize, counter_idx_mask: usize, buf_phys: Phys) =
+        EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH;
+    fn rustsbi_pmu_protect_event(event_idx: usize, quantum: usize, jitter: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_PROTECT_EVENT;
+    fn rustsbi_pmu_sampler_start(counter_idx_base: usize, counter_idx_mask: usize, period: usize, buf_phys: Phys, size: usize) =
+        EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_SAMPLER_START;
+    fn rustsbi_pmu_sampler_stop() = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_SAMPLER_STOP;
+    fn rustsbi_pmu_counter_read_clear(counter_idx: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_COUNTER_READ_CLEAR;
+    fn rustsbi_pmu_hart_status(hartid: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_HART_STATUS;
+    fn rustsbi_pmu_remote_control(hart_mask: usize, hart_mask_base: usize, request_phys: Phys) =
+        EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_REMOTE_CONTROL;
+    fn rustsbi_pmu_firmware_event_total(event_idx: usize, buf_phys: Phys, num_harts: usize) =
+        EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_EVENT_TOTAL;
+    fn rustsbi_pmu_leak_check(counter_idx_base: usize, flags: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_LEAK_CHECK;
+    fn rustsbi_pmu_reclaim() = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_RECLAIM;
+    fn rustsbi_stats_read(buf_phys: Phys, count: usize, flags: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_STATS_READ;
+    fn rustsbi_pmu_counter_watch(counter_idx: usize, threshold: u64, flags: usize, shmem_phys: Phys) =
+        EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_COUNTER_WATCH;
+    fn rustsbi_pmu_counter_info_read(buf_phys: Phys, num_entries: usize, flags: usize) =
+        EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ;
+    fn rustsbi_pmu_self_test_result(counter_idx_base: usize, flags: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT;
+    fn rustsbi_pmu_capabilities(flags: usize) = EXTENSION_RUSTSBI, FUNCTION_RUSTSBI_PMU_CAPABILITIES;
+}
+
+// Registers of the 64-bit arguments as the dispatcher of RustSBI reads them: `event_data` from a4, and
+// `initial_value` from a3, with their upper halves in a5 and a4 on RV32
+const_assert!(layout::pmu_counter_config_matching.0[4] == 4);
+const_assert!(layout::pmu_counter_config_matching.1 == if usize::BITS == 32 { 6 } else { 5 });
+const_assert!(layout::pmu_counter_start.0[3] == 3);
+const_assert!(layout::pmu_counter_start.1 == if usize::BITS == 32 { 5 } else { 4 });
+// the threshold of a counter watch from a1 the same way, moving the flags and the shared memory after it
+const_assert!(layout::rustsbi_pmu_counter_watch.0[1] == 1);
+const_assert!(layout::rustsbi_pmu_counter_watch.0[3] == if usize::BITS == 32 { 4 } else { 3 });
+
+// Physical addresses take two registers on both XLENs: a0 and a1 for the shared memory of the PMU
+// extension and the buffers of call statistics and counter information, a1 and a2 after the event of a
+// firmware event total
+const_assert!(layout::pmu_snapshot_set_shm.0[1] == 2);
+const_assert!(layout::rustsbi_stats_read.0[1] == 2);
+const_assert!(layout::rustsbi_pmu_counter_info_read.0[1] == 2);
+const_assert!(layout::pmu_event_get_info.0[1] == 2);
+const_assert!(layout::pmu_event_get_info.0[2] == 3);
+const_assert!(layout::rustsbi_pmu_firmware_event_total.0[2] == 3);
+
+// and a2 and a3 after counters or harts, or a3 and a4 after the sampling period
+const_assert!(layout::rustsbi_pmu_counter_read_batch.0[2] == 2);
+const_assert!(layout::rustsbi_pmu_remote_control.0[2] == 2);
+const_assert!(layout::rustsbi_pmu_sampler_start.0[3] == 3);
+const_assert!(layout::rustsbi_pmu_sampler_start.0[4] == 5);
+const_assert!(layout::rustsbi_pmu_sampler_start.1 == 6);
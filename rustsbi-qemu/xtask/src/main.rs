@@ -1,5 +1,5 @@
 use std::{
-    env,
+    env, fs,
     path::{Path, PathBuf},
     process::{self, Command, Stdio},
 };
@@ -9,10 +9,46 @@ extern crate clap;
 
 // 不要修改DEFAULT_TARGET；如果你需要编译到别的目标，请使用--target编译选项！
 const DEFAULT_TARGET: &'static str = "riscv64imac-unknown-none-elf";
+// 使用--rv32选项时的编译目标
+const RV32_TARGET: &'static str = "riscv32imac-unknown-none-elf";
 
 #[derive(Debug)]
 struct XtaskEnv {
     compile_mode: CompileMode,
+    target: &'static str,
+    // 是否打开QEMU的H扩展
+    hypervisor: bool,
+    // QEMU实现的可编程计数器个数，不指定时使用QEMU的默认值
+    pmu_num: Option<usize>,
+    // 是否以console特性编译PMU测试内核，测试后在串口上接受主机的PMU请求
+    console: bool,
+    // 从PMU测试内核的输出中解码perf.data流，写入此文件
+    perf_data: Option<PathBuf>,
+    // 将PMU测试内核以JSON行输出的测量结果写入此文件
+    json: Option<PathBuf>,
+    // 以bench或bench-check特性编译PMU测试内核，测量每次PMU调用的周期数
+    bench: Option<&'static str>,
+    // 是否以leak-check特性编译RustSBI，记录S层配置后未释放的计数器
+    leak_check: bool,
+    // 是否以call-stats特性编译RustSBI，按扩展和功能编号统计SBI调用
+    call_stats: bool,
+    // 是否以trace-record特性编译RustSBI，按顺序记录PMU调用供测试内核重放
+    trace_record: bool,
+    // 是否以pmu-self-test特性编译RustSBI，启动前自检每个计数器
+    self_test: bool,
+    // 是否以hide-hpmcounters特性编译RustSBI，S态读hpmcounter由固件模拟
+    hide_hpmcounters: bool,
+}
+
+impl XtaskEnv {
+    // 编译目标对应的架构名，用于选择QEMU和objcopy的目标架构
+    fn arch(&self) -> &'static str {
+        if self.target == RV32_TARGET {
+            "riscv32"
+        } else {
+            "riscv64"
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -48,10 +84,58 @@ fn main() {
         (@subcommand gdb =>
             (about: "Run GDB debugger")
         )
+        (@subcommand test =>
+            (about: "Run PMU test kernel in QEMU and check its output")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg rv32: --rv32 "Build and run for RV32 instead of RV64")
+            (@arg hypervisor: --hypervisor "Enable the hypervisor extension to test VS/VU-mode event filtering")
+            (@arg pmu_num: --("pmu-num") +takes_value "Number of programmable counters QEMU implements, such as 0, 8, 16 or 29")
+            (@arg perf_data: --("perf-data") +takes_value "Write samples of the sampling test into this perf.data file")
+            (@arg json: --json +takes_value "Write measurements of the tests into this file as JSON lines")
+            (@arg leak_check: --("leak-check") "Build RustSBI with the leak-check feature to test its counter leak check")
+            (@arg call_stats: --("call-stats") "Build RustSBI with the call-stats feature to test its SBI call statistics")
+            (@arg trace_record: --("trace-record") "Build RustSBI with the trace-record feature to test replaying recorded PMU calls")
+            (@arg self_test: --("self-test") "Build RustSBI with the pmu-self-test feature to test every counter before booting")
+            (@arg hide_hpmcounters: --("hide-hpmcounters") "Build RustSBI with the hide-hpmcounters feature to test emulated reads of hpmcounters")
+        )
+        (@subcommand console =>
+            (about: "Run PMU test kernel in QEMU, then serve PMU requests over the serial console")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg rv32: --rv32 "Build and run for RV32 instead of RV64")
+        )
+        (@subcommand harness =>
+            (about: "Check PMU counters against thresholds over the serial console of the PMU test kernel")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg rv32: --rv32 "Build and run for RV32 instead of RV64")
+            (@arg thresholds: --thresholds +takes_value "Events and bounds of their counts, pmu-harness/thresholds.txt by default")
+        )
+        (@subcommand bench =>
+            (about: "Run PMU test kernel in QEMU and measure cycles per PMU call")
+            (@arg release: --release "Build artifacts in release mode, with optimizations")
+            (@arg rv32: --rv32 "Build and run for RV32 instead of RV64")
+            (@arg check: --check "Fail on calls slower than their baselines in pmu-test-kernel/bench-baseline.txt")
+        )
+        (@subcommand perf =>
+            (about: "Decode the perf.data stream in a saved output of the PMU test kernel")
+            (@arg LOG: +required "Output of the PMU test kernel built with the perf-export feature")
+            (@arg OUTPUT: +required "perf.data file to write")
+        )
     )
     .get_matches();
     let mut xtask_env = XtaskEnv {
         compile_mode: CompileMode::Debug,
+        target: DEFAULT_TARGET,
+        hypervisor: false,
+        pmu_num: None,
+        console: false,
+        perf_data: None,
+        json: None,
+        bench: None,
+        leak_check: false,
+        call_stats: false,
+        trace_record: false,
+        self_test: false,
+        hide_hpmcounters: false,
     };
     eprintln!("xtask: mode: {:?}", xtask_env.compile_mode);
     if let Some(matches) = matches.subcommand_matches("make") {
@@ -91,6 +175,96 @@ fn main() {
         xtask_size_sbi(&xtask_env);
     } else if let Some(_matches) = matches.subcommand_matches("gdb") {
         xtask_gdb(&xtask_env);
+    } else if let Some(matches) = matches.subcommand_matches("test") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        if matches.is_present("rv32") {
+            xtask_env.target = RV32_TARGET;
+        }
+        if matches.is_present("hypervisor") {
+            xtask_env.hypervisor = true;
+        }
+        if let Some(pmu_num) = matches.value_of("pmu_num") {
+            let pmu_num = pmu_num.parse().expect("--pmu-num takes a number");
+            if pmu_num > 29 {
+                eprintln!("xtask: QEMU implements at most 29 programmable counters");
+                process::exit(1);
+            }
+            xtask_env.pmu_num = Some(pmu_num);
+        }
+        if let Some(perf_data) = matches.value_of("perf_data") {
+            xtask_env.perf_data = Some(PathBuf::from(perf_data));
+        }
+        if let Some(json) = matches.value_of("json") {
+            xtask_env.json = Some(PathBuf::from(json));
+        }
+        if matches.is_present("leak_check") {
+            xtask_env.leak_check = true;
+        }
+        if matches.is_present("call_stats") {
+            xtask_env.call_stats = true;
+        }
+        if matches.is_present("trace_record") {
+            xtask_env.trace_record = true;
+        }
+        if matches.is_present("self_test") {
+            xtask_env.self_test = true;
+        }
+        if matches.is_present("hide_hpmcounters") {
+            xtask_env.hide_hpmcounters = true;
+        }
+        xtask_build_sbi(&xtask_env);
+        xtask_binary_sbi(&xtask_env);
+        xtask_build_pmu_test_kernel(&xtask_env);
+        xtask_binary_pmu_test_kernel(&xtask_env);
+        xtask_pmu_test(&xtask_env);
+    } else if let Some(matches) = matches.subcommand_matches("console") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        if matches.is_present("rv32") {
+            xtask_env.target = RV32_TARGET;
+        }
+        xtask_env.console = true;
+        xtask_build_sbi(&xtask_env);
+        xtask_binary_sbi(&xtask_env);
+        xtask_build_pmu_test_kernel(&xtask_env);
+        xtask_binary_pmu_test_kernel(&xtask_env);
+        xtask_pmu_console(&xtask_env);
+    } else if let Some(matches) = matches.subcommand_matches("harness") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        if matches.is_present("rv32") {
+            xtask_env.target = RV32_TARGET;
+        }
+        xtask_env.console = true;
+        xtask_build_sbi(&xtask_env);
+        xtask_binary_sbi(&xtask_env);
+        xtask_build_pmu_test_kernel(&xtask_env);
+        xtask_binary_pmu_test_kernel(&xtask_env);
+        xtask_pmu_harness(&xtask_env, matches.value_of("thresholds"));
+    } else if let Some(matches) = matches.subcommand_matches("bench") {
+        if matches.is_present("release") {
+            xtask_env.compile_mode = CompileMode::Release;
+        }
+        if matches.is_present("rv32") {
+            xtask_env.target = RV32_TARGET;
+        }
+        xtask_env.bench = Some(if matches.is_present("check") {
+            "bench-check"
+        } else {
+            "bench"
+        });
+        xtask_build_sbi(&xtask_env);
+        xtask_binary_sbi(&xtask_env);
+        xtask_build_pmu_test_kernel(&xtask_env);
+        xtask_binary_pmu_test_kernel(&xtask_env);
+        xtask_pmu_test(&xtask_env);
+    } else if let Some(matches) = matches.subcommand_matches("perf") {
+        let log = fs::read_to_string(matches.value_of("LOG").unwrap()).expect("read output log");
+        xtask_perf_decode(&log, Path::new(matches.value_of("OUTPUT").unwrap()));
     } else {
         eprintln!("Use `cargo qemu` to run, `cargo xtask --help` for help")
     }
@@ -108,7 +282,26 @@ fn xtask_build_sbi(xtask_env: &XtaskEnv) {
         }
     }
     command.args(&["--package", "rustsbi-qemu"]);
-    command.args(&["--target", DEFAULT_TARGET]);
+    command.args(&["--target", xtask_env.target]);
+    let mut features = Vec::new();
+    if xtask_env.leak_check {
+        features.push("leak-check");
+    }
+    if xtask_env.call_stats {
+        features.push("call-stats");
+    }
+    if xtask_env.trace_record {
+        features.push("trace-record");
+    }
+    if xtask_env.self_test {
+        features.push("pmu-self-test");
+    }
+    if xtask_env.hide_hpmcounters {
+        features.push("hide-hpmcounters");
+    }
+    if !features.is_empty() {
+        command.args(&["--features", &features.join(",")]);
+    }
     let status = command.status().unwrap();
     if !status.success() {
         println!("cargo build failed");
@@ -128,7 +321,43 @@ fn xtask_build_test_kernel(xtask_env: &XtaskEnv) {
         }
     }
     command.args(&["--package", "test-kernel"]);
-    command.args(&["--target", DEFAULT_TARGET]);
+    command.args(&["--target", xtask_env.target]);
+    let status = command.status().unwrap();
+    if !status.success() {
+        println!("cargo build failed");
+        process::exit(1);
+    }
+}
+
+fn xtask_build_pmu_test_kernel(xtask_env: &XtaskEnv) {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut command = Command::new(cargo);
+    command.current_dir(project_root().join("pmu-test-kernel"));
+    command.arg("build");
+    match xtask_env.compile_mode {
+        CompileMode::Debug => {}
+        CompileMode::Release => {
+            command.arg("--release");
+        }
+    }
+    command.args(&["--package", "pmu-test-kernel"]);
+    let mut features = Vec::new();
+    if xtask_env.console {
+        features.push("console");
+    }
+    if xtask_env.perf_data.is_some() {
+        features.push("perf-export");
+    }
+    if xtask_env.json.is_some() {
+        features.push("json");
+    }
+    if let Some(bench) = xtask_env.bench {
+        features.push(bench);
+    }
+    if !features.is_empty() {
+        command.args(&["--features", &features.join(",")]);
+    }
+    command.args(&["--target", xtask_env.target]);
     let status = command.status().unwrap();
     if !status.success() {
         println!("cargo build failed");
@@ -171,7 +400,7 @@ fn xtask_binary_sbi(xtask_env: &XtaskEnv) {
     let status = Command::new(objcopy)
         .current_dir(dist_dir(xtask_env))
         .arg("rustsbi-qemu")
-        .arg("--binary-architecture=riscv64")
+        .arg(format!("--binary-architecture={}", xtask_env.arch()))
         .arg("--strip-all")
         .args(&["-O", "binary", "rustsbi-qemu.bin"])
         .status()
@@ -188,7 +417,7 @@ fn xtask_binary_test_kernel(xtask_env: &XtaskEnv) {
     let status = Command::new(objcopy)
         .current_dir(dist_dir(xtask_env))
         .arg("test-kernel")
-        .arg("--binary-architecture=riscv64")
+        .arg(format!("--binary-architecture={}", xtask_env.arch()))
         .arg("--strip-all")
         .args(&["-O", "binary", "test-kernel.bin"])
         .status()
@@ -200,6 +429,23 @@ fn xtask_binary_test_kernel(xtask_env: &XtaskEnv) {
     }
 }
 
+fn xtask_binary_pmu_test_kernel(xtask_env: &XtaskEnv) {
+    let objcopy = check_tool("objcopy").expect("Objcopy tool not found");
+    let status = Command::new(objcopy)
+        .current_dir(dist_dir(xtask_env))
+        .arg("pmu-test-kernel")
+        .arg(format!("--binary-architecture={}", xtask_env.arch()))
+        .arg("--strip-all")
+        .args(&["-O", "binary", "pmu-test-kernel.bin"])
+        .status()
+        .unwrap();
+
+    if !status.success() {
+        println!("objcopy binary failed");
+        process::exit(1);
+    }
+}
+
 fn xtask_qemu_run(xtask_env: &XtaskEnv) {
     /*
     qemu: build
@@ -211,7 +457,7 @@ fn xtask_qemu_run(xtask_env: &XtaskEnv) {
             -device loader,file={{test-kernel-bin}},addr=0x80200000 \
             -smp threads={{threads}}
     */
-    let status = Command::new("qemu-system-riscv64")
+    let status = Command::new(format!("qemu-system-{}", xtask_env.arch()))
         .current_dir(dist_dir(xtask_env))
         .args(&["-machine", "virt"])
         .args(&["-bios", "rustsbi-qemu.bin"])
@@ -227,7 +473,7 @@ fn xtask_qemu_run(xtask_env: &XtaskEnv) {
 }
 
 fn xtask_qemu_debug(xtask_env: &XtaskEnv) {
-    let status = Command::new("qemu-system-riscv64")
+    let status = Command::new(format!("qemu-system-{}", xtask_env.arch()))
         .current_dir(dist_dir(xtask_env))
         .args(&["-machine", "virt"])
         .args(&["-bios", "rustsbi-qemu.bin"])
@@ -243,6 +489,166 @@ fn xtask_qemu_debug(xtask_env: &XtaskEnv) {
     }
 }
 
+fn xtask_pmu_test(xtask_env: &XtaskEnv) {
+    let child = pmu_test_qemu(xtask_env)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn child process");
+    let output = child.wait_with_output().expect("wait on child");
+    let string = String::from_utf8_lossy(&output.stdout);
+    print!("{}", string);
+    // the sifive_test device makes QEMU exit with 0 on TEST_PASS, and a non-zero code on TEST_FAIL
+    if !output.status.success() {
+        println!("pmu test failed");
+        process::exit(output.status.code().unwrap_or(1));
+    }
+    if let Some(perf_data) = &xtask_env.perf_data {
+        xtask_perf_decode(&string, perf_data);
+    }
+    if let Some(json) = &xtask_env.json {
+        let lines: String = string
+            .lines()
+            .filter(|line| line.starts_with("{\"event\":"))
+            .map(|line| format!("{}\n", line.trim_end()))
+            .collect();
+        fs::write(json, lines).expect("write measurements");
+    }
+    // tests needing more programmable counters than QEMU implements are skipped, so only the
+    // number of counters the firmware detected is checked instead of the golden output
+    if let Some(pmu_num) = xtask_env.pmu_num {
+        let expected = format!("<< PMU-test: {} programmable counters", pmu_num);
+        if !string.lines().any(|line| line.trim_end() == expected) {
+            println!("pmu test did not detect {} programmable counters", pmu_num);
+            process::exit(1);
+        }
+        return;
+    }
+    let golden_path = project_root()
+        .join("pmu-test-kernel")
+        .join("expected-output.txt");
+    let golden = fs::read_to_string(&golden_path).expect("read golden output");
+    if let Some(line) = missing_golden_line(&string, &golden) {
+        println!("pmu test output does not match {}", golden_path.display());
+        println!("missing line: {}", line);
+        process::exit(1);
+    }
+}
+
+// the serial console stays on the terminal, so that requests can be typed in or piped from a host script
+fn xtask_pmu_console(xtask_env: &XtaskEnv) {
+    let status = pmu_test_qemu(xtask_env).status().expect("run qemu");
+    if !status.success() {
+        println!("pmu console failed");
+        process::exit(status.code().unwrap_or(1));
+    }
+}
+
+// the harness runs QEMU itself, with the same machine as the PMU test
+fn xtask_pmu_harness(xtask_env: &XtaskEnv, thresholds: Option<&str>) {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut command = Command::new(cargo);
+    command.current_dir(project_root());
+    command.args(&["run", "--package", "pmu-harness", "--"]);
+    command.arg("--dist").arg(dist_dir(xtask_env));
+    command.args(&["--qemu", &format!("qemu-system-{}", xtask_env.arch())]);
+    command.args(&["--cpu", &pmu_test_cpu(xtask_env)]);
+    if let Some(thresholds) = thresholds {
+        // cargo runs the harness from the project root
+        let thresholds = env::current_dir().unwrap().join(thresholds);
+        command.arg("--thresholds").arg(thresholds);
+    }
+    let status = command.status().unwrap();
+    if !status.success() {
+        println!("pmu harness failed");
+        process::exit(status.code().unwrap_or(1));
+    }
+}
+
+fn pmu_test_qemu(xtask_env: &XtaskEnv) -> Command {
+    // two harts, so that remote requests and counter isolation between harts are tested;
+    // QEMU counts `instret` by instruction only when icount is enabled
+    let mut command = Command::new(format!("qemu-system-{}", xtask_env.arch()));
+    command
+        .current_dir(dist_dir(xtask_env))
+        .args(&["-machine", "virt"])
+        .args(&["-cpu", &pmu_test_cpu(xtask_env)])
+        .args(&["-smp", "2"])
+        .args(&["-icount", "shift=0"])
+        .args(&["-bios", "rustsbi-qemu.bin"])
+        .args(&["-kernel", "pmu-test-kernel.bin"])
+        .arg("-nographic");
+    command
+}
+
+// Sscofpmf is enabled to test counter overflow interrupts,
+// the hypervisor extension on request to test VS/VU-mode filtering,
+// and the number of programmable counters on request to test their detection by the firmware
+fn pmu_test_cpu(xtask_env: &XtaskEnv) -> String {
+    let mut cpu = if xtask_env.target == RV32_TARGET {
+        String::from("rv32,sscofpmf=true")
+    } else {
+        String::from("rv64,sscofpmf=true")
+    };
+    if xtask_env.hypervisor {
+        cpu.push_str(",h=true");
+    }
+    if let Some(pmu_num) = xtask_env.pmu_num {
+        cpu.push_str(&format!(",pmu-num={}", pmu_num));
+    }
+    cpu
+}
+
+// the perf.data stream is printed in hexadecimal on lines starting with `PERF`, between `PERF BEGIN` and `PERF END`
+fn xtask_perf_decode(log: &str, output: &Path) {
+    let mut lines = log.lines().map(|line| line.trim_end());
+    if !lines.any(|line| line == "PERF BEGIN") {
+        println!("no perf.data stream in the output, is the PMU test kernel built with the perf-export feature?");
+        process::exit(1);
+    }
+    let mut bytes = Vec::new();
+    for line in lines {
+        if line == "PERF END" {
+            fs::write(output, &bytes).expect("write perf.data");
+            println!(
+                "xtask: {} bytes of perf.data written to {}",
+                bytes.len(),
+                output.display()
+            );
+            return;
+        }
+        // lines printed by other harts may come in between
+        let hex = match line.strip_prefix("PERF ") {
+            Some(hex) => hex,
+            None => continue,
+        };
+        for i in (0..hex.len()).step_by(2) {
+            match hex
+                .get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            {
+                Some(byte) => bytes.push(byte),
+                None => {
+                    println!("invalid perf.data line: {}", line);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+    println!("perf.data stream is not complete");
+    process::exit(1);
+}
+
+// every golden line must appear in the output in the same order; lines with changing values are left out of the golden file
+fn missing_golden_line<'a>(output: &str, golden: &'a str) -> Option<&'a str> {
+    let mut lines = output.lines().map(|line| line.trim_end());
+    for expected in golden.lines().filter(|line| !line.is_empty()) {
+        if !lines.any(|line| line == expected) {
+            return Some(expected);
+        }
+    }
+    None
+}
+
 fn xtask_gdb(xtask_env: &XtaskEnv) {
     let status = Command::new("riscv64-unknown-elf-gdb")
         .current_dir(dist_dir(xtask_env))
@@ -267,7 +673,7 @@ fn project_root() -> PathBuf {
 }
 
 fn dist_dir(xtask_env: &XtaskEnv) -> PathBuf {
-    let mut path_buf = project_root().join("target").join(DEFAULT_TARGET);
+    let mut path_buf = project_root().join("target").join(xtask_env.target);
     path_buf = match xtask_env.compile_mode {
         CompileMode::Debug => path_buf.join("debug"),
         CompileMode::Release => path_buf.join("release"),
@@ -313,6 +719,18 @@ fn check_tool<S: AsRef<str>>(tool: S) -> Option<String> {
 fn run_test_kernel() {
     let xtask_env = XtaskEnv {
         compile_mode: CompileMode::Debug,
+        target: DEFAULT_TARGET,
+        hypervisor: false,
+        pmu_num: None,
+        console: false,
+        perf_data: None,
+        json: None,
+        bench: None,
+        leak_check: false,
+        call_stats: false,
+        trace_record: false,
+        self_test: false,
+        hide_hpmcounters: false,
     };
     xtask_build_sbi(&xtask_env);
     xtask_binary_sbi(&xtask_env);
@@ -0,0 +1,284 @@
+//! Feed sequences of PMU calls into `rustsbi::ecall`, backed by `GenericPmu` over simulated hardware counters.
+//!
+//! Run with `cargo fuzz run ecall_pmu` in the `rustsbi` directory. Every call must return an error code
+//! defined by the SBI specification, the simulated hardware must only be accessed in ways the CSRs allow,
+//! and once supervisor stops and resets all counters, every counter must be free again.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustsbi::pmu::events::{event_idx, EVENT_NAMES, SBI_PMU_HW_CPU_CYCLES, SBI_PMU_HW_INSTRUCTIONS};
+use rustsbi::pmu::{
+    EventInfo, GenericPmu, PmuPlatform, COUNTER_CYCLE, COUNTER_INSTRET, COUNTER_TIME, EVENT_TYPE_FIRMWARE,
+    EVENT_TYPE_HARDWARE_CACHE, EVENT_TYPE_HARDWARE_GENERAL, EVENT_TYPE_HARDWARE_RAW, FIRMWARE_COUNTERS,
+    FIRST_HPM_COUNTER, SBI_PMU_FW_SET_TIMER, SBI_PMU_STOP_FLAG_RESET,
+};
+use rustsbi::SbiRet;
+use std::sync::{Mutex, OnceLock};
+
+const EXTENSION_PMU: usize = 0x504D55;
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+const FUNCTION_PMU_COUNTER_GET_INFO: usize = 0x1;
+const FUNCTION_PMU_COUNTER_CFG_MATCH: usize = 0x2;
+const FUNCTION_PMU_COUNTER_START: usize = 0x3;
+const FUNCTION_PMU_COUNTER_STOP: usize = 0x4;
+const FUNCTION_PMU_SNAPSHOT_SET_SHM: usize = 0x7;
+const FUNCTION_PMU_EVENT_GET_INFO: usize = 0x8;
+
+// error codes defined by the SBI specification, from SBI_ERR_FAILED to SBI_ERR_NO_SHMEM
+const SBI_ERR_FAILED: isize = -1;
+const SBI_ERR_NO_SHMEM: isize = -9;
+
+// `cycle`, `time`, `instret` and five programmable counters
+const NUM_HARDWARE_COUNTERS: usize = 8;
+// programmable counters implement fewer bits, so that they wrap around during a run
+const HPM_COUNTER_BITS: u32 = 20;
+// counters advance this much each time they are read while running
+const STEP: u64 = 4099;
+
+// hardware counters kept in memory, checking each access against what the CSRs allow
+struct Simulated {
+    sscofpmf: bool,
+    csrs: Mutex<Csrs>,
+}
+
+struct Csrs {
+    inhibit: usize,
+    counters: [u64; NUM_HARDWARE_COUNTERS],
+    mhpmevents: [u64; NUM_HARDWARE_COUNTERS],
+}
+
+impl Simulated {
+    fn new(sscofpmf: bool) -> Simulated {
+        Simulated {
+            sscofpmf,
+            csrs: Mutex::new(Csrs {
+                inhibit: 0,
+                counters: [0; NUM_HARDWARE_COUNTERS],
+                mhpmevents: [0; NUM_HARDWARE_COUNTERS],
+            }),
+        }
+    }
+
+    fn mask(counter_idx: usize) -> u64 {
+        if counter_idx >= FIRST_HPM_COUNTER {
+            (1 << HPM_COUNTER_BITS) - 1
+        } else {
+            u64::MAX
+        }
+    }
+}
+
+fn check_counter(counter_idx: usize) {
+    assert!(counter_idx < NUM_HARDWARE_COUNTERS, "counter {} is not implemented", counter_idx);
+}
+
+fn check_programmable(counter_idx: usize) {
+    check_counter(counter_idx);
+    assert!(counter_idx >= FIRST_HPM_COUNTER, "counter {} has no mhpmevent", counter_idx);
+}
+
+fn check_inhibit_bits(bits: usize) {
+    let implemented = (1 << NUM_HARDWARE_COUNTERS) - 1;
+    assert_eq!(bits & !implemented, 0, "mcountinhibit bits {:#x} of counters not implemented", bits);
+    assert_eq!(bits & (1 << COUNTER_TIME), 0, "mcountinhibit bit of time written");
+}
+
+impl PmuPlatform for Simulated {
+    fn num_counters(&self) -> usize {
+        NUM_HARDWARE_COUNTERS
+    }
+
+    fn has_sscofpmf(&self) -> bool {
+        self.sscofpmf
+    }
+
+    fn counter_can_monitor(&self, counter_idx: usize, event_idx: usize, event_data: u64) -> bool {
+        check_counter(counter_idx);
+        match counter_idx {
+            COUNTER_CYCLE => event_idx == event_idx_general(SBI_PMU_HW_CPU_CYCLES),
+            COUNTER_TIME => false,
+            COUNTER_INSTRET => event_idx == event_idx_general(SBI_PMU_HW_INSTRUCTIONS),
+            // each programmable counter monitors a different subset of events
+            _ => match event_idx >> 16 {
+                EVENT_TYPE_HARDWARE_GENERAL | EVENT_TYPE_HARDWARE_CACHE => (event_idx + counter_idx) % 3 != 0,
+                EVENT_TYPE_HARDWARE_RAW => event_data % 2 == (counter_idx % 2) as u64,
+                _ => false,
+            },
+        }
+    }
+
+    fn mhpmevent_value(&self, event_idx: usize, _event_data: u64) -> u64 {
+        event_idx as u64
+    }
+
+    fn has_mcountinhibit(&self) -> bool {
+        true
+    }
+
+    fn hart_id(&self) -> usize {
+        0
+    }
+
+    fn read_mcountinhibit(&self) -> usize {
+        self.csrs.lock().unwrap().inhibit
+    }
+
+    unsafe fn set_mcountinhibit(&self, bits: usize) {
+        check_inhibit_bits(bits);
+        self.csrs.lock().unwrap().inhibit |= bits;
+    }
+
+    unsafe fn clear_mcountinhibit(&self, bits: usize) {
+        check_inhibit_bits(bits);
+        self.csrs.lock().unwrap().inhibit &= !bits;
+    }
+
+    fn counter_width(&self, counter_idx: usize) -> u32 {
+        check_counter(counter_idx);
+        if counter_idx >= FIRST_HPM_COUNTER {
+            HPM_COUNTER_BITS
+        } else {
+            64
+        }
+    }
+
+    fn read_counter(&self, counter_idx: usize) -> u64 {
+        check_counter(counter_idx);
+        let mut csrs = self.csrs.lock().unwrap();
+        if csrs.inhibit & (1 << counter_idx) == 0 {
+            csrs.counters[counter_idx] = csrs.counters[counter_idx].wrapping_add(STEP) & Simulated::mask(counter_idx);
+        }
+        csrs.counters[counter_idx]
+    }
+
+    unsafe fn write_counter(&self, counter_idx: usize, value: u64) {
+        check_counter(counter_idx);
+        assert_ne!(counter_idx, COUNTER_TIME, "time counter written");
+        self.csrs.lock().unwrap().counters[counter_idx] = value & Simulated::mask(counter_idx);
+    }
+
+    fn read_mhpmevent(&self, counter_idx: usize) -> u64 {
+        check_programmable(counter_idx);
+        self.csrs.lock().unwrap().mhpmevents[counter_idx]
+    }
+
+    unsafe fn write_mhpmevent(&self, counter_idx: usize, value: u64) {
+        check_programmable(counter_idx);
+        self.csrs.lock().unwrap().mhpmevents[counter_idx] = value;
+    }
+}
+
+fn event_idx_general(code: usize) -> usize {
+    event_idx(EVENT_TYPE_HARDWARE_GENERAL, code)
+}
+
+// shared memory of snapshots and event queries, kept for the whole run, as the firmware may write into it
+// from later calls
+#[repr(C, align(4096))]
+struct Page([u8; 4096]);
+
+fn snapshot_page() -> usize {
+    static PAGE: OnceLock<usize> = OnceLock::new();
+    *PAGE.get_or_init(|| Box::leak(Box::new(Page([0; 4096]))) as *mut Page as usize)
+}
+
+const NUM_QUERIES: usize = 16;
+
+fn queries() -> usize {
+    static QUERIES: OnceLock<usize> = OnceLock::new();
+    *QUERIES.get_or_init(|| {
+        let queries: Vec<EventInfo> = (0..NUM_QUERIES)
+            .map(|_| EventInfo {
+                event_idx: 0,
+                output: 0,
+                event_data: 0,
+            })
+            .collect();
+        Box::leak(queries.into_boxed_slice()).as_mut_ptr() as usize
+    })
+}
+
+fn call(function: usize, param: [usize; 6]) -> SbiRet {
+    let ret = rustsbi::ecall(EXTENSION_PMU, function, param);
+    let error = ret.error as isize;
+    assert!(
+        error == 0 || (SBI_ERR_NO_SHMEM..=SBI_ERR_FAILED).contains(&error),
+        "function {} {:x?} returned error {}",
+        function,
+        param,
+        error
+    );
+    ret
+}
+
+// turn raw fuzzer input into parameters the call can act on; addresses of shared memory always point into
+// memory of the harness
+fn shape(function: usize, mut param: [usize; 6], num_counters: usize) -> [usize; 6] {
+    match function {
+        FUNCTION_PMU_COUNTER_GET_INFO => param[0] %= num_counters + 2,
+        FUNCTION_PMU_COUNTER_CFG_MATCH | FUNCTION_PMU_COUNTER_START | FUNCTION_PMU_COUNTER_STOP => {
+            param[0] %= num_counters + 2;
+            param[1] &= (1 << num_counters) - 1;
+            param[2] &= 0x1FF;
+            if function == FUNCTION_PMU_COUNTER_CFG_MATCH && param[3] >> 20 == 0 {
+                // mostly events which exist
+                param[3] = EVENT_NAMES[param[3] % EVENT_NAMES.len()].1;
+            }
+        }
+        FUNCTION_PMU_SNAPSHOT_SET_SHM if param[0] != usize::MAX => {
+            param[0] = snapshot_page() + param[0] % 2 * 8;
+            param[1] &= 1;
+            param[2] &= 1;
+        }
+        FUNCTION_PMU_EVENT_GET_INFO => {
+            param[0] = queries() + param[0] % 2 * 8;
+            param[1] &= 1;
+            param[2] %= NUM_QUERIES;
+            param[3] &= 1;
+        }
+        _ => {}
+    }
+    param
+}
+
+fn run(sscofpmf: bool, calls: &[(u8, [usize; 6])]) {
+    rustsbi::init_pmu(GenericPmu::new(Simulated::new(sscofpmf)));
+    let num_counters = call(FUNCTION_PMU_NUM_COUNTERS, [0; 6]).value;
+    for &(function, param) in calls {
+        let function = function as usize % (FUNCTION_PMU_EVENT_GET_INFO + 2);
+        let param = shape(function, param, num_counters);
+        let ret = call(function, param);
+        if function == FUNCTION_PMU_COUNTER_CFG_MATCH && ret.error == 0 {
+            let counter_idx = ret.value;
+            assert!(
+                counter_idx >= param[0] && counter_idx - param[0] < usize::BITS as usize && param[1] & 1 << (counter_idx - param[0]) != 0,
+                "counter {} is not in the set {:#x} from {}",
+                counter_idx,
+                param[1],
+                param[0]
+            );
+        }
+        assert_eq!(call(FUNCTION_PMU_NUM_COUNTERS, [0; 6]).value, num_counters, "number of counters changed");
+    }
+
+    // stop and release every counter, started or not; counters never bound are rejected
+    for counter_idx in 0..num_counters {
+        call(FUNCTION_PMU_COUNTER_START, [counter_idx, 1, 0, 0, 0, 0]);
+        call(FUNCTION_PMU_COUNTER_STOP, [counter_idx, 1, SBI_PMU_STOP_FLAG_RESET, 0, 0, 0]);
+    }
+    // no counter is left bound: `cycle` and `instret` take their events again, and so does every firmware counter
+    let all = ((1 << num_counters) - 1) & !(1 << COUNTER_TIME);
+    for &code in &[SBI_PMU_HW_CPU_CYCLES, SBI_PMU_HW_INSTRUCTIONS] {
+        let ret = call(FUNCTION_PMU_COUNTER_CFG_MATCH, [0, all, 0, event_idx_general(code), 0, 0]);
+        assert_eq!(ret.error, 0, "hardware event {} has no free counter", code);
+    }
+    let fw_event = event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_SET_TIMER);
+    for _ in 0..FIRMWARE_COUNTERS {
+        let ret = call(FUNCTION_PMU_COUNTER_CFG_MATCH, [0, all, 0, fw_event, 0, 0]);
+        assert_eq!(ret.error, 0, "firmware counter left bound");
+    }
+}
+
+fuzz_target!(|input: (bool, Vec<(u8, [usize; 6])>)| {
+    run(input.0, &input.1);
+});
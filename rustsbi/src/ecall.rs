@@ -8,7 +8,11 @@ mod legacy;
 mod srst;
 mod timer;
 mod rfence;
+#[cfg(feature = "pmu")]
 mod pmu;
+mod firmware;
+
+use core::convert::TryFrom;
 
 pub const EXTENSION_BASE: usize = 0x10;
 pub const EXTENSION_TIMER: usize = 0x54494D45;
@@ -17,6 +21,8 @@ pub const EXTENSION_RFENCE: usize = 0x52464E43;
 pub const EXTENSION_HSM: usize = 0x48534D;
 pub const EXTENSION_SRST: usize = 0x53525354;
 pub const EXTENSION_PMU: usize = 0x504D55;
+// firmware specific extension space starts from 0x0A000000, low bits are the implementation ID of RustSBI
+pub const EXTENSION_RUSTSBI: usize = 0x0A000000 + crate::IMPL_ID_RUSTSBI;
 
 const LEGACY_SET_TIMER: usize = 0x0;
 const LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
@@ -50,7 +56,7 @@ const LEGACY_SHUTDOWN: usize = 0x08;
 /// #[exception]
 /// fn handle_exception(ctx: &mut TrapFrame) {
 ///     if mcause::read().cause() == Trap::Exception(Exception::SupervisorEnvCall) {
-///         let params = [ctx.a0, ctx.a1, ctx.a2, ctx.a3, ctx.a4];
+///         let params = [ctx.a0, ctx.a1, ctx.a2, ctx.a3, ctx.a4, ctx.a5];
 ///         let ans = rustsbi::ecall(ctx.a7, ctx.a6, params);
 ///         ctx.a0 = ans.error;
 ///         ctx.a1 = ans.value;
@@ -62,33 +68,75 @@ const LEGACY_SHUTDOWN: usize = 0x08;
 ///
 /// Do not forget to advance `mepc` by 4 after an ecall is handled.
 /// This skips the `ecall` instruction itself which is 4-byte long in all conditions.
+///
+/// Calls of extensions RustSBI does not implement go to the handlers platforms register with
+/// `rustsbi::register_extension`; calls of unknown extensions return `SBI_ERR_NOT_SUPPORTED`.
+///
+/// With the `trace` feature, the call and its result are reported to the trace sink, see `rustsbi::trace`.
+/// With the `call-stats` feature, the call is counted by its extension and function IDs, see `rustsbi::stats`.
+/// With the `pmu` feature, it is counted by the platform specific firmware events `RUSTSBI_FW_PMU_SBI_CALLS`
+/// and, for calls of the PMU extension, `RUSTSBI_FW_PMU_PMU_CALLS`.
 #[inline]
-pub fn handle_ecall(extension: usize, function: usize, param: [usize; 5]) -> SbiRet {
-    match extension {
-        EXTENSION_RFENCE => rfence::handle_ecall_rfence(function, param[0], param[1], param[2], param[3], param[4]),
-        EXTENSION_TIMER => match () {
-            #[cfg(target_pointer_width = "64")]
-            () => timer::handle_ecall_timer_64(function, param[0]),
-            #[cfg(target_pointer_width = "32")]
-            () => timer::handle_ecall_timer_32(function, param[0], param[1]),
-        },
-        EXTENSION_IPI => ipi::handle_ecall_ipi(function, param[0], param[1]),
-        EXTENSION_BASE => base::handle_ecall_base(function, param[0]),
-        EXTENSION_HSM => hsm::handle_ecall_hsm(function, param[0], param[1], param[2]),
-        EXTENSION_SRST => srst::handle_ecall_srst(function, param[0], param[1]),
-        EXTENSION_PMU => pmu::handle_ecall_pmu(function, param[0], param[1], param[2],param[3],param[4] as u64),
-        LEGACY_SET_TIMER => match () {
+pub fn handle_ecall(extension: usize, function: usize, param: [usize; 6]) -> SbiRet {
+    #[cfg(feature = "call-stats")]
+    crate::stats::count_ecall(extension, function);
+    #[cfg(feature = "pmu")]
+    crate::pmu::count_sbi_call(extension);
+    let ans = dispatch_ecall(extension, function, param);
+    #[cfg(feature = "trace")]
+    crate::trace::trace_ecall(extension, function, param, &ans);
+    ans
+}
+
+/// Handler of the functions of one SBI extension
+///
+/// It is given the function ID from `a6` register and the parameters from `a0` to `a5` registers,
+/// and returns the result to be stored into `a0` and `a1`.
+pub type ExtensionHandler = fn(function: usize, param: [usize; 6]) -> SbiRet;
+
+// extensions implemented by RustSBI; legacy extensions ignore the function ID and keep their own return convention
+static BUILTIN_EXTENSIONS: &[(usize, ExtensionHandler)] = &[
+    (EXTENSION_RFENCE, |function, param| rfence::handle_ecall_rfence(function, param[0], param[1], param[2], param[3], param[4])),
+    (EXTENSION_TIMER, |function, param| match () {
+        #[cfg(target_pointer_width = "64")]
+        () => timer::handle_ecall_timer_64(function, param[0]),
+        #[cfg(target_pointer_width = "32")]
+        () => timer::handle_ecall_timer_32(function, param[0], param[1]),
+    }),
+    (EXTENSION_IPI, |function, param| ipi::handle_ecall_ipi(function, param[0], param[1])),
+    (EXTENSION_BASE, |function, param| base::handle_ecall_base(function, param[0])),
+    (EXTENSION_HSM, |function, param| hsm::handle_ecall_hsm(function, param[0], param[1], param[2])),
+    (EXTENSION_SRST, |function, param| srst::handle_ecall_srst(function, param[0], param[1])),
+    #[cfg(feature = "pmu")]
+    (EXTENSION_PMU, |function, param| {
+        pmu::handle_ecall_pmu(function, param[0], param[1], param[2], param[3], param[4], param[5])
+    }),
+    (EXTENSION_RUSTSBI, firmware::handle_ecall_firmware),
+    (LEGACY_SET_TIMER, |_, param| {
+        match () {
             #[cfg(target_pointer_width = "64")]
             () => legacy::set_timer_64(param[0]),
             #[cfg(target_pointer_width = "32")]
             () => legacy::set_timer_32(param[0], param[1]),
         }
-        .legacy_void(param[0], param[1]),
-        LEGACY_CONSOLE_PUTCHAR => legacy::console_putchar(param[0]).legacy_void(param[0], param[1]),
-        LEGACY_CONSOLE_GETCHAR => legacy::console_getchar().legacy_return(param[1]),
-        LEGACY_SEND_IPI => legacy::send_ipi(param[0]).legacy_void(param[0], param[1]),
-        LEGACY_SHUTDOWN => legacy::shutdown().legacy_void(param[0], param[1]),
-        _ => SbiRet::not_supported(),
+        .legacy_void(param[0], param[1])
+    }),
+    (LEGACY_CONSOLE_PUTCHAR, |_, param| legacy::console_putchar(param[0]).legacy_void(param[0], param[1])),
+    (LEGACY_CONSOLE_GETCHAR, |_, param| legacy::console_getchar().legacy_return(param[1])),
+    (LEGACY_SEND_IPI, |_, param| legacy::send_ipi(param[0]).legacy_void(param[0], param[1])),
+    (LEGACY_SHUTDOWN, |_, param| legacy::shutdown().legacy_void(param[0], param[1])),
+];
+
+// handler of a built-in extension
+pub(crate) fn builtin_extension(extension: usize) -> Option<ExtensionHandler> {
+    BUILTIN_EXTENSIONS.iter().find(|&&(id, _)| id == extension).map(|&(_, handler)| handler)
+}
+
+#[inline]
+fn dispatch_ecall(extension: usize, function: usize, param: [usize; 6]) -> SbiRet {
+    match crate::extension::extension_handler(extension) {
+        Some(handler) => handler(function, param),
+        None => SbiRet::not_supported(),
     }
 }
 
@@ -103,13 +151,16 @@ pub struct SbiRet {
     pub value: usize,
 }
 
-const SBI_SUCCESS: usize = 0;
-// const SBI_ERR_FAILED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-1));
+pub(crate) const SBI_SUCCESS: usize = 0;
+const SBI_ERR_FAILED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-1));
 const SBI_ERR_NOT_SUPPORTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-2));
 const SBI_ERR_INVALID_PARAM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-3));
-// const SBI_ERR_DENIED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-4));
-// const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
-// const SBI_ERR_ALREADY_AVAILABLE: usize = usize::from_ne_bytes(isize::to_ne_bytes(-6));
+const SBI_ERR_DENIED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-4));
+const SBI_ERR_INVALID_ADDRESS: usize = usize::from_ne_bytes(isize::to_ne_bytes(-5));
+const SBI_ERR_ALREADY_AVAILABLE: usize = usize::from_ne_bytes(isize::to_ne_bytes(-6));
+const SBI_ERR_ALREADY_STARTED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-7));
+const SBI_ERR_ALREADY_STOPPED: usize = usize::from_ne_bytes(isize::to_ne_bytes(-8));
+const SBI_ERR_NO_SHMEM: usize = usize::from_ne_bytes(isize::to_ne_bytes(-9));
 
 impl SbiRet {
     /// Return success SBI state with given value.
@@ -119,18 +170,70 @@ impl SbiRet {
             value,
         }
     }
-    pub(crate) fn not_supported() -> SbiRet {
+    /// Return failed SBI state, the operation failed for unknown reasons.
+    pub fn failed() -> SbiRet {
+        SbiRet {
+            error: SBI_ERR_FAILED,
+            value: 0,
+        }
+    }
+    /// Return not supported SBI state, the requested function is not supported.
+    pub fn not_supported() -> SbiRet {
         SbiRet {
             error: SBI_ERR_NOT_SUPPORTED,
             value: 0,
         }
     }
-    pub(crate) fn invalid_param() -> SbiRet {
+    /// Return invalid parameter SBI state, some of the parameters are invalid.
+    pub fn invalid_param() -> SbiRet {
         SbiRet {
             error: SBI_ERR_INVALID_PARAM,
             value: 0,
         }
     }
+    /// Return denied SBI state, the request is not allowed for the caller.
+    pub fn denied() -> SbiRet {
+        SbiRet {
+            error: SBI_ERR_DENIED,
+            value: 0,
+        }
+    }
+    /// Return invalid address SBI state, the memory address is invalid or not accessible.
+    pub fn invalid_address() -> SbiRet {
+        SbiRet {
+            error: SBI_ERR_INVALID_ADDRESS,
+            value: 0,
+        }
+    }
+    /// Return already started SBI state, the requested resource is already started.
+    pub fn already_started() -> SbiRet {
+        SbiRet {
+            error: SBI_ERR_ALREADY_STARTED,
+            value: 0,
+        }
+    }
+    /// Return already stopped SBI state, the requested resource is already stopped.
+    pub fn already_stopped() -> SbiRet {
+        SbiRet {
+            error: SBI_ERR_ALREADY_STOPPED,
+            value: 0,
+        }
+    }
+    /// Return no shared memory SBI state, the shared memory required by the request is not available.
+    pub fn no_shmem() -> SbiRet {
+        SbiRet {
+            error: SBI_ERR_NO_SHMEM,
+            value: 0,
+        }
+    }
+    /// Convert into a `Result`, error numbers not defined by the specification become `SbiError::Failed`.
+    pub fn into_result(self) -> Result<usize, SbiError> {
+        if self.error == SBI_SUCCESS {
+            Ok(self.value)
+        } else {
+            Err(SbiError::try_from(self.error).unwrap_or(SbiError::Failed))
+        }
+    }
     pub(crate) fn legacy_ok(legacy_value: usize) -> SbiRet {
         SbiRet {
             error: legacy_value,
@@ -151,3 +254,104 @@ impl SbiRet {
         }
     }
 }
+
+/// Error of an SBI call
+///
+/// Implementations of RustSBI traits may handle errors with `Result<usize, SbiError>` and the `?` operator,
+/// then convert the result into `SbiRet` returned to supervisor:
+///
+/// ```no_run
+/// # use rustsbi::{SbiError, SbiRet};
+/// fn counter_info(counter_idx: usize) -> Result<usize, SbiError> {
+///     if counter_idx >= 32 {
+///         return Err(SbiError::InvalidParam);
+///     }
+///     Ok(0xC00 + counter_idx)
+/// }
+///
+/// fn pmu_counter_get_info(counter_idx: usize) -> SbiRet {
+///     counter_info(counter_idx).into()
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbiError {
+    /// The operation failed for unknown reasons
+    Failed,
+    /// The requested function is not supported
+    NotSupported,
+    /// Some of the parameters are invalid
+    InvalidParam,
+    /// The operation is not allowed
+    Denied,
+    /// The memory address is invalid or not accessible
+    InvalidAddress,
+    /// The requested resource is already available
+    AlreadyAvailable,
+    /// The requested resource is already started
+    AlreadyStarted,
+    /// The requested resource is already stopped
+    AlreadyStopped,
+    /// The shared memory required by the request is not available
+    NoShmem,
+}
+
+impl SbiError {
+    /// Error number of this error in the SBI calling convention.
+    pub const fn error_code(self) -> usize {
+        match self {
+            SbiError::Failed => SBI_ERR_FAILED,
+            SbiError::NotSupported => SBI_ERR_NOT_SUPPORTED,
+            SbiError::InvalidParam => SBI_ERR_INVALID_PARAM,
+            SbiError::Denied => SBI_ERR_DENIED,
+            SbiError::InvalidAddress => SBI_ERR_INVALID_ADDRESS,
+            SbiError::AlreadyAvailable => SBI_ERR_ALREADY_AVAILABLE,
+            SbiError::AlreadyStarted => SBI_ERR_ALREADY_STARTED,
+            SbiError::AlreadyStopped => SBI_ERR_ALREADY_STOPPED,
+            SbiError::NoShmem => SBI_ERR_NO_SHMEM,
+        }
+    }
+}
+
+impl TryFrom<usize> for SbiError {
+    /// The error number, if it is success or not defined by the specification
+    type Error = usize;
+
+    fn try_from(error: usize) -> Result<SbiError, usize> {
+        match error {
+            SBI_ERR_FAILED => Ok(SbiError::Failed),
+            SBI_ERR_NOT_SUPPORTED => Ok(SbiError::NotSupported),
+            SBI_ERR_INVALID_PARAM => Ok(SbiError::InvalidParam),
+            SBI_ERR_DENIED => Ok(SbiError::Denied),
+            SBI_ERR_INVALID_ADDRESS => Ok(SbiError::InvalidAddress),
+            SBI_ERR_ALREADY_AVAILABLE => Ok(SbiError::AlreadyAvailable),
+            SBI_ERR_ALREADY_STARTED => Ok(SbiError::AlreadyStarted),
+            SBI_ERR_ALREADY_STOPPED => Ok(SbiError::AlreadyStopped),
+            SBI_ERR_NO_SHMEM => Ok(SbiError::NoShmem),
+            _ => Err(error),
+        }
+    }
+}
+
+impl From<SbiError> for SbiRet {
+    fn from(error: SbiError) -> SbiRet {
+        SbiRet {
+            error: error.error_code(),
+            value: 0,
+        }
+    }
+}
+
+impl From<Result<usize, SbiError>> for SbiRet {
+    fn from(result: Result<usize, SbiError>) -> SbiRet {
+        match result {
+            Ok(value) => SbiRet::ok(value),
+            Err(error) => error.into(),
+        }
+    }
+}
+
+impl From<SbiRet> for Result<usize, SbiError> {
+    fn from(ret: SbiRet) -> Result<usize, SbiError> {
+        ret.into_result()
+    }
+}
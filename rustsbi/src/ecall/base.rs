@@ -46,9 +46,8 @@ fn get_sbi_impl_version() -> SbiRet {
 #[inline]
 fn probe_extension(extension_id: usize) -> SbiRet {
     const NO_EXTENSION: usize = 0;
-    const HAS_EXTENSION: usize = 1;
     let ans = crate::extension::probe_extension(extension_id);
-    SbiRet::ok(if ans { HAS_EXTENSION } else { NO_EXTENSION })
+    SbiRet::ok(if ans { crate::extension::probe_value(extension_id) } else { NO_EXTENSION })
 }
 
 #[inline]
@@ -0,0 +1,451 @@
+//! firmware specific extension of RustSBI
+use super::SbiRet;
+
+const FUNCTION_RUSTSBI_PMU_DUMP: usize = 0x0;
+const FUNCTION_RUSTSBI_TRACE_READ: usize = 0x1;
+const FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH: usize = 0x2;
+const FUNCTION_RUSTSBI_PMU_PROTECT_EVENT: usize = 0x3;
+const FUNCTION_RUSTSBI_PMU_SAMPLER_START: usize = 0x4;
+const FUNCTION_RUSTSBI_PMU_SAMPLER_STOP: usize = 0x5;
+const FUNCTION_RUSTSBI_PMU_COUNTER_READ_CLEAR: usize = 0x6;
+const FUNCTION_RUSTSBI_PMU_HART_STATUS: usize = 0x7;
+const FUNCTION_RUSTSBI_PMU_REMOTE_CONTROL: usize = 0x8;
+const FUNCTION_RUSTSBI_PMU_EVENT_TOTAL: usize = 0x9;
+const FUNCTION_RUSTSBI_PMU_LEAK_CHECK: usize = 0xA;
+const FUNCTION_RUSTSBI_PMU_RECLAIM: usize = 0xB;
+const FUNCTION_RUSTSBI_STATS_READ: usize = 0xC;
+const FUNCTION_RUSTSBI_PMU_COUNTER_WATCH: usize = 0xD;
+const FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ: usize = 0xE;
+const FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT: usize = 0xF;
+const FUNCTION_RUSTSBI_PMU_CAPABILITIES: usize = 0x10;
+
+#[inline]
+pub fn handle_ecall_firmware(function: usize, param: [usize; 6]) -> SbiRet {
+    match function {
+        FUNCTION_RUSTSBI_PMU_DUMP => pmu_dump(),
+        FUNCTION_RUSTSBI_TRACE_READ => trace_read(param[0], param[1], param[2]),
+        FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH => pmu_counter_read_batch(param[0], param[1], param[2], param[3]),
+        FUNCTION_RUSTSBI_PMU_PROTECT_EVENT => pmu_protect_event(param[0], param[1], param[2]),
+        FUNCTION_RUSTSBI_PMU_SAMPLER_START => pmu_sampler_start(param),
+        FUNCTION_RUSTSBI_PMU_SAMPLER_STOP => pmu_sampler_stop(),
+        FUNCTION_RUSTSBI_PMU_COUNTER_READ_CLEAR => pmu_counter_read_clear(param[0]),
+        FUNCTION_RUSTSBI_PMU_HART_STATUS => pmu_hart_status(param[0]),
+        FUNCTION_RUSTSBI_PMU_REMOTE_CONTROL => pmu_remote_control(param[0], param[1], param[2], param[3]),
+        FUNCTION_RUSTSBI_PMU_EVENT_TOTAL => pmu_firmware_event_total(param[0], param[1], param[2], param[3]),
+        FUNCTION_RUSTSBI_PMU_LEAK_CHECK => pmu_leak_check(param[0], param[1]),
+        FUNCTION_RUSTSBI_PMU_RECLAIM => pmu_reclaim(),
+        FUNCTION_RUSTSBI_STATS_READ => stats_read(param[0], param[1], param[2], param[3]),
+        FUNCTION_RUSTSBI_PMU_COUNTER_WATCH => pmu_counter_watch(param),
+        FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ => pmu_counter_info_read(param[0], param[1], param[2], param[3]),
+        FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT => pmu_self_test_result(param[0], param[1]),
+        FUNCTION_RUSTSBI_PMU_CAPABILITIES => pmu_capabilities(param[0]),
+        _ => SbiRet::not_supported(),
+    }
+}
+
+#[inline]
+fn pmu_dump() -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_dump(),
+        #[cfg(not(feature = "pmu"))]
+        () => SbiRet::not_supported(),
+    }
+}
+
+#[inline]
+fn pmu_counter_read_batch(counter_idx_base: usize, counter_idx_mask: usize, buf_phys_lo: usize, buf_phys_hi: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_counter_read_batch(counter_idx_base, counter_idx_mask, buf_phys_lo, buf_phys_hi),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((counter_idx_base, counter_idx_mask, buf_phys_lo, buf_phys_hi));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_protect_event(event_idx: usize, quantum: usize, jitter: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_protect_event(event_idx, quantum, jitter),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((event_idx, quantum, jitter));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_sampler_start(param: [usize; 6]) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_sampler_start(param[0], param[1], param[2], param[3], param[4], param[5]),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop(param);
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_sampler_stop() -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_sampler_stop(),
+        #[cfg(not(feature = "pmu"))]
+        () => SbiRet::not_supported(),
+    }
+}
+
+#[inline]
+fn pmu_counter_read_clear(counter_idx: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_counter_read_clear(counter_idx),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop(counter_idx);
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_hart_status(hartid: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_hart_status(hartid),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop(hartid);
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_remote_control(hart_mask: usize, hart_mask_base: usize, request_phys_lo: usize, request_phys_hi: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_remote_control(hart_mask, hart_mask_base, request_phys_lo, request_phys_hi),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((hart_mask, hart_mask_base, request_phys_lo, request_phys_hi));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_firmware_event_total(event_idx: usize, buf_phys_lo: usize, buf_phys_hi: usize, num_harts: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_firmware_event_total(event_idx, buf_phys_lo, buf_phys_hi, num_harts),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((event_idx, buf_phys_lo, buf_phys_hi, num_harts));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_leak_check(counter_idx_base: usize, flags: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_leak_check(counter_idx_base, flags),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((counter_idx_base, flags));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_reclaim() -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_reclaim(),
+        #[cfg(not(feature = "pmu"))]
+        () => SbiRet::not_supported(),
+    }
+}
+
+#[inline]
+fn pmu_counter_watch(param: [usize; 6]) -> SbiRet {
+    match () {
+        #[cfg(all(feature = "pmu", target_pointer_width = "64"))]
+        () => crate::pmu::pmu_counter_watch(param[0], param[1] as u64, param[2], param[3], param[4]),
+        // threshold is passed in a1 (lower half) and a2 (upper half) on RV32, like `initial_value` of
+        // `sbi_pmu_counter_start`, moving the other parameters up by one register
+        #[cfg(all(feature = "pmu", target_pointer_width = "32"))]
+        () => crate::pmu::pmu_counter_watch(param[0], (param[2] as u64) << 32 | param[1] as u64, param[3], param[4], param[5]),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop(param);
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_counter_info_read(buf_phys_lo: usize, buf_phys_hi: usize, num_entries: usize, flags: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_counter_info_read(buf_phys_lo, buf_phys_hi, num_entries, flags),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((buf_phys_lo, buf_phys_hi, num_entries, flags));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_self_test_result(counter_idx_base: usize, flags: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_self_test_result(counter_idx_base, flags),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop((counter_idx_base, flags));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn pmu_capabilities(flags: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::pmu_capabilities(flags),
+        #[cfg(not(feature = "pmu"))]
+        () => {
+            drop(flags);
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn trace_read(buf_phys_lo: usize, buf_phys_hi: usize, count: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "trace")]
+        () => crate::trace::trace_read(buf_phys_lo, buf_phys_hi, count),
+        #[cfg(not(feature = "trace"))]
+        () => {
+            drop((buf_phys_lo, buf_phys_hi, count));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[inline]
+fn stats_read(buf_phys_lo: usize, buf_phys_hi: usize, count: usize, flags: usize) -> SbiRet {
+    match () {
+        #[cfg(feature = "call-stats")]
+        () => crate::stats::stats_read(buf_phys_lo, buf_phys_hi, count, flags),
+        #[cfg(not(feature = "call-stats"))]
+        () => {
+            drop((buf_phys_lo, buf_phys_hi, count, flags));
+            SbiRet::not_supported()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "pmu"))]
+mod tests {
+    use super::*;
+    use crate::pmu::mock::{with_mock_pmu, without_pmu, Call};
+    use crate::pmu::{run_pmu_self_test, WatchNotify, PMU_VERSION_0_3, RUSTSBI_PMU_CAPABILITIES_VERSION, RUSTSBI_PMU_CAP_READ_BATCH};
+    use crate::pmu::{RUSTSBI_PMU_CAP_SNAPSHOT, RUSTSBI_PMU_SELF_TEST_PASSED, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM};
+
+    // registers of the counter watch call, whose threshold takes one register on RV64 and two on RV32
+    #[cfg(target_pointer_width = "64")]
+    fn watch(counter_idx: usize, threshold: u64, flags: usize, shmem_phys_lo: usize, shmem_phys_hi: usize) -> SbiRet {
+        let param = [counter_idx, threshold as usize, flags, shmem_phys_lo, shmem_phys_hi, 0x5a5a];
+        handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_WATCH, param)
+    }
+    #[cfg(target_pointer_width = "32")]
+    fn watch(counter_idx: usize, threshold: u64, flags: usize, shmem_phys_lo: usize, shmem_phys_hi: usize) -> SbiRet {
+        let param = [counter_idx, threshold as usize, (threshold >> 32) as usize, flags, shmem_phys_lo, shmem_phys_hi];
+        handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_WATCH, param)
+    }
+
+    #[test]
+    fn passes_counter_watch() {
+        let mut flag = 0u64;
+        let shmem = &mut flag as *mut u64 as usize;
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            watch(40, 0x1_0000_0064, 0, 0, 0);
+            watch(41, 7, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, shmem, 0);
+        });
+        assert_eq!(
+            calls,
+            [
+                Call::Watch(40, 0x1_0000_0064, WatchNotify::SoftwareInterrupt),
+                Call::Watch(41, 7, WatchNotify::SharedMemory(shmem)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_counter_watch() {
+        let mut flag = 0u64;
+        let shmem = &mut flag as *mut u64 as usize;
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            // reserved flags
+            let ret = watch(40, 100, 1 << 1, 0, 0);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            // shared memory not aligned to its value
+            let ret = watch(40, 100, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, shmem + 4, 0);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            // shared memory above XLEN bits
+            let ret = watch(40, 100, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, shmem, 1);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+            // shared memory supervisor may not share
+            crate::shmem::init_test_shmem();
+            let ret = watch(40, 100, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM, crate::shmem::TEST_HIDDEN.start, 0);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+        });
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn reads_counter_info() {
+        let mut buf = [usize::MAX; 4];
+        let calls = with_mock_pmu(SbiRet::ok(3), || {
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [buf.as_mut_ptr() as usize, 0, 4, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 3));
+        });
+        // entries beyond the number of counters are left alone
+        assert_eq!(buf, [3, 3, 3, usize::MAX]);
+        assert_eq!(calls, [Call::NumCounters, Call::CounterGetInfo(0), Call::CounterGetInfo(1), Call::CounterGetInfo(2)]);
+
+        // fewer entries than counters, and none at all
+        let calls = with_mock_pmu(SbiRet::ok(3), || {
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [buf.as_mut_ptr() as usize, 0, 1, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 3));
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [0, 0, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 3));
+        });
+        assert_eq!(calls, [Call::NumCounters, Call::CounterGetInfo(0), Call::NumCounters]);
+    }
+
+    #[test]
+    fn rejects_bad_counter_info_read() {
+        let mut buf = [0usize; 4];
+        let base = buf.as_mut_ptr() as usize;
+        let calls = with_mock_pmu(SbiRet::ok(3), || {
+            // reserved flags
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [base, 0, 4, 1, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            // buffer not aligned to its entries
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [base + 2, 0, 4, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            // buffer above XLEN bits
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [base, 1, 4, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+            // buffer reaching into memory supervisor may not share, even past the last counter
+            crate::shmem::init_test_shmem();
+            let hidden = crate::shmem::TEST_HIDDEN.start - 4 * core::mem::size_of::<usize>();
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [hidden, 0, 5, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+            // a number of entries whose size overflows
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_INFO_READ, [base, 0, usize::MAX, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+        });
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn reports_self_test() {
+        // the mock PMU binds and starts every counter, but can not read them
+        let calls = with_mock_pmu(SbiRet::ok(3), || {
+            assert_eq!(run_pmu_self_test(), 3);
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT, [0, 0, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 0b111));
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT, [1, RUSTSBI_PMU_SELF_TEST_PASSED, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 0));
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT, [2, 0, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 0b1));
+            // counter index base past the last counter, and reserved flags
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT, [3, 0, 0, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_SELF_TEST_RESULT, [0, 1 << 1, 0, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+        });
+        assert_eq!(calls[..4], [Call::NumCounters, Call::CounterGetInfo(0), Call::ConfigMatching(0, 1, 2, 0x1, 0), Call::Start(0, 1, 0, 0)]);
+        assert_eq!(calls.iter().filter(|call| matches!(call, Call::Stop(_, 1, 1))).count(), 3);
+    }
+
+    #[test]
+    fn reports_capabilities() {
+        let caps = RUSTSBI_PMU_CAP_SNAPSHOT | RUSTSBI_PMU_CAP_READ_BATCH;
+        let calls = with_mock_pmu(SbiRet::ok(caps), || {
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_CAPABILITIES, [0, 0, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, caps));
+            // the mock PMU keeps the default version
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_CAPABILITIES, [RUSTSBI_PMU_CAPABILITIES_VERSION, 0, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, PMU_VERSION_0_3));
+            // reserved flags
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_CAPABILITIES, [1 << 1, 0, 0, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+        });
+        assert_eq!(calls, [Call::Capabilities]);
+        without_pmu(|| {
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_CAPABILITIES, [0, 0, 0, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::not_supported().error);
+        });
+    }
+
+    #[test]
+    fn rejects_hidden_read_batch_buffer() {
+        crate::shmem::init_test_shmem();
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            // the third value would be written into memory supervisor may not share
+            let buf = crate::shmem::TEST_HIDDEN.start - 16;
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_COUNTER_READ_BATCH, [3, 0b101, buf, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+        });
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn rejects_hidden_sampler_buffer() {
+        crate::shmem::init_test_shmem();
+        // the end of the ring buffer lies in memory supervisor may not share
+        let buf = crate::shmem::TEST_HIDDEN.start - 64;
+        let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_SAMPLER_START, [0, 0b1, 100, buf, 0, 128]);
+        assert_eq!(ret.error, SbiRet::invalid_address().error);
+    }
+
+    #[test]
+    fn writes_firmware_event_total() {
+        let mut buf = [u64::MAX; 4];
+        let calls = with_mock_pmu(SbiRet::ok(6), || {
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_EVENT_TOTAL, [0xf0001, buf.as_mut_ptr() as usize, 0, 3, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 6));
+            // with no harts only the total is returned
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_EVENT_TOTAL, [0xf0001, 0, 0, 0, 0, 0]);
+            assert_eq!((ret.error, ret.value), (0, 6));
+        });
+        assert_eq!(buf, [0, 1, 2, u64::MAX]);
+        assert_eq!(calls, [Call::FirmwareEventTotal(0xf0001, 3), Call::FirmwareEventTotal(0xf0001, 0)]);
+
+        crate::shmem::init_test_shmem();
+        let calls = with_mock_pmu(SbiRet::ok(6), || {
+            // the value of the third hart would be written into memory supervisor may not share
+            let buf = crate::shmem::TEST_HIDDEN.start - 16;
+            let ret = handle_ecall_firmware(FUNCTION_RUSTSBI_PMU_EVENT_TOTAL, [0xf0001, buf, 0, 3, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+        });
+        assert!(calls.is_empty());
+    }
+}
@@ -7,7 +7,15 @@ use riscv::register::{mie, mip};
 #[inline]
 pub fn console_putchar(param0: usize) -> SbiRet {
     let ch = (param0 & 0xff) as u8;
+    #[cfg(feature = "pmu")]
+    let start = riscv::register::mcycle::read64();
     legacy_stdio_putchar(ch);
+    // cycles spent here are counted by the platform specific firmware event `RUSTSBI_FW_PMU_CONSOLE_CYCLES`
+    #[cfg(feature = "pmu")]
+    crate::pmu::count_firmware_cycles(
+        crate::pmu::RUSTSBI_FW_PMU_CONSOLE_CYCLES,
+        riscv::register::mcycle::read64().wrapping_sub(start),
+    );
     SbiRet::ok(0) // the return value 0 is ignored in legacy
 }
 
@@ -1,43 +1,65 @@
 
 use super::SbiRet;
 
-use riscv::register;
-
 const FUNCTION_PMU_NUM_COUNTERS:usize =	0x0;
 const FUNCTION_PMU_COUNTER_GET_INFO:usize =	0x1;
 const FUNCTION_PMU_COUNTER_CFG_MATCH:usize =	0x2;
 const FUNCTION_PMU_COUNTER_START:usize =	0x3;
 const FUNCTION_PMU_COUNTER_STOP:usize =	0x4;
 const FUNCTION_PMU_COUNTER_FW_READ:usize =	0x5;
-/**
- * Generalized hardware cache events:
- *
- *       { L1-D, L1-I, LLC, ITLB, DTLB, BPU, NODE } x
- *       { read, write, prefetch } x
- *       { accesses, misses }
- */
-const SBI_PMU_HW_CACHE_L1D:usize = 0x0;
-const SBI_PMU_HW_CACHE_L1I:usize = 0x1;
-const SBI_PMU_HW_CACHE_LL:usize = 0x2;
-const SBI_PMU_HW_CACHE_DTLB:usize = 0x3;
-const SBI_PMU_HW_CACHE_ITLB:usize = 0x4;
-const SBI_PMU_HW_CACHE_BPU:usize = 0x5;
-const SBI_PMU_HW_CACHE_NODE:usize = 0x6;
-const SBI_PMU_HW_CACHE_MAX:usize =core::usize::MAX;
-
-
-
-const SBI_PMU_START_SET_INIT_VALUE:usize = 0x0;
+const FUNCTION_PMU_COUNTER_FW_READ_HI:usize =	0x6;
+const FUNCTION_PMU_SNAPSHOT_SET_SHM:usize =	0x7;
+const FUNCTION_PMU_EVENT_GET_INFO:usize =	0x8;
+
+
+
+// cycles spent here are counted by the platform specific firmware event `RUSTSBI_FW_PMU_ECALL_CYCLES`
+#[inline]
+pub fn handle_ecall_pmu(function: usize, param0: usize, param1: usize, param2: usize, param3: usize, param4: usize, param5: usize) -> SbiRet {
+    let start = read_mcycle();
+    let ans = dispatch_ecall_pmu(function, param0, param1, param2, param3, param4, param5);
+    crate::pmu::count_ecall_cycles(read_mcycle().wrapping_sub(start));
+    ans
+}
 
 #[inline]
-pub fn handle_ecall_pmu(function: usize, param0: usize, param1: usize, param2: usize, param3: usize, param4: u64) -> SbiRet {
+fn read_mcycle() -> u64 {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => riscv::register::mcycle::read64(),
+        // host-side tests, where no cycles are counted
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => 0,
+    }
+}
+
+#[inline]
+fn dispatch_ecall_pmu(function: usize, param0: usize, param1: usize, param2: usize, param3: usize, param4: usize, param5: usize) -> SbiRet {
     match function {
         FUNCTION_PMU_NUM_COUNTERS=>pmu_num_counters(),
-        FUNCTION_PMU_COUNTER_GET_INFO=>pmu_counter_get_info(),
-        FUNCTION_PMU_COUNTER_CFG_MATCH =>pmu_counter_cfg_map(),
-        FUNCTION_PMU_COUNTER_START => pmu_start(param1,param2,param3,param4),
-        FUNCTION_PMU_COUNTER_STOP => pmu_stop(param1,param2,param3),
-        FUNCTION_PMU_COUNTER_FW_READ => pmu_read(param1),
+        FUNCTION_PMU_COUNTER_GET_INFO=>pmu_counter_get_info(param0),
+        FUNCTION_PMU_COUNTER_CFG_MATCH => match () {
+            #[cfg(target_pointer_width = "64")]
+            () => {
+                drop(param5);
+                pmu_counter_cfg_map(param0,param1,param2,param3,param4 as u64)
+            }
+            // event_data is passed in a4 (lower half) and a5 (upper half) on RV32
+            #[cfg(target_pointer_width = "32")]
+            () => pmu_counter_cfg_map(param0,param1,param2,param3,concat_u32(param5, param4)),
+        },
+        FUNCTION_PMU_COUNTER_START => match () {
+            #[cfg(target_pointer_width = "64")]
+            () => pmu_start(param0,param1,param2,param3 as u64),
+            // initial_value is passed in a3 (lower half) and a4 (upper half) on RV32
+            #[cfg(target_pointer_width = "32")]
+            () => pmu_start(param0,param1,param2,concat_u32(param4, param3)),
+        },
+        FUNCTION_PMU_COUNTER_STOP => pmu_stop(param0,param1,param2),
+        FUNCTION_PMU_COUNTER_FW_READ => pmu_read(param0),
+        FUNCTION_PMU_COUNTER_FW_READ_HI => pmu_read_hi(param0),
+        FUNCTION_PMU_SNAPSHOT_SET_SHM => pmu_snapshot_set_shm(param0,param1,param2),
+        FUNCTION_PMU_EVENT_GET_INFO => pmu_event_get_info(param0,param1,param2,param3),
         _ => SbiRet::not_supported(),
     }
 }
@@ -57,19 +79,202 @@ fn pmu_read(counter_idx: usize) -> SbiRet {
     crate::pmu::pmu_fw_read(counter_idx)
 }
 
+#[inline]
+fn pmu_read_hi(counter_idx: usize) -> SbiRet {
+    crate::pmu::pmu_fw_read_hi(counter_idx)
+}
+
+#[inline]
+fn pmu_snapshot_set_shm(shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+    crate::pmu::pmu_snapshot_set_shm(shmem_phys_lo, shmem_phys_hi, flags)
+}
+
+#[inline]
+fn pmu_event_get_info(shmem_phys_lo: usize, shmem_phys_hi: usize, num_entries: usize, flags: usize) -> SbiRet {
+    crate::pmu::pmu_event_get_info(shmem_phys_lo, shmem_phys_hi, num_entries, flags)
+}
+
 #[inline]
 fn pmu_num_counters() ->SbiRet{
-    SbiRet::not_supported()
+    crate::pmu::pmu_num_counters()
+}
+
+#[inline]
+fn pmu_counter_get_info(counter_idx: usize) ->SbiRet{
+    crate::pmu::pmu_counter_get_info(counter_idx)
 }
 
 #[inline]
-fn pmu_counter_get_info() ->SbiRet{
-    SbiRet::not_supported()
+fn pmu_counter_cfg_map(counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) ->SbiRet{
+    crate::pmu::pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data)
 }
 
+#[cfg(target_pointer_width = "32")]
 #[inline]
-fn pmu_counter_cfg_map() ->SbiRet{
-    SbiRet::not_supported()
+fn concat_u32(h: usize, l: usize) -> u64 {
+    ((h as u64) << 32) | (l as u64)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pmu::mock::{with_mock_pmu, without_pmu, Call};
+    use crate::pmu::EventInfo;
+    use alloc::vec::Vec;
+
+    fn call(function: usize, param: [usize; 6]) -> SbiRet {
+        handle_ecall_pmu(function, param[0], param[1], param[2], param[3], param[4], param[5])
+    }
 
+    // registers of a 64-bit parameter: the whole value and an unused register on RV64,
+    // the lower and upper halves on RV32
+    #[cfg(target_pointer_width = "64")]
+    fn split_u64(value: u64) -> (usize, usize) {
+        (value as usize, 0x5a5a)
+    }
+    #[cfg(target_pointer_width = "32")]
+    fn split_u64(value: u64) -> (usize, usize) {
+        (value as usize, (value >> 32) as usize)
+    }
+
+    fn queries(num_entries: usize) -> Vec<EventInfo> {
+        (0..num_entries).map(|_| EventInfo { event_idx: 0x1, output: 0, event_data: 0 }).collect()
+    }
+
+    #[test]
+    fn dispatches_by_function() {
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            call(FUNCTION_PMU_NUM_COUNTERS, [0; 6]);
+            call(FUNCTION_PMU_COUNTER_GET_INFO, [5, 0, 0, 0, 0, 0]);
+            call(FUNCTION_PMU_COUNTER_STOP, [3, 0b101, 1, 0, 0, 0]);
+            call(FUNCTION_PMU_COUNTER_FW_READ, [7, 0, 0, 0, 0, 0]);
+            call(FUNCTION_PMU_COUNTER_FW_READ_HI, [8, 0, 0, 0, 0, 0]);
+            call(FUNCTION_PMU_SNAPSHOT_SET_SHM, [0x1000, 0, 0, 0, 0, 0]);
+        });
+        assert_eq!(
+            calls,
+            [
+                Call::NumCounters,
+                Call::CounterGetInfo(5),
+                Call::Stop(3, 0b101, 1),
+                Call::FwRead(7),
+                Call::FwReadHi(8),
+                Call::SnapshotSetShm(0x1000, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_function_is_not_supported() {
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            let ret = call(FUNCTION_PMU_EVENT_GET_INFO + 1, [0; 6]);
+            assert_eq!(ret.error, SbiRet::not_supported().error);
+        });
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn passes_event_data_in_one_or_two_registers() {
+        let event_data = 0x1234_5678_9abc_def0;
+        let (lo, hi) = split_u64(event_data);
+        let calls = with_mock_pmu(SbiRet::ok(4), || {
+            let ret = call(FUNCTION_PMU_COUNTER_CFG_MATCH, [3, 0b11, 0b110, 0x10009, lo, hi]);
+            assert_eq!((ret.error, ret.value), (0, 4));
+        });
+        assert_eq!(calls, [Call::ConfigMatching(3, 0b11, 0b110, 0x10009, event_data)]);
+    }
+
+    #[test]
+    fn passes_initial_value_in_one_or_two_registers() {
+        let initial_value = 0xfedc_ba98_7654_3210;
+        let (lo, hi) = split_u64(initial_value);
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            call(FUNCTION_PMU_COUNTER_START, [0, 1, 1, lo, hi, 0]);
+        });
+        assert_eq!(calls, [Call::Start(0, 1, 1, initial_value)]);
+    }
+
+    #[test]
+    fn passes_event_info_list() {
+        let mut entries = queries(3);
+        let shmem = entries.as_mut_ptr() as usize;
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            call(FUNCTION_PMU_EVENT_GET_INFO, [shmem, 0, 3, 0, 0, 0]);
+        });
+        assert_eq!(calls, [Call::EventGetInfo(3)]);
+        // longer lists are handed over in several parts
+        let mut entries = queries(20);
+        let shmem = entries.as_mut_ptr() as usize;
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            call(FUNCTION_PMU_EVENT_GET_INFO, [shmem, 0, 20, 0, 0, 0]);
+        });
+        assert_eq!(calls, [Call::EventGetInfo(16), Call::EventGetInfo(4)]);
+    }
+
+    #[test]
+    fn rejects_bad_event_info_list() {
+        let mut entries = queries(2);
+        let shmem = entries.as_mut_ptr() as usize;
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            // reserved flags
+            let ret = call(FUNCTION_PMU_EVENT_GET_INFO, [shmem, 0, 2, 1, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            // list not aligned to its entries
+            let ret = call(FUNCTION_PMU_EVENT_GET_INFO, [shmem + 8, 0, 1, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+            // list above XLEN bits
+            let ret = call(FUNCTION_PMU_EVENT_GET_INFO, [shmem, 1, 2, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+            // list reaching into memory supervisor may not share, or with a size overflowing
+            crate::shmem::init_test_shmem();
+            let ret = call(FUNCTION_PMU_EVENT_GET_INFO, [crate::shmem::TEST_HIDDEN.start - 16, 0, 2, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_address().error);
+            let ret = call(FUNCTION_PMU_EVENT_GET_INFO, [shmem, 0, usize::MAX / 8, 0, 0, 0]);
+            assert_eq!(ret.error, SbiRet::invalid_param().error);
+        });
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn propagates_errors() {
+        let errors = [SbiRet::invalid_param(), SbiRet::not_supported(), SbiRet::invalid_address()];
+        for err in errors.iter() {
+            let calls = with_mock_pmu(SbiRet { error: err.error, value: 0 }, || {
+                for function in FUNCTION_PMU_NUM_COUNTERS..FUNCTION_PMU_EVENT_GET_INFO {
+                    let ret = call(function, [0; 6]);
+                    assert_eq!(ret.error, err.error, "function {}", function);
+                }
+            });
+            assert_eq!(calls.len(), FUNCTION_PMU_EVENT_GET_INFO);
+        }
+    }
+
+    #[test]
+    fn not_supported_without_pmu() {
+        without_pmu(|| {
+            for function in FUNCTION_PMU_NUM_COUNTERS..=FUNCTION_PMU_EVENT_GET_INFO {
+                let ret = call(function, [0; 6]);
+                assert_eq!(ret.error, SbiRet::not_supported().error, "function {}", function);
+            }
+        });
+    }
+
+    #[test]
+    fn counts_sbi_calls() {
+        use crate::ecall::{handle_ecall, EXTENSION_BASE, EXTENSION_PMU};
+        use crate::pmu::{RUSTSBI_FW_PMU_PMU_CALLS, RUSTSBI_FW_PMU_SBI_CALLS};
+        let calls = with_mock_pmu(SbiRet::ok(0), || {
+            handle_ecall(EXTENSION_PMU, FUNCTION_PMU_NUM_COUNTERS, [0; 6]);
+            // `sbi_get_spec_version`
+            handle_ecall(EXTENSION_BASE, 0, [0; 6]);
+        });
+        // every call is counted before it is handled, calls of the PMU extension twice
+        let expected = [
+            Call::PlatformEvent(RUSTSBI_FW_PMU_SBI_CALLS, 1),
+            Call::PlatformEvent(RUSTSBI_FW_PMU_PMU_CALLS, 1),
+            Call::NumCounters,
+            Call::PlatformEvent(RUSTSBI_FW_PMU_SBI_CALLS, 1),
+        ];
+        assert_eq!(calls, expected);
+    }
+}
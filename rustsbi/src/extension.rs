@@ -1,4 +1,57 @@
 use crate::ecall::*;
+use alloc::vec::Vec;
+use spin::RwLock;
+
+lazy_static::lazy_static! {
+    static ref EXTENSIONS: RwLock<Vec<(usize, ExtensionHandler)>> = RwLock::new(Vec::new());
+}
+
+/// Register the handler of an extension RustSBI does not implement, such as a vendor extension of the platform.
+///
+/// After registration, `rustsbi::ecall` calls `handler` for every SBI call of extension `extension`, and the
+/// base extension reports the extension available when probed, with probe value 1. Extensions are usually
+/// registered while the platform is initializing, before supervisor starts.
+///
+/// ```no_run
+/// use rustsbi::SbiRet;
+///
+/// const EXTENSION_VENDOR: usize = 0x09000000;
+///
+/// fn handle_ecall_vendor(function: usize, param: [usize; 6]) -> SbiRet {
+///     match function {
+///         0 => SbiRet::ok(param[0] + param[1]),
+///         _ => SbiRet::not_supported(),
+///     }
+/// }
+///
+/// rustsbi::register_extension(EXTENSION_VENDOR, handle_ecall_vendor);
+/// ```
+///
+/// # Panics
+///
+/// If RustSBI implements `extension` itself, or a handler of `extension` is registered already.
+pub fn register_extension(extension: usize, handler: ExtensionHandler) {
+    assert!(builtin_extension(extension).is_none(), "extension {:#x} is implemented by RustSBI", extension);
+    let mut extensions = EXTENSIONS.write();
+    assert!(
+        extensions.iter().all(|&(id, _)| id != extension),
+        "extension {:#x} is registered already",
+        extension
+    );
+    extensions.push((extension, handler));
+}
+
+// handler of an extension, built into RustSBI or registered by the platform
+#[inline]
+pub(crate) fn extension_handler(extension: usize) -> Option<ExtensionHandler> {
+    builtin_extension(extension).or_else(|| {
+        EXTENSIONS
+            .read()
+            .iter()
+            .find(|&&(id, _)| id == extension)
+            .map(|&(_, handler)| handler)
+    })
+}
 
 #[inline]
 pub fn probe_extension(extension: usize) -> bool {
@@ -9,8 +62,32 @@ pub fn probe_extension(extension: usize) -> bool {
         EXTENSION_RFENCE => crate::rfence::probe_rfence(),
         EXTENSION_SRST => crate::reset::probe_reset(),
         EXTENSION_HSM => crate::hsm::probe_hsm(),
-        EXTENSION_PMU => crate::pmu::probe_pmu(),
-        // new extensions should be added here to be probed
-        _ => false,
+        EXTENSION_PMU => probe_pmu(),
+        // RustSBI's own extension dumps PMU state, reads trace records with the `trace` feature,
+        // and call statistics with the `call-stats` feature
+        EXTENSION_RUSTSBI => probe_pmu() || cfg!(feature = "trace") || cfg!(feature = "call-stats"),
+        // built-in extensions should be added here to be probed, vendor extensions are registered by the platform
+        _ => EXTENSIONS.read().iter().any(|&(id, _)| id == extension),
+    }
+}
+
+// extension specific nonzero value returned by probing an available extension
+#[inline]
+pub fn probe_value(extension: usize) -> usize {
+    match extension {
+        #[cfg(feature = "pmu")]
+        EXTENSION_PMU => crate::pmu::pmu_version(),
+        _ => 1,
+    }
+}
+
+// without the `pmu` feature, the PMU extension is compiled out and never available
+#[inline]
+fn probe_pmu() -> bool {
+    match () {
+        #[cfg(feature = "pmu")]
+        () => crate::pmu::probe_pmu(),
+        #[cfg(not(feature = "pmu"))]
+        () => false,
     }
 }
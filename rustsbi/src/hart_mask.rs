@@ -1,3 +1,4 @@
+use crate::index_mask::IndexMask;
 use core::mem::size_of;
 
 /// Hart mask structure reference
@@ -41,9 +42,27 @@ impl HartMask {
             // `base` if the starting hartid
             return false;
         }
-        let (i, j) = split_index_usize(hart_id - self.base);
+        let (i, _) = split_index_usize(hart_id - self.base);
+        self.word(i).contains(hart_id)
+    }
+
+    /// Iterate over hart ids included in this hart mask structure.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let words = if self.base == usize::MAX || self.base > self.max_hart_id {
+            0
+        } else {
+            split_index_usize(self.max_hart_id - self.base).0 + 1
+        };
+        let all = (0..=self.max_hart_id).filter(move |_| self.base == usize::MAX);
+        // each word of the bit vector selects harts like a mask with its own base
+        let selected = (0..words).flat_map(move |i| self.word(i).iter()).take_while(move |&hart_id| hart_id <= self.max_hart_id);
+        all.chain(selected)
+    }
+
+    // the `i`-th word of the bit vector, selecting harts from `base + i * XLEN`
+    fn word(&self, i: usize) -> IndexMask {
         let cur_vector = unsafe { get_vaddr_usize(self.bit_vector.add(i)) };
-        cur_vector & (1 << j) != 0
+        IndexMask::new(self.base + i * size_of::<usize>() * 8, cur_vector)
     }
 }
 
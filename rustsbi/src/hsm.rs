@@ -189,6 +189,8 @@ pub trait Hsm: Send {
 use alloc::boxed::Box;
 use spin::Mutex;
 
+const SUSPEND_NON_RETENTIVE: u32 = 0x8000_0000;
+
 lazy_static::lazy_static! {
     static ref HSM: Mutex<Option<Box<dyn Hsm>>> =
         Mutex::new(None);
@@ -213,6 +215,8 @@ pub(crate) fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> Sbi
 
 pub(crate) fn hart_stop(hartid: usize) -> SbiRet {
     if let Some(obj) = &mut *HSM.lock() {
+        #[cfg(feature = "pmu")]
+        crate::pmu::stop_pmu_hart();
         return obj.hart_stop(hartid);
     }
     SbiRet::not_supported()
@@ -228,6 +232,11 @@ pub(crate) fn hart_get_status(hartid: usize) -> SbiRet {
 pub(crate) fn hart_suspend(suspend_type: u32, resume_addr: usize, opaque: usize) -> SbiRet {
     if let Some(obj) = &mut *HSM.lock() {
         let suspend_type = suspend_type as u32;
+        if suspend_type & SUSPEND_NON_RETENTIVE != 0 {
+            // counter CSRs are lost in non-retentive suspend states
+            #[cfg(feature = "pmu")]
+            crate::pmu::save_pmu_context();
+        }
         return obj.hart_suspend(suspend_type, resume_addr, opaque);
     }
     SbiRet::not_supported()
@@ -0,0 +1,222 @@
+/// Set of indexes selected by a base index and a bit mask
+///
+/// SBI calls select harts and counters the same way: bit `i` of the mask selects index `base + i`.
+/// The PMU extension takes counters as `counter_idx_base` and `counter_idx_mask`, and the IPI and
+/// RFENCE extensions take harts as `hart_mask_base` and `hart_mask`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexMask {
+    base: usize,
+    mask: usize,
+}
+
+impl IndexMask {
+    /// Select index `base + i` for every bit `i` set in `mask`.
+    #[inline]
+    pub const fn new(base: usize, mask: usize) -> IndexMask {
+        IndexMask { base, mask }
+    }
+
+    /// The first index the mask is relative to.
+    #[inline]
+    pub const fn base(&self) -> usize {
+        self.base
+    }
+
+    /// The bit mask, relative to `base`.
+    #[inline]
+    pub const fn mask(&self) -> usize {
+        self.mask
+    }
+
+    /// Whether no index is selected.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.mask == 0
+    }
+
+    /// Number of selected indexes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Check if `index` is selected.
+    #[inline]
+    pub fn contains(&self, index: usize) -> bool {
+        match index.checked_sub(self.base) {
+            Some(i) if i < usize::BITS as usize => self.mask & (1 << i) != 0,
+            _ => false,
+        }
+    }
+
+    /// The largest selected index, `None` if no index is selected or it does not fit in `usize`.
+    #[inline]
+    pub fn highest(&self) -> Option<usize> {
+        if self.mask == 0 {
+            return None;
+        }
+        let i = usize::BITS as usize - 1 - self.mask.leading_zeros() as usize;
+        self.base.checked_add(i)
+    }
+
+    /// Check that at least one index is selected and every selected index is below `limit`.
+    ///
+    /// SBI calls return `SBI_ERR_INVALID_PARAM` for sets failing this check.
+    #[inline]
+    pub fn is_within(&self, limit: usize) -> bool {
+        matches!(self.highest(), Some(index) if index < limit)
+    }
+
+    /// Iterate over selected indexes in ascending order.
+    #[inline]
+    pub fn iter(&self) -> Iter {
+        Iter {
+            base: self.base,
+            mask: self.mask,
+        }
+    }
+}
+
+impl IntoIterator for IndexMask {
+    type Item = usize;
+    type IntoIter = Iter;
+    #[inline]
+    fn into_iter(self) -> Iter {
+        self.iter()
+    }
+}
+
+/// Iterator over indexes selected by an `IndexMask`, in ascending order
+///
+/// Indexes which do not fit in `usize` are not selected.
+#[derive(Clone, Debug)]
+pub struct Iter {
+    base: usize,
+    // selected bits not yet visited
+    mask: usize,
+}
+
+impl Iterator for Iter {
+    type Item = usize;
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        if self.mask == 0 {
+            return None;
+        }
+        let i = self.mask.trailing_zeros() as usize;
+        // clear the lowest set bit
+        self.mask &= self.mask - 1;
+        match self.base.checked_add(i) {
+            Some(index) => Some(index),
+            None => {
+                // every bit left selects an even larger index
+                self.mask = 0;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexMask;
+    use alloc::vec::Vec;
+
+    const BITS: usize = usize::BITS as usize;
+
+    // xorshift generator, so that every run checks the same masks
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+    }
+
+    // masks with few, many, and randomly distributed bits, with bases near zero and near the end of `usize`
+    fn samples() -> impl Iterator<Item = IndexMask> {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let edges = [0, 1, usize::MAX, 1 << (BITS - 1), usize::MAX - 1];
+        let mut cases = Vec::new();
+        for &base in &[0, 1, 3, 31, 64, usize::MAX - BITS, usize::MAX - 3, usize::MAX] {
+            for &mask in &edges {
+                cases.push(IndexMask::new(base, mask));
+            }
+            for _ in 0..256 {
+                let mask = match rng.next() % 3 {
+                    0 => rng.next(),
+                    1 => rng.next() & rng.next() & rng.next(),
+                    _ => rng.next() | rng.next() | rng.next(),
+                };
+                cases.push(IndexMask::new(base, mask));
+            }
+        }
+        cases.into_iter()
+    }
+
+    // indexes selected by the definition of the mask, with those overflowing `usize` left out
+    fn naive(set: IndexMask) -> Vec<usize> {
+        (0..BITS)
+            .filter(|i| set.mask() & (1 << i) != 0)
+            .filter_map(|i| set.base().checked_add(i))
+            .collect()
+    }
+
+    #[test]
+    fn iter_matches_definition() {
+        for set in samples() {
+            let indexes: Vec<usize> = set.iter().collect();
+            assert_eq!(indexes, naive(set), "{:?}", set);
+        }
+    }
+
+    #[test]
+    fn iter_is_ascending() {
+        for set in samples() {
+            let indexes: Vec<usize> = set.iter().collect();
+            assert!(indexes.windows(2).all(|w| w[0] < w[1]), "{:?}", set);
+        }
+    }
+
+    #[test]
+    fn contains_matches_iter() {
+        for set in samples() {
+            let indexes = naive(set);
+            for &index in &indexes {
+                assert!(set.contains(index), "{:?} {}", set, index);
+            }
+            let mut rng = Rng(set.mask() as u64 | 1);
+            for _ in 0..64 {
+                let index = set.base().wrapping_add(rng.next() % (2 * BITS)).wrapping_sub(BITS / 2);
+                assert_eq!(set.contains(index), indexes.contains(&index), "{:?} {}", set, index);
+            }
+        }
+    }
+
+    #[test]
+    fn len_and_highest() {
+        for set in samples() {
+            let indexes = naive(set);
+            assert_eq!(set.len(), indexes.len(), "{:?}", set);
+            assert_eq!(set.is_empty(), set.mask() == 0);
+            let overflows = indexes.len() != set.mask().count_ones() as usize;
+            let expected = if overflows { None } else { indexes.last().copied() };
+            assert_eq!(set.highest(), expected, "{:?}", set);
+        }
+    }
+
+    #[test]
+    fn is_within_matches_definition() {
+        for set in samples() {
+            let indexes = naive(set);
+            let overflows = indexes.len() != set.mask().count_ones() as usize;
+            for &limit in &[0, 1, 32, 64, set.base(), set.base().saturating_add(BITS), usize::MAX] {
+                let expected = !set.is_empty() && !overflows && indexes.iter().all(|&index| index < limit);
+                assert_eq!(set.is_within(limit), expected, "{:?} {}", set, limit);
+            }
+        }
+    }
+}
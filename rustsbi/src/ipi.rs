@@ -33,11 +33,14 @@ pub(crate) fn probe_ipi() -> bool {
 }
 
 pub(crate) fn send_ipi_many(hart_mask: HartMask) -> SbiRet {
-    if let Some(ipi) = IPI.lock().as_mut() {
-        ipi.send_ipi_many(hart_mask)
+    let ans = if let Some(ipi) = IPI.lock().as_mut() {
+        ipi.send_ipi_many(hart_mask.clone())
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    crate::pmu::count_sent_event(crate::pmu::SBI_PMU_FW_IPI_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn max_hart_id() -> Option<usize> { 
@@ -148,6 +148,7 @@ mod ecall;
 mod extension;
 mod hart_mask;
 mod hsm;
+mod index_mask;
 mod ipi;
 mod logo;
 mod privileged;
@@ -155,7 +156,13 @@ mod privileged;
 pub mod reset;
 mod timer;
 mod rfence;
-mod pmu;
+pub mod shmem;
+#[cfg(feature = "pmu")]
+pub mod pmu;
+#[cfg(feature = "trace")]
+pub mod trace;
+#[cfg(feature = "call-stats")]
+pub mod stats;
 
 const SBI_SPEC_MAJOR: usize = 0;
 const SBI_SPEC_MINOR: usize = 2;
@@ -174,8 +181,10 @@ const RUSTSBI_VERSION: usize = {
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
 pub use ecall::handle_ecall as ecall;
-pub use ecall::SbiRet;
+pub use ecall::{ExtensionHandler, SbiError, SbiRet};
+pub use extension::register_extension;
 pub use hart_mask::HartMask;
+pub use index_mask::IndexMask;
 pub use hsm::{init_hsm, Hsm};
 pub use ipi::{init_ipi, Ipi};
 pub use logo::LOGO;
@@ -183,6 +192,8 @@ pub use privileged::enter_privileged;
 pub use reset::{init_reset, Reset};
 pub use timer::{init_timer, Timer};
 pub use rfence::{init_rfence as init_remote_fence, Rfence as Fence};
+pub use shmem::{init_shared_memory, SharedMemory};
+#[cfg(feature = "pmu")]
 pub use pmu::{init_pmu, Pmu};
 #[doc(hidden)]
 pub use legacy_stdio::{legacy_stdio_getchar, legacy_stdio_putchar};
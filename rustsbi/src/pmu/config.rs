@@ -0,0 +1,146 @@
+//! Policies of the PMU, chosen by the platform in one place
+
+use super::{init_pmu, GenericPmu, HiddenRead, PmuPlatform, ProtectedPmu, RemappedPlatform, StoppedRead};
+use alloc::vec::Vec;
+
+/// PMU implementation built from a `PmuConfig`: `GenericPmu` over the platform with reserved counters left
+/// out, wrapped to coarsen protected events
+pub type ConfiguredPmu<P> = ProtectedPmu<GenericPmu<RemappedPlatform<P>>>;
+
+/// Policies of the PMU chosen by the platform at initialization
+///
+/// Each policy has its own builder method on `GenericPmu`, `RemappedPlatform` or `ProtectedPmu`, and the
+/// trace mask is set in the `trace` module. `PmuConfig` gathers them, so that a platform states every policy
+/// in one place and passes them to `init_generic_pmu`:
+///
+/// ```no_run
+/// use rustsbi::pmu::{init_generic_pmu, HiddenRead, PmuConfig, StoppedRead};
+///
+/// let config = PmuConfig::new()
+///     .stopped_read(StoppedRead::Frozen)
+///     .hidden_read(HiddenRead::Emulate)
+///     .reserve(31)
+///     .armed(seed);
+/// init_generic_pmu(platform, config);
+/// ```
+///
+/// Platforms building their own stack of PMU wrappers get the configured PMU with `build`, or apply the
+/// policies of `GenericPmu` alone with `GenericPmu::with_config`. Policies not chosen keep the defaults of
+/// each builder method.
+#[derive(Clone, Debug)]
+pub struct PmuConfig {
+    // policies of `GenericPmu`, applied by `GenericPmu::with_config`
+    pub(super) stopped_read: StoppedRead,
+    pub(super) hidden_read: HiddenRead,
+    pub(super) idle_timeout: Option<usize>,
+    pub(super) multiplex: bool,
+    // physical counters kept away from supervisor
+    reserved: Vec<usize>,
+    // event index, quantum and jitter of protected events
+    protected: Vec<(usize, u64, u64)>,
+    // seed of the jitter, `Some` if supervisor may protect events itself
+    armed: Option<usize>,
+    trace_mask: Option<usize>,
+}
+
+impl PmuConfig {
+    /// Default policies: counters read live when stopped, hidden counters not emulated, no idle timeout,
+    /// multiplexing allowed, no reserved counter, no protected event, and the trace mask left as it is.
+    pub fn new() -> PmuConfig {
+        PmuConfig {
+            stopped_read: StoppedRead::Live,
+            hidden_read: HiddenRead::Fault,
+            idle_timeout: None,
+            multiplex: true,
+            reserved: Vec::new(),
+            protected: Vec::new(),
+            armed: None,
+            trace_mask: None,
+        }
+    }
+
+    /// What supervisor reads through counter CSRs of stopped counters, see `GenericPmu::with_stopped_read`.
+    pub fn stopped_read(mut self, policy: StoppedRead) -> Self {
+        self.stopped_read = policy;
+        self
+    }
+
+    /// Whether reads of counter CSRs hidden by `mcounteren` are emulated, see `GenericPmu::with_hidden_read`.
+    pub fn hidden_read(mut self, policy: HiddenRead) -> Self {
+        self.hidden_read = policy;
+        self
+    }
+
+    /// Ticks of `mtime` after which a started counter is idle, see `GenericPmu::with_idle_timeout`.
+    pub fn idle_timeout(mut self, ticks: usize) -> Self {
+        self.idle_timeout = Some(ticks);
+        self
+    }
+
+    /// Whether hardware events may be counted by multiplexed counters when no hardware counter is free; allowed
+    /// by default. Multiplexed counters only exist with the `multiplex` feature.
+    pub fn multiplex(mut self, allowed: bool) -> Self {
+        self.multiplex = allowed;
+        self
+    }
+
+    /// Keep physical counter `counter_idx` for the firmware, see `RemappedPlatform::reserve`.
+    pub fn reserve(mut self, counter_idx: usize) -> Self {
+        self.reserved.push(counter_idx);
+        self
+    }
+
+    /// Coarsen values of `event_idx` read by supervisor, see `ProtectedPmu::protect`.
+    pub fn protect(mut self, event_idx: usize, quantum: u64, jitter: u64) -> Self {
+        self.protected.push((event_idx, quantum, jitter));
+        self
+    }
+
+    /// Let supervisor protect events itself, with jitter seeded by `seed`, see `ProtectedPmu::armed`.
+    pub fn armed(mut self, seed: usize) -> Self {
+        self.armed = Some(seed);
+        self
+    }
+
+    /// Extensions whose calls are traced, see `trace::set_trace_mask`; set by `init_generic_pmu`, and without
+    /// effect unless RustSBI is built with the `trace` feature.
+    pub fn trace_mask(mut self, mask: usize) -> Self {
+        self.trace_mask = Some(mask);
+        self
+    }
+
+    /// Build the PMU implementation over `platform` with these policies.
+    pub fn build<P: PmuPlatform>(&self, platform: P) -> ConfiguredPmu<P> {
+        let platform = self.reserved.iter().fold(RemappedPlatform::new(platform), |platform, &idx| platform.reserve(idx));
+        let generic = GenericPmu::new(platform).with_config(self);
+        let mut pmu = ProtectedPmu::new(generic);
+        for &(event_idx, quantum, jitter) in &self.protected {
+            pmu = pmu.protect(event_idx, quantum, jitter);
+        }
+        match self.armed {
+            Some(seed) => pmu.with_seed(seed).armed(),
+            None => pmu,
+        }
+    }
+}
+
+impl Default for PmuConfig {
+    fn default() -> Self {
+        PmuConfig::new()
+    }
+}
+
+/// Initialize the PMU extension with `GenericPmu` over `platform`, following the policies of `config`.
+pub fn init_generic_pmu<P: PmuPlatform + 'static>(platform: P, config: PmuConfig) {
+    match () {
+        #[cfg(feature = "trace")]
+        () => {
+            if let Some(mask) = config.trace_mask {
+                crate::trace::set_trace_mask(mask);
+            }
+        }
+        #[cfg(not(feature = "trace"))]
+        () => drop(config.trace_mask),
+    }
+    init_pmu(config.build(platform));
+}
@@ -0,0 +1,210 @@
+//! Forwarding `Pmu` methods of a wrapper type to an inner field
+
+/// Implement `Pmu` methods by forwarding them to a field which implements `Pmu`.
+///
+/// The macro is invoked inside an `impl Pmu` block. With only the field name, every method is forwarded;
+/// with a list of methods after `=>`, only the listed methods are, and the wrapper implements the others
+/// itself. Wrapper types such as loggers or filters then only write the methods they change:
+///
+/// ```no_run
+/// use rustsbi::{delegate_pmu, pmu::Pmu, SbiRet};
+///
+/// // deny hardware raw events, forward everything else
+/// struct NoRawEvents<T> {
+///     inner: T,
+/// }
+///
+/// impl<T: Pmu> Pmu for NoRawEvents<T> {
+///     fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+///         if event_idx >> 16 == 2 {
+///             return SbiRet::not_supported();
+///         }
+///         self.inner.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data)
+///     }
+///     delegate_pmu!(inner => is_available, pmu_version, pmu_num_counters, pmu_counter_get_info, pmu_counter_start,
+///         pmu_counter_stop, pmu_counter_fw_read, pmu_counter_fw_read_hi, pmu_snapshot_set_shm, pmu_event_get_info,
+///         pmu_save_context, pmu_restore_context, pmu_poll_overflow, pmu_rotate_multiplex, pmu_counter_read_batch,
+///         pmu_dump, pmu_firmware_event, pmu_ecall_cycles, pmu_enter_domain, pmu_protect_event, pmu_counter_read_clear,
+///         pmu_hart_summary, pmu_firmware_event_total, pmu_leak_check, pmu_reclaim, pmu_reset, pmu_platform_event,
+///         pmu_counter_watch, pmu_emulate_counter_read, pmu_capabilities);
+/// }
+/// ```
+///
+/// Methods added to `Pmu` are added to this macro as well, so a wrapper forwarding every method
+/// with `delegate_pmu!(field)` keeps forwarding all of them.
+#[macro_export]
+macro_rules! delegate_pmu {
+    ($field: ident) => {
+        $crate::delegate_pmu!($field => is_available, pmu_version, pmu_num_counters, pmu_counter_get_info,
+            pmu_counter_config_matching, pmu_counter_start, pmu_counter_stop, pmu_counter_fw_read,
+            pmu_counter_fw_read_hi, pmu_snapshot_set_shm, pmu_event_get_info, pmu_save_context, pmu_restore_context,
+            pmu_poll_overflow, pmu_rotate_multiplex, pmu_counter_read_batch, pmu_dump, pmu_firmware_event, pmu_ecall_cycles,
+            pmu_enter_domain, pmu_protect_event, pmu_counter_read_clear, pmu_hart_summary, pmu_firmware_event_total,
+            pmu_leak_check, pmu_reclaim, pmu_reset, pmu_platform_event, pmu_counter_watch,
+            pmu_emulate_counter_read, pmu_capabilities);
+    };
+    ($field: ident => $($method: ident),+ $(,)?) => {
+        $($crate::__delegate_pmu_method!($field, $method);)+
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __delegate_pmu_method {
+    ($field: ident, is_available) => {
+        fn is_available(&self) -> bool {
+            self.$field.is_available()
+        }
+    };
+    ($field: ident, pmu_version) => {
+        fn pmu_version(&self) -> usize {
+            self.$field.pmu_version()
+        }
+    };
+    ($field: ident, pmu_num_counters) => {
+        fn pmu_num_counters(&self) -> $crate::SbiRet {
+            self.$field.pmu_num_counters()
+        }
+    };
+    ($field: ident, pmu_counter_get_info) => {
+        fn pmu_counter_get_info(&self, counter_idx: usize) -> $crate::SbiRet {
+            self.$field.pmu_counter_get_info(counter_idx)
+        }
+    };
+    ($field: ident, pmu_counter_config_matching) => {
+        fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> $crate::SbiRet {
+            self.$field.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data)
+        }
+    };
+    ($field: ident, pmu_counter_start) => {
+        fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> $crate::SbiRet {
+            self.$field.pmu_counter_start(counter_idx_base, counter_idx_mask, start_flags, initial_value)
+        }
+    };
+    ($field: ident, pmu_counter_stop) => {
+        fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> $crate::SbiRet {
+            self.$field.pmu_counter_stop(counter_idx_base, counter_idx_mask, stop_flags)
+        }
+    };
+    ($field: ident, pmu_counter_fw_read) => {
+        fn pmu_counter_fw_read(&self, counter_idx: usize) -> $crate::SbiRet {
+            self.$field.pmu_counter_fw_read(counter_idx)
+        }
+    };
+    ($field: ident, pmu_counter_fw_read_hi) => {
+        fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> $crate::SbiRet {
+            self.$field.pmu_counter_fw_read_hi(counter_idx)
+        }
+    };
+    ($field: ident, pmu_snapshot_set_shm) => {
+        fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> $crate::SbiRet {
+            self.$field.pmu_snapshot_set_shm(shmem_phys_lo, shmem_phys_hi, flags)
+        }
+    };
+    ($field: ident, pmu_event_get_info) => {
+        fn pmu_event_get_info(&self, entries: &mut [$crate::pmu::EventInfo]) -> $crate::SbiRet {
+            self.$field.pmu_event_get_info(entries)
+        }
+    };
+    ($field: ident, pmu_save_context) => {
+        fn pmu_save_context(&mut self) {
+            self.$field.pmu_save_context()
+        }
+    };
+    ($field: ident, pmu_restore_context) => {
+        fn pmu_restore_context(&mut self) {
+            self.$field.pmu_restore_context()
+        }
+    };
+    ($field: ident, pmu_poll_overflow) => {
+        fn pmu_poll_overflow(&mut self) -> usize {
+            self.$field.pmu_poll_overflow()
+        }
+    };
+    ($field: ident, pmu_rotate_multiplex) => {
+        fn pmu_rotate_multiplex(&mut self) {
+            self.$field.pmu_rotate_multiplex()
+        }
+    };
+    ($field: ident, pmu_counter_read_batch) => {
+        fn pmu_counter_read_batch(&self, counter_idx_base: usize, counter_idx_mask: usize, values: &mut [u64; usize::BITS as usize]) -> $crate::SbiRet {
+            self.$field.pmu_counter_read_batch(counter_idx_base, counter_idx_mask, values)
+        }
+    };
+    ($field: ident, pmu_dump) => {
+        fn pmu_dump(&self) {
+            self.$field.pmu_dump()
+        }
+    };
+    ($field: ident, pmu_firmware_event) => {
+        fn pmu_firmware_event(&self, event_code: usize) {
+            self.$field.pmu_firmware_event(event_code)
+        }
+    };
+    ($field: ident, pmu_ecall_cycles) => {
+        fn pmu_ecall_cycles(&self, cycles: u64) {
+            self.$field.pmu_ecall_cycles(cycles)
+        }
+    };
+    ($field: ident, pmu_platform_event) => {
+        fn pmu_platform_event(&self, event_data: u64, value: u64) {
+            self.$field.pmu_platform_event(event_data, value)
+        }
+    };
+    ($field: ident, pmu_enter_domain) => {
+        fn pmu_enter_domain(&mut self, domain: usize) {
+            self.$field.pmu_enter_domain(domain)
+        }
+    };
+    ($field: ident, pmu_protect_event) => {
+        fn pmu_protect_event(&mut self, event_idx: usize, quantum: u64, jitter: u64) -> $crate::SbiRet {
+            self.$field.pmu_protect_event(event_idx, quantum, jitter)
+        }
+    };
+    ($field: ident, pmu_counter_read_clear) => {
+        fn pmu_counter_read_clear(&mut self, counter_idx: usize) -> $crate::SbiRet {
+            self.$field.pmu_counter_read_clear(counter_idx)
+        }
+    };
+    ($field: ident, pmu_hart_summary) => {
+        fn pmu_hart_summary(&self, hartid: usize) -> Option<$crate::pmu::HartSummary> {
+            self.$field.pmu_hart_summary(hartid)
+        }
+    };
+    ($field: ident, pmu_firmware_event_total) => {
+        fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> $crate::SbiRet {
+            self.$field.pmu_firmware_event_total(event_idx, per_hart)
+        }
+    };
+    ($field: ident, pmu_leak_check) => {
+        fn pmu_leak_check(&mut self, counter_idx_base: usize, end_session: bool) -> $crate::SbiRet {
+            self.$field.pmu_leak_check(counter_idx_base, end_session)
+        }
+    };
+    ($field: ident, pmu_reclaim) => {
+        fn pmu_reclaim(&mut self) -> $crate::SbiRet {
+            self.$field.pmu_reclaim()
+        }
+    };
+    ($field: ident, pmu_counter_watch) => {
+        fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: $crate::pmu::WatchNotify) -> $crate::SbiRet {
+            self.$field.pmu_counter_watch(counter_idx, threshold, notify)
+        }
+    };
+    ($field: ident, pmu_reset) => {
+        fn pmu_reset(&mut self, all_harts: bool) {
+            self.$field.pmu_reset(all_harts)
+        }
+    };
+    ($field: ident, pmu_emulate_counter_read) => {
+        fn pmu_emulate_counter_read(&self, counter_idx: usize) -> Option<u64> {
+            self.$field.pmu_emulate_counter_read(counter_idx)
+        }
+    };
+    ($field: ident, pmu_capabilities) => {
+        fn pmu_capabilities(&self) -> usize {
+            self.$field.pmu_capabilities()
+        }
+    };
+}
+
@@ -0,0 +1,264 @@
+//! Partitioning counters among supervisor domains
+
+use super::{current_hartid, HartSummary, Pmu, WatchNotify};
+use crate::ecall::{SbiRet, SBI_SUCCESS};
+use crate::index_mask::IndexMask;
+use alloc::vec::Vec;
+
+/// PMU implementation which partitions counters among supervisor domains
+///
+/// A platform running several supervisors on the same harts, each in its own domain, assigns counters
+/// to domains with `assign`; counters not assigned to any domain are shared by all of them. The platform
+/// tells RustSBI which domain runs on the calling hart with `enter_pmu_domain` whenever it switches domains;
+/// every hart starts in domain 0.
+///
+/// Supervisor of one domain cannot use counters assigned to other domains:
+///
+/// - `pmu_counter_config_matching` only selects counters from the caller's domain and the shared counters
+///   in the given set, and returns `SBI_ERR_NOT_SUPPORTED` if there is none;
+/// - starting, stopping and reading a set of counters which has a counter of another domain returns
+///   `SBI_ERR_INVALID_PARAM`.
+///
+/// The number of counters and `counter_info` of every counter are still reported, so that supervisor
+/// software enumerating counters works unchanged. When a hart leaves a domain, the started counters of
+/// that domain are stopped, and they are started again when the hart enters the domain back, so a domain
+/// only counts events happening while it runs.
+///
+/// Counters can only be assigned if their index is below XLEN. Supervisor reads hardware counters
+/// directly through counter CSRs; the platform should also clear `mcounteren` bits of counters which are
+/// not in `visible_counters` of the domain being entered.
+///
+/// ```no_run
+/// use rustsbi::{pmu::{DomainPmu, GenericPmu}, IndexMask};
+///
+/// // counters 3 to 5 for domain 1, counters 6 to 8 for domain 2
+/// rustsbi::init_pmu(DomainPmu::new(GenericPmu::new(platform)).assign(1, IndexMask::new(3, 0b111)).assign(2, IndexMask::new(6, 0b111)));
+/// ```
+pub struct DomainPmu<T> {
+    inner: T,
+    // counters assigned to each domain, bit `i` for counter `i`
+    assigned: Vec<usize>,
+    harts: Vec<HartDomain>,
+}
+
+#[derive(Default)]
+struct HartDomain {
+    domain: usize,
+    // counters of the hart started by supervisor and not stopped since
+    running: usize,
+    // counters stopped when the hart left each domain, to be started again when it comes back
+    paused: Vec<usize>,
+}
+
+impl<T: Pmu> DomainPmu<T> {
+    /// Share every counter of `inner` among all domains; assign them to domains with `assign`.
+    #[inline]
+    pub fn new(inner: T) -> DomainPmu<T> {
+        DomainPmu {
+            inner,
+            assigned: Vec::new(),
+            harts: Vec::new(),
+        }
+    }
+    /// Assign `counters` to `domain`, taking them from domains they were assigned to before.
+    ///
+    /// Counters whose indexes are not below XLEN are left shared.
+    pub fn assign(mut self, domain: usize, counters: IndexMask) -> DomainPmu<T> {
+        let mut bits = 0;
+        for idx in counters.iter().filter(|&idx| idx < usize::BITS as usize) {
+            bits |= 1 << idx;
+        }
+        for assigned in self.assigned.iter_mut() {
+            *assigned &= !bits;
+        }
+        if self.assigned.len() <= domain {
+            self.assigned.resize(domain + 1, 0);
+        }
+        self.assigned[domain] |= bits;
+        self
+    }
+    /// Counters below XLEN which supervisor of `domain` may use, bit `i` for counter `i`.
+    #[inline]
+    pub fn visible_counters(&self, domain: usize) -> usize {
+        let all_assigned = self.assigned.iter().fold(0, |acc, bits| acc | bits);
+        !all_assigned | self.assigned_to(domain)
+    }
+    /// The wrapped PMU implementation.
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+    #[inline]
+    fn assigned_to(&self, domain: usize) -> usize {
+        self.assigned.get(domain).copied().unwrap_or(0)
+    }
+    #[inline]
+    fn current_domain(&self) -> usize {
+        self.harts.get(current_hartid()).map_or(0, |hart| hart.domain)
+    }
+    fn hart_mut(&mut self) -> &mut HartDomain {
+        let hartid = current_hartid();
+        if self.harts.len() <= hartid {
+            self.harts.resize_with(hartid + 1, HartDomain::default);
+        }
+        &mut self.harts[hartid]
+    }
+    // whether every counter in the set may be used by the current domain
+    fn is_visible(&self, counter_idx_base: usize, counter_idx_mask: usize) -> bool {
+        let visible = self.visible_counters(self.current_domain());
+        IndexMask::new(counter_idx_base, counter_idx_mask)
+            .iter()
+            .all(|idx| idx >= usize::BITS as usize || visible & (1 << idx) != 0)
+    }
+    // clear bits of counters in the set which the current domain may not use
+    fn restrict(&self, counter_idx_base: usize, counter_idx_mask: usize) -> usize {
+        let visible = self.visible_counters(self.current_domain());
+        let mut mask = counter_idx_mask;
+        for idx in IndexMask::new(counter_idx_base, counter_idx_mask) {
+            if idx < usize::BITS as usize && visible & (1 << idx) == 0 {
+                mask &= !(1 << (idx - counter_idx_base));
+            }
+        }
+        mask
+    }
+}
+
+// counters of the set whose indexes are below XLEN, bit `i` for counter `i`
+fn absolute_bits(counter_idx_base: usize, counter_idx_mask: usize) -> usize {
+    let mut bits = 0;
+    for idx in IndexMask::new(counter_idx_base, counter_idx_mask).iter().filter(|&idx| idx < usize::BITS as usize) {
+        bits |= 1 << idx;
+    }
+    bits
+}
+
+impl<T: Pmu> Pmu for DomainPmu<T> {
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        let mask = self.restrict(counter_idx_base, counter_idx_mask);
+        if mask == 0 && counter_idx_mask != 0 {
+            return SbiRet::not_supported();
+        }
+        let ans = self.inner.pmu_counter_config_matching(counter_idx_base, mask, config_flags, event_idx, event_data);
+        if ans.error == SBI_SUCCESS && config_flags & super::SBI_PMU_CFG_FLAG_AUTO_START != 0 {
+            self.hart_mut().running |= absolute_bits(ans.value, 1);
+        }
+        ans
+    }
+    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+        if !self.is_visible(counter_idx_base, counter_idx_mask) {
+            return SbiRet::invalid_param();
+        }
+        let ans = self.inner.pmu_counter_start(counter_idx_base, counter_idx_mask, start_flags, initial_value);
+        if ans.error == SBI_SUCCESS {
+            self.hart_mut().running |= absolute_bits(counter_idx_base, counter_idx_mask);
+        }
+        ans
+    }
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+        if !self.is_visible(counter_idx_base, counter_idx_mask) {
+            return SbiRet::invalid_param();
+        }
+        let ans = self.inner.pmu_counter_stop(counter_idx_base, counter_idx_mask, stop_flags);
+        if ans.error == SBI_SUCCESS {
+            self.hart_mut().running &= !absolute_bits(counter_idx_base, counter_idx_mask);
+        }
+        ans
+    }
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet {
+        if !self.is_visible(counter_idx, 1) {
+            return SbiRet::invalid_param();
+        }
+        self.inner.pmu_counter_fw_read(counter_idx)
+    }
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        if !self.is_visible(counter_idx, 1) {
+            return SbiRet::invalid_param();
+        }
+        self.inner.pmu_counter_fw_read_hi(counter_idx)
+    }
+    fn pmu_emulate_counter_read(&self, counter_idx: usize) -> Option<u64> {
+        if !self.is_visible(counter_idx, 1) {
+            return None;
+        }
+        self.inner.pmu_emulate_counter_read(counter_idx)
+    }
+    fn pmu_counter_read_clear(&mut self, counter_idx: usize) -> SbiRet {
+        if !self.is_visible(counter_idx, 1) {
+            return SbiRet::invalid_param();
+        }
+        self.inner.pmu_counter_read_clear(counter_idx)
+    }
+    fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: WatchNotify) -> SbiRet {
+        if !self.is_visible(counter_idx, 1) {
+            return SbiRet::invalid_param();
+        }
+        self.inner.pmu_counter_watch(counter_idx, threshold, notify)
+    }
+    fn pmu_counter_read_batch(&self, counter_idx_base: usize, counter_idx_mask: usize, values: &mut [u64; usize::BITS as usize]) -> SbiRet {
+        if !self.is_visible(counter_idx_base, counter_idx_mask) {
+            return SbiRet::invalid_param();
+        }
+        self.inner.pmu_counter_read_batch(counter_idx_base, counter_idx_mask, values)
+    }
+    fn pmu_enter_domain(&mut self, domain: usize) {
+        let leaving = self.assigned_to(self.current_domain());
+        let entering = self.assigned_to(domain);
+        let hart = self.hart_mut();
+        if hart.domain == domain {
+            return;
+        }
+        let old = hart.domain;
+        let pause = hart.running & leaving;
+        let resume = hart.paused.get(domain).copied().unwrap_or(0) & entering;
+        if hart.paused.len() <= old.max(domain) {
+            hart.paused.resize(old.max(domain) + 1, 0);
+        }
+        hart.paused[old] = pause;
+        hart.paused[domain] = 0;
+        hart.running = (hart.running & !pause) | resume;
+        hart.domain = domain;
+        if pause != 0 {
+            self.inner.pmu_counter_stop(0, pause, 0);
+        }
+        if resume != 0 {
+            self.inner.pmu_counter_start(0, resume, 0, 0);
+        }
+    }
+    // a summary counts counters of every domain, which a supervisor must not learn about
+    fn pmu_hart_summary(&self, hartid: usize) -> Option<HartSummary> {
+        drop(hartid);
+        None
+    }
+    // so do totals of firmware events
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        drop((event_idx, per_hart));
+        SbiRet::not_supported()
+    }
+    // leaks are only reported for counters the caller's domain can see
+    fn pmu_leak_check(&mut self, counter_idx_base: usize, end_session: bool) -> SbiRet {
+        let ans = self.inner.pmu_leak_check(counter_idx_base, end_session);
+        if ans.error != SBI_SUCCESS {
+            return ans;
+        }
+        let visible = IndexMask::new(counter_idx_base, ans.value).iter().filter(|&idx| self.is_visible(idx, 1));
+        SbiRet::ok(visible.fold(0, |mask, idx| mask | 1 << (idx - counter_idx_base)))
+    }
+    // reclaiming would free counters of other domains as well
+    fn pmu_reclaim(&mut self) -> SbiRet {
+        SbiRet::not_supported()
+    }
+    // counters paused in other domains are gone too; harts stay in their domains
+    fn pmu_reset(&mut self, all_harts: bool) {
+        self.inner.pmu_reset(all_harts);
+        let hartid = current_hartid();
+        for (idx, hart) in self.harts.iter_mut().enumerate() {
+            if all_harts || idx == hartid {
+                hart.running = 0;
+                hart.paused.clear();
+            }
+        }
+    }
+    crate::delegate_pmu!(inner => is_available, pmu_version, pmu_num_counters, pmu_counter_get_info, pmu_snapshot_set_shm,
+        pmu_event_get_info, pmu_save_context, pmu_restore_context, pmu_poll_overflow, pmu_rotate_multiplex, pmu_dump, pmu_firmware_event,
+        pmu_ecall_cycles, pmu_protect_event, pmu_platform_event, pmu_capabilities);
+}
@@ -0,0 +1,464 @@
+//! Event encodings of the PMU extension and their `perf` compatible names
+//!
+//! Supervisor software refers to events with names such as `cycles`, `instructions` or
+//! `dTLB-load-misses` when using Linux `perf`. This module maps these names to `event_idx`
+//! values defined by the SBI specification, so that callers need not spell out event encodings.
+//!
+//! It also defines `EventIdx` and the flags of the counter calls, which both sides of the calls share.
+//! This module depends on nothing but `core`; supervisor software, for example the PMU test kernel
+//! and `sbi-pmu-client`, may include this file with a `#[path]` attribute without depending on RustSBI.
+
+/// Event type of hardware general events
+pub const EVENT_TYPE_HARDWARE_GENERAL: usize = 0;
+/// Event type of hardware cache events
+pub const EVENT_TYPE_HARDWARE_CACHE: usize = 1;
+/// Event type of hardware raw events
+pub const EVENT_TYPE_HARDWARE_RAW: usize = 2;
+/// Event type of hardware raw events v2, whose selector is the whole `event_data`
+pub const EVENT_TYPE_HARDWARE_RAW_V2: usize = 3;
+/// Event type of firmware events
+pub const EVENT_TYPE_FIRMWARE: usize = 15;
+
+/// Hardware general event: CPU cycles
+pub const SBI_PMU_HW_CPU_CYCLES: usize = 1;
+/// Hardware general event: retired instructions
+pub const SBI_PMU_HW_INSTRUCTIONS: usize = 2;
+/// Hardware general event: cache accesses
+pub const SBI_PMU_HW_CACHE_REFERENCES: usize = 3;
+/// Hardware general event: cache misses
+pub const SBI_PMU_HW_CACHE_MISSES: usize = 4;
+/// Hardware general event: retired branch instructions
+pub const SBI_PMU_HW_BRANCH_INSTRUCTIONS: usize = 5;
+/// Hardware general event: mispredicted branch instructions
+pub const SBI_PMU_HW_BRANCH_MISSES: usize = 6;
+/// Hardware general event: bus cycles
+pub const SBI_PMU_HW_BUS_CYCLES: usize = 7;
+/// Hardware general event: stalled cycles during issue
+pub const SBI_PMU_HW_STALLED_CYCLES_FRONTEND: usize = 8;
+/// Hardware general event: stalled cycles during retirement
+pub const SBI_PMU_HW_STALLED_CYCLES_BACKEND: usize = 9;
+/// Hardware general event: total cycles, not affected by CPU frequency scaling
+pub const SBI_PMU_HW_REF_CPU_CYCLES: usize = 10;
+
+/// Misaligned load trap event
+pub const SBI_PMU_FW_MISALIGNED_LOAD: usize = 0;
+/// Misaligned store trap event
+pub const SBI_PMU_FW_MISALIGNED_STORE: usize = 1;
+/// Load access trap event
+pub const SBI_PMU_FW_ACCESS_LOAD: usize = 2;
+/// Store access trap event
+pub const SBI_PMU_FW_ACCESS_STORE: usize = 3;
+/// Illegal instruction trap event
+pub const SBI_PMU_FW_ILLEGAL_INSN: usize = 4;
+/// Set timer event
+pub const SBI_PMU_FW_SET_TIMER: usize = 5;
+/// Sent IPI to other hart event
+pub const SBI_PMU_FW_IPI_SENT: usize = 6;
+/// Received IPI from other hart event
+pub const SBI_PMU_FW_IPI_RECEIVED: usize = 7;
+/// Sent FENCE.I request to other hart event
+pub const SBI_PMU_FW_FENCE_I_SENT: usize = 8;
+/// Received FENCE.I request from other hart event
+pub const SBI_PMU_FW_FENCE_I_RECEIVED: usize = 9;
+/// Sent SFENCE.VMA request to other hart event
+pub const SBI_PMU_FW_SFENCE_VMA_SENT: usize = 10;
+/// Received SFENCE.VMA request from other hart event
+pub const SBI_PMU_FW_SFENCE_VMA_RECEIVED: usize = 11;
+/// Sent SFENCE.VMA with ASID request to other hart event
+pub const SBI_PMU_FW_SFENCE_VMA_ASID_SENT: usize = 12;
+/// Received SFENCE.VMA with ASID request from other hart event
+pub const SBI_PMU_FW_SFENCE_VMA_ASID_RECEIVED: usize = 13;
+/// Sent HFENCE.GVMA request to other hart event
+pub const SBI_PMU_FW_HFENCE_GVMA_SENT: usize = 14;
+/// Received HFENCE.GVMA request from other hart event
+pub const SBI_PMU_FW_HFENCE_GVMA_RECEIVED: usize = 15;
+/// Sent HFENCE.GVMA with VMID request to other hart event
+pub const SBI_PMU_FW_HFENCE_GVMA_VMID_SENT: usize = 16;
+/// Received HFENCE.GVMA with VMID request from other hart event
+pub const SBI_PMU_FW_HFENCE_GVMA_VMID_RECEIVED: usize = 17;
+/// Sent HFENCE.VVMA request to other hart event
+pub const SBI_PMU_FW_HFENCE_VVMA_SENT: usize = 18;
+/// Received HFENCE.VVMA request from other hart event
+pub const SBI_PMU_FW_HFENCE_VVMA_RECEIVED: usize = 19;
+/// Sent HFENCE.VVMA with ASID request to other hart event
+pub const SBI_PMU_FW_HFENCE_VVMA_ASID_SENT: usize = 20;
+/// Received HFENCE.VVMA with ASID request from other hart event
+pub const SBI_PMU_FW_HFENCE_VVMA_ASID_RECEIVED: usize = 21;
+/// Number of firmware events defined by the specification; larger event codes are reserved
+pub const NUM_FIRMWARE_EVENTS: usize = 22;
+/// Platform specific firmware event, selected by `event_data`
+pub const SBI_PMU_FW_PLATFORM: usize = 65535;
+
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting the cycles spent by RustSBI handling calls of the PMU extension
+///
+/// Supervisor subtracts the counted cycles from its own measurements to remove the overhead of PMU calls.
+pub const RUSTSBI_FW_PMU_ECALL_CYCLES: u64 = 1;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting the cycles spent by the firmware handling traps from supervisor
+///
+/// Every trap taken from supervisor counts, SBI calls, emulated instructions and machine interrupts alike;
+/// platforms measure it in their trap handler and report it through `count_firmware_cycles`.
+pub const RUSTSBI_FW_PMU_TRAP_CYCLES: u64 = 2;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting the cycles spent by the firmware handling inter-processor interrupts
+pub const RUSTSBI_FW_PMU_IPI_CYCLES: u64 = 3;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting the cycles spent by RustSBI writing console output of supervisor
+pub const RUSTSBI_FW_PMU_CONSOLE_CYCLES: u64 = 4;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting machine timer interrupts taken by the firmware
+///
+/// This and the next two events count interrupts, not cycles; supervisor looks at them to tell interrupt storms
+/// during a benchmark apart from slow code.
+pub const RUSTSBI_FW_PMU_MTIMER_INTERRUPTS: u64 = 5;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting machine software interrupts taken by the firmware
+pub const RUSTSBI_FW_PMU_MSOFT_INTERRUPTS: u64 = 6;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting machine external interrupts taken by the firmware
+pub const RUSTSBI_FW_PMU_MEXT_INTERRUPTS: u64 = 7;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting reads of counter CSRs by supervisor which the firmware emulated
+///
+/// Each read trapped because the counter's `mcounteren` bit was clear, and costs a trap into the firmware; supervisor
+/// looks at this event to tell whether it should read the counters through SBI calls instead.
+pub const RUSTSBI_FW_PMU_EMULATED_COUNTER_READS: u64 = 8;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting SBI calls serviced by RustSBI for the calling hart
+///
+/// Calls of every extension count, those answered with an error included, and so does the call reading the counter;
+/// benchmarks report it next to hardware events to show how much SBI traffic they generated.
+pub const RUSTSBI_FW_PMU_SBI_CALLS: u64 = 9;
+/// `event_data` of `SBI_PMU_FW_PLATFORM` selecting calls of the PMU extension serviced by RustSBI for the calling hart
+pub const RUSTSBI_FW_PMU_PMU_CALLS: u64 = 10;
+
+/// Event index, a 20 bits wide number identifying a hardware or firmware event
+///
+/// | Bits   | Description
+/// |:-------|:------------
+/// | 19:16  | Event type
+/// | 15:0   | Event code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventIdx(usize);
+
+impl EventIdx {
+    /// Wrap a raw `event_idx` value, returns `None` if bits above bit 19 are set.
+    #[inline]
+    pub const fn new(event_idx: usize) -> Option<EventIdx> {
+        if event_idx >> 20 != 0 {
+            None
+        } else {
+            Some(EventIdx(event_idx))
+        }
+    }
+    /// Build an event index from event type and event code.
+    #[inline]
+    pub const fn from_parts(event_type: usize, code: usize) -> EventIdx {
+        EventIdx(((event_type & 0xF) << 16) | (code & 0xFFFF))
+    }
+    /// Raw `event_idx` value.
+    #[inline]
+    pub const fn raw(self) -> usize {
+        self.0
+    }
+    /// Event type, `event_idx[19:16]`.
+    #[inline]
+    pub const fn event_type(self) -> usize {
+        self.0 >> 16
+    }
+    /// Event code, `event_idx[15:0]`.
+    #[inline]
+    pub const fn code(self) -> usize {
+        self.0 & 0xFFFF
+    }
+}
+
+/// Build an `event_idx` value from event type and event code.
+#[inline]
+pub const fn event_idx(event_type: usize, code: usize) -> usize {
+    ((event_type & 0xF) << 16) | (code & 0xFFFF)
+}
+
+/// Counter information returned by `sbi_pmu_counter_get_info`
+///
+/// | Bits        | Description
+/// |:------------|:------------
+/// | 11:0        | CSR number of a hardware counter
+/// | 17:12       | Width of a hardware counter, one less than its number of bits
+/// | XLEN-2:18   | Reserved
+/// | XLEN-1      | Type, 0 for hardware counters and 1 for firmware counters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CounterInfo(usize);
+
+impl CounterInfo {
+    const FIRMWARE: usize = 1 << (usize::BITS - 1);
+
+    /// Information of a hardware counter read through CSR `csr`, `bits` bits wide.
+    #[inline]
+    pub const fn hardware(csr: usize, bits: usize) -> CounterInfo {
+        CounterInfo((csr & 0xFFF) | ((bits.saturating_sub(1) & 0x3F) << 12))
+    }
+    /// Information of a firmware counter; CSR number and width are ignored.
+    #[inline]
+    pub const fn firmware() -> CounterInfo {
+        CounterInfo(Self::FIRMWARE)
+    }
+    /// Wrap a raw `counter_info` value.
+    #[inline]
+    pub const fn from_raw(counter_info: usize) -> CounterInfo {
+        CounterInfo(counter_info)
+    }
+    /// Raw `counter_info` value.
+    #[inline]
+    pub const fn raw(self) -> usize {
+        self.0
+    }
+    /// Whether the counter is a firmware counter, `counter_info[XLEN-1]`.
+    #[inline]
+    pub const fn is_firmware(self) -> bool {
+        self.0 & Self::FIRMWARE != 0
+    }
+    /// CSR number of a hardware counter, `counter_info[11:0]`.
+    #[inline]
+    pub const fn csr(self) -> usize {
+        self.0 & 0xFFF
+    }
+    /// Number of bits of a hardware counter, one more than `counter_info[17:12]`.
+    #[inline]
+    pub const fn bits(self) -> usize {
+        ((self.0 >> 12) & 0x3F) + 1
+    }
+}
+
+/// Skip the counter matching
+pub const SBI_PMU_CFG_FLAG_SKIP_MATCH: usize = 1 << 0;
+/// Clear (or zero) the counter value in counter configuration
+pub const SBI_PMU_CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+/// Start the counter after configuring a matching counter
+pub const SBI_PMU_CFG_FLAG_AUTO_START: usize = 1 << 2;
+/// Event counting inhibited in VU-mode
+pub const SBI_PMU_CFG_FLAG_SET_VUINH: usize = 1 << 3;
+/// Event counting inhibited in VS-mode
+pub const SBI_PMU_CFG_FLAG_SET_VSINH: usize = 1 << 4;
+/// Event counting inhibited in U-mode
+pub const SBI_PMU_CFG_FLAG_SET_UINH: usize = 1 << 5;
+/// Event counting inhibited in S-mode
+pub const SBI_PMU_CFG_FLAG_SET_SINH: usize = 1 << 6;
+/// Event counting inhibited in M-mode
+pub const SBI_PMU_CFG_FLAG_SET_MINH: usize = 1 << 7;
+
+/// Set the value of counters based on the `initial_value` parameter
+pub const SBI_PMU_START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+/// Initialize the given counters from the snapshot shared memory, in place of the `initial_value` parameter
+pub const SBI_PMU_START_FLAG_INIT_SNAPSHOT: usize = 1 << 1;
+
+/// Reset the counter to event mapping
+pub const SBI_PMU_STOP_FLAG_RESET: usize = 1 << 0;
+/// Save a snapshot of the given counters' values in the shared memory
+pub const SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT: usize = 1 << 1;
+/// End the session of supervisor after checking it for leaked counters, with the leak check call of RustSBI
+pub const RUSTSBI_PMU_LEAK_CHECK_END_SESSION: usize = 1 << 0;
+/// Notify supervisor by writing into shared memory instead of raising a supervisor software interrupt, with the
+/// counter watch call of RustSBI
+pub const RUSTSBI_PMU_WATCH_NOTIFY_SHMEM: usize = 1 << 0;
+/// Return counters which passed the boot-time self-test instead of those which failed, with the self-test result
+/// call of RustSBI
+pub const RUSTSBI_PMU_SELF_TEST_PASSED: usize = 1 << 0;
+/// Return the version of the PMU extension instead of its capabilities, with the capabilities call of RustSBI
+pub const RUSTSBI_PMU_CAPABILITIES_VERSION: usize = 1 << 0;
+
+/// Capability: the snapshot shared memory, `sbi_pmu_snapshot_set_shmem`
+pub const RUSTSBI_PMU_CAP_SNAPSHOT: usize = 1 << 0;
+/// Capability: overflow interrupts of hardware counters, by the Sscofpmf extension
+pub const RUSTSBI_PMU_CAP_SSCOFPMF: usize = 1 << 1;
+/// Capability: firmware counters counting firmware events
+pub const RUSTSBI_PMU_CAP_FIRMWARE_EVENTS: usize = 1 << 2;
+/// Capability: the batch read call of RustSBI
+pub const RUSTSBI_PMU_CAP_READ_BATCH: usize = 1 << 3;
+/// Capability: periodic sampling of counters, with the sampler start call of RustSBI
+pub const RUSTSBI_PMU_CAP_SAMPLER: usize = 1 << 4;
+
+// event code of a hardware cache event, see `CacheEvent` for the meaning of each field
+const fn cache(id: usize, op: usize, result: usize) -> usize {
+    event_idx(EVENT_TYPE_HARDWARE_CACHE, (id << 3) | (op << 1) | result)
+}
+
+const fn general(code: usize) -> usize {
+    event_idx(EVENT_TYPE_HARDWARE_GENERAL, code)
+}
+
+const fn firmware(code: usize) -> usize {
+    event_idx(EVENT_TYPE_FIRMWARE, code)
+}
+
+// cache ids, operations and results of hardware cache events
+const L1D: usize = 0;
+const L1I: usize = 1;
+const LL: usize = 2;
+const DTLB: usize = 3;
+const ITLB: usize = 4;
+const BPU: usize = 5;
+const NODE: usize = 6;
+const READ: usize = 0;
+const WRITE: usize = 1;
+const PREFETCH: usize = 2;
+const ACCESS: usize = 0;
+const MISS: usize = 1;
+
+/// Event names and their `event_idx` values
+///
+/// Hardware general and cache events use the names of Linux `perf`; aliases map to the same event.
+/// Firmware events, which `perf` has no names for, are named after the specification with a `fw-` prefix.
+pub const EVENT_NAMES: &[(&str, usize)] = &[
+    ("cycles", general(SBI_PMU_HW_CPU_CYCLES)),
+    ("cpu-cycles", general(SBI_PMU_HW_CPU_CYCLES)),
+    ("instructions", general(SBI_PMU_HW_INSTRUCTIONS)),
+    ("cache-references", general(SBI_PMU_HW_CACHE_REFERENCES)),
+    ("cache-misses", general(SBI_PMU_HW_CACHE_MISSES)),
+    ("branch-instructions", general(SBI_PMU_HW_BRANCH_INSTRUCTIONS)),
+    ("branches", general(SBI_PMU_HW_BRANCH_INSTRUCTIONS)),
+    ("branch-misses", general(SBI_PMU_HW_BRANCH_MISSES)),
+    ("bus-cycles", general(SBI_PMU_HW_BUS_CYCLES)),
+    ("stalled-cycles-frontend", general(SBI_PMU_HW_STALLED_CYCLES_FRONTEND)),
+    ("idle-cycles-frontend", general(SBI_PMU_HW_STALLED_CYCLES_FRONTEND)),
+    ("stalled-cycles-backend", general(SBI_PMU_HW_STALLED_CYCLES_BACKEND)),
+    ("idle-cycles-backend", general(SBI_PMU_HW_STALLED_CYCLES_BACKEND)),
+    ("ref-cycles", general(SBI_PMU_HW_REF_CPU_CYCLES)),
+    ("L1-dcache-loads", cache(L1D, READ, ACCESS)),
+    ("L1-dcache-load-misses", cache(L1D, READ, MISS)),
+    ("L1-dcache-stores", cache(L1D, WRITE, ACCESS)),
+    ("L1-dcache-store-misses", cache(L1D, WRITE, MISS)),
+    ("L1-dcache-prefetches", cache(L1D, PREFETCH, ACCESS)),
+    ("L1-dcache-prefetch-misses", cache(L1D, PREFETCH, MISS)),
+    ("L1-icache-loads", cache(L1I, READ, ACCESS)),
+    ("L1-icache-load-misses", cache(L1I, READ, MISS)),
+    ("L1-icache-prefetches", cache(L1I, PREFETCH, ACCESS)),
+    ("L1-icache-prefetch-misses", cache(L1I, PREFETCH, MISS)),
+    ("LLC-loads", cache(LL, READ, ACCESS)),
+    ("LLC-load-misses", cache(LL, READ, MISS)),
+    ("LLC-stores", cache(LL, WRITE, ACCESS)),
+    ("LLC-store-misses", cache(LL, WRITE, MISS)),
+    ("LLC-prefetches", cache(LL, PREFETCH, ACCESS)),
+    ("LLC-prefetch-misses", cache(LL, PREFETCH, MISS)),
+    ("dTLB-loads", cache(DTLB, READ, ACCESS)),
+    ("dTLB-load-misses", cache(DTLB, READ, MISS)),
+    ("dTLB-stores", cache(DTLB, WRITE, ACCESS)),
+    ("dTLB-store-misses", cache(DTLB, WRITE, MISS)),
+    ("dTLB-prefetches", cache(DTLB, PREFETCH, ACCESS)),
+    ("dTLB-prefetch-misses", cache(DTLB, PREFETCH, MISS)),
+    ("iTLB-loads", cache(ITLB, READ, ACCESS)),
+    ("iTLB-load-misses", cache(ITLB, READ, MISS)),
+    ("branch-loads", cache(BPU, READ, ACCESS)),
+    ("branch-load-misses", cache(BPU, READ, MISS)),
+    ("node-loads", cache(NODE, READ, ACCESS)),
+    ("node-load-misses", cache(NODE, READ, MISS)),
+    ("node-stores", cache(NODE, WRITE, ACCESS)),
+    ("node-store-misses", cache(NODE, WRITE, MISS)),
+    ("node-prefetches", cache(NODE, PREFETCH, ACCESS)),
+    ("node-prefetch-misses", cache(NODE, PREFETCH, MISS)),
+    ("fw-misaligned-load", firmware(SBI_PMU_FW_MISALIGNED_LOAD)),
+    ("fw-misaligned-store", firmware(SBI_PMU_FW_MISALIGNED_STORE)),
+    ("fw-access-load", firmware(SBI_PMU_FW_ACCESS_LOAD)),
+    ("fw-access-store", firmware(SBI_PMU_FW_ACCESS_STORE)),
+    ("fw-illegal-insn", firmware(SBI_PMU_FW_ILLEGAL_INSN)),
+    ("fw-set-timer", firmware(SBI_PMU_FW_SET_TIMER)),
+    ("fw-ipi-sent", firmware(SBI_PMU_FW_IPI_SENT)),
+    ("fw-ipi-received", firmware(SBI_PMU_FW_IPI_RECEIVED)),
+    ("fw-fence-i-sent", firmware(SBI_PMU_FW_FENCE_I_SENT)),
+    ("fw-fence-i-received", firmware(SBI_PMU_FW_FENCE_I_RECEIVED)),
+    ("fw-sfence-vma-sent", firmware(SBI_PMU_FW_SFENCE_VMA_SENT)),
+    ("fw-sfence-vma-received", firmware(SBI_PMU_FW_SFENCE_VMA_RECEIVED)),
+    ("fw-sfence-vma-asid-sent", firmware(SBI_PMU_FW_SFENCE_VMA_ASID_SENT)),
+    ("fw-sfence-vma-asid-received", firmware(SBI_PMU_FW_SFENCE_VMA_ASID_RECEIVED)),
+    ("fw-hfence-gvma-sent", firmware(SBI_PMU_FW_HFENCE_GVMA_SENT)),
+    ("fw-hfence-gvma-received", firmware(SBI_PMU_FW_HFENCE_GVMA_RECEIVED)),
+    ("fw-hfence-gvma-vmid-sent", firmware(SBI_PMU_FW_HFENCE_GVMA_VMID_SENT)),
+    ("fw-hfence-gvma-vmid-received", firmware(SBI_PMU_FW_HFENCE_GVMA_VMID_RECEIVED)),
+    ("fw-hfence-vvma-sent", firmware(SBI_PMU_FW_HFENCE_VVMA_SENT)),
+    ("fw-hfence-vvma-received", firmware(SBI_PMU_FW_HFENCE_VVMA_RECEIVED)),
+    ("fw-hfence-vvma-asid-sent", firmware(SBI_PMU_FW_HFENCE_VVMA_ASID_SENT)),
+    ("fw-hfence-vvma-asid-received", firmware(SBI_PMU_FW_HFENCE_VVMA_ASID_RECEIVED)),
+    ("fw-platform", firmware(SBI_PMU_FW_PLATFORM)),
+];
+
+/// Look up the `event_idx` of an event by its name, returns `None` for unknown names.
+pub fn lookup(name: &str) -> Option<usize> {
+    EVENT_NAMES.iter().find(|(n, _)| *n == name).map(|&(_, event_idx)| event_idx)
+}
+
+/// Name of the event, the first one in `EVENT_NAMES` if it has aliases; returns `None` for
+/// events without a name, such as hardware raw events.
+pub fn name(event_idx: usize) -> Option<&'static str> {
+    EVENT_NAMES.iter().find(|(_, idx)| *idx == event_idx).map(|&(n, _)| n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CounterInfo, EventIdx};
+
+    // xorshift generator, so that every run checks the same values
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+    }
+
+    const SAMPLES: usize = 4096;
+
+    #[test]
+    fn event_idx_round_trip() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..SAMPLES {
+            let (event_type, code) = (rng.next() & 0xF, rng.next() & 0xFFFF);
+            let event = EventIdx::from_parts(event_type, code);
+            assert_eq!((event.event_type(), event.code()), (event_type, code));
+            assert_eq!(EventIdx::new(event.raw()), Some(event));
+            assert_eq!(event.raw(), super::event_idx(event_type, code));
+        }
+    }
+
+    #[test]
+    fn event_idx_keeps_20_bits() {
+        let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+        for _ in 0..SAMPLES {
+            let raw = rng.next();
+            match EventIdx::new(raw) {
+                Some(event) => {
+                    assert!(raw < 1 << 20, "{:#x}", raw);
+                    assert_eq!(EventIdx::from_parts(event.event_type(), event.code()), event);
+                }
+                None => assert!(raw >= 1 << 20, "{:#x}", raw),
+            }
+            // out of range parts are cut to their fields, never spill into the other one
+            let (event_type, code) = (rng.next(), rng.next());
+            let event = EventIdx::from_parts(event_type, code);
+            assert!(event.raw() < 1 << 20);
+            assert_eq!((event.event_type(), event.code()), (event_type & 0xF, code & 0xFFFF));
+        }
+    }
+
+    #[test]
+    fn counter_info_round_trip() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..SAMPLES {
+            let (csr, bits) = (rng.next() & 0xFFF, rng.next() % 64 + 1);
+            let info = CounterInfo::hardware(csr, bits);
+            assert_eq!((info.csr(), info.bits(), info.is_firmware()), (csr, bits, false), "{:#x}", info.raw());
+            assert_eq!(info.raw() >> 18, 0, "{:#x}", info.raw());
+            assert_eq!(CounterInfo::from_raw(info.raw()), info);
+        }
+        let info = CounterInfo::firmware();
+        assert!(info.is_firmware());
+        assert_eq!(info.raw(), 1 << (usize::BITS - 1));
+    }
+
+    #[test]
+    fn counter_info_fields_are_independent() {
+        let mut rng = Rng(0xBF58_476D_1CE4_E5B9);
+        for _ in 0..SAMPLES {
+            let raw = rng.next();
+            let info = CounterInfo::from_raw(raw);
+            let rebuilt = CounterInfo::hardware(info.csr(), info.bits());
+            // the hardware fields survive, reserved bits and the type bit are dropped
+            assert_eq!(rebuilt.raw(), raw & 0x3FFFF, "{:#x}", raw);
+            assert_eq!(info.is_firmware(), raw >> (usize::BITS - 1) == 1);
+        }
+    }
+}
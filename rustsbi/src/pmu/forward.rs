@@ -0,0 +1,156 @@
+//! PMU implementation forwarding calls to the SBI implementation below
+
+use super::{Pmu, PMU_VERSION_0_3};
+use crate::ecall::{SbiRet, EXTENSION_BASE, EXTENSION_PMU};
+
+const FUNCTION_BASE_PROBE_EXTENSION: usize = 0x3;
+
+const FUNCTION_PMU_NUM_COUNTERS: usize = 0x0;
+const FUNCTION_PMU_COUNTER_GET_INFO: usize = 0x1;
+const FUNCTION_PMU_COUNTER_CFG_MATCH: usize = 0x2;
+const FUNCTION_PMU_COUNTER_START: usize = 0x3;
+const FUNCTION_PMU_COUNTER_STOP: usize = 0x4;
+const FUNCTION_PMU_COUNTER_FW_READ: usize = 0x5;
+const FUNCTION_PMU_COUNTER_FW_READ_HI: usize = 0x6;
+const FUNCTION_PMU_SNAPSHOT_SET_SHM: usize = 0x7;
+
+/// PMU implementation which forwards every call to the host SBI implementation
+///
+/// When RustSBI runs as the SBI implementation of a guest, for example in HS-mode under a hypervisor
+/// or in a nested virtualization test setup, it cannot access counter CSRs of machine mode. `ForwardPmu`
+/// makes the same PMU calls to the host SBI implementation through `ecall` instead, so supervisor
+/// sees the counters of the host: counter indexes, events and returned errors are passed through unchanged,
+/// and 64-bit parameters are split into two registers on RV32 as the specification requires.
+///
+/// Firmware events counted by RustSBI itself are not forwarded; firmware counters count the events
+/// of the host implementation.
+///
+/// The PMU implementation is selected when the platform initializes RustSBI:
+///
+/// ```no_run
+/// match ForwardPmu::probe() {
+///     // running under another SBI implementation which has the PMU extension
+///     Some(pmu) => rustsbi::init_pmu(pmu),
+///     // running in machine mode
+///     None => rustsbi::init_pmu(GenericPmu::new(platform)),
+/// }
+/// ```
+pub struct ForwardPmu {
+    version: usize,
+}
+
+impl ForwardPmu {
+    /// Probe the PMU extension of the host SBI implementation, returns `None` if the host does not implement it.
+    ///
+    /// This function makes an SBI call, it must not be called from machine mode.
+    pub fn probe() -> Option<ForwardPmu> {
+        let ans = sbi_call(EXTENSION_BASE, FUNCTION_BASE_PROBE_EXTENSION, [EXTENSION_PMU, 0, 0, 0, 0, 0]);
+        match ans.into_result() {
+            Ok(0) | Err(_) => None,
+            Ok(version) => Some(ForwardPmu { version }),
+        }
+    }
+}
+
+impl Pmu for ForwardPmu {
+    // implementations older than this crate return 1 on probe, which is taken as version 0.3
+    fn pmu_version(&self) -> usize {
+        if self.version == 1 {
+            PMU_VERSION_0_3
+        } else {
+            self.version
+        }
+    }
+
+    fn pmu_num_counters(&self) -> SbiRet {
+        sbi_call(EXTENSION_PMU, FUNCTION_PMU_NUM_COUNTERS, [0; 6])
+    }
+
+    fn pmu_counter_get_info(&self, counter_idx: usize) -> SbiRet {
+        sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_GET_INFO, [counter_idx, 0, 0, 0, 0, 0])
+    }
+
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        let (lo, hi) = split_u64(event_data);
+        sbi_call(
+            EXTENSION_PMU,
+            FUNCTION_PMU_COUNTER_CFG_MATCH,
+            [counter_idx_base, counter_idx_mask, config_flags, event_idx, lo, hi],
+        )
+    }
+
+    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+        let (lo, hi) = split_u64(initial_value);
+        sbi_call(
+            EXTENSION_PMU,
+            FUNCTION_PMU_COUNTER_START,
+            [counter_idx_base, counter_idx_mask, start_flags, lo, hi, 0],
+        )
+    }
+
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+        sbi_call(
+            EXTENSION_PMU,
+            FUNCTION_PMU_COUNTER_STOP,
+            [counter_idx_base, counter_idx_mask, stop_flags, 0, 0, 0],
+        )
+    }
+
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet {
+        sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_FW_READ, [counter_idx, 0, 0, 0, 0, 0])
+    }
+
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        sbi_call(EXTENSION_PMU, FUNCTION_PMU_COUNTER_FW_READ_HI, [counter_idx, 0, 0, 0, 0, 0])
+    }
+
+    // the shared memory address is a guest physical address, which the host translates itself
+    fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+        sbi_call(
+            EXTENSION_PMU,
+            FUNCTION_PMU_SNAPSHOT_SET_SHM,
+            [shmem_phys_lo, shmem_phys_hi, flags, 0, 0, 0],
+        )
+    }
+}
+
+// a 64-bit parameter takes one register on RV64, and two registers, lower half first, on RV32;
+// the second register is left as zero on RV64
+#[inline]
+fn split_u64(value: u64) -> (usize, usize) {
+    match () {
+        #[cfg(target_pointer_width = "32")]
+        () => (value as usize, (value >> 32) as usize),
+        #[cfg(not(target_pointer_width = "32"))]
+        () => (value as usize, 0),
+    }
+}
+
+#[inline]
+fn sbi_call(extension: usize, function: usize, param: [usize; 6]) -> SbiRet {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => {
+            let (error, value);
+            unsafe {
+                asm!(
+                    "ecall",
+                    inlateout("a0") param[0] => error,
+                    inlateout("a1") param[1] => value,
+                    in("a2") param[2],
+                    in("a3") param[3],
+                    in("a4") param[4],
+                    in("a5") param[5],
+                    in("a6") function,
+                    in("a7") extension,
+                )
+            };
+            SbiRet { error, value }
+        }
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {
+            drop((extension, function, param));
+            unimplemented!("not RISC-V instruction set architecture")
+        }
+    }
+}
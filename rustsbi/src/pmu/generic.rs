@@ -0,0 +1,2527 @@
+//! Generic PMU implementation over a platform hardware description
+
+use super::{
+    events, interrupt_free, mhpmevent_inhibit_bits, CounterInfo, EventIdx, EventInfo, HartSummary, Pmu, SnapshotArea, WatchNotify, EVENT_INFO_SUPPORTED, EVENT_TYPE_FIRMWARE,
+    EVENT_TYPE_HARDWARE_CACHE, EVENT_TYPE_HARDWARE_GENERAL, EVENT_TYPE_HARDWARE_RAW, EVENT_TYPE_HARDWARE_RAW_V2,
+    MHPMEVENT_MINH, MHPMEVENT_OF, MHPMEVENT_SINH, MHPMEVENT_UINH, MHPMEVENT_VSINH, MHPMEVENT_VUINH, PMU_VERSION_0_3,
+    PMU_VERSION_3_0, RAW_EVENT_MASK, RUSTSBI_PMU_CAP_FIRMWARE_EVENTS, RUSTSBI_PMU_CAP_READ_BATCH, RUSTSBI_PMU_CAP_SNAPSHOT,
+    RUSTSBI_PMU_CAP_SSCOFPMF,
+    SBI_PMU_CFG_FLAG_AUTO_START, SBI_PMU_CFG_FLAG_CLEAR_VALUE, SBI_PMU_CFG_FLAG_SKIP_MATCH,
+    NUM_FIRMWARE_EVENTS, RUSTSBI_FW_PMU_ECALL_CYCLES, RUSTSBI_FW_PMU_PMU_CALLS, SBI_PMU_FW_PLATFORM,
+    SBI_PMU_START_FLAG_INIT_SNAPSHOT, SBI_PMU_START_FLAG_SET_INIT_VALUE, SBI_PMU_STOP_FLAG_RESET,
+    SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT, SNAPSHOT_AREA_SIZE,
+};
+pub use super::config::{init_generic_pmu, ConfiguredPmu, PmuConfig};
+use crate::ecall::SbiRet;
+use crate::index_mask::IndexMask;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use fixed::FixedCounters;
+use multiplex::Multiplexer;
+pub use crate::shmem::pmp_allows_supervisor;
+pub use remap::RemappedPlatform;
+
+mod fixed;
+mod multiplex;
+mod remap;
+
+/// Maximum number of hardware counters defined by the RISC-V privileged specification
+pub const MAX_HARDWARE_COUNTERS: usize = 32;
+/// Counter index of the `cycle` counter
+pub const COUNTER_CYCLE: usize = 0;
+/// Counter index of the `time` counter, which can never be used for event counting
+pub const COUNTER_TIME: usize = 1;
+/// Counter index of the `instret` counter
+pub const COUNTER_INSTRET: usize = 2;
+/// CSR number of the `time` counter, reported in its `counter_info` whatever the platform's `counter_csr`
+pub const CSR_TIME: usize = 0xC01;
+/// Counter index of the first programmable counter `hpmcounter3`
+pub const FIRST_HPM_COUNTER: usize = 3;
+/// Number of firmware counters on each hart, placed after hardware counters
+pub const FIRMWARE_COUNTERS: usize = 16;
+/// Number of multiplexed counters on each hart, placed after firmware counters
+///
+/// Multiplexed counters are only provided with the `multiplex` feature.
+#[cfg(feature = "multiplex")]
+pub const MULTIPLEX_COUNTERS: usize = 16;
+/// Number of multiplexed counters on each hart, zero without the `multiplex` feature
+#[cfg(not(feature = "multiplex"))]
+pub const MULTIPLEX_COUNTERS: usize = 0;
+
+// flag bits defined by the specification, other bits are reserved
+const CONFIG_FLAGS_MASK: usize = 0xFF;
+const START_FLAGS_MASK: usize = SBI_PMU_START_FLAG_SET_INIT_VALUE | SBI_PMU_START_FLAG_INIT_SNAPSHOT;
+const STOP_FLAGS_MASK: usize = SBI_PMU_STOP_FLAG_RESET | SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT;
+// `mhpmevent` encodings remembered at most; raw events let supervisor try any number of them
+const MAX_KNOWN_ENCODINGS: usize = 64;
+
+/// Hardware description of a platform's performance monitoring unit
+///
+/// A platform implements this trait to describe which counters can monitor which events,
+/// how events are encoded in `mhpmeventX`, and how counter CSRs are accessed on the calling hart.
+/// `GenericPmu` builds the whole SBI PMU state machine on top of it.
+///
+/// Counter indexes are the same as offsets of counter CSRs: counter `i` is read by
+/// supervisor through CSR `0xC00 + i`, and is backed by `mhpmcounter` CSR `0xB00 + i` and
+/// (for `i >= 3`) `mhpmevent` CSR `0x320 + i`. `RemappedPlatform` presents them under other
+/// indexes, leaving out counters the firmware keeps for itself.
+pub trait PmuPlatform: Send + Sync {
+    /// Number of implemented hardware counters; counters `0..num_counters` are present.
+    fn num_counters(&self) -> usize;
+    /// Whether the platform implements the Sscofpmf extension.
+    ///
+    /// Without Sscofpmf, privilege filter hints are ignored and counter overflow is detected by software.
+    fn has_sscofpmf(&self) -> bool;
+    /// Whether the platform implements the hypervisor extension.
+    ///
+    /// VS-mode and VU-mode filter bits of `mhpmeventX` are only programmed with the hypervisor extension,
+    /// they are reserved otherwise. Defaults to `false`.
+    fn has_hypervisor(&self) -> bool {
+        false
+    }
+    /// Whether the hardware counter `counter_idx` can monitor the given event.
+    ///
+    /// Hardware general and cache events supported by the platform are looked up once when `GenericPmu`
+    /// is created, so that `sbi_pmu_counter_config_matching` reports events no counter can monitor with
+    /// `SBI_ERR_NOT_SUPPORTED`, and events whose counters are all in use with `SBI_ERR_FAILED`.
+    fn counter_can_monitor(&self, counter_idx: usize, event_idx: usize, event_data: u64) -> bool;
+    /// Value to be written into `mhpmeventX` to count the given event, excluding privilege filter bits.
+    ///
+    /// Not called for hardware raw events, whose selector is taken from `event_data` directly.
+    fn mhpmevent_value(&self, event_idx: usize, event_data: u64) -> u64;
+    /// Whether hardware raw events v2 are supported, i.e. the whole `event_data` can be written into `mhpmeventX`.
+    ///
+    /// When supported, PMU extension version 3.0 is reported to supervisor. Defaults to `false`.
+    fn raw_event_v2(&self) -> bool {
+        false
+    }
+    /// Whether the `mcountinhibit` CSR is implemented.
+    ///
+    /// Counters are started and stopped through `mcountinhibit`, so the PMU extension is not provided
+    /// without it, as the SBI specification requires. The default implementation reads the CSR on the
+    /// calling hart and recovers from the illegal instruction exception if it is missing; it must be
+    /// called in machine mode.
+    fn has_mcountinhibit(&self) -> bool {
+        probe_mcountinhibit()
+    }
+    /// Read hart id of the calling hart.
+    fn hart_id(&self) -> usize;
+    /// Read `mcounteren` CSR. Defaults to all counters readable by supervisor.
+    ///
+    /// Supervisor cannot read hardware counters whose bits are clear through counter CSRs, for example on
+    /// platforms where `mcounteren` bits are hardwired to zero; `sbi_pmu_counter_fw_read` reads such counters
+    /// on behalf of supervisor instead of rejecting them as hardware counters.
+    fn read_mcounteren(&self) -> usize {
+        usize::MAX
+    }
+    /// Set bits of `mcounteren` CSR, letting supervisor read the corresponding counters through counter CSRs.
+    ///
+    /// Called with `StoppedRead::Frozen` on bits cleared by `clear_mcounteren` before, and on `mcounteren.TM`
+    /// when the hart is reset, following `time_csr`. Defaults to doing nothing, leaving both without effect.
+    unsafe fn set_mcounteren(&self, bits: usize) {
+        drop(bits);
+    }
+    /// Clear bits of `mcounteren` CSR, so that supervisor reads of the corresponding counter CSRs trap.
+    ///
+    /// Called with `StoppedRead::Frozen`, and on `mcounteren.TM` when the hart is reset. Defaults to doing nothing.
+    unsafe fn clear_mcounteren(&self, bits: usize) {
+        drop(bits);
+    }
+    /// How supervisor reads the `time` counter on this platform. Defaults to `TimeCsr::Hardware`.
+    ///
+    /// `GenericPmu` sets or clears `mcounteren.TM` accordingly whenever the calling hart is reset, see
+    /// `reset_pmu_hart`.
+    fn time_csr(&self) -> TimeCsr {
+        TimeCsr::Hardware
+    }
+    /// Read `mtime`, the clock of the `time` counter, written into snapshot shared memory along with counter
+    /// values, and read by `sbi_pmu_counter_fw_read` of the `time` counter with `TimeCsr::Firmware`. Defaults to
+    /// zero, telling supervisor that the time of snapshots is unknown.
+    fn read_mtime(&self) -> u64 {
+        0
+    }
+    /// Whether `size` bytes at physical address `shmem_phys` may be shared with supervisor. Defaults to
+    /// `crate::shmem::shmem_accessible`, which checks every other buffer supervisor passes to RustSBI.
+    ///
+    /// Checked when supervisor registers snapshot shared memory, which fails with `SBI_ERR_INVALID_ADDRESS`
+    /// otherwise. Platforms accept RAM outside firmware memory which supervisor can read and write under the
+    /// PMP configuration of the calling hart, checked with `pmp_allows_supervisor`.
+    fn shmem_accessible(&self, shmem_phys: usize, size: usize) -> bool {
+        crate::shmem::shmem_accessible(shmem_phys, size)
+    }
+    /// Read `mcountinhibit` CSR.
+    fn read_mcountinhibit(&self) -> usize;
+    /// Set bits of `mcountinhibit` CSR, stopping the corresponding counters.
+    ///
+    /// All hardware counters stopped by one `sbi_pmu_counter_stop` call are passed together,
+    /// so that they stop at the same time; platforms should set them with a single CSR write.
+    unsafe fn set_mcountinhibit(&self, bits: usize);
+    /// Clear bits of `mcountinhibit` CSR, starting the corresponding counters.
+    unsafe fn clear_mcountinhibit(&self, bits: usize);
+    /// CSR number supervisor reads the hardware counter through, reported in `counter_info`.
+    /// Defaults to `0xC00 + counter_idx`. Read once, when `GenericPmu` is created.
+    fn counter_csr(&self, counter_idx: usize) -> usize {
+        0xC00 + counter_idx
+    }
+    /// Number of implemented bits of the hardware counter, from 1 to 64. Defaults to 64.
+    ///
+    /// Values read from the counter are masked to this width, and the width is reported to supervisor in
+    /// `counter_info`, so that supervisor handles the counter wrapping around. This function is called on
+    /// every counter read; platforms with narrower counters detect widths once with `detect_counter_width`.
+    fn counter_width(&self, counter_idx: usize) -> u32 {
+        drop(counter_idx);
+        64
+    }
+    /// Read value of the hardware counter.
+    fn read_counter(&self, counter_idx: usize) -> u64;
+    /// Write value of the hardware counter.
+    unsafe fn write_counter(&self, counter_idx: usize, value: u64);
+    /// Read `mhpmeventX` CSR of a programmable counter.
+    fn read_mhpmevent(&self, counter_idx: usize) -> u64;
+    /// Write `mhpmeventX` CSR of a programmable counter.
+    unsafe fn write_mhpmevent(&self, counter_idx: usize, value: u64);
+}
+
+/// Generic implementation of `Pmu` for platforms described by `PmuPlatform`
+///
+/// It keeps track of counter to event bindings for each hart, emulates overflow detection
+/// on platforms without Sscofpmf extension, writes the overflow bitmap into the registered
+/// snapshot shared memory, and saves counter state across HSM stop and non-retentive suspend.
+///
+/// `FIRMWARE_COUNTERS` firmware counters follow the hardware counters: if the platform has `n`
+/// hardware counters, counters `n..n + FIRMWARE_COUNTERS` count firmware events and are read by
+/// supervisor through `sbi_pmu_counter_fw_read`. So are hardware counters whose `mcounteren` bits
+/// are clear, as supervisor cannot read them through counter CSRs. Every call, `sbi_pmu_counter_get_info`
+/// included, tells the kinds of counters apart by index this way, and no two hardware counters may report
+/// the same CSR.
+///
+/// With the `multiplex` feature, `MULTIPLEX_COUNTERS` multiplexed counters follow the firmware counters.
+/// They count hardware events when no hardware counter is free, by taking turns on the programmable
+/// counters not bound to any event; the platform rotates them by calling `rotate_pmu_multiplex`
+/// periodically. Supervisor sees them as firmware counters, and reads values scaled by the fraction
+/// of time they were counting.
+///
+/// Values written into `mhpmevent` CSRs are kept in memory, so that the state dump does not read the CSRs back.
+/// With the `shadow-check` feature, debug builds check the copies against the CSRs when dumping state and before
+/// saving it.
+///
+/// Stopping a counter takes one of two phases. Without `SBI_PMU_STOP_FLAG_RESET`, the counter is frozen: it
+/// keeps its event and value, and `sbi_pmu_counter_start` resumes it, as the Linux driver does between context
+/// switches and on overflow. With the flag, it is released: stopped if it was running, then unbound from its
+/// event, so it must be configured again. Releasing frozen counters is allowed, as the Linux driver stops an
+/// event before deleting it; the counters are released and `SBI_ERR_ALREADY_STOPPED` is returned like OpenSBI
+/// does. With `SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT`, running counters in the set are stopped and final values and
+/// overflow bits of the whole set are written into the snapshot shared memory by the same call, also when some
+/// of them were stopped before.
+///
+/// Calls taking a counter set skip `time` and counters not bound to an event like OpenSBI does, as the Linux
+/// driver passes every counter it knows of, `time` included; they fail only if no counter in the set is bound.
+///
+/// Counters started or stopped by one call form a group, like a `perf` event group: all of them are prepared
+/// first, then firmware counters and hardware counters, including those multiplexed counters run on, switch
+/// together with interrupts disabled, the hardware ones with a single write of `mcountinhibit`.
+///
+/// On cores whose `mcountinhibit` bits of `cycle` and `instret` are hardwired to zero, these counters are
+/// stopped virtually: values reported to supervisor stay still while stopped and are offset while running.
+/// Such platforms should clear the counters' `mcounteren` bits so that supervisor reads them through
+/// `sbi_pmu_counter_fw_read`.
+///
+/// Per-hart state of hardware counters is kept in arrays of `N` entries, and at most `N` hardware counters of
+/// the platform are used, as `num_counters` reports. Platforms with few counters, or firmware short of memory,
+/// choose a smaller `N` with `GenericPmu::new_bounded`; `N` can not exceed `MAX_HARDWARE_COUNTERS`. States of
+/// harts are still allocated on the heap the first time each hart uses the PMU.
+pub struct GenericPmu<P, const N: usize = MAX_HARDWARE_COUNTERS> {
+    platform: P,
+    // hardware events the platform can monitor, computed once
+    supported: SupportedEvents,
+    // `mhpmevent` encodings written before, and whether the hardware kept them, oldest first
+    encodings: Vec<(u64, bool)>,
+    // encodings of hardware events registered by the platform besides its own, highest priority first
+    aliases: Vec<(usize, Vec<EventAlias>)>,
+    // `counter_info` of hardware counters, which never changes; supervisor queries every counter at boot
+    infos: Vec<usize>,
+    // `cycle` and `instret` counters which can not be stopped, virtualized by `FixedCounters`
+    free_running: usize,
+    // ticks of `mtime` after which a started counter not used by supervisor is idle, `None` to not keep track
+    idle_timeout: Option<usize>,
+    // what supervisor reads through counter CSRs of stopped counters
+    stopped_read: StoppedRead,
+    // whether reads of counter CSRs kept away from supervisor by `mcounteren` are emulated
+    hidden_read: HiddenRead,
+    // whether hardware events may go to multiplexed counters
+    multiplex: bool,
+    harts: Vec<HartState<N>>,
+}
+
+// what `sbi_pmu_counter_stop` does with the counters in the set
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StopPhase {
+    // counting stops; counters keep their events and values, and are started again as they are
+    Freeze,
+    // counters are stopped if still running, then unbound from their events, and must be configured again
+    Release,
+}
+
+impl StopPhase {
+    fn from_flags(stop_flags: usize) -> StopPhase {
+        if stop_flags & SBI_PMU_STOP_FLAG_RESET != 0 {
+            StopPhase::Release
+        } else {
+            StopPhase::Freeze
+        }
+    }
+}
+
+/// How supervisor reads the `time` counter, see `PmuPlatform::time_csr`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeCsr {
+    /// The hart implements the `time` CSR; `mcounteren.TM` is set and supervisor reads it directly.
+    Hardware,
+    /// Reads of the `time` CSR trap; `mcounteren.TM` is cleared and the platform emulates them with `mtime`
+    /// in its illegal instruction handler.
+    Emulated,
+    /// The `time` CSR is not usable; `mcounteren.TM` is cleared, and supervisor reads `mtime` through
+    /// `sbi_pmu_counter_fw_read` of the `time` counter, as RustSBI reads it with `PmuPlatform::read_mtime`.
+    Firmware,
+}
+
+/// What supervisor reads through the counter CSR of a stopped hardware counter, see `GenericPmu::with_stopped_read`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoppedRead {
+    /// Counter CSRs stay readable, and reads return whatever the hardware counter holds.
+    Live,
+    /// `mcounteren` bits of stopped counters are cleared, and reads trap into the platform, which returns the value
+    /// the counter was stopped with through `emulate_counter_csr_read`.
+    Frozen,
+}
+
+/// What happens when supervisor reads the counter CSR of a hardware counter whose `mcounteren` bit the platform
+/// keeps clear, see `GenericPmu::with_hidden_read`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HiddenRead {
+    /// The read is not emulated, and the illegal instruction trap is left to supervisor.
+    Fault,
+    /// The read is emulated with the value `sbi_pmu_counter_fw_read` would return.
+    Emulate,
+}
+
+/// Encoding of a hardware event on some hardware counters, registered with `GenericPmu::with_event_aliases`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventAlias {
+    /// Value written into `mhpmeventX` to count the event, excluding privilege filter bits
+    pub mhpmevent: u64,
+    /// Hardware counters which count the event with this encoding, bit `i` for counter `i`
+    pub counters: usize,
+}
+
+// hardware general and cache events which at least one hardware counter of the platform can monitor,
+// one bit for each event code
+struct SupportedEvents {
+    general: u64,
+    cache: u64,
+}
+
+impl SupportedEvents {
+    fn new<P: PmuPlatform>(platform: &P, num_hardware_counters: usize) -> SupportedEvents {
+        let bitmap = |event_type| {
+            (0..u64::BITS as usize)
+                .filter(|&code| {
+                    let event_idx = EventIdx::from_parts(event_type, code).raw();
+                    (0..num_hardware_counters).any(|idx| platform.counter_can_monitor(idx, event_idx, 0))
+                })
+                .fold(0, |bits, code| bits | (1 << code))
+        };
+        SupportedEvents {
+            general: bitmap(EVENT_TYPE_HARDWARE_GENERAL),
+            cache: bitmap(EVENT_TYPE_HARDWARE_CACHE),
+        }
+    }
+
+    // record an event some hardware counter monitors through an alias; other events are not kept in the table
+    fn insert(&mut self, event: EventIdx) {
+        let bitmap = match event.event_type() {
+            EVENT_TYPE_HARDWARE_GENERAL => &mut self.general,
+            EVENT_TYPE_HARDWARE_CACHE => &mut self.cache,
+            _ => return,
+        };
+        if event.code() < u64::BITS as usize {
+            *bitmap |= 1 << event.code();
+        }
+    }
+
+    // `None` for events not kept in the table
+    fn contains(&self, event: EventIdx) -> Option<bool> {
+        let bitmap = match event.event_type() {
+            EVENT_TYPE_HARDWARE_GENERAL => self.general,
+            EVENT_TYPE_HARDWARE_CACHE => self.cache,
+            _ => return None,
+        };
+        Some(event.code() < u64::BITS as usize && bitmap & (1 << event.code()) != 0)
+    }
+}
+
+struct HartState<const N: usize> {
+    // event bound to each counter, `None` if the counter is free
+    events: [Option<usize>; N],
+    // values written into `mhpmevent` of counters bound directly, zero once unbound, so that queries do not read
+    // the CSRs back; overflow bits set by hardware are not in them
+    mhpmevents: [u64; N],
+    // hardware counters started by supervisor; `mcountinhibit` can only be read on its own hart,
+    // this copy is read by other harts for `pmu_hart_summary`
+    started: usize,
+    // counters whose overflow is detected by software
+    tracked: usize,
+    // counter values at last overflow detection; a smaller value now means the counter wrapped around
+    last_values: [u64; N],
+    // emulated `scountovf`
+    overflow: usize,
+    // firmware event bound to each firmware counter, `None` if the counter is free
+    fw_events: [Option<usize>; FIRMWARE_COUNTERS],
+    // `event_data` of counters bound to `SBI_PMU_FW_PLATFORM`, selecting the platform specific event
+    fw_data: [u64; FIRMWARE_COUNTERS],
+    // started firmware counters
+    fw_started: usize,
+    fw_values: [FirmwareValue; FIRMWARE_COUNTERS],
+    // thresholds of watchpoints on firmware counters, zero if disarmed; disarmed through shared references
+    // when reached, like values are counted
+    fw_watch: [FirmwareValue; FIRMWARE_COUNTERS],
+    // how supervisor is notified when each watchpoint is reached
+    fw_notify: [WatchNotify; FIRMWARE_COUNTERS],
+    // multiplexed counters
+    mux: Multiplexer,
+    // physical address of snapshot shared memory
+    snapshot: Option<usize>,
+    // counter index base of the last snapshot, which the overflow bitmap in shared memory is relative to
+    snapshot_base: usize,
+    // counter state saved before the hart is stopped or suspended
+    saved: Option<SavedContext<N>>,
+    // virtual stop of `cycle` and `instret`; every access to `mcountinhibit` and hardware counter values
+    // made on behalf of supervisor goes through it
+    fixed: FixedCounters,
+    // counters configured in the current session of supervisor, bit `i` for counter `i`
+    #[cfg(feature = "leak-check")]
+    session: u64,
+    // lower bits of `mtime` when supervisor last used each counter through an SBI call, empty without an idle timeout
+    last_used: Vec<AtomicUsize>,
+    // stopped hardware counters whose `mcounteren` bits were cleared, so that supervisor reads of them trap
+    trapped: usize,
+}
+
+// value of a firmware counter, changed through shared references when firmware events are counted;
+// 64-bit atomics are not available on RV32, where the value is kept in two halves.
+//
+// A machine interrupt handler may count a firmware event in the middle of another update or read on
+// the same hart. A single atomic is never seen half updated; on RV32 both halves are updated with
+// machine interrupts disabled, so that a read on the same hart never sees the lower half wrapped
+// around before the carry reaches the upper half.
+#[derive(Default)]
+struct FirmwareValue {
+    lo: AtomicUsize,
+    #[cfg(target_pointer_width = "32")]
+    hi: AtomicUsize,
+}
+
+impl FirmwareValue {
+    fn get(&self) -> u64 {
+        match () {
+            #[cfg(target_pointer_width = "32")]
+            () => loop {
+                // read again if the lower half wrapped around in between
+                let hi = self.hi.load(Ordering::Relaxed);
+                let lo = self.lo.load(Ordering::Relaxed);
+                if self.hi.load(Ordering::Relaxed) == hi {
+                    return (hi as u64) << 32 | lo as u64;
+                }
+            },
+            #[cfg(not(target_pointer_width = "32"))]
+            () => self.lo.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    fn set(&self, value: u64) {
+        match () {
+            #[cfg(target_pointer_width = "32")]
+            () => riscv::interrupt::free(|_| {
+                self.lo.store(value as usize, Ordering::Relaxed);
+                self.hi.store((value >> 32) as usize, Ordering::Relaxed);
+            }),
+            #[cfg(not(target_pointer_width = "32"))]
+            () => self.lo.store(value as usize, Ordering::Relaxed),
+        }
+    }
+
+    // read the value and reset it to zero, without losing an increase in between
+    fn take(&self) -> u64 {
+        match () {
+            #[cfg(target_pointer_width = "32")]
+            () => riscv::interrupt::free(|_| {
+                let value = self.get();
+                self.set(0);
+                value
+            }),
+            #[cfg(not(target_pointer_width = "32"))]
+            () => self.lo.swap(0, Ordering::Relaxed) as u64,
+        }
+    }
+
+    // only the hart owning the counter increases it
+    fn add(&self, value: u64) {
+        match () {
+            #[cfg(target_pointer_width = "32")]
+            () => riscv::interrupt::free(|_| {
+                let lo = self.lo.fetch_add(value as usize, Ordering::Relaxed);
+                let carry = lo.checked_add(value as usize).is_none() as usize;
+                self.hi.fetch_add((value >> 32) as usize + carry, Ordering::Relaxed);
+            }),
+            #[cfg(not(target_pointer_width = "32"))]
+            () => {
+                self.lo.fetch_add(value as usize, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn increment(&self) {
+        match () {
+            #[cfg(target_pointer_width = "32")]
+            () => riscv::interrupt::free(|_| {
+                if self.lo.fetch_add(1, Ordering::Relaxed) == usize::MAX {
+                    self.hi.fetch_add(1, Ordering::Relaxed);
+                }
+            }),
+            #[cfg(not(target_pointer_width = "32"))]
+            () => {
+                self.lo.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+struct SavedContext<const N: usize> {
+    // `mcountinhibit` as supervisor sees it, with virtual bits of `cycle` and `instret`
+    inhibit: usize,
+    // hardware counters multiplexed counters were running on
+    multiplexed: usize,
+    events: [u64; N],
+    // values as supervisor sees them
+    counters: [u64; N],
+}
+
+impl<P: PmuPlatform> GenericPmu<P> {
+    /// Create a generic PMU over the platform description.
+    pub fn new(platform: P) -> GenericPmu<P> {
+        GenericPmu::new_bounded(platform)
+    }
+}
+
+impl<P: PmuPlatform, const N: usize> GenericPmu<P, N> {
+    /// Create a generic PMU over the platform description using at most `N` hardware counters.
+    ///
+    /// ```no_run
+    /// use rustsbi::pmu::GenericPmu;
+    ///
+    /// // `cycle`, `time`, `instret` and five programmable counters
+    /// rustsbi::init_pmu(GenericPmu::<_, 8>::new_bounded(platform));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is larger than `MAX_HARDWARE_COUNTERS`, or if the platform reports a hardware counter read
+    /// through a CSR which is not a counter CSR, or through the CSR of another hardware counter.
+    pub fn new_bounded(platform: P) -> GenericPmu<P, N> {
+        assert!(N <= MAX_HARDWARE_COUNTERS, "at most {} hardware counters are supported", MAX_HARDWARE_COUNTERS);
+        let num_hardware_counters = platform.num_counters().min(N);
+        // csr = 0xC00 + physical counter index, type = 0 (hardware counter); `time` is always read through its
+        // own CSR, and `mtime` is 64 bits wide
+        let infos: Vec<usize> = (0..num_hardware_counters)
+            .map(|idx| match idx {
+                COUNTER_TIME => CounterInfo::hardware(CSR_TIME, 64).raw(),
+                _ => CounterInfo::hardware(platform.counter_csr(idx), platform.counter_width(idx) as usize).raw(),
+            })
+            .collect();
+        check_counter_csrs(&infos);
+        GenericPmu {
+            supported: SupportedEvents::new(&platform, num_hardware_counters),
+            free_running: if platform.has_mcountinhibit() { fixed::probe_free_running(&platform) } else { 0 },
+            platform,
+            encodings: Vec::new(),
+            aliases: Vec::new(),
+            infos,
+            idle_timeout: None,
+            stopped_read: StoppedRead::Live,
+            hidden_read: HiddenRead::Fault,
+            multiplex: true,
+            harts: Vec::new(),
+        }
+    }
+
+    /// Keep track of counters supervisor leaves running without using them.
+    ///
+    /// A started counter is idle once no SBI call used it for more than `ticks` ticks of `mtime`; calls which
+    /// configure, start, stop or read a counter use it, reads through counter CSRs do not. Idle counters are
+    /// reported by the hart status call, so that supervisor finds counters left bound by a crashed kernel and
+    /// frees them with the reclaim call. The platform must provide `read_mtime`, and on RV32 `ticks` must be
+    /// less than 2^31, as only the lower XLEN bits of `mtime` are kept.
+    pub fn with_idle_timeout(mut self, ticks: usize) -> Self {
+        self.idle_timeout = Some(ticks);
+        self
+    }
+
+    /// Choose what supervisor reads through counter CSRs of stopped hardware counters; `StoppedRead::Live` by default.
+    ///
+    /// Kernels reading counter CSRs directly expect a stopped counter to keep its value. Hardware stopped through
+    /// `mcountinhibit` keeps it anyway, but `cycle` and `instret` stopped virtually keep counting, as may counters
+    /// of cores where `mcountinhibit` is not honoured. With `StoppedRead::Frozen`, `sbi_pmu_counter_stop` clears
+    /// the `mcounteren` bits of the stopped counters through `PmuPlatform::clear_mcounteren`, and
+    /// `sbi_pmu_counter_start` sets them again; the platform calls `emulate_counter_csr_read` when supervisor
+    /// reads them in between, which returns the value the counter was stopped with. Counters stopped with
+    /// `SBI_PMU_STOP_FLAG_RESET` are left readable, as they no longer count anything.
+    pub fn with_stopped_read(mut self, policy: StoppedRead) -> Self {
+        self.stopped_read = policy;
+        self
+    }
+
+    /// Choose whether reads of counter CSRs whose `mcounteren` bits the platform keeps clear are emulated;
+    /// `HiddenRead::Fault` by default.
+    ///
+    /// Platforms restricting `mcounteren`, for example to keep supervisor from timing other software, leave
+    /// kernels only `sbi_pmu_counter_fw_read` to read those counters. Older kernels read counter CSRs directly
+    /// and take an illegal instruction exception; with `HiddenRead::Emulate`, the platform calls
+    /// `emulate_counter_csr_read` in its illegal instruction handler, which returns the same value as the SBI
+    /// call, at the cost of a trap per read counted by the `RUSTSBI_FW_PMU_EMULATED_COUNTER_READS` event.
+    pub fn with_hidden_read(mut self, policy: HiddenRead) -> Self {
+        self.hidden_read = policy;
+        self
+    }
+
+    /// Apply the policies of `config` which concern `GenericPmu`: reads of stopped and hidden counters, the idle
+    /// timeout, and whether multiplexed counters are used. Other policies are applied by `PmuConfig::build`.
+    pub fn with_config(mut self, config: &PmuConfig) -> Self {
+        self.stopped_read = config.stopped_read;
+        self.hidden_read = config.hidden_read;
+        self.idle_timeout = config.idle_timeout;
+        self.multiplex = config.multiplex;
+        self
+    }
+
+    /// Register other encodings of hardware general or cache event `event_idx`, in order of priority.
+    ///
+    /// Some events are measured at more than one place, such as last level cache misses counted at the L2 cache
+    /// or at the memory controller, each by its own encoding on its own counters. `sbi_pmu_counter_config_matching`
+    /// binds the event with the first alias which has a free counter in the given set, and only then with the
+    /// encoding of `PmuPlatform::mhpmevent_value` on a counter `PmuPlatform::counter_can_monitor` accepts, which is
+    /// also the one multiplexed counters use. Aliases the hardware drops when written are skipped from then on.
+    /// Registering aliases of an event again replaces them; aliases of other event types, and bits of counters
+    /// which are not programmable, are ignored.
+    ///
+    /// ```no_run
+    /// use rustsbi::pmu::{events, EventAlias, GenericPmu};
+    ///
+    /// // LLC misses at the L2 cache on `hpmcounter3` and `hpmcounter4`, or at the memory controller on `hpmcounter5`
+    /// let pmu = GenericPmu::new(platform).with_event_aliases(
+    ///     events::event_idx(events::EVENT_TYPE_HARDWARE_GENERAL, events::SBI_PMU_HW_CACHE_MISSES),
+    ///     &[EventAlias { mhpmevent: 0x102, counters: 0b11000 }, EventAlias { mhpmevent: 0x4001, counters: 0b100000 }],
+    /// );
+    /// ```
+    pub fn with_event_aliases(mut self, event_idx: usize, aliases: &[EventAlias]) -> Self {
+        let event = match EventIdx::new(event_idx) {
+            Some(event) if matches!(event.event_type(), EVENT_TYPE_HARDWARE_GENERAL | EVENT_TYPE_HARDWARE_CACHE) => event,
+            _ => return self,
+        };
+        let programmable = (FIRST_HPM_COUNTER..self.num_hardware_counters()).fold(0, |bits, idx| bits | 1 << idx);
+        let aliases: Vec<EventAlias> = aliases
+            .iter()
+            .map(|alias| EventAlias { mhpmevent: alias.mhpmevent, counters: alias.counters & programmable })
+            .filter(|alias| alias.counters != 0)
+            .collect();
+        if !aliases.is_empty() {
+            self.supported.insert(event);
+        }
+        self.aliases.retain(|(idx, _)| *idx != event_idx);
+        self.aliases.push((event_idx, aliases));
+        self
+    }
+
+    /// Returns a reference to the platform description.
+    pub fn platform(&self) -> &P {
+        &self.platform
+    }
+
+    fn num_hardware_counters(&self) -> usize {
+        self.platform.num_counters().min(N)
+    }
+
+    fn num_counters(&self) -> usize {
+        self.num_hardware_counters() + FIRMWARE_COUNTERS + MULTIPLEX_COUNTERS
+    }
+
+    // value of a firmware or multiplexed counter on the calling hart, `None` if `counter_idx` is not one of them
+    fn firmware_value(&self, counter_idx: usize) -> Option<u64> {
+        if counter_idx >= self.num_counters() {
+            return None;
+        }
+        let state = self.harts.get(self.platform.hart_id());
+        match (classify(counter_idx, self.num_hardware_counters()), state) {
+            (Counter::Hardware(_), _) => None,
+            (Counter::Firmware(fw_idx), Some(state)) => Some(state.fw_values[fw_idx].get()),
+            (Counter::Multiplexed(mux_idx), Some(state)) => Some(state.mux.value(&self.platform, mux_idx)),
+            // no counter was ever configured on this hart
+            (_, None) => Some(0),
+        }
+    }
+
+    // value read by `sbi_pmu_counter_fw_read`: firmware counters, and hardware counters which
+    // supervisor cannot read through counter CSRs
+    fn fw_read_value(&self, counter_idx: usize) -> Option<u64> {
+        if let Some(value) = self.firmware_value(counter_idx) {
+            return Some(value);
+        }
+        // `time` is read through the firmware only if the platform has no usable `time` CSR
+        if counter_idx == COUNTER_TIME && counter_idx < self.num_hardware_counters() {
+            return match self.platform.time_csr() {
+                TimeCsr::Firmware => Some(self.platform.read_mtime()),
+                TimeCsr::Hardware | TimeCsr::Emulated => None,
+            };
+        }
+        // counters whose bits were cleared by `StoppedRead::Frozen` are still read through counter CSRs
+        let trapped = self.harts.get(self.platform.hart_id()).map_or(0, |state| state.trapped);
+        let hidden = counter_idx < self.num_hardware_counters()
+            && (self.platform.read_mcounteren() | trapped) & (1 << counter_idx) == 0;
+        if hidden {
+            Some(self.hardware_value(counter_idx))
+        } else {
+            None
+        }
+    }
+
+    // value of a hardware counter of the calling hart as seen by supervisor
+    fn hardware_value(&self, counter_idx: usize) -> u64 {
+        match self.harts.get(self.platform.hart_id()) {
+            Some(state) => state.fixed.read(&self.platform, counter_idx),
+            None => read_counter(&self.platform, counter_idx),
+        }
+    }
+
+    // record use of the counters in the set by supervisor on the calling hart
+    fn touch(&self, counter_idx_base: usize, counter_idx_mask: usize) {
+        if let Some(state) = self.harts.get(self.platform.hart_id()) {
+            state.touch(&self.platform, counter_idx_base, counter_idx_mask);
+        }
+    }
+
+    // every counter in the set must exist; `time` and counters not bound to an event are skipped by the calls
+    // taking a set, as the Linux driver passes every counter it knows of, `time` included
+    fn counters_valid(&self, counter_idx_base: usize, counter_idx_mask: usize) -> bool {
+        IndexMask::new(counter_idx_base, counter_idx_mask).is_within(self.num_counters())
+    }
+
+    // check that an event can be counted at all, returns the decoded event and its `mhpmevent` encoding,
+    // zero for firmware events
+    fn check_event(&self, event_idx: usize, event_data: u64) -> Result<(EventIdx, u64), SbiRet> {
+        let event = match EventIdx::new(event_idx) {
+            Some(event) => event,
+            None => return Err(SbiRet::invalid_param()),
+        };
+        let raw = match event.event_type() {
+            EVENT_TYPE_HARDWARE_RAW => Some(RAW_EVENT_MASK),
+            EVENT_TYPE_HARDWARE_RAW_V2 if self.platform.raw_event_v2() => Some(!0),
+            EVENT_TYPE_HARDWARE_RAW_V2 => return Err(SbiRet::not_supported()),
+            EVENT_TYPE_HARDWARE_GENERAL | EVENT_TYPE_HARDWARE_CACHE | EVENT_TYPE_FIRMWARE => None,
+            // reserved event types
+            _ => return Err(SbiRet::invalid_param()),
+        };
+        if raw.is_some() && event.code() != 0 {
+            // raw events are selected by `event_data`, event code must be zero
+            return Err(SbiRet::invalid_param());
+        }
+        let firmware = event.event_type() == EVENT_TYPE_FIRMWARE;
+        if firmware && event.code() == SBI_PMU_FW_PLATFORM {
+            // platform specific events are the cycles spent in firmware paths, from the PMU extension handler
+            // to console output, counts of machine interrupts, of emulated counter reads and of SBI calls
+            if !(RUSTSBI_FW_PMU_ECALL_CYCLES..=RUSTSBI_FW_PMU_PMU_CALLS).contains(&event_data) {
+                return Err(SbiRet::not_supported());
+            }
+        } else if firmware && event.code() >= NUM_FIRMWARE_EVENTS {
+            return Err(SbiRet::invalid_param());
+        }
+        // events no hardware counter can monitor are told apart from events whose counters are all in use
+        let supported = match self.supported.contains(event) {
+            Some(supported) => supported,
+            // raw events are looked up on every hardware counter
+            None => {
+                firmware
+                    || (0..self.num_hardware_counters()).any(|idx| self.platform.counter_can_monitor(idx, event_idx, event_data))
+            }
+        };
+        if !supported {
+            return Err(SbiRet::not_supported());
+        }
+        let selector = match raw {
+            // raw events carry the selector in low 48 bits of `event_data`, or the whole of it for v2
+            Some(mask) => event_data & mask,
+            None if firmware => 0,
+            None => self.platform.mhpmevent_value(event_idx, event_data),
+        };
+        // encodings the hardware dropped before count nothing on any counter, unless an alias is left
+        let dropped = |selector| self.encoding_sticks(selector) == Some(false);
+        if !firmware && dropped(selector) && self.aliases_of(event_idx).iter().all(|alias| dropped(alias.mhpmevent)) {
+            return Err(SbiRet::not_supported());
+        }
+        Ok((event, selector))
+    }
+
+    // aliases of a hardware event registered by the platform, highest priority first
+    fn aliases_of(&self, event_idx: usize) -> &[EventAlias] {
+        match self.aliases.iter().find(|(idx, _)| *idx == event_idx) {
+            Some((_, aliases)) => aliases,
+            None => &[],
+        }
+    }
+
+    // counter in the set taken by the first alias of the event with a free counter there, and the encoding
+    // of the alias; with `skip_match`, the first counter in the set whether it is free or not
+    fn match_alias(&self, event_idx: usize, counter_idx_base: usize, counter_idx_mask: usize, skip_match: bool) -> Option<(usize, u64)> {
+        let state = self.harts.get(self.platform.hart_id());
+        let first = counters(counter_idx_base, counter_idx_mask).next();
+        self.aliases_of(event_idx)
+            .iter()
+            .filter(|alias| self.encoding_sticks(alias.mhpmevent) != Some(false))
+            .find_map(|alias| {
+                let usable = |idx: usize| idx < usize::BITS as usize && alias.counters & (1 << idx) != 0;
+                let found = if skip_match {
+                    first.filter(|&idx| usable(idx))
+                } else {
+                    // no counter was ever configured on this hart if it has no state
+                    counters(counter_idx_base, counter_idx_mask).find(|&idx| usable(idx) && state.map_or(true, |state| state.events[idx].is_none()))
+                };
+                found.map(|idx| (idx, alias.mhpmevent))
+            })
+    }
+
+    // whether the hardware kept `selector` when it was written into `mhpmevent`, `None` if it never was
+    fn encoding_sticks(&self, selector: u64) -> Option<bool> {
+        self.encodings.iter().find(|&&(value, _)| value == selector).map(|&(_, sticks)| sticks)
+    }
+
+    // remember whether the hardware kept `selector`, forgetting the oldest encoding once there are too many;
+    // a forgotten encoding is only read back again the next time it is written
+    fn remember_encoding(&mut self, selector: u64, sticks: bool) {
+        self.encodings.retain(|&(value, _)| value != selector);
+        if self.encodings.len() >= MAX_KNOWN_ENCODINGS {
+            self.encodings.remove(0);
+        }
+        self.encodings.push((selector, sticks));
+    }
+
+    // state of the calling hart, allocated on first use
+    fn split(&mut self) -> (&P, &mut HartState<N>) {
+        let hartid = self.platform.hart_id();
+        if self.harts.len() <= hartid {
+            let harts: Vec<HartState<N>> = (self.harts.len()..=hartid).map(|_| self.new_hart()).collect();
+            self.harts.extend(harts);
+        }
+        (&self.platform, &mut self.harts[hartid])
+    }
+
+    // state of a hart before supervisor uses any counter
+    fn new_hart(&self) -> HartState<N> {
+        let num_used = if self.idle_timeout.is_some() { self.num_counters() } else { 0 };
+        HartState {
+            events: [None; N],
+            mhpmevents: [0; N],
+            started: 0,
+            tracked: 0,
+            last_values: [0; N],
+            overflow: 0,
+            fw_events: Default::default(),
+            fw_data: Default::default(),
+            fw_started: 0,
+            fw_values: Default::default(),
+            fw_watch: Default::default(),
+            fw_notify: [WatchNotify::SoftwareInterrupt; FIRMWARE_COUNTERS],
+            mux: Default::default(),
+            snapshot: None,
+            snapshot_base: 0,
+            saved: None,
+            fixed: FixedCounters::new(self.free_running),
+            #[cfg(feature = "leak-check")]
+            session: 0,
+            last_used: (0..num_used).map(|_| AtomicUsize::new(0)).collect(),
+            trapped: 0,
+        }
+    }
+}
+
+impl<const N: usize> HartState<N> {
+    // clear `mcounteren` bits of stopped hardware counters, leaving those supervisor could not read anyway
+    unsafe fn trap_reads<P: PmuPlatform>(&mut self, platform: &P, bits: usize) {
+        let bits = bits & platform.read_mcounteren() & !(1 << COUNTER_TIME);
+        if bits != 0 {
+            platform.clear_mcounteren(bits);
+            self.trapped |= bits;
+        }
+    }
+
+    // set `mcounteren` bits cleared by `trap_reads` again
+    unsafe fn untrap_reads<P: PmuPlatform>(&mut self, platform: &P, bits: usize) {
+        let bits = bits & self.trapped;
+        if bits != 0 {
+            platform.set_mcounteren(bits);
+            self.trapped &= !bits;
+        }
+    }
+
+    // start detecting overflow of the counter and clear its overflow bit
+    fn track<P: PmuPlatform>(&mut self, platform: &P, counter_idx: usize) {
+        self.tracked |= 1 << counter_idx;
+        self.overflow &= !(1 << counter_idx);
+        self.last_values[counter_idx] = self.fixed.read(platform, counter_idx);
+    }
+
+    // bitmaps of hardware, firmware and multiplexed counters in the set bound to an event, skipping the others
+    // like OpenSBI does; `None` if none of them is bound
+    fn bound_counters(&self, counter_idx_base: usize, counter_idx_mask: usize, num_hardware_counters: usize) -> Option<(usize, usize, usize)> {
+        let (mut bits, mut fw_bits, mut mux_bits) = (0, 0, 0);
+        for idx in counters(counter_idx_base, counter_idx_mask) {
+            match classify(idx, num_hardware_counters) {
+                // `time` is never bound to an event
+                Counter::Hardware(idx) if self.events[idx].is_some() => bits |= 1 << idx,
+                Counter::Firmware(fw_idx) if self.fw_events[fw_idx].is_some() => fw_bits |= 1 << fw_idx,
+                Counter::Multiplexed(mux_idx) if self.mux.is_bound(mux_idx) => mux_bits |= 1 << mux_idx,
+                _ => {}
+            }
+        }
+        if bits | fw_bits | mux_bits == 0 {
+            return None;
+        }
+        Some((bits, fw_bits, mux_bits))
+    }
+
+    // unbind the counters in the set from their events, all of them stopped; counters not bound are left alone
+    fn unbind<P: PmuPlatform>(&mut self, platform: &P, counter_idx_base: usize, counter_idx_mask: usize, num_hardware_counters: usize) {
+        for idx in counters(counter_idx_base, counter_idx_mask) {
+            match classify(idx, num_hardware_counters) {
+                Counter::Hardware(_) if self.events[idx].is_none() => continue,
+                Counter::Hardware(_) => {}
+                Counter::Firmware(fw_idx) => {
+                    self.fw_events[fw_idx] = None;
+                    self.fw_watch[fw_idx].set(0);
+                    continue;
+                }
+                Counter::Multiplexed(mux_idx) => {
+                    self.mux.unbind(mux_idx);
+                    continue;
+                }
+            }
+            if idx >= FIRST_HPM_COUNTER {
+                unsafe { self.write_mhpmevent(platform, idx, 0) };
+            }
+            self.events[idx] = None;
+            self.untrack(idx);
+        }
+    }
+
+    // record a counter configured by supervisor, for `pmu_leak_check` and the idle timeout
+    fn configured<P: PmuPlatform>(&mut self, platform: &P, counter_idx: usize) {
+        self.touch(platform, counter_idx, 1);
+        match () {
+            #[cfg(feature = "leak-check")]
+            () => self.session |= 1 << counter_idx,
+            #[cfg(not(feature = "leak-check"))]
+            () => drop(counter_idx),
+        }
+    }
+
+    // event bound to the counter, `None` if the counter is free
+    #[cfg(feature = "leak-check")]
+    fn bound_event(&self, counter_idx: usize, num_hardware_counters: usize) -> Option<usize> {
+        match classify(counter_idx, num_hardware_counters) {
+            Counter::Hardware(idx) => self.events[idx],
+            Counter::Firmware(fw_idx) => self.fw_events[fw_idx].map(|code| events::event_idx(EVENT_TYPE_FIRMWARE, code)),
+            Counter::Multiplexed(mux_idx) => self.mux.event_idx(mux_idx),
+        }
+    }
+
+    // record use of the counters in the set by supervisor, nothing without an idle timeout
+    fn touch<P: PmuPlatform>(&self, platform: &P, counter_idx_base: usize, counter_idx_mask: usize) {
+        if self.last_used.is_empty() {
+            return;
+        }
+        let now = platform.read_mtime() as usize;
+        for idx in counters(counter_idx_base, counter_idx_mask) {
+            self.last_used[idx].store(now, Ordering::Relaxed);
+        }
+    }
+
+    // number of started counters not used by supervisor for more than `timeout` ticks before `now`
+    fn idle_counters(&self, now: usize, timeout: usize, num_hardware_counters: usize) -> usize {
+        let started = |idx| match classify(idx, num_hardware_counters) {
+            Counter::Hardware(idx) => self.started & (1 << idx) != 0,
+            Counter::Firmware(fw_idx) => self.fw_started & (1 << fw_idx) != 0,
+            Counter::Multiplexed(mux_idx) => self.mux.started() & (1 << mux_idx) != 0,
+        };
+        (0..self.last_used.len())
+            .filter(|&idx| started(idx) && now.wrapping_sub(self.last_used[idx].load(Ordering::Relaxed)) > timeout)
+            .count()
+    }
+
+    // stop all counters and unbind them from their events, returns the number of counters which were bound
+    fn release<P: PmuPlatform>(&mut self, platform: &P, num_hardware_counters: usize) -> usize {
+        let bound = self.events.iter().filter(|event| event.is_some()).count()
+            + self.fw_events.iter().filter(|event| event.is_some()).count()
+            + (0..MULTIPLEX_COUNTERS).filter(|&mux_idx| self.mux.is_bound(mux_idx)).count();
+        // stop everything at once, hardware counters multiplexed counters run on included
+        let mux_started = self.mux.started();
+        let running = self.started | self.mux.hardware_bits(mux_started);
+        interrupt_free(|| {
+            if running != 0 {
+                unsafe { self.fixed.set_inhibit(platform, running) };
+            }
+            self.started = 0;
+            self.fw_started = 0;
+        });
+        if mux_started != 0 {
+            self.mux.stop(platform, &self.events[..num_hardware_counters], mux_started);
+        }
+        // then unbind counters from events like `SBI_PMU_STOP_FLAG_RESET` does
+        for idx in 0..num_hardware_counters {
+            if self.events[idx].is_none() {
+                continue;
+            }
+            if idx >= FIRST_HPM_COUNTER {
+                unsafe { self.write_mhpmevent(platform, idx, 0) };
+            }
+            self.events[idx] = None;
+            self.untrack(idx);
+        }
+        for fw_idx in 0..FIRMWARE_COUNTERS {
+            self.fw_events[fw_idx] = None;
+            self.fw_values[fw_idx].set(0);
+            self.fw_watch[fw_idx].set(0);
+        }
+        #[cfg(feature = "multiplex")]
+        for mux_idx in 0..MULTIPLEX_COUNTERS {
+            self.mux.unbind(mux_idx);
+        }
+        unsafe { self.untrap_reads(platform, self.trapped) };
+        bound
+    }
+
+    // notify supervisor if firmware counter `fw_idx` reached the threshold of its watchpoint, which is disarmed then
+    fn check_watch(&self, fw_idx: usize) {
+        let threshold = self.fw_watch[fw_idx].get();
+        let value = self.fw_values[fw_idx].get();
+        // an interrupt counting the event in between notifies once, whichever takes the threshold first
+        if threshold == 0 || value < threshold || self.fw_watch[fw_idx].take() == 0 {
+            return;
+        }
+        match self.fw_notify[fw_idx] {
+            WatchNotify::SoftwareInterrupt => raise_supervisor_soft(),
+            WatchNotify::SharedMemory(address) => unsafe { write_volatile(address as *mut u64, value) },
+        }
+    }
+
+    // write `mhpmevent` of a programmable counter bound directly, or unbound from its event, and its shadow
+    unsafe fn write_mhpmevent<P: PmuPlatform>(&mut self, platform: &P, counter_idx: usize, value: u64) {
+        platform.write_mhpmevent(counter_idx, value);
+        self.mhpmevents[counter_idx] = value;
+    }
+
+    // `mhpmevent` of a programmable counter as last written, by a direct binding or a multiplexed counter running on it
+    fn mhpmevent(&self, counter_idx: usize) -> u64 {
+        self.mux.mhpmevent(counter_idx).unwrap_or(self.mhpmevents[counter_idx])
+    }
+
+    // the shadows of counters in use must match their CSRs, but for bits the hardware may change or drop
+    #[cfg(feature = "shadow-check")]
+    fn check_mhpmevents<P: PmuPlatform>(&self, platform: &P, num_hardware_counters: usize) {
+        for idx in FIRST_HPM_COUNTER..num_hardware_counters {
+            if self.events[idx].is_some() || self.mux.mhpmevent(idx).is_some() {
+                debug_assert!(
+                    mhpmevent_kept(platform, idx, self.mhpmevent(idx)),
+                    "mhpmevent{} is {:#x}, shadow {:#x}",
+                    idx,
+                    platform.read_mhpmevent(idx),
+                    self.mhpmevent(idx)
+                );
+            }
+        }
+    }
+
+    // start the counters which were running when the context was saved, multiplexed counters on whichever
+    // hardware counters are free now
+    unsafe fn resume_saved<P: PmuPlatform>(&mut self, platform: &P, saved: &SavedContext<N>, num_hardware_counters: usize) {
+        let scheduled = self.mux.resume(platform, &self.events[..num_hardware_counters]);
+        self.fixed.clear_inhibit(platform, !saved.inhibit & !saved.multiplexed | scheduled);
+    }
+
+    fn untrack(&mut self, counter_idx: usize) {
+        self.tracked &= !(1 << counter_idx);
+        self.overflow &= !(1 << counter_idx);
+    }
+
+    // detect wrap-around of running tracked counters, then publish the bitmap into snapshot shared memory;
+    // `inhibit` is the current value of `mcountinhibit`, with virtual bits of `cycle` and `instret`
+    fn poll<P: PmuPlatform>(&mut self, platform: &P, inhibit: usize) -> usize {
+        let running = self.tracked & !inhibit;
+        for idx in 0..N {
+            if running & (1 << idx) == 0 {
+                continue;
+            }
+            let value = self.fixed.read(platform, idx);
+            if value < self.last_values[idx] {
+                self.overflow |= 1 << idx;
+            }
+            self.last_values[idx] = value;
+        }
+        if let Some(shmem) = self.snapshot {
+            let area = shmem as *mut SnapshotArea;
+            let overflow = if self.snapshot_base < usize::BITS as usize { self.overflow >> self.snapshot_base } else { 0 };
+            unsafe { write_volatile(&mut (*area).counter_overflow_bitmap, overflow as u64) };
+        }
+        self.overflow
+    }
+
+    // value of the counter at index `i` of snapshot shared memory, `None` if there is no shared memory
+    fn snapshot_value(&self, i: usize) -> Option<u64> {
+        let area = self.snapshot? as *const SnapshotArea;
+        Some(unsafe { read_volatile(&(*area).counter_values[i]) })
+    }
+
+    // write values and overflow bits of the counters in the set into snapshot shared memory,
+    // both relative to `counter_idx_base`, which later overflow bitmaps are relative to as well
+    fn take_snapshot<P: PmuPlatform>(&mut self, platform: &P, counter_idx_base: usize, counter_idx_mask: usize, num_hardware_counters: usize) {
+        let area = match self.snapshot {
+            Some(shmem) => shmem as *mut SnapshotArea,
+            None => return,
+        };
+        self.snapshot_base = counter_idx_base;
+        let mut overflow = 0;
+        for idx in counters(counter_idx_base, counter_idx_mask) {
+            let i = idx - counter_idx_base;
+            let value = match classify(idx, num_hardware_counters) {
+                // `time` has no `mhpmcounter` CSR behind it
+                Counter::Hardware(COUNTER_TIME) => platform.read_mtime(),
+                Counter::Hardware(idx) => {
+                    let overflown = if platform.has_sscofpmf() {
+                        idx >= FIRST_HPM_COUNTER && platform.read_mhpmevent(idx) & MHPMEVENT_OF != 0
+                    } else {
+                        self.overflow & (1 << idx) != 0
+                    };
+                    if overflown {
+                        overflow |= 1 << i;
+                    }
+                    self.fixed.read(platform, idx)
+                }
+                Counter::Firmware(fw_idx) => self.fw_values[fw_idx].get(),
+                Counter::Multiplexed(mux_idx) => self.mux.value(platform, mux_idx),
+            };
+            unsafe { write_volatile(&mut (*area).counter_values[i], value) };
+        }
+        unsafe {
+            write_volatile(&mut (*area).counter_overflow_bitmap, overflow);
+            write_volatile(&mut (*area).mtime, platform.read_mtime());
+        }
+    }
+}
+
+impl<P: PmuPlatform, const N: usize> Pmu for GenericPmu<P, N> {
+    fn is_available(&self) -> bool {
+        self.platform.has_mcountinhibit()
+    }
+
+    fn pmu_version(&self) -> usize {
+        if self.platform.raw_event_v2() {
+            PMU_VERSION_3_0
+        } else {
+            PMU_VERSION_0_3
+        }
+    }
+
+    fn pmu_num_counters(&self) -> SbiRet {
+        SbiRet::ok(self.num_counters())
+    }
+
+    fn pmu_counter_get_info(&self, counter_idx: usize) -> SbiRet {
+        if counter_idx >= self.num_counters() {
+            return SbiRet::invalid_param();
+        }
+        // the same index space every other call works in, so that the type reported for an index is the kind
+        // of counter calls on it operate on
+        match classify(counter_idx, self.num_hardware_counters()) {
+            Counter::Hardware(idx) => SbiRet::ok(self.infos[idx]),
+            // type = 1 (firmware counter), csr and width are ignored;
+            // multiplexed counters are read through `sbi_pmu_counter_fw_read` as well
+            Counter::Firmware(_) | Counter::Multiplexed(_) => SbiRet::ok(CounterInfo::firmware().raw()),
+        }
+    }
+
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        if !self.counters_valid(counter_idx_base, counter_idx_mask) || config_flags & !CONFIG_FLAGS_MASK != 0 {
+            return SbiRet::invalid_param();
+        }
+        let (event, mut selector) = match self.check_event(event_idx, event_data) {
+            Ok(checked) => checked,
+            Err(ans) => return ans,
+        };
+        let firmware = event.event_type() == EVENT_TYPE_FIRMWARE;
+        let num_hardware_counters = self.num_hardware_counters();
+        let skip_match = config_flags & SBI_PMU_CFG_FLAG_SKIP_MATCH != 0;
+        // aliases take precedence over the platform's own encoding
+        let alias = if firmware { None } else { self.match_alias(event_idx, counter_idx_base, counter_idx_mask, skip_match) };
+        if let Some((_, mhpmevent)) = alias {
+            selector = mhpmevent;
+        }
+        // encodings never written before are read back once from the counter they are written into
+        let verify = !firmware && self.encoding_sticks(selector).is_none();
+        let alias_counters = self.aliases_of(event_idx).iter().fold(0, |bits, alias| bits | alias.counters);
+        let multiplex = self.multiplex;
+        let (platform, state) = self.split();
+        // hardware events may go to a multiplexed counter if any programmable counter can monitor them
+        let can_multiplex = multiplex
+            && !firmware
+            && (FIRST_HPM_COUNTER..num_hardware_counters).any(|idx| platform.counter_can_monitor(idx, event_idx, event_data));
+        let counter_idx = if let Some((idx, _)) = alias {
+            idx
+        } else if skip_match {
+            // skip matching, use the first counter in the set
+            match counters(counter_idx_base, counter_idx_mask).next() {
+                Some(idx) => match classify(idx, num_hardware_counters) {
+                    Counter::Hardware(_) if !firmware && idx != COUNTER_TIME => idx,
+                    Counter::Firmware(_) if firmware => idx,
+                    Counter::Multiplexed(_) if can_multiplex => idx,
+                    _ => return SbiRet::invalid_param(),
+                },
+                None => return SbiRet::invalid_param(),
+            }
+        } else {
+            // multiplexed counters come after hardware counters, they are only used when no hardware counter is free
+            let found = counters(counter_idx_base, counter_idx_mask).find(|&idx| match classify(idx, num_hardware_counters) {
+                Counter::Hardware(idx) => {
+                    !firmware
+                        && idx != COUNTER_TIME
+                        && state.events[idx].is_none()
+                        && platform.counter_can_monitor(idx, event_idx, event_data)
+                }
+                Counter::Firmware(fw_idx) => firmware && state.fw_events[fw_idx].is_none(),
+                Counter::Multiplexed(mux_idx) => can_multiplex && !state.mux.is_bound(mux_idx),
+            });
+            match found {
+                Some(idx) => idx,
+                None => {
+                    // the event is supported, but it may still be out of reach of the counters in the set
+                    // through an alias as well
+                    let in_reach = counters(counter_idx_base, counter_idx_mask).any(|idx| match classify(idx, num_hardware_counters) {
+                        Counter::Hardware(idx) => {
+                            !firmware
+                                && idx != COUNTER_TIME
+                                && (platform.counter_can_monitor(idx, event_idx, event_data) || alias_counters & (1 << idx) != 0)
+                        }
+                        Counter::Firmware(_) => firmware,
+                        Counter::Multiplexed(_) => can_multiplex,
+                    });
+                    return if in_reach { SbiRet::failed() } else { SbiRet::not_supported() };
+                }
+            }
+        };
+        let mut mhpmevent = selector;
+        if platform.has_sscofpmf() {
+            // privilege filter bits are hints, ignored without Sscofpmf;
+            // there is no VS-mode or VU-mode to filter without the hypervisor extension
+            let mut inhibit = mhpmevent_inhibit_bits(config_flags);
+            if !platform.has_hypervisor() {
+                inhibit &= !(MHPMEVENT_VSINH | MHPMEVENT_VUINH);
+            }
+            mhpmevent |= inhibit;
+        }
+        let clear_value = config_flags & SBI_PMU_CFG_FLAG_CLEAR_VALUE != 0;
+        if let Counter::Multiplexed(mux_idx) = classify(counter_idx, num_hardware_counters) {
+            state.mux.bind(platform, mux_idx, event_idx, event_data, mhpmevent, clear_value);
+            if config_flags & SBI_PMU_CFG_FLAG_AUTO_START != 0 {
+                let scheduled = state.mux.start(platform, &state.events[..num_hardware_counters], 1 << mux_idx);
+                if scheduled != 0 {
+                    unsafe { platform.clear_mcountinhibit(scheduled) };
+                }
+            } else if state.mux.started() & (1 << mux_idx) != 0 {
+                state.mux.stop(platform, &state.events[..num_hardware_counters], 1 << mux_idx);
+            }
+            state.configured(platform, counter_idx);
+            return SbiRet::ok(counter_idx);
+        }
+        if firmware {
+            // firmware counters are kept in memory, privilege filter hints do not apply
+            let fw_idx = counter_idx - num_hardware_counters;
+            state.fw_events[fw_idx] = Some(event.code());
+            state.fw_data[fw_idx] = event_data;
+            if clear_value {
+                state.fw_values[fw_idx].set(0);
+            }
+            if config_flags & SBI_PMU_CFG_FLAG_AUTO_START != 0 {
+                state.fw_started |= 1 << fw_idx;
+            } else {
+                state.fw_started &= !(1 << fw_idx);
+            }
+            state.configured(platform, counter_idx);
+            return SbiRet::ok(counter_idx);
+        }
+        // a directly bound counter is taken away from multiplexed counters
+        state.mux.release(platform, counter_idx);
+        // stop counting while configuring
+        unsafe { state.fixed.set_inhibit(platform, 1 << counter_idx) };
+        state.started &= !(1 << counter_idx);
+        if counter_idx >= FIRST_HPM_COUNTER {
+            unsafe { state.write_mhpmevent(platform, counter_idx, mhpmevent) };
+            if verify && !mhpmevent_kept(platform, counter_idx, mhpmevent) {
+                // the core dropped an encoding it does not implement, the counter would count nothing
+                unsafe { state.write_mhpmevent(platform, counter_idx, 0) };
+                state.events[counter_idx] = None;
+                state.untrack(counter_idx);
+                self.remember_encoding(selector, false);
+                return SbiRet::not_supported();
+            }
+        }
+        state.events[counter_idx] = Some(event_idx);
+        if clear_value {
+            unsafe { state.fixed.write(platform, counter_idx, 0) };
+        }
+        if !platform.has_sscofpmf() {
+            state.track(platform, counter_idx);
+        }
+        if config_flags & SBI_PMU_CFG_FLAG_AUTO_START != 0 {
+            unsafe { state.fixed.clear_inhibit(platform, 1 << counter_idx) };
+            state.started |= 1 << counter_idx;
+        }
+        state.configured(platform, counter_idx);
+        if verify && counter_idx >= FIRST_HPM_COUNTER {
+            self.remember_encoding(selector, true);
+        }
+        SbiRet::ok(counter_idx)
+    }
+
+    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+        if !self.counters_valid(counter_idx_base, counter_idx_mask) || start_flags & !START_FLAGS_MASK != 0 {
+            return SbiRet::invalid_param();
+        }
+        let num_hardware_counters = self.num_hardware_counters();
+        let (platform, state) = self.split();
+        let init_snapshot = start_flags & SBI_PMU_START_FLAG_INIT_SNAPSHOT != 0;
+        if init_snapshot && state.snapshot.is_none() {
+            return SbiRet::no_shmem();
+        }
+        let (bits, fw_bits, mux_bits) = match state.bound_counters(counter_idx_base, counter_idx_mask, num_hardware_counters) {
+            Some(bits) => bits,
+            // counters without an event can not be started
+            None => return SbiRet::invalid_param(),
+        };
+        if state.fixed.inhibit(platform) & bits != bits || state.fw_started & fw_bits != 0 || state.mux.started() & mux_bits != 0 {
+            return SbiRet::already_started();
+        }
+        // the counters in the set are prepared while stopped, then started together below; values from snapshot
+        // shared memory take precedence over `initial_value`
+        let set_init_value = start_flags & SBI_PMU_START_FLAG_SET_INIT_VALUE != 0;
+        for idx in counters(counter_idx_base, counter_idx_mask) {
+            // `time` and counters without an event are skipped, their values are left as they are
+            let bound = match classify(idx, num_hardware_counters) {
+                Counter::Hardware(_) => bits & (1 << idx) != 0,
+                Counter::Firmware(fw_idx) => fw_bits & (1 << fw_idx) != 0,
+                Counter::Multiplexed(mux_idx) => mux_bits & (1 << mux_idx) != 0,
+            };
+            if !bound {
+                continue;
+            }
+            let value = if init_snapshot {
+                state.snapshot_value(idx - counter_idx_base)
+            } else if set_init_value {
+                Some(initial_value)
+            } else {
+                None
+            };
+            match classify(idx, num_hardware_counters) {
+                Counter::Hardware(_) => {}
+                Counter::Firmware(fw_idx) => {
+                    if let Some(value) = value {
+                        state.fw_values[fw_idx].set(value);
+                    }
+                    continue;
+                }
+                Counter::Multiplexed(mux_idx) => {
+                    if let Some(value) = value {
+                        state.mux.reset_value(mux_idx, value);
+                    }
+                    continue;
+                }
+            }
+            if let Some(value) = value {
+                unsafe { state.fixed.write(platform, idx, value) };
+            }
+            if !platform.has_sscofpmf() {
+                // clear overflow bit on start like Sscofpmf does
+                state.track(platform, idx);
+            } else if idx >= FIRST_HPM_COUNTER {
+                // clear overflow bit, so that the counter raises overflow interrupt again
+                if platform.read_mhpmevent(idx) & MHPMEVENT_OF != 0 {
+                    unsafe { platform.write_mhpmevent(idx, state.mhpmevent(idx)) };
+                }
+            }
+        }
+        let mux_scheduled = state.mux.start(platform, &state.events[..num_hardware_counters], mux_bits);
+        // firmware counters start counting right before hardware counters, all of which start with a single
+        // write of `mcountinhibit`, so that counters started in one call form a group measuring the same interval
+        interrupt_free(|| {
+            state.fw_started |= fw_bits;
+            unsafe { state.fixed.clear_inhibit(platform, bits | mux_scheduled) };
+            state.started |= bits;
+        });
+        unsafe { state.untrap_reads(platform, bits) };
+        state.touch(platform, counter_idx_base, counter_idx_mask);
+        SbiRet::ok(0)
+    }
+
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+        if !self.counters_valid(counter_idx_base, counter_idx_mask) || stop_flags & !STOP_FLAGS_MASK != 0 {
+            return SbiRet::invalid_param();
+        }
+        let num_hardware_counters = self.num_hardware_counters();
+        let stopped_read = self.stopped_read;
+        let phase = StopPhase::from_flags(stop_flags);
+        let (platform, state) = self.split();
+        let (bits, fw_bits, mux_bits) = match state.bound_counters(counter_idx_base, counter_idx_mask, num_hardware_counters) {
+            Some(bits) => bits,
+            None => return SbiRet::invalid_param(),
+        };
+        // `mcountinhibit` is read once, and all hardware counters in the set are stopped by a single write
+        let inhibit = state.fixed.inhibit(platform);
+        let (frozen, fw_frozen) = (inhibit & bits, fw_bits & !state.fw_started);
+        let mux_frozen = mux_bits & !state.mux.started();
+        let already_stopped = frozen | fw_frozen | mux_frozen != 0;
+        let take_snapshot = stop_flags & SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT != 0;
+        if take_snapshot && state.snapshot.is_none() {
+            return SbiRet::no_shmem();
+        }
+        // frozen counters are released all the same, so that supervisor frees counters it stopped before
+        // without starting them again; a snapshot covers the whole set as well, as the Linux driver stops
+        // every counter in use with one snapshot call on context switch, some of them stopped on overflow
+        if already_stopped && phase == StopPhase::Freeze && !take_snapshot {
+            return SbiRet::already_stopped();
+        }
+        let (running, fw_running, mux_running) = (bits & !frozen, fw_bits & !fw_frozen, mux_bits & !mux_frozen);
+        // detect wrap-around for the last time before stopping
+        state.poll(platform, inhibit);
+        // stop the group at once like it was started: hardware counters, including those multiplexed
+        // counters are running on, with a single write of `mcountinhibit`, then firmware counters
+        let mux_hardware = state.mux.hardware_bits(mux_running);
+        interrupt_free(|| {
+            if running | mux_hardware != 0 {
+                unsafe { state.fixed.set_inhibit(platform, running | mux_hardware) };
+            }
+            state.started &= !running;
+            state.fw_started &= !fw_running;
+        });
+        if mux_running != 0 {
+            state.mux.stop(platform, &state.events[..num_hardware_counters], mux_running);
+        }
+        if take_snapshot {
+            // values are final now that the counters are stopped
+            state.take_snapshot(platform, counter_idx_base, counter_idx_mask, num_hardware_counters);
+        }
+        match phase {
+            StopPhase::Release => {
+                state.unbind(platform, counter_idx_base, counter_idx_mask, num_hardware_counters);
+                // unbound counters no longer count anything, there is no value to keep
+                unsafe { state.untrap_reads(platform, bits) };
+            }
+            StopPhase::Freeze if stopped_read == StoppedRead::Frozen => unsafe { state.trap_reads(platform, bits) },
+            StopPhase::Freeze => {}
+        }
+        state.touch(platform, counter_idx_base, counter_idx_mask);
+        if already_stopped {
+            // released or written into the snapshot, but reported like OpenSBI does, which the Linux driver
+            // ignores when releasing
+            return SbiRet::already_stopped();
+        }
+        SbiRet::ok(0)
+    }
+
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet {
+        let value = self.fw_read_value(counter_idx);
+        if value.is_some() {
+            self.touch(counter_idx, 1);
+        }
+        match value {
+            Some(value) => SbiRet::ok(value as usize),
+            None => SbiRet::invalid_param(),
+        }
+    }
+
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        let value = self.fw_read_value(counter_idx);
+        if value.is_some() {
+            self.touch(counter_idx, 1);
+        }
+        match value {
+            #[cfg(target_pointer_width = "32")]
+            Some(value) => SbiRet::ok((value >> 32) as usize),
+            #[cfg(not(target_pointer_width = "32"))]
+            Some(_) => SbiRet::ok(0),
+            None => SbiRet::invalid_param(),
+        }
+    }
+
+    fn pmu_counter_read_clear(&mut self, counter_idx: usize) -> SbiRet {
+        if counter_idx >= self.num_counters() {
+            return SbiRet::invalid_param();
+        }
+        match classify(counter_idx, self.num_hardware_counters()) {
+            Counter::Firmware(fw_idx) => {
+                let (platform, state) = self.split();
+                state.touch(platform, counter_idx, 1);
+                SbiRet::ok(state.fw_values[fw_idx].take() as usize)
+            }
+            // hardware and multiplexed counters are counted by hardware, not kept by RustSBI
+            _ => SbiRet::invalid_param(),
+        }
+    }
+
+    // the notification is in place before the threshold arms the watchpoint
+    fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: WatchNotify) -> SbiRet {
+        if counter_idx >= self.num_counters() {
+            return SbiRet::invalid_param();
+        }
+        match classify(counter_idx, self.num_hardware_counters()) {
+            Counter::Firmware(fw_idx) => {
+                let (platform, state) = self.split();
+                if state.fw_events[fw_idx].is_none() {
+                    return SbiRet::invalid_param();
+                }
+                state.touch(platform, counter_idx, 1);
+                state.fw_watch[fw_idx].set(0);
+                state.fw_notify[fw_idx] = notify;
+                state.fw_watch[fw_idx].set(threshold);
+                SbiRet::ok(0)
+            }
+            // hardware counters overflow into Sscofpmf interrupts or the sampler instead
+            _ => SbiRet::invalid_param(),
+        }
+    }
+
+    fn pmu_counter_read_batch(&self, counter_idx_base: usize, counter_idx_mask: usize, values: &mut [u64; usize::BITS as usize]) -> SbiRet {
+        if !self.counters_valid(counter_idx_base, counter_idx_mask) {
+            return SbiRet::invalid_param();
+        }
+        for idx in counters(counter_idx_base, counter_idx_mask) {
+            values[idx - counter_idx_base] = match self.firmware_value(idx) {
+                Some(value) => value,
+                None if idx == COUNTER_TIME => self.platform.read_mtime(),
+                None => self.hardware_value(idx),
+            };
+        }
+        self.touch(counter_idx_base, counter_idx_mask);
+        SbiRet::ok(counter_idx_mask.count_ones() as usize)
+    }
+
+    fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+        if flags == 0 && shmem_phys_lo == usize::MAX && shmem_phys_hi == usize::MAX {
+            // all-ones address disables the shared memory
+            self.split().1.snapshot = None;
+            return SbiRet::ok(0);
+        }
+        if flags != 0 || shmem_phys_lo % SNAPSHOT_AREA_SIZE != 0 {
+            return SbiRet::invalid_param();
+        }
+        if shmem_phys_hi != 0 {
+            // memory above XLEN bits is not accessible from machine mode
+            return SbiRet::invalid_address();
+        }
+        // the firmware writes into the shared memory on every snapshot, long after this call
+        if !self.platform.shmem_accessible(shmem_phys_lo, SNAPSHOT_AREA_SIZE) {
+            return SbiRet::invalid_address();
+        }
+        let (platform, state) = self.split();
+        state.snapshot = Some(shmem_phys_lo);
+        state.snapshot_base = 0;
+        // publish current overflow bitmap at once
+        state.poll(platform, state.fixed.inhibit(platform));
+        SbiRet::ok(0)
+    }
+
+    // hardware events are reported unsupported once their encoding is known to be dropped by the hardware
+    fn pmu_event_get_info(&self, entries: &mut [EventInfo]) -> SbiRet {
+        for entry in entries.iter_mut() {
+            let supported = self.check_event(entry.event_idx as usize, entry.event_data).is_ok();
+            entry.output = if supported { EVENT_INFO_SUPPORTED } else { 0 };
+        }
+        SbiRet::ok(0)
+    }
+
+    fn pmu_save_context(&mut self) {
+        let num_counters = self.num_hardware_counters();
+        let (platform, state) = self.split();
+        #[cfg(feature = "shadow-check")]
+        state.check_mhpmevents(platform, num_counters);
+        let mut saved = SavedContext {
+            inhibit: state.fixed.inhibit(platform),
+            multiplexed: state.mux.hardware_bits(!0),
+            events: [0; N],
+            counters: [0; N],
+        };
+        // pause all counters while reading, so that saved values are consistent; multiplexed counters end their
+        // turn, keeping what they counted
+        unsafe { state.fixed.set_inhibit(platform, !0) };
+        state.mux.suspend(platform);
+        for idx in (0..num_counters).filter(|&idx| idx != COUNTER_TIME) {
+            saved.counters[idx] = state.fixed.read(platform, idx);
+            if idx >= FIRST_HPM_COUNTER {
+                saved.events[idx] = platform.read_mhpmevent(idx);
+            }
+        }
+        // the hart keeps running if suspend fails, resume counters that were running
+        unsafe { state.resume_saved(platform, &saved, num_counters) };
+        state.saved = Some(saved);
+    }
+
+    fn pmu_restore_context(&mut self) {
+        let num_counters = self.num_hardware_counters();
+        let (platform, state) = self.split();
+        if let Some(saved) = state.saved.take() {
+            unsafe {
+                state.fixed.set_inhibit(platform, !0);
+                // turns taken since the context was saved counted into CSRs which are lost now
+                state.mux.forget_turns();
+                for idx in (0..num_counters).filter(|&idx| idx != COUNTER_TIME) {
+                    if idx >= FIRST_HPM_COUNTER {
+                        platform.write_mhpmevent(idx, saved.events[idx]);
+                    }
+                    state.fixed.write(platform, idx, saved.counters[idx]);
+                }
+                state.resume_saved(platform, &saved, num_counters);
+            }
+        }
+    }
+
+    fn pmu_poll_overflow(&mut self) -> usize {
+        let (platform, state) = self.split();
+        state.poll(platform, state.fixed.inhibit(platform))
+    }
+
+    fn pmu_rotate_multiplex(&mut self) {
+        let num_hardware_counters = self.num_hardware_counters();
+        let (platform, state) = self.split();
+        state.mux.rotate(platform, &state.events[..num_hardware_counters]);
+    }
+
+    fn pmu_dump(&self) {
+        let hartid = self.platform.hart_id();
+        let num_hardware_counters = self.num_hardware_counters();
+        crate::println!("[rustsbi-pmu] PMU state of hart {}", hartid);
+        let state = match self.harts.get(hartid) {
+            Some(state) => state,
+            None => {
+                crate::println!("[rustsbi-pmu]   no counter was ever configured");
+                return;
+            }
+        };
+        #[cfg(feature = "shadow-check")]
+        state.check_mhpmevents(&self.platform, num_hardware_counters);
+        let inhibit = state.fixed.inhibit(&self.platform);
+        for idx in 0..num_hardware_counters {
+            if let Some(event_idx) = state.events[idx] {
+                crate::println!(
+                    "[rustsbi-pmu]   {}: hardware, event {:#x} ({}), {}, value {}, mhpmevent {:#x}",
+                    idx,
+                    event_idx,
+                    events::name(event_idx).unwrap_or("-"),
+                    running_state(inhibit & (1 << idx) == 0),
+                    state.fixed.read(&self.platform, idx),
+                    if idx >= FIRST_HPM_COUNTER { state.mhpmevent(idx) } else { 0 }
+                );
+            }
+        }
+        for fw_idx in 0..FIRMWARE_COUNTERS {
+            if let Some(code) = state.fw_events[fw_idx] {
+                let event_idx = events::event_idx(EVENT_TYPE_FIRMWARE, code);
+                crate::println!(
+                    "[rustsbi-pmu]   {}: firmware, event {:#x} ({}), {}, value {}",
+                    num_hardware_counters + fw_idx,
+                    event_idx,
+                    events::name(event_idx).unwrap_or("-"),
+                    running_state(state.fw_started & (1 << fw_idx) != 0),
+                    state.fw_values[fw_idx].get()
+                );
+            }
+        }
+        let bound = (0..MULTIPLEX_COUNTERS).filter_map(|mux_idx| Some((mux_idx, state.mux.event_idx(mux_idx)?)));
+        for (mux_idx, event_idx) in bound {
+            let running = match state.mux.slot(mux_idx) {
+                Some(_) => "running",
+                None if state.mux.started() & (1 << mux_idx) != 0 => "waiting",
+                None => "stopped",
+            };
+            crate::println!(
+                "[rustsbi-pmu]   {}: multiplexed, event {:#x} ({}), {}, value {}",
+                num_hardware_counters + FIRMWARE_COUNTERS + mux_idx,
+                event_idx,
+                events::name(event_idx).unwrap_or("-"),
+                running,
+                state.mux.value(&self.platform, mux_idx)
+            );
+        }
+    }
+
+    // counts of other harts are kept in memory; overflow bits of Sscofpmf are in `mhpmevent` CSRs,
+    // and are only seen on the calling hart
+    fn pmu_hart_summary(&self, hartid: usize) -> Option<HartSummary> {
+        let state = match self.harts.get(hartid) {
+            Some(state) => state,
+            // no counter was ever configured on this hart
+            None => return Some(HartSummary::default()),
+        };
+        let num_hardware_counters = self.num_hardware_counters();
+        let bound = state.events.iter().filter(|event| event.is_some()).count()
+            + state.fw_events.iter().filter(|event| event.is_some()).count()
+            + (0..MULTIPLEX_COUNTERS).filter(|&mux_idx| state.mux.is_bound(mux_idx)).count();
+        let started = state.started.count_ones() + state.fw_started.count_ones() + state.mux.started().count_ones();
+        let local_overflow = hartid == self.platform.hart_id()
+            && self.platform.has_sscofpmf()
+            && (FIRST_HPM_COUNTER..num_hardware_counters)
+                .any(|idx| state.events[idx].is_some() && self.platform.read_mhpmevent(idx) & MHPMEVENT_OF != 0);
+        // `mtime` is shared by all harts
+        let idle = match self.idle_timeout {
+            Some(timeout) => state.idle_counters(self.platform.read_mtime() as usize, timeout, num_hardware_counters),
+            None => 0,
+        };
+        Some(HartSummary {
+            bound,
+            started: started as usize,
+            overflow_pending: state.overflow != 0 || local_overflow,
+            idle,
+        })
+    }
+
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        let event_code = match EventIdx::new(event_idx) {
+            Some(event) if event.event_type() == EVENT_TYPE_FIRMWARE => event.code(),
+            _ => return SbiRet::invalid_param(),
+        };
+        per_hart.iter_mut().for_each(|value| *value = 0);
+        let mut total: u64 = 0;
+        for (hartid, state) in self.harts.iter().enumerate() {
+            // firmware counters are kept in memory, and read from any hart
+            let value = (0..FIRMWARE_COUNTERS)
+                .find(|&fw_idx| state.fw_events[fw_idx] == Some(event_code))
+                .map_or(0, |fw_idx| state.fw_values[fw_idx].get());
+            if let Some(slot) = per_hart.get_mut(hartid) {
+                *slot = value;
+            }
+            total = total.wrapping_add(value);
+        }
+        SbiRet::ok(total as usize)
+    }
+
+    // leaks of the calling hart are printed by the firmware, where supervisor software cannot hide them
+    #[cfg(feature = "leak-check")]
+    fn pmu_leak_check(&mut self, counter_idx_base: usize, end_session: bool) -> SbiRet {
+        let num_counters = self.num_counters();
+        if counter_idx_base >= num_counters {
+            return SbiRet::invalid_param();
+        }
+        let num_hardware_counters = self.num_hardware_counters();
+        let hartid = self.platform.hart_id();
+        let (_, state) = self.split();
+        let mut leaked = 0;
+        for idx in (0..num_counters).filter(|&idx| state.session & (1 << idx) != 0) {
+            let event_idx = match state.bound_event(idx, num_hardware_counters) {
+                Some(event_idx) => event_idx,
+                // released in this session
+                None => continue,
+            };
+            if end_session {
+                crate::println!(
+                    "[rustsbi-pmu] hart {} leaked counter {}, event {:#x} ({})",
+                    hartid,
+                    idx,
+                    event_idx,
+                    events::name(event_idx).unwrap_or("-")
+                );
+            }
+            if idx >= counter_idx_base && idx - counter_idx_base < usize::BITS as usize {
+                leaked |= 1 << (idx - counter_idx_base);
+            }
+        }
+        if end_session {
+            state.session = 0;
+        }
+        SbiRet::ok(leaked)
+    }
+
+    // counters of the calling hart are stopped and freed whatever supervisor left them in
+    fn pmu_reclaim(&mut self) -> SbiRet {
+        let num_hardware_counters = self.num_hardware_counters();
+        let (platform, state) = self.split();
+        SbiRet::ok(state.release(platform, num_hardware_counters))
+    }
+
+    // the state of the calling hart is replaced once its counters are freed, the snapshot shared memory of
+    // the previous supervisor and the sessions of the leak check included
+    fn pmu_reset(&mut self, all_harts: bool) {
+        let num_hardware_counters = self.num_hardware_counters();
+        let hartid = self.platform.hart_id();
+        let (platform, state) = self.split();
+        state.release(platform, num_hardware_counters);
+        // supervisor reads `time` directly only if the hart implements it
+        match platform.time_csr() {
+            TimeCsr::Hardware => unsafe { platform.set_mcounteren(1 << COUNTER_TIME) },
+            TimeCsr::Emulated | TimeCsr::Firmware => unsafe { platform.clear_mcounteren(1 << COUNTER_TIME) },
+        }
+        // programmable counters stay stopped until the next supervisor starts them; `cycle` and `instret` not
+        // bound to an event keep counting, as after a reset of the hart
+        let programmable = (FIRST_HPM_COUNTER..num_hardware_counters).fold(0, |bits, idx| bits | 1 << idx);
+        if programmable != 0 {
+            unsafe { platform.set_mcountinhibit(programmable) };
+        }
+        for idx in (0..self.harts.len()).filter(|&idx| all_harts || idx == hartid) {
+            let state = self.new_hart();
+            self.harts[idx] = state;
+        }
+    }
+
+    fn pmu_emulate_counter_read(&self, counter_idx: usize) -> Option<u64> {
+        if counter_idx >= self.num_hardware_counters() {
+            return None;
+        }
+        // reads trapped by `StoppedRead::Frozen` see the value the counter was stopped with
+        let trapped = self.harts.get(self.platform.hart_id()).map_or(0, |state| state.trapped);
+        if trapped & (1 << counter_idx) != 0 {
+            return Some(self.hardware_value(counter_idx));
+        }
+        // counters the platform keeps away from supervisor are read like with `sbi_pmu_counter_fw_read`
+        match self.hidden_read {
+            HiddenRead::Emulate => self.fw_read_value(counter_idx),
+            HiddenRead::Fault => None,
+        }
+    }
+
+    fn pmu_capabilities(&self) -> usize {
+        let sscofpmf = if self.platform.has_sscofpmf() { RUSTSBI_PMU_CAP_SSCOFPMF } else { 0 };
+        RUSTSBI_PMU_CAP_SNAPSHOT | RUSTSBI_PMU_CAP_FIRMWARE_EVENTS | RUSTSBI_PMU_CAP_READ_BATCH | sscofpmf
+    }
+
+    fn pmu_firmware_event(&self, event_code: usize) {
+        // no firmware counter was ever configured on this hart if it has no state
+        if let Some(state) = self.harts.get(self.platform.hart_id()) {
+            for fw_idx in 0..FIRMWARE_COUNTERS {
+                if state.fw_started & (1 << fw_idx) != 0 && state.fw_events[fw_idx] == Some(event_code) {
+                    state.fw_values[fw_idx].increment();
+                    state.check_watch(fw_idx);
+                }
+            }
+        }
+    }
+
+    fn pmu_ecall_cycles(&self, cycles: u64) {
+        self.pmu_platform_event(RUSTSBI_FW_PMU_ECALL_CYCLES, cycles);
+    }
+
+    fn pmu_platform_event(&self, event_data: u64, value: u64) {
+        if let Some(state) = self.harts.get(self.platform.hart_id()) {
+            for fw_idx in 0..FIRMWARE_COUNTERS {
+                if state.fw_started & (1 << fw_idx) != 0
+                    && state.fw_events[fw_idx] == Some(SBI_PMU_FW_PLATFORM)
+                    && state.fw_data[fw_idx] == event_data
+                {
+                    state.fw_values[fw_idx].add(value);
+                    state.check_watch(fw_idx);
+                }
+            }
+        }
+    }
+}
+
+// set `mip.SSIP` of the calling hart, seen by supervisor as a pending supervisor software interrupt
+fn raise_supervisor_soft() {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => unsafe { riscv::register::mip::set_ssoft() },
+        // host-side tests and fuzzing, where there is no supervisor to notify
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {}
+    }
+}
+
+// read `mcountinhibit` with a temporary trap handler, which skips the read and clears the result
+// if it raises an illegal instruction exception; `mstatus` and `mepc` changed by the trap are restored
+fn probe_mcountinhibit() -> bool {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => {
+            let present: usize;
+            riscv::interrupt::free(|_| unsafe {
+                asm!(
+                    "csrr   {mstatus}, mstatus",
+                    "csrr   {mepc}, mepc",
+                    "la     {tmp}, 2f",
+                    "csrrw  {mtvec}, mtvec, {tmp}",
+                    "li     {present}, 1",
+                    "csrr   zero, 0x320",
+                    "j      3f",
+                    // `mtvec` must be 4-byte aligned
+                    ".align 2",
+                    "2:",
+                    "csrr   {tmp}, mepc",
+                    "addi   {tmp}, {tmp}, 4",
+                    "csrw   mepc, {tmp}",
+                    "li     {present}, 0",
+                    "mret",
+                    "3:",
+                    "csrw   mtvec, {mtvec}",
+                    "csrw   mepc, {mepc}",
+                    "csrw   mstatus, {mstatus}",
+                    present = out(reg) present,
+                    tmp = out(reg) _,
+                    mtvec = out(reg) _,
+                    mepc = out(reg) _,
+                    mstatus = out(reg) _,
+                )
+            });
+            present != 0
+        }
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => unimplemented!("not RISC-V instruction set architecture"),
+    }
+}
+
+/// Detect the number of implemented bits of a hardware counter by writing all ones into it and reading back.
+///
+/// The counter is stopped while detecting, and its value and running state are restored afterwards.
+/// This function must be called in machine mode.
+pub fn detect_counter_width<P: PmuPlatform>(platform: &P, counter_idx: usize) -> u32 {
+    let running = platform.read_mcountinhibit() & (1 << counter_idx) == 0;
+    unsafe { platform.set_mcountinhibit(1 << counter_idx) };
+    let value = platform.read_counter(counter_idx);
+    unsafe { platform.write_counter(counter_idx, u64::MAX) };
+    let ones = platform.read_counter(counter_idx);
+    unsafe { platform.write_counter(counter_idx, value) };
+    if running {
+        unsafe { platform.clear_mcountinhibit(1 << counter_idx) };
+    }
+    u64::BITS - ones.leading_zeros()
+}
+
+// read back `mhpmevent` after writing `value`; privilege filter and overflow bits of Sscofpmf are left out,
+// and so are upper halves on RV32 without the extension, where `mhpmeventh` does not exist
+fn mhpmevent_kept<P: PmuPlatform>(platform: &P, counter_idx: usize, value: u64) -> bool {
+    let mut mask = !(MHPMEVENT_OF | MHPMEVENT_MINH | MHPMEVENT_SINH | MHPMEVENT_UINH | MHPMEVENT_VSINH | MHPMEVENT_VUINH);
+    if !platform.has_sscofpmf() {
+        mask &= usize::MAX as u64;
+    }
+    platform.read_mhpmevent(counter_idx) & mask == value & mask
+}
+
+// value of a hardware counter, with bits above its width cleared
+fn read_counter<P: PmuPlatform>(platform: &P, counter_idx: usize) -> u64 {
+    let width = platform.counter_width(counter_idx);
+    let value = platform.read_counter(counter_idx);
+    if width >= u64::BITS {
+        value
+    } else {
+        value & ((1 << width) - 1)
+    }
+}
+
+fn running_state(running: bool) -> &'static str {
+    if running {
+        "running"
+    } else {
+        "stopped"
+    }
+}
+
+// kind of a counter, with its index among counters of the same kind
+enum Counter {
+    Hardware(usize),
+    Firmware(usize),
+    Multiplexed(usize),
+}
+
+fn classify(counter_idx: usize, num_hardware_counters: usize) -> Counter {
+    if counter_idx < num_hardware_counters {
+        Counter::Hardware(counter_idx)
+    } else if counter_idx < num_hardware_counters + FIRMWARE_COUNTERS {
+        Counter::Firmware(counter_idx - num_hardware_counters)
+    } else {
+        Counter::Multiplexed(counter_idx - num_hardware_counters - FIRMWARE_COUNTERS)
+    }
+}
+
+// hardware counters must be read through distinct counter CSRs, or supervisor reading one counter through its
+// CSR would read another one
+fn check_counter_csrs(infos: &[usize]) {
+    for (idx, &info) in infos.iter().enumerate() {
+        let csr = CounterInfo::from_raw(info).csr();
+        assert!((0xC00..0xC20).contains(&csr), "counter {} is read through CSR {:#x}, not a counter CSR", idx, csr);
+        if let Some(other) = infos[..idx].iter().position(|&info| CounterInfo::from_raw(info).csr() == csr) {
+            panic!("counters {} and {} are both read through CSR {:#x}", other, idx, csr);
+        }
+    }
+}
+
+// iterate over counter indexes in the counter set
+fn counters(counter_idx_base: usize, counter_idx_mask: usize) -> impl Iterator<Item = usize> {
+    IndexMask::new(counter_idx_base, counter_idx_mask).iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pmu::events::{
+        event_idx, SBI_PMU_CFG_FLAG_SET_SINH, SBI_PMU_CFG_FLAG_SET_UINH, SBI_PMU_HW_BRANCH_MISSES, SBI_PMU_HW_CACHE_MISSES,
+    };
+    use alloc::boxed::Box;
+    use spin::Mutex;
+
+    // `cycle`, `time`, `instret` and five programmable counters
+    const NUM_COUNTERS: usize = 8;
+    // the platform's own encoding of cache misses, only counted by `hpmcounter7`
+    const OWN_ENCODING: u64 = 0x7;
+    const L2_MISSES: u64 = 0x102;
+    const MEMORY_MISSES: u64 = 0x4001;
+    const MTIME: u64 = 0x1_2345_6789;
+
+    #[derive(Default)]
+    struct Csrs {
+        inhibit: usize,
+        mcounteren: usize,
+        counters: [u64; NUM_COUNTERS],
+        mhpmevents: [u64; NUM_COUNTERS],
+    }
+
+    struct Platform {
+        csrs: Mutex<Csrs>,
+        time_csr: TimeCsr,
+        // CSR supervisor reads each hardware counter through
+        counter_csr: fn(usize) -> usize,
+        // without Sscofpmf, overflow is detected by software
+        sscofpmf: bool,
+    }
+
+    impl Default for Platform {
+        fn default() -> Self {
+            // supervisor reads every counter but `time` through counter CSRs
+            let csrs = Csrs { mcounteren: !(1 << COUNTER_TIME), ..Csrs::default() };
+            Platform {
+                csrs: Mutex::new(csrs),
+                time_csr: TimeCsr::Hardware,
+                counter_csr: |counter_idx| 0xC00 + counter_idx,
+                sscofpmf: true,
+            }
+        }
+    }
+
+    impl PmuPlatform for Platform {
+        fn num_counters(&self) -> usize {
+            NUM_COUNTERS
+        }
+        fn has_sscofpmf(&self) -> bool {
+            self.sscofpmf
+        }
+        fn counter_can_monitor(&self, counter_idx: usize, event_idx: usize, _event_data: u64) -> bool {
+            counter_idx == 7 && event_idx == cache_misses()
+        }
+        fn mhpmevent_value(&self, _event_idx: usize, _event_data: u64) -> u64 {
+            OWN_ENCODING
+        }
+        fn has_mcountinhibit(&self) -> bool {
+            true
+        }
+        fn hart_id(&self) -> usize {
+            0
+        }
+        fn time_csr(&self) -> TimeCsr {
+            self.time_csr
+        }
+        fn read_mtime(&self) -> u64 {
+            MTIME
+        }
+        fn counter_csr(&self, counter_idx: usize) -> usize {
+            (self.counter_csr)(counter_idx)
+        }
+        fn read_mcounteren(&self) -> usize {
+            self.csrs.lock().mcounteren
+        }
+        unsafe fn set_mcounteren(&self, bits: usize) {
+            self.csrs.lock().mcounteren |= bits;
+        }
+        unsafe fn clear_mcounteren(&self, bits: usize) {
+            self.csrs.lock().mcounteren &= !bits;
+        }
+        fn read_mcountinhibit(&self) -> usize {
+            self.csrs.lock().inhibit
+        }
+        unsafe fn set_mcountinhibit(&self, bits: usize) {
+            self.csrs.lock().inhibit |= bits;
+        }
+        unsafe fn clear_mcountinhibit(&self, bits: usize) {
+            self.csrs.lock().inhibit &= !bits;
+        }
+        fn read_counter(&self, counter_idx: usize) -> u64 {
+            self.csrs.lock().counters[counter_idx]
+        }
+        unsafe fn write_counter(&self, counter_idx: usize, value: u64) {
+            self.csrs.lock().counters[counter_idx] = value;
+        }
+        fn read_mhpmevent(&self, counter_idx: usize) -> u64 {
+            self.csrs.lock().mhpmevents[counter_idx]
+        }
+        unsafe fn write_mhpmevent(&self, counter_idx: usize, value: u64) {
+            self.csrs.lock().mhpmevents[counter_idx] = value;
+        }
+    }
+
+    // every counter but `time`
+    const ALL: usize = 0b1111_1101;
+
+    fn cache_misses() -> usize {
+        event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CACHE_MISSES)
+    }
+
+    // cache misses at the L2 cache on `hpmcounter3` and `hpmcounter4`, then at the memory controller on
+    // `hpmcounter5`; `time` and the counters left out are not taken
+    fn aliased() -> GenericPmu<Platform, NUM_COUNTERS> {
+        let aliases = [
+            EventAlias { mhpmevent: L2_MISSES, counters: 0b11010 },
+            EventAlias { mhpmevent: MEMORY_MISSES, counters: 0b100000 },
+        ];
+        GenericPmu::new_bounded(Platform::default()).with_event_aliases(cache_misses(), &aliases)
+    }
+
+    #[test]
+    fn aliases_are_matched_by_priority() {
+        let mut pmu = aliased();
+        let mut taken = Vec::new();
+        loop {
+            let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+            if ret.error != 0 {
+                // every counter which counts the event is in use
+                assert_eq!(ret.error, SbiRet::failed().error);
+                break;
+            }
+            taken.push((ret.value, pmu.platform().read_mhpmevent(ret.value)));
+        }
+        assert_eq!(taken, [(3, L2_MISSES), (4, L2_MISSES), (5, MEMORY_MISSES), (7, OWN_ENCODING)]);
+
+        // a freed counter of a higher priority alias is taken first
+        assert_eq!(pmu.pmu_counter_stop(4, 1, SBI_PMU_STOP_FLAG_RESET).error, 0);
+        assert_eq!(pmu.pmu_counter_stop(5, 1, SBI_PMU_STOP_FLAG_RESET).error, 0);
+        let ret = pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 4));
+        // counters of the set only
+        let ret = pmu.pmu_counter_config_matching(5, 1, 0, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 5));
+        assert_eq!(pmu.platform().read_mhpmevent(5), MEMORY_MISSES);
+        // skipping the match takes the first counter of the set with its alias
+        assert_eq!(pmu.pmu_counter_stop(3, 1, SBI_PMU_STOP_FLAG_RESET).error, 0);
+        let ret = pmu.pmu_counter_config_matching(3, 1, SBI_PMU_CFG_FLAG_SKIP_MATCH, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 3));
+        assert_eq!(pmu.platform().read_mhpmevent(3), L2_MISSES);
+    }
+
+    #[test]
+    fn context_restored_after_suspend() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_start(7, 1, SBI_PMU_START_FLAG_SET_INIT_VALUE, 100).error, 0);
+        unsafe { pmu.platform().set_mcountinhibit(1 << 3) };
+        pmu.pmu_save_context();
+        // the counter keeps running if the hart is not suspended after all
+        assert_eq!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        // counter CSRs are lost in non-retentive suspend
+        unsafe {
+            pmu.platform().write_counter(7, 0);
+            pmu.platform().write_mhpmevent(7, 0);
+            pmu.platform().set_mcountinhibit(!0);
+        }
+        pmu.pmu_restore_context();
+        assert_eq!(pmu.platform().read_counter(7), 100);
+        assert_eq!(pmu.platform().read_mhpmevent(7), OWN_ENCODING);
+        assert_eq!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        // counters stopped before stay stopped
+        assert_ne!(pmu.platform().read_mcountinhibit() & 1 << 3, 0);
+    }
+
+    #[test]
+    fn aliases_make_events_supported() {
+        let branch_misses = event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_BRANCH_MISSES);
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let ret = pmu.pmu_counter_config_matching(0, ALL, 0, branch_misses, 0);
+        assert_eq!(ret.error, SbiRet::not_supported().error);
+
+        let mut pmu = pmu.with_event_aliases(branch_misses, &[EventAlias { mhpmevent: 0x55, counters: 1 << 6 }]);
+        let mut entries = [EventInfo { event_idx: branch_misses as u32, output: 0, event_data: 0 }];
+        assert_eq!(pmu.pmu_event_get_info(&mut entries).error, 0);
+        assert_eq!(entries[0].output, EVENT_INFO_SUPPORTED);
+        // out of reach of the counters in the set, then in reach but in use
+        let ret = pmu.pmu_counter_config_matching(3, 0b111, 0, branch_misses, 0);
+        assert_eq!(ret.error, SbiRet::not_supported().error);
+        let ret = pmu.pmu_counter_config_matching(0, ALL, 0, branch_misses, 0);
+        assert_eq!((ret.error, ret.value), (0, 6));
+        assert_eq!(pmu.platform().read_mhpmevent(6), 0x55);
+        let ret = pmu.pmu_counter_config_matching(0, ALL, 0, branch_misses, 0);
+        assert_eq!(ret.error, SbiRet::failed().error);
+    }
+
+    #[test]
+    fn stopped_counters_read_frozen() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default()).with_stopped_read(StoppedRead::Frozen);
+        let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 7));
+        unsafe { pmu.platform().write_counter(7, 100) };
+        assert_eq!(pmu.pmu_emulate_counter_read(7), None);
+
+        // reads trap once the counter is stopped, and are emulated with its value
+        assert_eq!(pmu.pmu_counter_stop(7, 1, 0).error, 0);
+        assert_eq!(pmu.platform().read_mcounteren() & 1 << 7, 0);
+        assert_eq!(pmu.pmu_emulate_counter_read(7), Some(100));
+        assert_eq!(pmu.pmu_emulate_counter_read(3), None);
+        // supervisor still reads it through the counter CSR, not through firmware reads
+        assert_eq!(pmu.pmu_counter_fw_read(7).error, SbiRet::invalid_param().error);
+
+        assert_eq!(pmu.pmu_counter_start(7, 1, 0, 0).error, 0);
+        assert_ne!(pmu.platform().read_mcounteren() & 1 << 7, 0);
+        assert_eq!(pmu.pmu_emulate_counter_read(7), None);
+        // counters stopped with reset are readable right away
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_RESET).error, 0);
+        assert_ne!(pmu.platform().read_mcounteren() & 1 << 7, 0);
+
+        // and with the live policy, counter CSRs are never trapped
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+        assert_eq!(pmu.pmu_counter_stop(ret.value, 1, 0).error, 0);
+        assert_eq!(pmu.platform().read_mcounteren(), !(1 << COUNTER_TIME));
+        assert_eq!(pmu.pmu_emulate_counter_read(ret.value), None);
+    }
+
+    #[test]
+    fn hidden_counters_read_emulated() {
+        let platform = Platform::default();
+        // the platform keeps `hpmcounter5` away from supervisor
+        unsafe {
+            platform.clear_mcounteren(1 << 5);
+            platform.write_counter(5, 42);
+        }
+        let pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(platform);
+        assert_eq!(pmu.pmu_emulate_counter_read(5), None);
+
+        let pmu = pmu.with_hidden_read(HiddenRead::Emulate);
+        assert_eq!(pmu.pmu_emulate_counter_read(5), Some(42));
+        assert_eq!(pmu.pmu_counter_fw_read(5).value, 42);
+        // counters supervisor reads anyway, `time`, and firmware counters are never emulated
+        assert_eq!(pmu.pmu_emulate_counter_read(4), None);
+        assert_eq!(pmu.pmu_emulate_counter_read(COUNTER_TIME), None);
+        assert_eq!(pmu.pmu_emulate_counter_read(NUM_COUNTERS), None);
+    }
+
+    #[test]
+    fn full_mask_skips_time_and_unbound() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let set_timer = event_idx(EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_SET_TIMER);
+        // every counter the Linux driver finds through `sbi_pmu_counter_get_info`, `time` included
+        let cmask = (1 << (NUM_COUNTERS + FIRMWARE_COUNTERS)) - 1;
+        assert_eq!(pmu.pmu_counter_config_matching(0, cmask, 0, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_config_matching(0, cmask, 0, set_timer, 0).value, NUM_COUNTERS);
+        // only the two bound counters take the initial value and start
+        assert_eq!(pmu.pmu_counter_start(0, cmask, SBI_PMU_START_FLAG_SET_INIT_VALUE, 9).error, 0);
+        assert_eq!(pmu.platform().read_counter(7), 9);
+        assert_eq!(pmu.pmu_counter_fw_read(NUM_COUNTERS).value, 9);
+        assert_eq!(pmu.platform().read_counter(3), 0);
+        assert_eq!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        let mut values = [0; usize::BITS as usize];
+        assert_eq!(pmu.pmu_counter_read_batch(0, cmask & 0xFF, &mut values).error, 0);
+        assert_eq!((values[COUNTER_TIME], values[7]), (MTIME, 9));
+        // `stop_all` of the Linux driver releases every counter at once
+        assert_eq!(pmu.pmu_counter_stop(0, cmask, SBI_PMU_STOP_FLAG_RESET).error, 0);
+        assert_ne!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        assert_eq!(pmu.pmu_counter_start(7, 1, 0, 0).error, SbiRet::invalid_param().error);
+        // with no counter in the set bound to an event, there is nothing to stop
+        assert_eq!(pmu.pmu_counter_stop(0, cmask, SBI_PMU_STOP_FLAG_RESET).error, SbiRet::invalid_param().error);
+    }
+
+    #[test]
+    fn time_counter_follows_platform() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let info = CounterInfo::from_raw(pmu.pmu_counter_get_info(COUNTER_TIME).value);
+        assert_eq!((info.csr(), info.bits(), info.is_firmware()), (CSR_TIME, 64, false));
+        // read directly through the `time` CSR once the hart is reset
+        assert_eq!(pmu.pmu_counter_fw_read(COUNTER_TIME).error, SbiRet::invalid_param().error);
+        pmu.pmu_reset(false);
+        assert_ne!(pmu.platform().read_mcounteren() & 1 << COUNTER_TIME, 0);
+
+        // without a usable `time` CSR, supervisor reads `mtime` through the firmware
+        let platform = Platform { time_csr: TimeCsr::Firmware, ..Platform::default() };
+        unsafe { platform.set_mcounteren(1 << COUNTER_TIME) };
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(platform);
+        pmu.pmu_reset(false);
+        assert_eq!(pmu.platform().read_mcounteren() & 1 << COUNTER_TIME, 0);
+        assert_eq!(pmu.pmu_counter_fw_read(COUNTER_TIME).value, MTIME as usize);
+        assert_eq!(pmu.pmu_counter_fw_read_hi(COUNTER_TIME).value, if usize::BITS == 32 { 1 } else { 0 });
+        // and still cannot start, stop or configure it
+        assert_eq!(pmu.pmu_counter_start(COUNTER_TIME, 1, 0, 0).error, SbiRet::invalid_param().error);
+    }
+
+    #[test]
+    fn frozen_counters_resume() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 7));
+        unsafe { pmu.platform().write_counter(7, 100) };
+        // frozen, the counter keeps its event and value
+        assert_eq!(pmu.pmu_counter_stop(7, 1, 0).error, 0);
+        assert_ne!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        assert_eq!(pmu.platform().read_mhpmevent(7), OWN_ENCODING);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, 0).error, SbiRet::already_stopped().error);
+        // and is started again as it is
+        assert_eq!(pmu.pmu_counter_start(7, 1, 0, 0).error, 0);
+        assert_eq!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        assert_eq!(pmu.platform().read_counter(7), 100);
+        let ret = pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0);
+        assert_eq!(ret.error, SbiRet::failed().error);
+    }
+
+    #[test]
+    fn released_counters_are_unbound() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        // a running counter is stopped and released at once
+        let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+        assert_eq!(pmu.pmu_counter_stop(ret.value, 1, SBI_PMU_STOP_FLAG_RESET).error, 0);
+        assert_ne!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        assert_eq!(pmu.platform().read_mhpmevent(7), 0);
+        assert_eq!(pmu.pmu_counter_start(7, 1, 0, 0).error, SbiRet::invalid_param().error);
+
+        // a frozen counter is released as well, reported already stopped
+        let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 7));
+        assert_eq!(pmu.pmu_counter_stop(7, 1, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_RESET).error, SbiRet::already_stopped().error);
+        assert_eq!(pmu.platform().read_mhpmevent(7), 0);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_RESET).error, SbiRet::invalid_param().error);
+        // and is free for the next event
+        let ret = pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0);
+        assert_eq!((ret.error, ret.value), (0, 7));
+    }
+
+    #[test]
+    fn config_sets_policies() {
+        let config = PmuConfig::new().stopped_read(StoppedRead::Frozen).hidden_read(HiddenRead::Emulate).multiplex(false);
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default()).with_config(&config);
+        assert_eq!((pmu.stopped_read, pmu.hidden_read, pmu.idle_timeout, pmu.multiplex), (StoppedRead::Frozen, HiddenRead::Emulate, None, false));
+        let ret = pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0);
+        assert_eq!(pmu.pmu_counter_stop(ret.value, 1, 0).error, 0);
+        assert_eq!(pmu.platform().read_mcounteren() & 1 << ret.value, 0);
+        // with the only counter of the event in use, no multiplexed counter takes it
+        let every = ((1 << pmu.num_counters()) - 1) & !(1 << COUNTER_TIME);
+        assert_eq!(pmu.pmu_counter_config_matching(0, every, 0, cache_misses(), 0).error, SbiRet::failed().error);
+    }
+
+    // snapshot shared memory, aligned to its size as `sbi_pmu_snapshot_set_shmem` requires
+    #[repr(C, align(4096))]
+    struct Shmem([u64; SNAPSHOT_AREA_SIZE / 8]);
+
+    #[test]
+    fn counters_start_from_snapshot() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let set_timer = event_idx(EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_SET_TIMER);
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_config_matching(NUM_COUNTERS, 1, 0, set_timer, 0).value, NUM_COUNTERS);
+        let flags = SBI_PMU_START_FLAG_INIT_SNAPSHOT | SBI_PMU_START_FLAG_SET_INIT_VALUE;
+        assert_eq!(pmu.pmu_counter_start(7, 0b11, flags, 5).error, SbiRet::no_shmem().error);
+        // counter values follow the overflow bitmap, relative to the counter index base
+        let mut shmem = Box::new(Shmem([0; SNAPSHOT_AREA_SIZE / 8]));
+        shmem.0[1] = 1000;
+        shmem.0[2] = 2000;
+        let shmem_phys = shmem.0.as_ptr() as usize;
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem_phys, 0, 0).error, 0);
+        // values in shared memory take precedence over `initial_value`
+        assert_eq!(pmu.pmu_counter_start(7, 0b11, flags, 5).error, 0);
+        assert_eq!(pmu.platform().read_counter(7), 1000);
+        assert_eq!(pmu.pmu_counter_fw_read(NUM_COUNTERS).value, 2000);
+    }
+
+    #[test]
+    fn stop_snapshots_stopped_counters() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let set_timer = event_idx(EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_SET_TIMER);
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_config_matching(NUM_COUNTERS, 1, 0, set_timer, 0).value, NUM_COUNTERS);
+        assert_eq!(pmu.pmu_counter_start(7, 0b11, SBI_PMU_START_FLAG_SET_INIT_VALUE, 42).error, 0);
+        assert_eq!(pmu.pmu_counter_stop(7, 0b11, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT).error, SbiRet::no_shmem().error);
+        let shmem = Box::new(Shmem([0; SNAPSHOT_AREA_SIZE / 8]));
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem.0.as_ptr() as usize, 0, 0).error, 0);
+        // the firmware counter is stopped first, like the Linux driver does on overflow
+        assert_eq!(pmu.pmu_counter_stop(NUM_COUNTERS, 1, 0).error, 0);
+        // the running counter is stopped and the whole set is written, then the stopped one is reported
+        let ret = pmu.pmu_counter_stop(7, 0b11, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT);
+        assert_eq!(ret.error, SbiRet::already_stopped().error);
+        assert_ne!(pmu.platform().read_mcountinhibit() & 1 << 7, 0);
+        let values = unsafe { (read_volatile(&shmem.0[1]), read_volatile(&shmem.0[2])) };
+        assert_eq!(values, (42, 42));
+    }
+
+    #[test]
+    fn polled_overflow_follows_snapshot_base() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform { sscofpmf: false, ..Platform::default() });
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0).value, 7);
+        let shmem = Box::new(Shmem([0; SNAPSHOT_AREA_SIZE / 8]));
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem.0.as_ptr() as usize, 0, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_start(7, 1, 0, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT).error, 0);
+        assert_eq!(pmu.pmu_counter_start(7, 1, 0, 0).error, 0);
+        // the counter wraps around between two polls
+        unsafe { pmu.platform().write_counter(7, u64::MAX) };
+        pmu.pmu_poll_overflow();
+        unsafe { pmu.platform().write_counter(7, 5) };
+        assert_eq!(pmu.pmu_poll_overflow(), 1 << 7);
+        // the bitmap in shared memory is relative to the base of the last snapshot, like the snapshot itself
+        assert_eq!(unsafe { read_volatile(&shmem.0[0]) }, 1);
+    }
+
+    #[test]
+    fn snapshot_rejects_bad_shmem() {
+        crate::shmem::init_test_shmem();
+        let shmem = Box::new(Shmem([0; SNAPSHOT_AREA_SIZE / 8]));
+        let shmem_phys = shmem.0.as_ptr() as usize;
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        // not aligned to the size of the shared memory
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem_phys + 8, 0, 0).error, SbiRet::invalid_param().error);
+        // above XLEN bits
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem_phys, 1, 0).error, SbiRet::invalid_address().error);
+        // memory hidden from supervisor, checked like any other buffer supervisor passes to RustSBI
+        let hidden = crate::shmem::TEST_HIDDEN.start;
+        assert_eq!(pmu.pmu_snapshot_set_shm(hidden, 0, 0).error, SbiRet::invalid_address().error);
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT).error, SbiRet::no_shmem().error);
+    }
+
+    #[test]
+    fn snapshot_shmem_disabled_and_forgotten() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let shmem = Box::new(Shmem([0; SNAPSHOT_AREA_SIZE / 8]));
+        let shmem_phys = shmem.0.as_ptr() as usize;
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem_phys, 0, 0).error, 0);
+        // the disable form takes no flags either
+        assert_eq!(pmu.pmu_snapshot_set_shm(usize::MAX, usize::MAX, 1).error, SbiRet::invalid_param().error);
+        assert_eq!(pmu.pmu_snapshot_set_shm(usize::MAX, usize::MAX, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT).error, SbiRet::no_shmem().error);
+        // the next supervisor on this hart finds no shared memory of the previous one
+        assert_eq!(pmu.pmu_snapshot_set_shm(shmem_phys, 0, 0).error, 0);
+        pmu.pmu_reset(false);
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, SBI_PMU_CFG_FLAG_AUTO_START, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_stop(7, 1, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT).error, SbiRet::no_shmem().error);
+    }
+
+    #[test]
+    fn counter_index_space() {
+        let pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let num_counters = pmu.pmu_num_counters().value;
+        assert_eq!(num_counters, NUM_COUNTERS + FIRMWARE_COUNTERS + MULTIPLEX_COUNTERS);
+        // hardware counters first, each read through the counter CSR of its own index, then firmware counters
+        for idx in 0..num_counters {
+            let info = CounterInfo::from_raw(pmu.pmu_counter_get_info(idx).value);
+            if idx < NUM_COUNTERS {
+                assert!(!info.is_firmware() && info.csr() == 0xC00 + idx, "counter {}", idx);
+            } else {
+                assert!(info.is_firmware(), "counter {}", idx);
+            }
+        }
+        assert_eq!(pmu.pmu_counter_get_info(num_counters).error, SbiRet::invalid_param().error);
+    }
+
+    #[test]
+    #[should_panic(expected = "counters 3 and 4 are both read through CSR 0xc03")]
+    fn aliased_counter_csrs_rejected() {
+        let counter_csr = |counter_idx: usize| 0xC00 + counter_idx.min(3);
+        GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform { counter_csr, ..Platform::default() });
+    }
+
+    #[test]
+    fn hardware_flags_on_firmware_counters() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let set_timer = event_idx(EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_SET_TIMER);
+        // matching is skipped onto a counter of the wrong kind, both ways
+        let skip_match = SBI_PMU_CFG_FLAG_SKIP_MATCH;
+        assert_eq!(pmu.pmu_counter_config_matching(7, 1, skip_match, set_timer, 0).error, SbiRet::invalid_param().error);
+        let ret = pmu.pmu_counter_config_matching(NUM_COUNTERS, 1, skip_match, cache_misses(), 0);
+        assert_eq!(ret.error, SbiRet::invalid_param().error);
+        // privilege filters do not apply to firmware counters, which count the event anyway
+        let flags = SBI_PMU_CFG_FLAG_SET_SINH | SBI_PMU_CFG_FLAG_SET_UINH | SBI_PMU_CFG_FLAG_AUTO_START;
+        let ret = pmu.pmu_counter_config_matching(NUM_COUNTERS, 1, flags, set_timer, 0);
+        assert_eq!(ret.error, 0);
+        let fw_idx = ret.value;
+        assert_eq!(fw_idx, NUM_COUNTERS);
+        pmu.pmu_firmware_event(events::SBI_PMU_FW_SET_TIMER);
+        assert_eq!(pmu.pmu_counter_fw_read(fw_idx).value, 1);
+        // the firmware counter and the hardware counter below it start together, from the same initial value
+        assert_eq!(pmu.pmu_counter_config_matching(0, ALL, 0, cache_misses(), 0).value, 7);
+        assert_eq!(pmu.pmu_counter_stop(fw_idx, 1, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_start(7, 0b11, SBI_PMU_START_FLAG_SET_INIT_VALUE, 100).error, 0);
+        assert_eq!(pmu.platform().read_counter(7), 100);
+        assert_eq!(pmu.pmu_counter_fw_read(fw_idx).value, 100);
+        // hardware counters are not read through the firmware, whatever their value
+        assert_eq!(pmu.pmu_counter_fw_read(7).error, SbiRet::invalid_param().error);
+    }
+
+    #[test]
+    fn firmware_counters_count_from_initial_value() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        let set_timer = event_idx(EVENT_TYPE_FIRMWARE, events::SBI_PMU_FW_SET_TIMER);
+        let fw_idx = pmu.pmu_counter_config_matching(NUM_COUNTERS, 1, 0, set_timer, 0).value;
+        // the whole 64-bit value, from its lower and upper halves on RV32
+        let fw_value = |pmu: &GenericPmu<Platform, NUM_COUNTERS>| {
+            let lo = pmu.pmu_counter_fw_read(fw_idx).value as u64;
+            let hi = pmu.pmu_counter_fw_read_hi(fw_idx).value as u64;
+            if cfg!(target_pointer_width = "32") {
+                hi << 32 | lo
+            } else {
+                assert_eq!(hi, 0);
+                lo
+            }
+        };
+        // counting carries into the upper half, which RV32 reads through `sbi_pmu_counter_fw_read_hi`
+        let initial_value = u32::MAX as u64;
+        assert_eq!(pmu.pmu_counter_start(fw_idx, 1, SBI_PMU_START_FLAG_SET_INIT_VALUE, initial_value).error, 0);
+        pmu.pmu_firmware_event(events::SBI_PMU_FW_SET_TIMER);
+        pmu.pmu_firmware_event(events::SBI_PMU_FW_SET_TIMER);
+        assert_eq!(fw_value(&pmu), initial_value + 2);
+        #[cfg(target_pointer_width = "32")]
+        {
+            assert_eq!(pmu.pmu_counter_fw_read(fw_idx).value, 1);
+            assert_eq!(pmu.pmu_counter_fw_read_hi(fw_idx).value, 1);
+        }
+        // without the flag, the counter resumes from its value, and `initial_value` is ignored
+        assert_eq!(pmu.pmu_counter_stop(fw_idx, 1, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_start(fw_idx, 1, 0, 5).error, 0);
+        pmu.pmu_firmware_event(events::SBI_PMU_FW_SET_TIMER);
+        assert_eq!(fw_value(&pmu), initial_value + 3);
+        // firmware counters are 64 bits wide and wrap around like hardware counters do
+        assert_eq!(pmu.pmu_counter_stop(fw_idx, 1, 0).error, 0);
+        assert_eq!(pmu.pmu_counter_start(fw_idx, 1, SBI_PMU_START_FLAG_SET_INIT_VALUE, u64::MAX).error, 0);
+        assert_eq!(fw_value(&pmu), u64::MAX);
+        pmu.pmu_firmware_event(events::SBI_PMU_FW_SET_TIMER);
+        assert_eq!(fw_value(&pmu), 0);
+    }
+
+    #[test]
+    fn known_encodings_are_bounded() {
+        let mut pmu = GenericPmu::<_, NUM_COUNTERS>::new_bounded(Platform::default());
+        for selector in 0..(MAX_KNOWN_ENCODINGS + 10) as u64 {
+            pmu.remember_encoding(selector, selector % 2 == 0);
+        }
+        assert_eq!(pmu.encodings.len(), MAX_KNOWN_ENCODINGS);
+        // the oldest encodings are forgotten, and are read back again when written next time
+        assert_eq!(pmu.encoding_sticks(9), None);
+        assert_eq!(pmu.encoding_sticks(10), Some(true));
+        assert_eq!(pmu.encoding_sticks(11), Some(false));
+        // an encoding is remembered once
+        pmu.remember_encoding(11, true);
+        assert_eq!(pmu.encodings.len(), MAX_KNOWN_ENCODINGS);
+        assert_eq!(pmu.encoding_sticks(11), Some(true));
+    }
+}
@@ -0,0 +1,103 @@
+//! Virtual stop of the `cycle` and `instret` counters
+//!
+//! The `mcountinhibit` bits of `cycle` and `instret` may be hardwired to zero, leaving both counters
+//! running all the time. Supervisor still expects a stopped counter to keep its value, and a started
+//! counter to count from the value it was started with. For such counters the hardware value is left
+//! alone: the value seen by supervisor is kept while the counter is stopped, and is the hardware value
+//! minus an offset while it is running.
+//!
+//! Supervisor reads hardware counters directly through CSRs, which bypasses the offset; platforms
+//! virtualizing these counters clear their `mcounteren` bits, so that supervisor reads them through
+//! `sbi_pmu_counter_fw_read` instead.
+
+use super::{read_counter, PmuPlatform, COUNTER_CYCLE, COUNTER_INSTRET};
+
+const FIXED_COUNTERS: usize = 1 << COUNTER_CYCLE | 1 << COUNTER_INSTRET;
+
+#[derive(Default)]
+pub(super) struct FixedCounters {
+    // counters which can not be stopped by hardware, virtualized on this hart
+    virtualized: usize,
+    // virtualized counters stopped by supervisor, the virtual `mcountinhibit` bits
+    stopped: usize,
+    // hardware value minus the value seen by supervisor, for running counters
+    offsets: [u64; COUNTER_INSTRET + 1],
+    // value seen by supervisor, for stopped counters
+    frozen: [u64; COUNTER_INSTRET + 1],
+}
+
+impl FixedCounters {
+    pub fn new(virtualized: usize) -> FixedCounters {
+        FixedCounters {
+            virtualized,
+            ..FixedCounters::default()
+        }
+    }
+
+    // `mcountinhibit` with virtual bits in place of the hardwired ones
+    pub fn inhibit<P: PmuPlatform>(&self, platform: &P) -> usize {
+        platform.read_mcountinhibit() & !self.virtualized | self.stopped
+    }
+
+    pub unsafe fn set_inhibit<P: PmuPlatform>(&mut self, platform: &P, bits: usize) {
+        let hardware = bits & !self.virtualized;
+        if hardware != 0 {
+            platform.set_mcountinhibit(hardware);
+        }
+        for idx in indexes(bits & self.virtualized & !self.stopped) {
+            self.frozen[idx] = self.read(platform, idx);
+        }
+        self.stopped |= bits & self.virtualized;
+    }
+
+    pub unsafe fn clear_inhibit<P: PmuPlatform>(&mut self, platform: &P, bits: usize) {
+        for idx in indexes(bits & self.stopped) {
+            self.offsets[idx] = read_counter(platform, idx).wrapping_sub(self.frozen[idx]);
+        }
+        self.stopped &= !bits;
+        let hardware = bits & !self.virtualized;
+        if hardware != 0 {
+            platform.clear_mcountinhibit(hardware);
+        }
+    }
+
+    // value of a hardware counter as seen by supervisor
+    pub fn read<P: PmuPlatform>(&self, platform: &P, counter_idx: usize) -> u64 {
+        if self.virtualized & (1 << counter_idx) == 0 {
+            return read_counter(platform, counter_idx);
+        }
+        if self.stopped & (1 << counter_idx) != 0 {
+            return self.frozen[counter_idx];
+        }
+        let value = read_counter(platform, counter_idx).wrapping_sub(self.offsets[counter_idx]);
+        match platform.counter_width(counter_idx) {
+            width if width >= u64::BITS => value,
+            width => value & ((1 << width) - 1),
+        }
+    }
+
+    pub unsafe fn write<P: PmuPlatform>(&mut self, platform: &P, counter_idx: usize, value: u64) {
+        if self.virtualized & (1 << counter_idx) == 0 {
+            platform.write_counter(counter_idx, value);
+        } else if self.stopped & (1 << counter_idx) != 0 {
+            self.frozen[counter_idx] = value;
+        } else {
+            self.offsets[counter_idx] = read_counter(platform, counter_idx).wrapping_sub(value);
+        }
+    }
+}
+
+// counter indexes of `cycle` and `instret` in `bits`
+fn indexes(bits: usize) -> impl Iterator<Item = usize> {
+    [COUNTER_CYCLE, COUNTER_INSTRET].iter().copied().filter(move |&idx| bits & (1 << idx) != 0)
+}
+
+// `cycle` and `instret` counters whose `mcountinhibit` bits are hardwired to zero; the bits are set,
+// read back, and cleared again if they were clear before
+pub(super) fn probe_free_running<P: PmuPlatform>(platform: &P) -> usize {
+    let before = platform.read_mcountinhibit();
+    unsafe { platform.set_mcountinhibit(FIXED_COUNTERS) };
+    let kept = platform.read_mcountinhibit();
+    unsafe { platform.clear_mcountinhibit(FIXED_COUNTERS & !before) };
+    FIXED_COUNTERS & !kept
+}
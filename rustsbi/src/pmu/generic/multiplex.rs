@@ -0,0 +1,232 @@
+//! Time multiplexing of hardware events over programmable counters
+//!
+//! Multiplexed counters are logical counters without hardware of their own. Started multiplexed
+//! counters take turns on programmable hardware counters which are not bound to any event directly;
+//! the platform rotates them by calling `rotate_pmu_multiplex` periodically. Each counter remembers how
+//! many rotations it was started and how many of them it was running on hardware, and reports its value
+//! scaled by the ratio of the two, like Linux `perf` does for multiplexed events.
+//!
+//! Supervisor reads hardware counters directly through CSRs, so a hardware counter bound by
+//! `sbi_pmu_counter_config_matching` is never taken away; multiplexed counters are reported as
+//! firmware counters instead and read through `sbi_pmu_counter_fw_read`.
+
+use super::{read_counter, PmuPlatform, FIRST_HPM_COUNTER, MULTIPLEX_COUNTERS};
+
+#[derive(Default)]
+pub(super) struct Multiplexer {
+    // event bound to each multiplexed counter, `None` if the counter is free
+    events: [Option<Event>; MULTIPLEX_COUNTERS],
+    started: usize,
+    // hardware counter each multiplexed counter is running on, `None` if it is waiting for its turn
+    slots: [Option<usize>; MULTIPLEX_COUNTERS],
+    // value set on start, not scaled
+    initial_values: [u64; MULTIPLEX_COUNTERS],
+    // events counted in finished turns
+    values: [u64; MULTIPLEX_COUNTERS],
+    // rotations while the counter was started, and while it was running on hardware
+    enabled: [u64; MULTIPLEX_COUNTERS],
+    running: [u64; MULTIPLEX_COUNTERS],
+    // multiplexed counter to be scheduled first on next rotation
+    next: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Event {
+    event_idx: usize,
+    event_data: u64,
+    mhpmevent: u64,
+}
+
+impl Multiplexer {
+    pub(super) fn is_bound(&self, mux_idx: usize) -> bool {
+        self.events[mux_idx].is_some()
+    }
+
+    pub(super) fn started(&self) -> usize {
+        self.started
+    }
+
+    pub(super) fn event_idx(&self, mux_idx: usize) -> Option<usize> {
+        self.events[mux_idx].map(|event| event.event_idx)
+    }
+
+    // `mhpmevent` written into the hardware counter for the multiplexed counter running on it, `None` if none is
+    pub(super) fn mhpmevent(&self, counter_idx: usize) -> Option<u64> {
+        let mux_idx = self.slots.iter().position(|&slot| slot == Some(counter_idx))?;
+        self.events[mux_idx].as_ref().map(|event| event.mhpmevent)
+    }
+
+    // hardware counter the counter is running on, `None` if it is stopped or waiting for its turn
+    pub(super) fn slot(&self, mux_idx: usize) -> Option<usize> {
+        self.slots[mux_idx]
+    }
+
+    // bind the counter to an event; `mhpmevent` is the value to be written into `mhpmeventX`
+    pub(super) fn bind<P: PmuPlatform>(&mut self, platform: &P, mux_idx: usize, event_idx: usize, event_data: u64, mhpmevent: u64, clear_value: bool) {
+        self.unschedule(platform, 1 << mux_idx);
+        self.events[mux_idx] = Some(Event {
+            event_idx,
+            event_data,
+            mhpmevent,
+        });
+        if clear_value {
+            self.reset_value(mux_idx, 0);
+        }
+    }
+
+    pub(super) fn unbind(&mut self, mux_idx: usize) {
+        self.events[mux_idx] = None;
+    }
+
+    // start all counters in `mux_bits`; the hardware counters they are scheduled on are left stopped and
+    // returned, so that the caller starts them with a single write of `mcountinhibit` along with other counters
+    pub(super) fn start<P: PmuPlatform>(&mut self, platform: &P, bound: &[Option<usize>], mux_bits: usize) -> usize {
+        self.started |= mux_bits;
+        let (_, scheduled) = self.place(platform, bound);
+        scheduled
+    }
+
+    // hardware counters the counters in `mux_bits` are running on
+    pub(super) fn hardware_bits(&self, mux_bits: usize) -> usize {
+        (0..MULTIPLEX_COUNTERS)
+            .filter(|&mux_idx| mux_bits & (1 << mux_idx) != 0)
+            .filter_map(|mux_idx| self.slots[mux_idx])
+            .fold(0usize, |bits, idx| bits | 1 << idx)
+    }
+
+    // stop all counters in `mux_bits`, their hardware counters are stopped with a single write of `mcountinhibit`
+    pub(super) fn stop<P: PmuPlatform>(&mut self, platform: &P, bound: &[Option<usize>], mux_bits: usize) {
+        self.unschedule(platform, mux_bits);
+        self.started &= !mux_bits;
+        // give the hardware counters to waiting multiplexed counters at once
+        self.schedule(platform, bound);
+    }
+
+    // current value of the counter, scaled by the fraction of rotations it was running on hardware
+    pub(super) fn value<P: PmuPlatform>(&self, platform: &P, mux_idx: usize) -> u64 {
+        let mut counted = self.values[mux_idx];
+        if let Some(counter_idx) = self.slots[mux_idx] {
+            counted = counted.wrapping_add(read_counter(platform, counter_idx));
+        }
+        let (enabled, running) = (self.enabled[mux_idx], self.running[mux_idx]);
+        if running != 0 && running != enabled {
+            counted = (counted as u128 * enabled as u128 / running as u128) as u64;
+        }
+        self.initial_values[mux_idx].wrapping_add(counted)
+    }
+
+    // the hardware counter is about to be bound directly, move its multiplexed counter off it
+    pub(super) fn release<P: PmuPlatform>(&mut self, platform: &P, counter_idx: usize) {
+        if let Some(mux_idx) = self.slots.iter().position(|&slot| slot == Some(counter_idx)) {
+            self.unschedule(platform, 1 << mux_idx);
+        }
+    }
+
+    // end the current turn of all running counters, then schedule counters starting from the first one
+    // that had to wait, so that every started counter gets its turn
+    pub(super) fn rotate<P: PmuPlatform>(&mut self, platform: &P, bound: &[Option<usize>]) {
+        let mut running = 0;
+        for (mux_idx, slot) in self.slots.iter().enumerate() {
+            if self.started & (1 << mux_idx) == 0 {
+                continue;
+            }
+            self.enabled[mux_idx] += 1;
+            if slot.is_some() {
+                self.running[mux_idx] += 1;
+                running |= 1 << mux_idx;
+            }
+        }
+        self.unschedule(platform, running);
+        if let Some(waiting) = self.schedule(platform, bound) {
+            self.next = waiting;
+        }
+    }
+
+    // end the turns of all running counters, keeping what they counted, before the hart loses its counter CSRs
+    pub(super) fn suspend<P: PmuPlatform>(&mut self, platform: &P) {
+        self.unschedule(platform, self.started);
+    }
+
+    // end the turns of all running counters without reading their hardware counters, which were lost
+    pub(super) fn forget_turns(&mut self) {
+        self.slots = [None; MULTIPLEX_COUNTERS];
+    }
+
+    // program started counters into free programmable counters again after `suspend` or `forget_turns`; the
+    // hardware counters are left stopped and returned, as `start` does
+    pub(super) fn resume<P: PmuPlatform>(&mut self, platform: &P, bound: &[Option<usize>]) -> usize {
+        let (_, scheduled) = self.place(platform, bound);
+        scheduled
+    }
+
+    // set the value of a stopped counter, which starts counting from it
+    pub(super) fn reset_value(&mut self, mux_idx: usize, value: u64) {
+        self.initial_values[mux_idx] = value;
+        self.values[mux_idx] = 0;
+        self.enabled[mux_idx] = 0;
+        self.running[mux_idx] = 0;
+    }
+
+    // run waiting counters on free programmable counters, returns the first counter still waiting;
+    // `bound` holds events bound directly to each hardware counter
+    fn schedule<P: PmuPlatform>(&mut self, platform: &P, bound: &[Option<usize>]) -> Option<usize> {
+        let (waiting, scheduled) = self.place(platform, bound);
+        if scheduled != 0 {
+            unsafe { platform.clear_mcountinhibit(scheduled) };
+        }
+        waiting
+    }
+
+    // program waiting counters into free programmable counters without starting them, returns the first
+    // counter still waiting and the hardware counters programmed
+    fn place<P: PmuPlatform>(&mut self, platform: &P, bound: &[Option<usize>]) -> (Option<usize>, usize) {
+        let mut scheduled = 0;
+        let mut busy = self.slots.iter().flatten().fold(0usize, |bits, &idx| bits | 1 << idx);
+        let mut waiting = None;
+        for mux_idx in (self.next..MULTIPLEX_COUNTERS).chain(0..self.next) {
+            if self.started & (1 << mux_idx) == 0 || self.slots[mux_idx].is_some() {
+                continue;
+            }
+            let event = match self.events[mux_idx] {
+                Some(event) => event,
+                None => continue,
+            };
+            let free = (FIRST_HPM_COUNTER..bound.len()).find(|&idx| {
+                bound[idx].is_none()
+                    && busy & (1 << idx) == 0
+                    && platform.counter_can_monitor(idx, event.event_idx, event.event_data)
+            });
+            let counter_idx = match free {
+                Some(idx) => idx,
+                None => {
+                    waiting.get_or_insert(mux_idx);
+                    continue;
+                }
+            };
+            busy |= 1 << counter_idx;
+            scheduled |= 1 << counter_idx;
+            unsafe {
+                platform.set_mcountinhibit(1 << counter_idx);
+                platform.write_mhpmevent(counter_idx, event.mhpmevent);
+                platform.write_counter(counter_idx, 0);
+            }
+            self.slots[mux_idx] = Some(counter_idx);
+        }
+        (waiting, scheduled)
+    }
+
+    // stop the turns of the counters in `mux_bits` and collect events counted on hardware
+    fn unschedule<P: PmuPlatform>(&mut self, platform: &P, mux_bits: usize) {
+        let inhibit = self.hardware_bits(mux_bits);
+        if inhibit == 0 {
+            return;
+        }
+        unsafe { platform.set_mcountinhibit(inhibit) };
+        for mux_idx in (0..MULTIPLEX_COUNTERS).filter(|&mux_idx| mux_bits & (1 << mux_idx) != 0) {
+            if let Some(counter_idx) = self.slots[mux_idx].take() {
+                self.values[mux_idx] = self.values[mux_idx].wrapping_add(read_counter(platform, counter_idx));
+                unsafe { platform.write_mhpmevent(counter_idx, 0) };
+            }
+        }
+    }
+}
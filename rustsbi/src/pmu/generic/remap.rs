@@ -0,0 +1,151 @@
+//! Counter indexes presented to supervisor, remapped onto physical counters
+//!
+//! `GenericPmu` numbers hardware counters from zero without gaps, and so does supervisor. When firmware keeps
+//! some programmable counters for itself, the logical counters `GenericPmu` works with are backed by the
+//! remaining physical counters in order; every counter index, and every bit of `mcountinhibit` and `mcounteren`,
+//! is translated on the way to the platform.
+
+use super::{PmuPlatform, TimeCsr, FIRST_HPM_COUNTER, MAX_HARDWARE_COUNTERS};
+use alloc::vec::Vec;
+
+/// Platform description presenting a dense range of counters with some physical counters left out
+///
+/// Firmware may reserve programmable counters, for example to profile its own code, by wrapping the platform
+/// description before handing it to `GenericPmu`. Logical counter `i` is backed by the `i`-th physical counter
+/// not reserved; `cycle`, `time` and `instret` are never reserved and keep their indexes. Supervisor reads
+/// a hardware counter through the CSR of its physical counter, which `sbi_pmu_counter_get_info` reports.
+///
+/// ```no_run
+/// use rustsbi::pmu::{GenericPmu, RemappedPlatform};
+///
+/// // physical counter 3 counts for the firmware; supervisor sees counter 3 backed by physical counter 4
+/// rustsbi::init_pmu(GenericPmu::new(RemappedPlatform::new(platform).reserve(3)));
+/// ```
+pub struct RemappedPlatform<P> {
+    inner: P,
+    // physical counter backing each logical counter
+    physical: Vec<usize>,
+}
+
+impl<P: PmuPlatform> RemappedPlatform<P> {
+    /// Present every counter of `inner` under its own index; leave counters out with `reserve`.
+    pub fn new(inner: P) -> RemappedPlatform<P> {
+        let num_counters = inner.num_counters().min(MAX_HARDWARE_COUNTERS);
+        RemappedPlatform {
+            inner,
+            physical: (0..num_counters).collect(),
+        }
+    }
+
+    /// Keep physical counter `counter_idx` out of reach of supervisor; logical counters after it are backed
+    /// by the next physical counter.
+    ///
+    /// # Panics
+    ///
+    /// If `counter_idx` is not a programmable counter.
+    pub fn reserve(mut self, counter_idx: usize) -> Self {
+        assert!(counter_idx >= FIRST_HPM_COUNTER, "counter {} is not a programmable counter", counter_idx);
+        self.physical.retain(|&idx| idx != counter_idx);
+        self
+    }
+
+    /// Returns a reference to the wrapped platform description.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    // physical counter backing logical counter `counter_idx`
+    fn physical(&self, counter_idx: usize) -> usize {
+        self.physical[counter_idx]
+    }
+
+    // bits of physical counters backing the logical counters in `bits`
+    fn to_physical(&self, bits: usize) -> usize {
+        self.physical
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| bits & (1 << idx) != 0)
+            .fold(0, |physical, (_, &counter_idx)| physical | 1 << counter_idx)
+    }
+
+    // bits of logical counters backed by the physical counters in `bits`; bits of reserved counters are dropped
+    fn to_logical(&self, bits: usize) -> usize {
+        self.physical
+            .iter()
+            .enumerate()
+            .filter(|&(_, &counter_idx)| bits & (1 << counter_idx) != 0)
+            .fold(0, |logical, (idx, _)| logical | 1 << idx)
+    }
+}
+
+impl<P: PmuPlatform> PmuPlatform for RemappedPlatform<P> {
+    fn num_counters(&self) -> usize {
+        self.physical.len()
+    }
+    fn has_sscofpmf(&self) -> bool {
+        self.inner.has_sscofpmf()
+    }
+    fn has_hypervisor(&self) -> bool {
+        self.inner.has_hypervisor()
+    }
+    fn counter_can_monitor(&self, counter_idx: usize, event_idx: usize, event_data: u64) -> bool {
+        self.inner.counter_can_monitor(self.physical(counter_idx), event_idx, event_data)
+    }
+    fn mhpmevent_value(&self, event_idx: usize, event_data: u64) -> u64 {
+        self.inner.mhpmevent_value(event_idx, event_data)
+    }
+    fn raw_event_v2(&self) -> bool {
+        self.inner.raw_event_v2()
+    }
+    fn has_mcountinhibit(&self) -> bool {
+        self.inner.has_mcountinhibit()
+    }
+    fn hart_id(&self) -> usize {
+        self.inner.hart_id()
+    }
+    fn read_mcounteren(&self) -> usize {
+        self.to_logical(self.inner.read_mcounteren())
+    }
+    unsafe fn set_mcounteren(&self, bits: usize) {
+        self.inner.set_mcounteren(self.to_physical(bits))
+    }
+    unsafe fn clear_mcounteren(&self, bits: usize) {
+        self.inner.clear_mcounteren(self.to_physical(bits))
+    }
+    fn time_csr(&self) -> TimeCsr {
+        self.inner.time_csr()
+    }
+    fn read_mtime(&self) -> u64 {
+        self.inner.read_mtime()
+    }
+    fn shmem_accessible(&self, shmem_phys: usize, size: usize) -> bool {
+        self.inner.shmem_accessible(shmem_phys, size)
+    }
+    fn read_mcountinhibit(&self) -> usize {
+        self.to_logical(self.inner.read_mcountinhibit())
+    }
+    unsafe fn set_mcountinhibit(&self, bits: usize) {
+        self.inner.set_mcountinhibit(self.to_physical(bits))
+    }
+    unsafe fn clear_mcountinhibit(&self, bits: usize) {
+        self.inner.clear_mcountinhibit(self.to_physical(bits))
+    }
+    fn counter_csr(&self, counter_idx: usize) -> usize {
+        self.inner.counter_csr(self.physical(counter_idx))
+    }
+    fn counter_width(&self, counter_idx: usize) -> u32 {
+        self.inner.counter_width(self.physical(counter_idx))
+    }
+    fn read_counter(&self, counter_idx: usize) -> u64 {
+        self.inner.read_counter(self.physical(counter_idx))
+    }
+    unsafe fn write_counter(&self, counter_idx: usize, value: u64) {
+        self.inner.write_counter(self.physical(counter_idx), value)
+    }
+    fn read_mhpmevent(&self, counter_idx: usize) -> u64 {
+        self.inner.read_mhpmevent(self.physical(counter_idx))
+    }
+    unsafe fn write_mhpmevent(&self, counter_idx: usize, value: u64) {
+        self.inner.write_mhpmevent(self.physical(counter_idx), value)
+    }
+}
@@ -0,0 +1,114 @@
+//! PMU recording every call, for host-side tests
+//!
+//! Tests share the PMU registered with RustSBI, so each of them registers a fresh `MockPmu` through
+//! `with_mock_pmu`, which also keeps the tests from running at the same time.
+use super::{EventInfo, Pmu, WatchNotify, PMU};
+use crate::ecall::SbiRet;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+/// A PMU call with its parameters, as `MockPmu` received it
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Call {
+    NumCounters,
+    CounterGetInfo(usize),
+    ConfigMatching(usize, usize, usize, usize, u64),
+    Start(usize, usize, usize, u64),
+    Stop(usize, usize, usize),
+    FwRead(usize),
+    FwReadHi(usize),
+    SnapshotSetShm(usize, usize, usize),
+    // length of each part of the list of queries
+    EventGetInfo(usize),
+    Watch(usize, u64, WatchNotify),
+    // event index and number of harts; the value of each hart is its hart ID
+    FirmwareEventTotal(usize, usize),
+    Capabilities,
+    // `event_data` and value of a platform specific firmware event
+    PlatformEvent(u64, u64),
+}
+
+/// PMU answering every call with the same `SbiRet`, recording the calls
+pub(crate) struct MockPmu {
+    ret: (usize, usize),
+    calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl MockPmu {
+    fn record(&self, call: Call) -> SbiRet {
+        self.calls.lock().push(call);
+        SbiRet {
+            error: self.ret.0,
+            value: self.ret.1,
+        }
+    }
+}
+
+impl Pmu for MockPmu {
+    fn pmu_num_counters(&self) -> SbiRet {
+        self.record(Call::NumCounters)
+    }
+    fn pmu_counter_get_info(&self, counter_idx: usize) -> SbiRet {
+        self.record(Call::CounterGetInfo(counter_idx))
+    }
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        self.record(Call::ConfigMatching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data))
+    }
+    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+        self.record(Call::Start(counter_idx_base, counter_idx_mask, start_flags, initial_value))
+    }
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+        self.record(Call::Stop(counter_idx_base, counter_idx_mask, stop_flags))
+    }
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet {
+        self.record(Call::FwRead(counter_idx))
+    }
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        self.record(Call::FwReadHi(counter_idx))
+    }
+    fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+        self.record(Call::SnapshotSetShm(shmem_phys_lo, shmem_phys_hi, flags))
+    }
+    fn pmu_event_get_info(&self, entries: &mut [EventInfo]) -> SbiRet {
+        self.record(Call::EventGetInfo(entries.len()))
+    }
+    fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: WatchNotify) -> SbiRet {
+        self.record(Call::Watch(counter_idx, threshold, notify))
+    }
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        for (hartid, value) in per_hart.iter_mut().enumerate() {
+            *value = hartid as u64;
+        }
+        self.record(Call::FirmwareEventTotal(event_idx, per_hart.len()))
+    }
+    fn pmu_capabilities(&self) -> usize {
+        self.record(Call::Capabilities).value
+    }
+    fn pmu_platform_event(&self, event_data: u64, value: u64) {
+        self.record(Call::PlatformEvent(event_data, value));
+    }
+}
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Register a fresh `MockPmu` answering every call with `ret`, run `f`, then return the calls it received.
+pub(crate) fn with_mock_pmu(ret: SbiRet, f: impl FnOnce()) -> Vec<Call> {
+    let _guard = LOCK.lock();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let mock = MockPmu {
+        ret: (ret.error, ret.value),
+        calls: calls.clone(),
+    };
+    *PMU.write() = Some(Box::new(mock));
+    f();
+    *PMU.write() = None;
+    let calls = calls.lock().clone();
+    calls
+}
+
+/// Run `f` with no PMU registered.
+pub(crate) fn without_pmu(f: impl FnOnce()) {
+    let _guard = LOCK.lock();
+    *PMU.write() = None;
+    f();
+}
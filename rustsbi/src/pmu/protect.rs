@@ -0,0 +1,273 @@
+//! Coarsening counter values of protected events against timing side channels
+
+use super::{current_hartid, EventIdx, Pmu, SnapshotArea, WatchNotify, SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT};
+use crate::ecall::{SbiRet, SBI_SUCCESS};
+use crate::index_mask::IndexMask;
+use alloc::vec::Vec;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// PMU implementation which coarsens values of protected events read by supervisor
+///
+/// Precise counts of cache misses, branch mispredictions or even cycles let software observe what other
+/// software on the same hart does. For each protected event, values of counters bound to it are rounded
+/// down to a multiple of `quantum`, then a random number below `jitter` is added, before they are returned
+/// by `sbi_pmu_counter_fw_read`, `sbi_pmu_counter_fw_read_hi` and the batch read call, or written into
+/// the snapshot shared memory. Events are protected per event index; hardware raw events are all
+/// protected together, whatever their `event_data`. Values with jitter added are no longer monotonic.
+///
+/// Events are protected by the platform with `protect` before the PMU is initialized. Supervisor may
+/// opt in more events, or coarsen protected ones further, through the event protection call of the firmware
+/// specific extension of RustSBI (EID `0x0A000004`, FID `3`), but only if the platform allowed it with
+/// `armed`; otherwise the call returns `SBI_ERR_DENIED`. Protection is never weakened once set.
+///
+/// Supervisor also reads hardware counters directly through counter CSRs; the platform clears `mcounteren`
+/// bits of hardware counters which may count protected events, so that they are read through SBI calls only.
+///
+/// ```no_run
+/// use rustsbi::pmu::{EventIdx, GenericPmu, ProtectedPmu, EVENT_TYPE_HARDWARE_RAW};
+///
+/// // hardware raw events are counted in steps of 1024, supervisor may protect other events
+/// let raw = EventIdx::from_parts(EVENT_TYPE_HARDWARE_RAW, 0).raw();
+/// rustsbi::init_pmu(ProtectedPmu::new(GenericPmu::new(platform)).protect(raw, 1024, 0).with_seed(mcycle::read()).armed());
+/// ```
+pub struct ProtectedPmu<T> {
+    inner: T,
+    armed: bool,
+    rules: Vec<Rule>,
+    harts: Vec<HartBindings>,
+    // state of the random number generator of jitter
+    noise: AtomicUsize,
+}
+
+#[derive(Clone, Copy)]
+struct Rule {
+    event_idx: usize,
+    quantum: u64,
+    jitter: u64,
+}
+
+#[derive(Default)]
+struct HartBindings {
+    // event bound to each counter by supervisor, indexed by counter index
+    events: Vec<Option<usize>>,
+    // physical address of the snapshot shared memory
+    snapshot: Option<usize>,
+}
+
+impl<T: Pmu> ProtectedPmu<T> {
+    /// Protect no event on `inner`; protect them with `protect`, and let supervisor protect them with `armed`.
+    #[inline]
+    pub fn new(inner: T) -> ProtectedPmu<T> {
+        ProtectedPmu {
+            inner,
+            armed: false,
+            rules: Vec::new(),
+            harts: Vec::new(),
+            noise: AtomicUsize::new(0),
+        }
+    }
+    /// Round values of `event_idx` down to a multiple of `quantum`, and add a random number below `jitter`.
+    ///
+    /// Zero leaves out the rounding or the jitter. If the event is already protected, the larger quantum
+    /// and jitter are kept.
+    pub fn protect(mut self, event_idx: usize, quantum: u64, jitter: u64) -> ProtectedPmu<T> {
+        self.add_rule(event_idx, quantum, jitter);
+        self
+    }
+    /// Allow supervisor to protect events through the event protection call.
+    #[inline]
+    pub fn armed(mut self) -> ProtectedPmu<T> {
+        self.armed = true;
+        self
+    }
+    /// Seed the random number generator of jitter, for example with a value of `mcycle` or an entropy source.
+    #[inline]
+    pub fn with_seed(self, seed: usize) -> ProtectedPmu<T> {
+        self.noise.store(seed, Ordering::Relaxed);
+        self
+    }
+    /// The wrapped PMU implementation.
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+    fn add_rule(&mut self, event_idx: usize, quantum: u64, jitter: u64) {
+        match self.rules.iter_mut().find(|rule| rule.event_idx == event_idx) {
+            Some(rule) => {
+                rule.quantum = rule.quantum.max(quantum);
+                rule.jitter = rule.jitter.max(jitter);
+            }
+            None => self.rules.push(Rule { event_idx, quantum, jitter }),
+        }
+    }
+    fn hart_mut(&mut self) -> &mut HartBindings {
+        let hartid = current_hartid();
+        if self.harts.len() <= hartid {
+            self.harts.resize_with(hartid + 1, HartBindings::default);
+        }
+        &mut self.harts[hartid]
+    }
+    // protection of the event bound to the counter on the calling hart
+    fn rule(&self, counter_idx: usize) -> Option<Rule> {
+        let event_idx = (*self.harts.get(current_hartid())?.events.get(counter_idx)?)?;
+        self.rules.iter().find(|rule| rule.event_idx == event_idx).copied()
+    }
+    fn coarsen(&self, rule: Rule, value: u64) -> u64 {
+        let mut value = value;
+        if rule.quantum != 0 {
+            value -= value % rule.quantum;
+        }
+        if rule.jitter != 0 {
+            value = value.wrapping_add(self.random() as u64 % rule.jitter);
+        }
+        value
+    }
+    // one step of a counter based generator; several harts may draw numbers at once
+    fn random(&self) -> usize {
+        let mut x = self.noise.fetch_add(0x9E37_79B9, Ordering::Relaxed).wrapping_add(0x9E37_79B9);
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x45D9_F3B);
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x45D9_F3B);
+        x ^ (x >> 16)
+    }
+    // the 64-bit value of a firmware counter, read in two halves on RV32
+    fn fw_value(&self, counter_idx: usize, lo: usize) -> Result<u64, SbiRet> {
+        if usize::BITS == 32 {
+            let hi = self.inner.pmu_counter_fw_read_hi(counter_idx);
+            if hi.error != SBI_SUCCESS {
+                return Err(hi);
+            }
+            Ok(((hi.value as u64) << 32) | lo as u64)
+        } else {
+            Ok(lo as u64)
+        }
+    }
+}
+
+impl<T: Pmu> Pmu for ProtectedPmu<T> {
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        let ans = self.inner.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data);
+        if ans.error == SBI_SUCCESS {
+            let counter_idx = ans.value;
+            let hart = self.hart_mut();
+            if hart.events.len() <= counter_idx {
+                hart.events.resize(counter_idx + 1, None);
+            }
+            hart.events[counter_idx] = Some(event_idx);
+        }
+        ans
+    }
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+        let ans = self.inner.pmu_counter_stop(counter_idx_base, counter_idx_mask, stop_flags);
+        if ans.error != SBI_SUCCESS || stop_flags & SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT == 0 {
+            return ans;
+        }
+        let area = match self.harts.get(current_hartid()).and_then(|hart| hart.snapshot) {
+            Some(addr) => addr as *mut SnapshotArea,
+            None => return ans,
+        };
+        for idx in IndexMask::new(counter_idx_base, counter_idx_mask) {
+            if let Some(rule) = self.rule(idx) {
+                let i = idx - counter_idx_base;
+                unsafe {
+                    let value = addr_of_mut!((*area).counter_values[i]);
+                    write_volatile(value, self.coarsen(rule, read_volatile(value)));
+                }
+            }
+        }
+        ans
+    }
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet {
+        let ans = self.inner.pmu_counter_fw_read(counter_idx);
+        let rule = match self.rule(counter_idx) {
+            Some(rule) if ans.error == SBI_SUCCESS => rule,
+            _ => return ans,
+        };
+        match self.fw_value(counter_idx, ans.value) {
+            Ok(value) => SbiRet::ok(self.coarsen(rule, value) as usize),
+            Err(ans) => ans,
+        }
+    }
+    // emulated reads of counter CSRs are coarsened like reads through `sbi_pmu_counter_fw_read`
+    fn pmu_emulate_counter_read(&self, counter_idx: usize) -> Option<u64> {
+        let value = self.inner.pmu_emulate_counter_read(counter_idx)?;
+        match self.rule(counter_idx) {
+            Some(rule) => Some(self.coarsen(rule, value)),
+            None => Some(value),
+        }
+    }
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        let ans = self.inner.pmu_counter_fw_read_hi(counter_idx);
+        let rule = match self.rule(counter_idx) {
+            Some(rule) if ans.error == SBI_SUCCESS && usize::BITS == 32 => rule,
+            _ => return ans,
+        };
+        let lo = self.inner.pmu_counter_fw_read(counter_idx);
+        if lo.error != SBI_SUCCESS {
+            return lo;
+        }
+        let value = ((ans.value as u64) << 32) | lo.value as u64;
+        SbiRet::ok((self.coarsen(rule, value) >> 32) as usize)
+    }
+    fn pmu_counter_read_clear(&mut self, counter_idx: usize) -> SbiRet {
+        let ans = self.inner.pmu_counter_read_clear(counter_idx);
+        match self.rule(counter_idx) {
+            Some(rule) if ans.error == SBI_SUCCESS => SbiRet::ok(self.coarsen(rule, ans.value as u64) as usize),
+            _ => ans,
+        }
+    }
+    // a notification at an exact value would undo the coarsening; watchpoints are still disarmed
+    fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: WatchNotify) -> SbiRet {
+        if threshold != 0 && self.rule(counter_idx).is_some() {
+            return SbiRet::denied();
+        }
+        self.inner.pmu_counter_watch(counter_idx, threshold, notify)
+    }
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        let ans = self.inner.pmu_firmware_event_total(event_idx, per_hart);
+        let rule = match self.rules.iter().find(|rule| rule.event_idx == event_idx) {
+            Some(&rule) if ans.error == SBI_SUCCESS => rule,
+            _ => return ans,
+        };
+        for value in per_hart.iter_mut() {
+            *value = self.coarsen(rule, *value);
+        }
+        SbiRet::ok(self.coarsen(rule, ans.value as u64) as usize)
+    }
+    fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+        let ans = self.inner.pmu_snapshot_set_shm(shmem_phys_lo, shmem_phys_hi, flags);
+        if ans.error == SBI_SUCCESS {
+            let disabled = shmem_phys_lo == usize::MAX && shmem_phys_hi == usize::MAX;
+            self.hart_mut().snapshot = if disabled { None } else { Some(shmem_phys_lo) };
+        }
+        ans
+    }
+    fn pmu_counter_read_batch(&self, counter_idx_base: usize, counter_idx_mask: usize, values: &mut [u64; usize::BITS as usize]) -> SbiRet {
+        let ans = self.inner.pmu_counter_read_batch(counter_idx_base, counter_idx_mask, values);
+        if ans.error == SBI_SUCCESS {
+            for idx in IndexMask::new(counter_idx_base, counter_idx_mask) {
+                if let Some(rule) = self.rule(idx) {
+                    let i = idx - counter_idx_base;
+                    values[i] = self.coarsen(rule, values[i]);
+                }
+            }
+        }
+        ans
+    }
+    fn pmu_protect_event(&mut self, event_idx: usize, quantum: u64, jitter: u64) -> SbiRet {
+        if !self.armed {
+            return SbiRet::denied();
+        }
+        if EventIdx::new(event_idx).is_none() {
+            return SbiRet::invalid_param();
+        }
+        self.add_rule(event_idx, quantum, jitter);
+        SbiRet::ok(0)
+    }
+    crate::delegate_pmu!(inner => is_available, pmu_version, pmu_num_counters, pmu_counter_get_info, pmu_counter_start,
+        pmu_save_context, pmu_restore_context, pmu_poll_overflow, pmu_rotate_multiplex, pmu_dump, pmu_firmware_event,
+        pmu_ecall_cycles, pmu_enter_domain, pmu_event_get_info, pmu_hart_summary, pmu_leak_check, pmu_reclaim, pmu_reset,
+        pmu_platform_event, pmu_capabilities);
+}
@@ -0,0 +1,244 @@
+//! Counter control of other harts through inter-processor interrupts
+
+use super::{current_hartid, with_pmu_mut};
+use crate::ecall::{SbiRet, SBI_SUCCESS};
+use crate::hsm::hart_get_status;
+use crate::index_mask::IndexMask;
+use crate::shmem::check_shmem;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr::read_volatile;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+/// Inter-processor interrupts carrying PMU requests between harts
+///
+/// Counters can only be configured, started and stopped on their own hart, but profilers often run on
+/// one hart and control counters everywhere. Supervisor makes the remote control call of the firmware
+/// specific extension of RustSBI (EID `0x0A000004`, FID `8`) with a hart mask; RustSBI leaves the request
+/// in a mailbox of each target hart, and interrupts it through this trait.
+///
+/// The platform raises a machine software interrupt on the target hart, distinguishable from supervisor
+/// IPIs and remote fences, and calls `handle_pmu_ipi` in the interrupt handler. The calling hart waits
+/// until every target hart has handled the request, so target harts must take machine software interrupts.
+/// Harts the HSM extension does not report started are left out, and a hart which does not answer in time
+/// fails the call with `SBI_ERR_FAILED`.
+pub trait PmuIpi: Send {
+    /// Get the maximum hart id PMU requests can be sent to; every hart up to it must exist.
+    fn max_hart_id(&self) -> usize;
+    /// Interrupt hart `hartid` to handle its PMU request.
+    fn send_pmu_ipi(&mut self, hartid: usize);
+}
+
+// remote request in supervisor memory, 64-bit fields on RV32 as well
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Request {
+    operation: u64,
+    counter_idx_base: u64,
+    counter_idx_mask: u64,
+    // configuration, start or stop flags
+    flags: u64,
+    // event index of a configuration request
+    event_idx: u64,
+    // event data of a configuration request, or initial value of a start request
+    value: u64,
+}
+
+const OPERATION_CONFIG: u64 = 0;
+const OPERATION_START: u64 = 1;
+const OPERATION_STOP: u64 = 2;
+// reset of the target hart's counters before a reboot, never requested by supervisor
+const OPERATION_RESET: u64 = u64::MAX;
+
+// `hart_get_status` value of a started hart
+const HART_STARTED: usize = 0;
+
+// polls of a mailbox before its hart is given up on
+const MAX_WAIT_POLLS: usize = 1 << 24;
+
+// request left for one hart, and its result once handled
+#[derive(Default)]
+struct Mailbox {
+    request: Option<Request>,
+    result: Option<SbiRet>,
+    // request the result is expected for; a hart given up on may still answer an older one
+    ticket: usize,
+}
+
+struct Remote {
+    ipi: Box<dyn PmuIpi>,
+    mailboxes: Vec<Mailbox>,
+    // ticket of the latest request
+    ticket: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref REMOTE: Mutex<Option<Remote>> = Mutex::new(None);
+}
+
+// one remote request in flight at a time; a hart waiting for its turn keeps handling requests sent to it
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// Register the inter-processor interrupt used to control counters of other harts.
+///
+/// Without a registered IPI, supervisor is told that remote counter control is not supported.
+pub fn init_pmu_ipi<T: PmuIpi + 'static>(ipi: T) {
+    *REMOTE.lock() = Some(Remote {
+        ipi: Box::new(ipi),
+        mailboxes: Vec::new(),
+        ticket: 0,
+    });
+}
+
+/// Handle the PMU request sent to the calling hart, if any.
+///
+/// Platforms call this function in the machine software interrupt raised by `PmuIpi::send_pmu_ipi`.
+pub fn handle_pmu_ipi() {
+    let hartid = current_hartid();
+    let (request, ticket) = match REMOTE.lock().as_mut().and_then(|remote| remote.mailboxes.get_mut(hartid)) {
+        Some(mailbox) => (mailbox.request.take(), mailbox.ticket),
+        None => return,
+    };
+    if let Some(request) = request {
+        // the PMU is called without holding the mailboxes, so that the caller can poll them
+        let ans = execute(&request);
+        if let Some(mailbox) = REMOTE.lock().as_mut().and_then(|remote| remote.mailboxes.get_mut(hartid)) {
+            if mailbox.ticket == ticket {
+                mailbox.result = Some(ans);
+            }
+        }
+    }
+}
+
+// perform a request on the calling hart
+fn execute(request: &Request) -> SbiRet {
+    let counter_idx_base = request.counter_idx_base as usize;
+    let counter_idx_mask = request.counter_idx_mask as usize;
+    let flags = request.flags as usize;
+    with_pmu_mut(|obj| match request.operation {
+        OPERATION_CONFIG => {
+            obj.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, flags, request.event_idx as usize, request.value)
+        }
+        OPERATION_START => obj.pmu_counter_start(counter_idx_base, counter_idx_mask, flags, request.value),
+        OPERATION_RESET => {
+            obj.pmu_reset(false);
+            SbiRet::ok(0)
+        }
+        _ => obj.pmu_counter_stop(counter_idx_base, counter_idx_mask, flags),
+    })
+    .unwrap_or_else(SbiRet::not_supported)
+}
+
+// perform the request at physical address `request_phys_lo` on every started hart of the hart mask, the calling
+// hart included; returns the number of harts on success, or the error of the first failing hart with its hart id
+pub(crate) fn pmu_remote_control(hart_mask: usize, hart_mask_base: usize, request_phys_lo: usize, request_phys_hi: usize) -> SbiRet {
+    let request = match check_shmem::<Request>(request_phys_lo, request_phys_hi, 1) {
+        Ok(request) => request,
+        Err(ans) => return ans,
+    };
+    let max_hart_id = match REMOTE.lock().as_ref() {
+        Some(remote) => remote.ipi.max_hart_id(),
+        None => return SbiRet::not_supported(),
+    };
+    let request = unsafe { read_volatile(request) };
+    if request.operation > OPERATION_STOP {
+        return SbiRet::invalid_param();
+    }
+    let targets: Vec<usize> = if hart_mask_base == usize::MAX {
+        (0..=max_hart_id).collect()
+    } else {
+        let harts = IndexMask::new(hart_mask_base, hart_mask);
+        if !harts.is_within(max_hart_id + 1) {
+            return SbiRet::invalid_param();
+        }
+        harts.iter().collect()
+    };
+    perform(&request, targets, max_hart_id)
+}
+
+// have every other hart stop and unbind its own counters, before the state of all harts is forgotten;
+// does nothing without a registered IPI
+pub(crate) fn reset_remote_harts() {
+    let max_hart_id = match REMOTE.lock().as_ref() {
+        Some(remote) => remote.ipi.max_hart_id(),
+        None => return,
+    };
+    let this_hart = current_hartid();
+    let request = Request {
+        operation: OPERATION_RESET,
+        counter_idx_base: 0,
+        counter_idx_mask: 0,
+        flags: 0,
+        event_idx: 0,
+        value: 0,
+    };
+    perform(&request, (0..=max_hart_id).filter(|&hartid| hartid != this_hart).collect(), max_hart_id);
+}
+
+// perform the request on every started hart of `targets`, hart ids up to `max_hart_id`; returns the number of
+// harts or the error of the first failing hart with its hart id
+fn perform(request: &Request, targets: Vec<usize>, max_hart_id: usize) -> SbiRet {
+    let this_hart = current_hartid();
+    // stopped and suspended harts do not take the interrupt; without HSM every hart is taken as started
+    let targets: Vec<usize> = targets
+        .into_iter()
+        .filter(|&hartid| {
+            let status = hart_get_status(hartid);
+            hartid == this_hart || status.error != SBI_SUCCESS || status.value == HART_STARTED
+        })
+        .collect();
+    while BUSY.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        handle_pmu_ipi();
+        core::hint::spin_loop();
+    }
+    if let Some(remote) = REMOTE.lock().as_mut() {
+        // the platform may learn its number of harts after registering the IPI
+        if remote.mailboxes.len() <= max_hart_id {
+            remote.mailboxes.resize_with(max_hart_id + 1, Mailbox::default);
+        }
+        remote.ticket = remote.ticket.wrapping_add(1);
+        for &hartid in targets.iter().filter(|&&hartid| hartid != this_hart) {
+            remote.mailboxes[hartid] = Mailbox {
+                request: Some(*request),
+                result: None,
+                ticket: remote.ticket,
+            };
+            remote.ipi.send_pmu_ipi(hartid);
+        }
+    }
+    let mut ans = SbiRet::ok(targets.len());
+    for &hartid in targets.iter() {
+        let result = if hartid == this_hart {
+            execute(request)
+        } else {
+            wait_result(hartid)
+        };
+        if result.error != SBI_SUCCESS && ans.error == SBI_SUCCESS {
+            ans = SbiRet {
+                error: result.error,
+                value: hartid,
+            };
+        }
+    }
+    BUSY.store(false, Ordering::Release);
+    ans
+}
+
+// result of the request sent to `hartid`; a hart which does not answer in time has its request taken back
+fn wait_result(hartid: usize) -> SbiRet {
+    for _ in 0..MAX_WAIT_POLLS {
+        let result = REMOTE.lock().as_mut().and_then(|remote| remote.mailboxes[hartid].result.take());
+        if let Some(result) = result {
+            return result;
+        }
+        core::hint::spin_loop();
+    }
+    if let Some(remote) = REMOTE.lock().as_mut() {
+        let mailbox = &mut remote.mailboxes[hartid];
+        mailbox.request = None;
+        // a request the hart has already taken is answered too late, and its result dropped
+        mailbox.ticket = mailbox.ticket.wrapping_sub(1);
+    }
+    SbiRet::failed()
+}
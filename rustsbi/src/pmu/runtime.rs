@@ -0,0 +1,472 @@
+//! Registered PMU implementation, and the glue dispatching SBI calls and platform hooks to it
+//!
+//! RustSBI keeps the single `Pmu` implementation registered by the platform with `init_pmu`. SBI calls of the PMU
+//! extension and of the firmware specific extension reach it through the functions of this module, which check
+//! and copy supervisor memory; platforms call the public functions from their trap, interrupt and HSM handlers.
+
+use super::{probe_pmu_sampler, reset_remote_harts, reset_sampler};
+use super::{CounterInfo, EventInfo, Pmu, WatchNotify};
+use super::{RUSTSBI_FW_PMU_EMULATED_COUNTER_READS, RUSTSBI_PMU_CAPABILITIES_VERSION, RUSTSBI_PMU_CAP_SAMPLER};
+use super::{RUSTSBI_FW_PMU_PMU_CALLS, RUSTSBI_FW_PMU_SBI_CALLS};
+use super::{RUSTSBI_PMU_LEAK_CHECK_END_SESSION, RUSTSBI_PMU_WATCH_NOTIFY_SHMEM};
+use crate::ecall::{SbiRet, EXTENSION_PMU, SBI_SUCCESS};
+use crate::hart_mask::HartMask;
+use crate::index_mask::IndexMask;
+use crate::shmem::check_shmem;
+use alloc::boxed::Box;
+use core::mem::size_of;
+use core::ptr::{read_volatile, write_volatile};
+use spin::RwLock;
+
+lazy_static::lazy_static! {
+    pub(crate) static ref PMU: RwLock<Option<Box<dyn Pmu>>> =
+        RwLock::new(None);
+}
+
+#[doc(hidden)] // use through a macro or a call from implementation
+pub fn init_pmu<T: Pmu + Send + Sync + 'static>(pmu: T) {
+    if !pmu.is_available() {
+        return;
+    }
+    *PMU.write() = Some(Box::new(pmu));
+}
+
+// Firmware events are counted in machine interrupt handlers under the shared lock. If such an
+// interrupt were taken on a hart holding the exclusive lock, the handler would spin on the lock
+// forever, so machine interrupts stay disabled until the exclusive lock is released.
+pub(crate) fn with_pmu_mut<R>(f: impl FnOnce(&mut dyn Pmu) -> R) -> Option<R> {
+    interrupt_free(|| PMU.write().as_mut().map(|obj| f(obj.as_mut())))
+}
+
+// run `f` with machine interrupts disabled on the calling hart; host-side tests and fuzzing run
+// without machine interrupts
+pub(crate) fn interrupt_free<R>(f: impl FnOnce() -> R) -> R {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => riscv::interrupt::free(|_| f()),
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => f(),
+    }
+}
+
+// id of the calling hart; host-side tests and fuzzing run on hart 0
+pub(crate) fn current_hartid() -> usize {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => riscv::register::mhartid::read(),
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => 0,
+    }
+}
+
+#[inline]
+pub(crate) fn probe_pmu() -> bool {
+    PMU.read().as_ref().is_some()
+}
+
+pub(crate) fn pmu_version() -> usize {
+    if let Some(obj) = &*PMU.read() {
+        return obj.pmu_version();
+    }
+    0
+}
+
+pub(crate) fn pmu_num_counters() -> SbiRet {
+    if let Some(obj) = &*PMU.read() {
+        return obj.pmu_num_counters();
+    }
+    SbiRet::not_supported()
+}
+
+pub(crate) fn pmu_counter_get_info(counter_idx: usize) -> SbiRet {
+    if let Some(obj) = &*PMU.read() {
+        return obj.pmu_counter_get_info(counter_idx);
+    }
+    SbiRet::not_supported()
+}
+
+// `counter_info` of the first `num_entries` counters into supervisor memory at physical address `buf`, one
+// XLEN-bit value for each counter, so that supervisor needs one call at boot instead of one for each counter;
+// returns the number of counters
+pub(crate) fn pmu_counter_info_read(buf_phys_lo: usize, buf_phys_hi: usize, num_entries: usize, flags: usize) -> SbiRet {
+    if flags != 0 {
+        return SbiRet::invalid_param();
+    }
+    // the whole buffer supervisor gives is checked, however many counters there are
+    let buf = match check_shmem::<usize>(buf_phys_lo, buf_phys_hi, num_entries) {
+        Ok(buf) => buf,
+        Err(ans) => return ans,
+    };
+    if let Some(obj) = &*PMU.read() {
+        let num_counters = obj.pmu_num_counters();
+        if num_counters.error != SBI_SUCCESS {
+            return num_counters;
+        }
+        for counter_idx in 0..num_entries.min(num_counters.value) {
+            let info = obj.pmu_counter_get_info(counter_idx);
+            if info.error != SBI_SUCCESS {
+                return info;
+            }
+            unsafe { write_volatile(buf.add(counter_idx), info.value) };
+        }
+        return num_counters;
+    }
+    SbiRet::not_supported()
+}
+
+pub(crate) fn pmu_counter_config_matching(counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+    with_pmu_mut(|obj| {
+        obj.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data)
+    })
+    .unwrap_or_else(SbiRet::not_supported)
+}
+
+pub(crate) fn pmu_start(counter_id_base: usize, counter_id_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+    with_pmu_mut(|obj| obj.pmu_counter_start(counter_id_base, counter_id_mask, start_flags, initial_value))
+        .unwrap_or_else(SbiRet::not_supported)
+}
+
+pub(crate) fn pmu_stop(counter_id_base: usize, counter_id_mask: usize, stop_flags: usize) -> SbiRet {
+    with_pmu_mut(|obj| obj.pmu_counter_stop(counter_id_base, counter_id_mask, stop_flags))
+        .unwrap_or_else(SbiRet::not_supported)
+}
+
+pub(crate) fn pmu_fw_read(counter_idx: usize) -> SbiRet {
+    if let Some(obj) = &*PMU.read() {
+        return obj.pmu_counter_fw_read(counter_idx);
+    }
+    SbiRet::not_supported()
+}
+
+pub(crate) fn pmu_fw_read_hi(counter_idx: usize) -> SbiRet {
+    if let Some(obj) = &*PMU.read() {
+        return obj.pmu_counter_fw_read_hi(counter_idx);
+    }
+    SbiRet::not_supported()
+}
+
+pub(crate) fn pmu_snapshot_set_shm(shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+    with_pmu_mut(|obj| obj.pmu_snapshot_set_shm(shmem_phys_lo, shmem_phys_hi, flags))
+        .unwrap_or_else(SbiRet::not_supported)
+}
+
+// query a list of events in supervisor memory at physical address `shmem_phys_lo`, 16 bytes for each entry;
+// entries are copied in and out a few at a time, the implementation never sees supervisor memory
+pub(crate) fn pmu_event_get_info(shmem_phys_lo: usize, shmem_phys_hi: usize, num_entries: usize, flags: usize) -> SbiRet {
+    if flags != 0 || shmem_phys_lo % size_of::<EventInfo>() != 0 {
+        return SbiRet::invalid_param();
+    }
+    let shmem = match check_shmem::<EventInfo>(shmem_phys_lo, shmem_phys_hi, num_entries) {
+        Ok(shmem) => shmem,
+        Err(ans) => return ans,
+    };
+    if let Some(obj) = &*PMU.read() {
+        let mut chunk = [EventInfo { event_idx: 0, output: 0, event_data: 0 }; EVENT_INFO_CHUNK];
+        for start in (0..num_entries).step_by(EVENT_INFO_CHUNK) {
+            let entries = &mut chunk[..EVENT_INFO_CHUNK.min(num_entries - start)];
+            for (i, entry) in entries.iter_mut().enumerate() {
+                *entry = unsafe { read_volatile(shmem.add(start + i)) };
+            }
+            let ans = obj.pmu_event_get_info(entries);
+            if ans.error != SBI_SUCCESS {
+                return ans;
+            }
+            for (i, entry) in entries.iter().enumerate() {
+                unsafe { write_volatile(shmem.add(start + i), *entry) };
+            }
+        }
+        return SbiRet::ok(0);
+    }
+    SbiRet::not_supported()
+}
+
+// entries of the event information list handed to the implementation at a time
+const EVENT_INFO_CHUNK: usize = 16;
+
+pub(crate) fn pmu_dump() -> SbiRet {
+    if let Some(obj) = &*PMU.read() {
+        obj.pmu_dump();
+        return SbiRet::ok(0);
+    }
+    SbiRet::not_supported()
+}
+
+// read a set of counters into supervisor memory at physical address `buf`, one 64-bit value
+// for each bit of the mask, relative to `counter_idx_base`
+pub(crate) fn pmu_counter_read_batch(counter_idx_base: usize, counter_idx_mask: usize, buf_phys_lo: usize, buf_phys_hi: usize) -> SbiRet {
+    // values go up to the highest bit of the mask
+    let count = (usize::BITS - counter_idx_mask.leading_zeros()) as usize;
+    let buf = match check_shmem::<u64>(buf_phys_lo, buf_phys_hi, count) {
+        Ok(buf) => buf,
+        Err(ans) => return ans,
+    };
+    if let Some(obj) = &*PMU.read() {
+        let mut values = [0; usize::BITS as usize];
+        let ans = obj.pmu_counter_read_batch(counter_idx_base, counter_idx_mask, &mut values);
+        if ans.error == SBI_SUCCESS {
+            for i in IndexMask::new(0, counter_idx_mask) {
+                unsafe { write_volatile(buf.add(i), values[i]) };
+            }
+        }
+        return ans;
+    }
+    SbiRet::not_supported()
+}
+
+pub(crate) fn pmu_protect_event(event_idx: usize, quantum: usize, jitter: usize) -> SbiRet {
+    with_pmu_mut(|obj| obj.pmu_protect_event(event_idx, quantum as u64, jitter as u64)).unwrap_or_else(SbiRet::not_supported)
+}
+
+pub(crate) fn pmu_counter_read_clear(counter_idx: usize) -> SbiRet {
+    with_pmu_mut(|obj| obj.pmu_counter_read_clear(counter_idx)).unwrap_or_else(SbiRet::not_supported)
+}
+
+// summary of counters of hart `hartid`; harts unknown to the HSM extension are rejected the same
+// way as `sbi_hart_get_status` does
+pub(crate) fn pmu_hart_status(hartid: usize) -> SbiRet {
+    if crate::hsm::probe_hsm() {
+        let status = crate::hsm::hart_get_status(hartid);
+        if status.error != SBI_SUCCESS {
+            return status;
+        }
+    }
+    if let Some(obj) = &*PMU.read() {
+        if let Some(summary) = obj.pmu_hart_summary(hartid) {
+            return SbiRet::ok(summary.encode());
+        }
+    }
+    SbiRet::not_supported()
+}
+
+// count a firmware event over all harts; with `num_harts` other than zero, the value of each hart is written
+// into supervisor memory at physical address `buf`, one 64-bit value for each hart up to `num_harts`, and
+// up to XLEN harts at most
+pub(crate) fn pmu_firmware_event_total(event_idx: usize, buf_phys_lo: usize, buf_phys_hi: usize, num_harts: usize) -> SbiRet {
+    let num_harts = num_harts.min(usize::BITS as usize);
+    let buf = match check_shmem::<u64>(buf_phys_lo, buf_phys_hi, num_harts) {
+        Ok(buf) => buf,
+        Err(ans) => return ans,
+    };
+    if let Some(obj) = &*PMU.read() {
+        let mut values = [0; usize::BITS as usize];
+        let ans = obj.pmu_firmware_event_total(event_idx, &mut values[..num_harts]);
+        if ans.error == SBI_SUCCESS {
+            for (hartid, &value) in values[..num_harts].iter().enumerate() {
+                unsafe { write_volatile(buf.add(hartid), value) };
+            }
+        }
+        return ans;
+    }
+    SbiRet::not_supported()
+}
+
+// optional capabilities of the PMU, or with `RUSTSBI_PMU_CAPABILITIES_VERSION`, version of the PMU extension
+// as supervisor reads it on probe; other flags are reserved
+pub(crate) fn pmu_capabilities(flags: usize) -> SbiRet {
+    if flags & !RUSTSBI_PMU_CAPABILITIES_VERSION != 0 {
+        return SbiRet::invalid_param();
+    }
+    if let Some(obj) = &*PMU.read() {
+        if flags & RUSTSBI_PMU_CAPABILITIES_VERSION != 0 {
+            return SbiRet::ok(obj.pmu_version());
+        }
+        let sampler = if probe_pmu_sampler() { RUSTSBI_PMU_CAP_SAMPLER } else { 0 };
+        return SbiRet::ok(obj.pmu_capabilities() | sampler);
+    }
+    SbiRet::not_supported()
+}
+
+// check counters of the calling hart for leaks; flags other than ending the session are reserved
+pub(crate) fn pmu_leak_check(counter_idx_base: usize, flags: usize) -> SbiRet {
+    if flags & !RUSTSBI_PMU_LEAK_CHECK_END_SESSION != 0 {
+        return SbiRet::invalid_param();
+    }
+    with_pmu_mut(|obj| obj.pmu_leak_check(counter_idx_base, flags & RUSTSBI_PMU_LEAK_CHECK_END_SESSION != 0))
+        .unwrap_or_else(SbiRet::not_supported)
+}
+
+pub(crate) fn pmu_reclaim() -> SbiRet {
+    with_pmu_mut(|obj| obj.pmu_reclaim()).unwrap_or_else(SbiRet::not_supported)
+}
+
+// arm a watchpoint on a firmware counter of the calling hart; shared memory is only given with
+// `RUSTSBI_PMU_WATCH_NOTIFY_SHMEM`
+pub(crate) fn pmu_counter_watch(counter_idx: usize, threshold: u64, flags: usize, shmem_phys_lo: usize, shmem_phys_hi: usize) -> SbiRet {
+    if flags & !RUSTSBI_PMU_WATCH_NOTIFY_SHMEM != 0 {
+        return SbiRet::invalid_param();
+    }
+    let notify = if flags & RUSTSBI_PMU_WATCH_NOTIFY_SHMEM != 0 {
+        // the value is written when the counter reaches the threshold, long after this call
+        if let Err(ans) = check_shmem::<u64>(shmem_phys_lo, shmem_phys_hi, 1) {
+            return ans;
+        }
+        WatchNotify::SharedMemory(shmem_phys_lo)
+    } else {
+        WatchNotify::SoftwareInterrupt
+    };
+    with_pmu_mut(|obj| obj.pmu_counter_watch(counter_idx, threshold, notify)).unwrap_or_else(SbiRet::not_supported)
+}
+
+// forget counter state of the calling hart, or of all harts, before the next supervisor boots; other harts
+// stop their own counters first
+pub(crate) fn reset_pmu(all_harts: bool) {
+    if all_harts {
+        reset_remote_harts();
+    }
+    reset_sampler(all_harts);
+    with_pmu_mut(|obj| obj.pmu_reset(all_harts));
+}
+
+pub(crate) fn save_pmu_context() {
+    with_pmu_mut(|obj| obj.pmu_save_context());
+}
+
+// save counter state of the calling hart before it stops, and disable its snapshot shared memory: the hart is
+// started again at an address of supervisor's choosing, possibly by a new kernel after kexec which no longer
+// owns the memory, and registers shared memory again if it takes snapshots
+pub(crate) fn stop_pmu_hart() {
+    with_pmu_mut(|obj| {
+        obj.pmu_save_context();
+        obj.pmu_snapshot_set_shm(usize::MAX, usize::MAX, 0);
+    });
+}
+
+/// Detect counter overflow of the calling hart by software, returns the overflow bitmap.
+///
+/// Platforms without Sscofpmf extension should call this function periodically, for example
+/// in machine timer interrupt, and when emulating supervisor reads of `scountovf` CSR.
+pub fn poll_pmu_overflow() -> usize {
+    with_pmu_mut(|obj| obj.pmu_poll_overflow()).unwrap_or(0)
+}
+
+/// Let multiplexed events of the calling hart take turns on hardware counters.
+///
+/// Platforms should call this function periodically, for example in machine timer interrupt;
+/// multiplexed events are only counted by the generic PMU with the `multiplex` feature.
+pub fn rotate_pmu_multiplex() {
+    with_pmu_mut(|obj| obj.pmu_rotate_multiplex());
+}
+
+/// Emulate a read of counter CSR `csr` by supervisor, returns the value to write into the destination register.
+///
+/// Platforms call this function when supervisor traps into an illegal instruction `csrr` of a counter CSR,
+/// such as `hpmcounter3`, whose `mcounteren` bit is clear; on RV32, `csr` may also be the CSR of the upper
+/// half, such as `hpmcounter3h`. `None` means the read is not emulated, and the illegal instruction should be
+/// delegated to supervisor as before. Emulated reads are counted by the platform specific firmware event
+/// `RUSTSBI_FW_PMU_EMULATED_COUNTER_READS`.
+pub fn emulate_counter_csr_read(csr: usize) -> Option<usize> {
+    // on RV32, the upper half of the counter reported at `csr - 0x80`
+    let (csr, shift) = match csr {
+        0xC80..=0xC9F if usize::BITS == 32 => (csr - 0x80, 32),
+        _ => (csr, 0),
+    };
+    let pmu = PMU.read();
+    let obj = pmu.as_ref()?;
+    let num_counters = obj.pmu_num_counters();
+    if num_counters.error != SBI_SUCCESS {
+        return None;
+    }
+    let counter_idx = (0..num_counters.value).find(|&idx| {
+        let ans = obj.pmu_counter_get_info(idx);
+        let info = CounterInfo::from_raw(ans.value);
+        ans.error == SBI_SUCCESS && !info.is_firmware() && info.csr() == csr
+    })?;
+    let value = obj.pmu_emulate_counter_read(counter_idx)?;
+    obj.pmu_platform_event(RUSTSBI_FW_PMU_EMULATED_COUNTER_READS, 1);
+    Some((value >> shift) as usize)
+}
+
+/// Restore PMU counter state of the calling hart.
+///
+/// Platform HSM implementations should call this function on the resume path of a
+/// non-retentive suspend, and when a stopped hart is started again, before jumping
+/// back to supervisor mode. The state was saved by RustSBI when the hart was stopped
+/// or suspended.
+pub fn restore_pmu_context() {
+    with_pmu_mut(|obj| obj.pmu_restore_context());
+}
+
+/// Reset PMU counter state of the calling hart.
+///
+/// Platform HSM implementations should call this function when the hart starts from a reset rather than
+/// from where it was stopped, in place of `restore_pmu_context`, before jumping to supervisor mode; platforms
+/// call it on every hart at boot as well, after `init_pmu`. Counters are unbound and stopped, firmware counters
+/// are cleared, and sampling of the hart stops. `GenericPmu` also sets or clears `mcounteren.TM` following
+/// `PmuPlatform::time_csr`.
+pub fn reset_pmu_hart() {
+    reset_pmu(false);
+}
+
+/// Switch the calling hart to supervisor domain `domain`.
+///
+/// Platforms running several supervisors in separate domains should call this function whenever the calling
+/// hart is about to run a different domain. Every hart starts in domain 0.
+pub fn enter_pmu_domain(domain: usize) {
+    with_pmu_mut(|obj| obj.pmu_enter_domain(domain));
+}
+
+// count a firmware event once for each target hart of a successful remote request
+pub(crate) fn count_sent_event(event_code: usize, hart_mask: &HartMask, ans: &SbiRet) {
+    if ans.error != SBI_SUCCESS {
+        return;
+    }
+    if let Some(obj) = &*PMU.read() {
+        for _ in hart_mask.iter() {
+            obj.pmu_firmware_event(event_code);
+        }
+    }
+}
+
+// count an SBI call serviced for the calling hart before it is handled, and calls of the PMU extension on their own
+pub(crate) fn count_sbi_call(extension: usize) {
+    if let Some(obj) = &*PMU.read() {
+        obj.pmu_platform_event(RUSTSBI_FW_PMU_SBI_CALLS, 1);
+        if extension == EXTENSION_PMU {
+            obj.pmu_platform_event(RUSTSBI_FW_PMU_PMU_CALLS, 1);
+        }
+    }
+}
+
+// add cycles spent in the PMU extension handler to firmware counters of the calling hart
+pub(crate) fn count_ecall_cycles(cycles: u64) {
+    if let Some(obj) = &*PMU.read() {
+        obj.pmu_ecall_cycles(cycles);
+    }
+}
+
+/// Record one occurrence of a firmware event on the calling hart.
+///
+/// RustSBI counts firmware events of SBI calls by itself; platforms should call this function
+/// for events only visible to them, for example `SBI_PMU_FW_IPI_RECEIVED` when a software
+/// interrupt from another hart is handled.
+///
+/// This function may be called from machine interrupt handlers, even if the interrupt is taken
+/// in the middle of an SBI call: RustSBI keeps machine interrupts disabled while counters are
+/// being configured, and counting never waits for a lock held by the interrupted code.
+pub fn count_firmware_event(event_code: usize) {
+    if let Some(obj) = &*PMU.read() {
+        obj.pmu_firmware_event(event_code);
+    }
+}
+
+/// Add cycles the firmware spent in one of its own paths to platform specific firmware events of the calling hart.
+///
+/// `event_data` selects the path, such as `RUSTSBI_FW_PMU_TRAP_CYCLES` for the whole trap handler or
+/// `RUSTSBI_FW_PMU_IPI_CYCLES` for inter-processor interrupts; platforms measure `cycles` with `mcycle`
+/// around the path. Like `count_firmware_event`, this function may be called from machine interrupt handlers.
+pub fn count_firmware_cycles(event_data: u64, cycles: u64) {
+    if let Some(obj) = &*PMU.read() {
+        obj.pmu_platform_event(event_data, cycles);
+    }
+}
+
+/// Count one machine interrupt the firmware took on the calling hart.
+///
+/// `event_data` is the platform specific firmware event of the interrupt: `RUSTSBI_FW_PMU_MTIMER_INTERRUPTS`,
+/// `RUSTSBI_FW_PMU_MSOFT_INTERRUPTS` or `RUSTSBI_FW_PMU_MEXT_INTERRUPTS`. Platforms call this function first
+/// thing in their interrupt handler, so that interrupt storms show up even if handling goes wrong.
+pub fn count_machine_interrupt(event_data: u64) {
+    if let Some(obj) = &*PMU.read() {
+        obj.pmu_platform_event(event_data, 1);
+    }
+}
@@ -0,0 +1,207 @@
+//! Periodic sampling of counters driven by the machine timer
+
+use super::{current_hartid, interrupt_free, PMU};
+use crate::ecall::{SbiRet, SBI_SUCCESS};
+use crate::index_mask::IndexMask;
+use crate::shmem::check_shmem;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::write_volatile;
+use spin::Mutex;
+
+/// Machine timer used by the PMU sampler
+///
+/// Platforms without Sscofpmf extension cannot interrupt supervisor when a counter overflows, so sampling
+/// profilers have nothing to sample on. RustSBI samples counters periodically instead: supervisor starts
+/// sampling with the sampler start call of the firmware specific extension of RustSBI (EID `0x0A000004`,
+/// FID `4`), and RustSBI writes the values of a set of counters into a ring buffer in supervisor memory
+/// on every period, from the machine timer interrupt.
+///
+/// The machine timer is shared with the timer extension. The platform raises the machine timer interrupt
+/// at the earlier of the deadline set by supervisor and the sampling deadline set by this trait, calls
+/// `pmu_sample_tick` in the interrupt handler, and raises the supervisor timer interrupt only when the
+/// deadline of supervisor has passed.
+pub trait SampleTimer: Send {
+    /// Read the current time, the same clock as `mtime` and the `time` CSR.
+    fn now(&self) -> u64;
+    /// Set the sampling deadline of the calling hart, `None` cancels it.
+    fn set_sample_deadline(&mut self, deadline: Option<u64>);
+}
+
+// sampling of the calling hart
+struct Sampling {
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    period: u64,
+    next: u64,
+    buf: usize,
+    // number of records the buffer holds
+    capacity: usize,
+    written: u64,
+}
+
+struct Sampler {
+    timer: Box<dyn SampleTimer>,
+    harts: Vec<Option<Sampling>>,
+}
+
+lazy_static::lazy_static! {
+    static ref SAMPLER: Mutex<Option<Sampler>> = Mutex::new(None);
+}
+
+/// Register the machine timer used to sample counters periodically.
+///
+/// Without a registered timer, supervisor is told that the sampler is not supported.
+pub fn init_pmu_sampler<T: SampleTimer + 'static>(timer: T) {
+    *SAMPLER.lock() = Some(Sampler {
+        timer: Box::new(timer),
+        harts: Vec::new(),
+    });
+}
+
+// whether the platform registered a machine timer to sample counters with
+pub(crate) fn probe_pmu_sampler() -> bool {
+    SAMPLER.lock().is_some()
+}
+
+// each record is the time of the sample followed by one value for each counter in the set
+const HEADER_SIZE: usize = size_of::<u64>();
+
+// start sampling a set of counters of the calling hart every `period` ticks of the time counter, into
+// a ring buffer of `size` bytes at physical address `buf`
+pub(crate) fn pmu_sampler_start(counter_idx_base: usize, counter_idx_mask: usize, period: usize, buf_phys_lo: usize, buf_phys_hi: usize, size: usize) -> SbiRet {
+    if buf_phys_lo % size_of::<u64>() != 0 || period == 0 || counter_idx_mask == 0 {
+        return SbiRet::invalid_param();
+    }
+    // samples are written into the buffer from timer interrupts long after this call, the whole of it is
+    // checked now
+    if let Err(ans) = check_shmem::<u8>(buf_phys_lo, buf_phys_hi, size) {
+        return ans;
+    }
+    let record_size = (1 + counter_idx_mask.count_ones() as usize) * size_of::<u64>();
+    let capacity = size.saturating_sub(HEADER_SIZE) / record_size;
+    if capacity == 0 {
+        return SbiRet::invalid_param();
+    }
+    interrupt_free(|| {
+        let mut sampler = SAMPLER.lock();
+        let sampler = match sampler.as_mut() {
+            Some(sampler) => sampler,
+            None => return SbiRet::not_supported(),
+        };
+        // counters are checked by reading them once
+        let mut values = [0; usize::BITS as usize];
+        let ans = match &*PMU.read() {
+            Some(obj) => obj.pmu_counter_read_batch(counter_idx_base, counter_idx_mask, &mut values),
+            None => return SbiRet::not_supported(),
+        };
+        if ans.error != SBI_SUCCESS {
+            return ans;
+        }
+        let hartid = current_hartid();
+        if sampler.harts.len() <= hartid {
+            sampler.harts.resize_with(hartid + 1, || None);
+        }
+        if sampler.harts[hartid].is_some() {
+            return SbiRet::already_started();
+        }
+        unsafe { write_volatile(buf_phys_lo as *mut u64, 0) };
+        let next = sampler.timer.now().wrapping_add(period as u64);
+        sampler.harts[hartid] = Some(Sampling {
+            counter_idx_base,
+            counter_idx_mask,
+            period: period as u64,
+            next,
+            buf: buf_phys_lo,
+            capacity,
+            written: 0,
+        });
+        sampler.timer.set_sample_deadline(Some(next));
+        SbiRet::ok(capacity)
+    })
+}
+
+// stop sampling on the calling hart, returns the number of samples taken
+pub(crate) fn pmu_sampler_stop() -> SbiRet {
+    interrupt_free(|| {
+        let mut sampler = SAMPLER.lock();
+        let sampler = match sampler.as_mut() {
+            Some(sampler) => sampler,
+            None => return SbiRet::not_supported(),
+        };
+        match sampler.harts.get_mut(current_hartid()).and_then(Option::take) {
+            Some(sampling) => {
+                sampler.timer.set_sample_deadline(None);
+                SbiRet::ok(sampling.written as usize)
+            }
+            None => SbiRet::already_stopped(),
+        }
+    })
+}
+
+// stop sampling on the calling hart, or on all harts, before the next supervisor reuses the memory
+// samples are written into
+pub(crate) fn reset_sampler(all_harts: bool) {
+    interrupt_free(|| {
+        let mut sampler = SAMPLER.lock();
+        let sampler = match sampler.as_mut() {
+            Some(sampler) => sampler,
+            None => return,
+        };
+        if sampler.harts.get_mut(current_hartid()).and_then(Option::take).is_some() {
+            sampler.timer.set_sample_deadline(None);
+        }
+        // other harts find no sampling on their next timer interrupt
+        if all_harts {
+            sampler.harts.iter_mut().for_each(|sampling| *sampling = None);
+        }
+    })
+}
+
+/// Sample counters of the calling hart if its sampling deadline has passed.
+///
+/// Platforms which registered a `SampleTimer` should call this function in the machine timer interrupt
+/// handler; the next sampling deadline is set through `SampleTimer::set_sample_deadline`.
+pub fn pmu_sample_tick() {
+    interrupt_free(|| {
+        let mut sampler = SAMPLER.lock();
+        let sampler = match sampler.as_mut() {
+            Some(sampler) => sampler,
+            None => return,
+        };
+        let now = sampler.timer.now();
+        let sampling = match sampler.harts.get_mut(current_hartid()).and_then(Option::as_mut) {
+            Some(sampling) => sampling,
+            None => return,
+        };
+        if now < sampling.next {
+            // the interrupt is for the deadline of supervisor
+            return;
+        }
+        let mut values = [0; usize::BITS as usize];
+        if let Some(obj) = &*PMU.read() {
+            obj.pmu_counter_read_batch(sampling.counter_idx_base, sampling.counter_idx_mask, &mut values);
+        }
+        let record_len = 1 + sampling.counter_idx_mask.count_ones() as usize;
+        let slot = (sampling.written % sampling.capacity as u64) as usize;
+        let record = (sampling.buf + HEADER_SIZE) as *mut u64;
+        unsafe {
+            let record = record.add(slot * record_len);
+            write_volatile(record, now);
+            for (j, i) in IndexMask::new(0, sampling.counter_idx_mask).iter().enumerate() {
+                write_volatile(record.add(1 + j), values[i]);
+            }
+        }
+        // the count is updated after the record, so that supervisor never reads a record being written
+        sampling.written += 1;
+        unsafe { write_volatile(sampling.buf as *mut u64, sampling.written) };
+        // periods missed while machine interrupts were disabled are skipped
+        sampling.next = sampling.next.wrapping_add(sampling.period);
+        if sampling.next <= now {
+            sampling.next = now.wrapping_add(sampling.period);
+        }
+        let next = sampling.next;
+        sampler.timer.set_sample_deadline(Some(next));
+    })
+}
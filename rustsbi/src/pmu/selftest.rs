@@ -0,0 +1,150 @@
+//! Boot-time self-test of every counter
+//!
+//! Platforms whose counters may be broken, such as a new core or an emulator with a misconfigured PMU, run
+//! the self-test once at initialization, before supervisor boots: each counter is bound to an event it can
+//! count, started, given a busy loop to count, and stopped, and passes if its value moved. The results are
+//! printed to the console as a table, and supervisor queries them with the self-test result call of the
+//! firmware specific extension of RustSBI (EID `0x0A000004`, FID `15`).
+use super::events::{event_idx, SBI_PMU_HW_CPU_CYCLES, SBI_PMU_HW_INSTRUCTIONS};
+use super::{with_pmu_mut, Pmu, EVENT_TYPE_FIRMWARE, EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_FW_SET_TIMER};
+use super::{RUSTSBI_PMU_SELF_TEST_PASSED, SBI_PMU_CFG_FLAG_CLEAR_VALUE, SBI_PMU_STOP_FLAG_RESET};
+use crate::ecall::{SbiRet, SBI_SUCCESS};
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use spin::Mutex;
+
+// events a counter is tried with, in order; hardware counters take the first one they can count, firmware
+// counters take the firmware event
+const EVENTS: [usize; 3] = [
+    event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_CPU_CYCLES),
+    event_idx(EVENT_TYPE_HARDWARE_GENERAL, SBI_PMU_HW_INSTRUCTIONS),
+    event_idx(EVENT_TYPE_FIRMWARE, SBI_PMU_FW_SET_TIMER),
+];
+// iterations of the busy loop; each counts one firmware event as well, for firmware counters
+const BUSY_LOOP: usize = 1000;
+
+// result of the self-test for one counter
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SelfTestOutcome {
+    // the counter counted the busy loop
+    Pass,
+    // the counter was bound to an event, but an SBI call on it failed or its value did not move
+    Fail,
+    // the counter can count none of the events of the self-test, like the `time` counter
+    Skip,
+}
+
+lazy_static::lazy_static! {
+    static ref RESULTS: Mutex<Option<Vec<SelfTestOutcome>>> = Mutex::new(None);
+}
+
+/// Run the self-test on every counter of the calling hart, and print a PASS/FAIL table to the console.
+///
+/// Platforms call this function once after `init_pmu`, before any hart enters supervisor mode. Counters are
+/// left unbound and firmware counters cleared, so supervisor starts with none configured. Returns the number
+/// of failed counters; results of each counter are kept for the self-test result call.
+pub fn run_pmu_self_test() -> usize {
+    let results = match with_pmu_mut(test_all) {
+        Some(results) => results,
+        None => return 0,
+    };
+    super::reset_pmu(false);
+    crate::println!("[rustsbi-pmu] PMU self-test");
+    for (idx, outcome) in results.iter().enumerate() {
+        let text = match outcome {
+            SelfTestOutcome::Pass => "PASS",
+            SelfTestOutcome::Fail => "FAIL",
+            SelfTestOutcome::Skip => "skip",
+        };
+        crate::println!("[rustsbi-pmu]   {:>2}: {}", idx, text);
+    }
+    let failed = results.iter().filter(|&&outcome| outcome == SelfTestOutcome::Fail).count();
+    crate::println!("[rustsbi-pmu] {} of {} counters failed", failed, results.len());
+    *RESULTS.lock() = Some(results);
+    failed
+}
+
+fn test_all(obj: &mut dyn Pmu) -> Vec<SelfTestOutcome> {
+    let num_counters = obj.pmu_num_counters();
+    if num_counters.error != SBI_SUCCESS {
+        return Vec::new();
+    }
+    (0..num_counters.value).map(|idx| test_counter(obj, idx)).collect()
+}
+
+fn test_counter(obj: &mut dyn Pmu, counter_idx: usize) -> SelfTestOutcome {
+    let info = obj.pmu_counter_get_info(counter_idx);
+    if info.error != SBI_SUCCESS {
+        return SelfTestOutcome::Fail;
+    }
+    // multiplexed counters report themselves as firmware counters, but take hardware events; the `time`
+    // counter takes none
+    let bound = EVENTS.iter().any(|&event| {
+        obj.pmu_counter_config_matching(counter_idx, 1, SBI_PMU_CFG_FLAG_CLEAR_VALUE, event, 0).error == SBI_SUCCESS
+    });
+    if !bound {
+        return SelfTestOutcome::Skip;
+    }
+    if obj.pmu_counter_start(counter_idx, 1, 0, 0).error != SBI_SUCCESS {
+        obj.pmu_counter_stop(counter_idx, 1, SBI_PMU_STOP_FLAG_RESET);
+        return SelfTestOutcome::Fail;
+    }
+    let before = read(obj, counter_idx);
+    busy_loop(obj);
+    let after = read(obj, counter_idx);
+    let stopped = obj.pmu_counter_stop(counter_idx, 1, SBI_PMU_STOP_FLAG_RESET);
+    match (before, after) {
+        (Some(before), Some(after)) if stopped.error == SBI_SUCCESS && after != before => SelfTestOutcome::Pass,
+        _ => SelfTestOutcome::Fail,
+    }
+}
+
+// value of one counter, hardware or firmware, as `pmu_counter_read_batch` reads it
+fn read(obj: &dyn Pmu, counter_idx: usize) -> Option<u64> {
+    let mut values = [0; usize::BITS as usize];
+    let ret = obj.pmu_counter_read_batch(counter_idx, 1, &mut values);
+    if ret.error != SBI_SUCCESS {
+        return None;
+    }
+    Some(values[0])
+}
+
+// cycles and instructions for hardware counters, and firmware events for firmware counters
+fn busy_loop(obj: &dyn Pmu) {
+    let mut sum = 0usize;
+    for i in 0..BUSY_LOOP {
+        unsafe {
+            let value = read_volatile(&sum);
+            write_volatile(&mut sum, value.wrapping_add(i));
+        }
+        obj.pmu_firmware_event(SBI_PMU_FW_SET_TIMER);
+    }
+}
+
+// bitmap of counters which passed, or with flags clear, failed the self-test, relative to `counter_idx_base`
+pub(crate) fn pmu_self_test_result(counter_idx_base: usize, flags: usize) -> SbiRet {
+    if flags & !RUSTSBI_PMU_SELF_TEST_PASSED != 0 {
+        return SbiRet::invalid_param();
+    }
+    let results = RESULTS.lock();
+    let results = match &*results {
+        Some(results) => results,
+        // the platform did not run the self-test
+        None => return SbiRet::not_supported(),
+    };
+    if counter_idx_base >= results.len() {
+        return SbiRet::invalid_param();
+    }
+    let wanted = if flags & RUSTSBI_PMU_SELF_TEST_PASSED != 0 {
+        SelfTestOutcome::Pass
+    } else {
+        SelfTestOutcome::Fail
+    };
+    let bits = results[counter_idx_base..]
+        .iter()
+        .take(usize::BITS as usize)
+        .enumerate()
+        .filter(|&(_, &outcome)| outcome == wanted)
+        .fold(0, |bits, (i, _)| bits | 1 << i);
+    SbiRet::ok(bits)
+}
@@ -0,0 +1,887 @@
+//! Types and constants of the SBI PMU extension, and the `Pmu` trait implementing it
+//!
+//! Event encodings and call flags live in the `events` module, which depends only on `core` and is shared with
+//! supervisor-side crates; they are re-exported here, so that firmware implementing `Pmu` needs this module alone.
+
+pub use super::events::*;
+use crate::ecall::SbiRet;
+
+/// Performance Monitoring Unit Extension 
+///
+/// The RISC-V hardware performance counters such as `mcycle`, `minstret`, and
+/// `mhpmcounterX` CSRs are accessible as read-only from supervisor-mode using
+/// `cycle`, `instret`, and `hpmcounterX` CSRs. The SBI performance monitoring
+/// unit (PMU) extension is an interface for supervisor-mode to configure and
+/// use the RISC-V hardware performance counters with assistance from the
+/// machine-mode (or hypervisor-mode). These hardware performance counters
+/// can only be started, stopped, or configured from machine-mode using
+/// `mcountinhibit` and `mhpmeventX` CSRs. Due to this, a machine-mode SBI
+/// implementation may choose to disallow SBI PMU extension if `mcountinhibit`
+/// CSR is not implemented by the RISC-V platform.
+/// 
+/// A RISC-V platform generally supports monitoring of various hardware events
+/// using a limited number of hardware performance counters which are up to
+/// 64 bits wide. In addition, a SBI implementation can also provide firmware
+/// performance counters which can monitor firmware events such as number of
+/// misaligned load/store instructions, number of RFENCEs, number of IPIs, etc.
+/// The firmware counters are always 64 bits wide.
+/// 
+/// The SBI PMU extension provides:
+/// 
+/// 1. An interface for supervisor-mode software to discover and configure
+/// per-HART hardware/firmware counters
+/// 2. A typical https://en.wikipedia.org/wiki/Perf_(Linux)[perf] compatible
+///    interface for hardware/firmware performance counters and events
+/// 3. Full access to microarchitecture's raw event encodings
+/// 
+/// To define SBI PMU extension calls, we first define important entities
+/// `counter_idx`, `event_idx`, and `event_data`. The `counter_idx` is a
+/// logical number assigned to each hardware/firmware counter. The `event_idx`
+/// represents a hardware (or firmware) event whereas the `event_data` is
+/// 64 bits wide and represents additional configuration (or parameters) for
+/// a hardware (or firmware) event.
+/// 
+/// The event_idx is a 20 bits wide number encoded as follows:
+/// [source, C]
+/// ----
+///     event_idx[19:16] = type
+///     event_idx[15:0] = code
+/// ----
+/// 
+/// Ref: [Section 9, RISC-V Supervisor Binary Interface Specification](https://github.com/riscv/riscv-sbi-doc/blob/master/riscv-sbi.adoc#performance-monitoring-unit-extension-eid-0x504d55-pmu)
+///
+/// # Concurrency
+///
+/// Functions taking `&self`, such as `pmu_counter_fw_read` and `pmu_firmware_event`, are on the
+/// fast path of counting and reading; RustSBI calls them under a shared lock, so they may run on
+/// several harts at once and never wait for each other. Implementations keep the state changed
+/// by them, such as firmware counter values, in atomics or other interior mutable types.
+/// Functions taking `&mut self` configure counters and are called under an exclusive lock,
+/// with machine interrupts disabled.
+///
+/// `pmu_firmware_event` and `pmu_platform_event` may also be called from a machine interrupt handler
+/// nested in any of the functions taking `&self` on the same hart, so a counter value must be valid after
+/// every single step of an update; a value split into several words is updated with interrupts disabled.
+///
+/// # Wrapper types
+///
+/// Types wrapping another `Pmu` forward the methods they do not change with [`delegate_pmu!`](crate::delegate_pmu).
+pub trait Pmu: Send + Sync {
+    /// Whether the PMU extension can be provided on this platform.
+    ///
+    /// Checked once by `init_pmu`; if `false`, the PMU is not registered, and supervisor probes
+    /// the PMU extension as unavailable. The default implementation returns `true`.
+    fn is_available(&self) -> bool {
+        true
+    }
+    /// Returns the version of PMU extension implemented, encoded the same way as SBI specification version.
+    ///
+    /// RustSBI returns this value when supervisor probes the PMU extension, so that supervisor
+    /// software can decide whether newer event types such as hardware raw events v2 are available.
+    ///
+    /// The default implementation returns `PMU_VERSION_0_3`.
+    fn pmu_version(&self) -> usize {
+        PMU_VERSION_0_3
+    }
+    /// Returns the number of counters (both hardware and firmware) in `SbiRet.value`
+    /// and always `SBI_SUCCESS` in `SbiRet.error`.
+    fn pmu_num_counters(&self) -> SbiRet {
+        SbiRet::not_supported()
+    }
+    /// Get details about the specified counter such as underlying CSR number, width of the counter,
+    /// type of counter hardware/firmware, etc.
+    ///
+    /// The `counter_info` returned by this SBI call is encoded as follows:
+    ///
+    /// ```text
+    ///     counter_info[11:0] = CSR (12bit CSR number)
+    ///     counter_info[17:12] = Width (One less than number of bits in CSR)
+    ///     counter_info[XLEN-2:18] = Reserved for future use
+    ///     counter_info[XLEN-1] = Type (0 = hardware and 1 = firmware)
+    /// ```
+    /// If `counter_info.type` == 1 then `counter_info.csr` and `counter_info.width` should be ignored.
+    ///
+    /// # Return value
+    ///
+    /// Returns the `counter_info` described above in `SbiRet.value`.
+    ///
+    /// The possible return error codes returned in `SbiRet.error` are shown in the table below:
+    ///
+    /// | Return code             | Description
+    /// |:------------------------|:----------------------------------------------
+    /// | SBI_SUCCESS             | `counter_info` read successfully.
+    /// | SBI_ERR_INVALID_PARAM   | `counter_idx` points to an invalid counter.
+    fn pmu_counter_get_info(&self, counter_idx: usize) -> SbiRet {
+        drop(counter_idx);
+        SbiRet::not_supported()
+    }
+    /// Find and configure a counter from a set of counters which is not started (or enabled)
+    /// and can monitor the specified event.
+    ///
+    /// # Parameters
+    ///
+    /// The `counter_idx_base` and `counter_idx_mask` parameters represent the set of counters,
+    /// whereas the `event_idx` represent the event to be monitored
+    /// and `event_data` represents any additional event configuration.
+    ///
+    /// The `config_flags` parameter represent additional counter configuration and filter flags.
+    /// The bit definitions of the `config_flags` parameter are shown in the table below:
+    ///
+    /// | Flag Name                    | Bits       | Description
+    /// |:-----------------------------|:-----------|:------------
+    /// | SBI_PMU_CFG_FLAG_SKIP_MATCH  | 0:0        | Skip the counter matching
+    /// | SBI_PMU_CFG_FLAG_CLEAR_VALUE | 1:1        | Clear (or zero) the counter value in counter configuration
+    /// | SBI_PMU_CFG_FLAG_AUTO_START  | 2:2        | Start the counter after configuring a matching counter
+    /// | SBI_PMU_CFG_FLAG_SET_VUINH   | 3:3        | Event counting inhibited in VU-mode
+    /// | SBI_PMU_CFG_FLAG_SET_VSINH   | 4:4        | Event counting inhibited in VS-mode
+    /// | SBI_PMU_CFG_FLAG_SET_UINH    | 5:5        | Event counting inhibited in U-mode
+    /// | SBI_PMU_CFG_FLAG_SET_SINH    | 6:6        | Event counting inhibited in S-mode
+    /// | SBI_PMU_CFG_FLAG_SET_MINH    | 7:7        | Event counting inhibited in M-mode
+    /// | *RESERVED*                   | 8:(XLEN-1) | All non-zero values are reserved for future use
+    ///
+    /// *NOTE:* When *SBI_PMU_CFG_FLAG_SKIP_MATCH* is set in `config_flags`, the
+    /// SBI implementation will unconditionally select the first counter from the
+    /// set of counters specified by the `counter_idx_base` and `counter_idx_mask`.
+    ///
+    /// *NOTE:* The *SBI_PMU_CFG_FLAG_AUTO_START* flag in `config_flags` has no
+    /// impact on the counter value.
+    ///
+    /// *NOTE:* The `config_flags[3:7]` bits are event filtering hints so these
+    /// can be ignored or overridden by the SBI implementation for security concerns
+    /// or due to lack of event filtering support in the underlying RISC-V platform.
+    ///
+    /// # Return value
+    ///
+    /// Returns the `counter_idx` in `SbiRet.value` upon success.
+    ///
+    /// In case of failure, the possible error codes returned in `SbiRet.error` are shown in the table below:
+    ///
+    /// | Return code             | Description
+    /// |:------------------------|:----------------------------------------------
+    /// | SBI_SUCCESS             | counter found and configured successfully.
+    /// | SBI_ERR_INVALID_PARAM   | set of counters has an invalid counter.
+    /// | SBI_ERR_NOT_SUPPORTED   | none of the counters can monitor specified event.
+    /// | SBI_ERR_FAILED          | counters which can monitor specified event are all in use.
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        drop((counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data));
+        SbiRet::not_supported()
+    }
+    /// Start or enable a sef of counters on the calling HART with the specified initial value. The counter_idx_base and counter_idx_mask parameters represent the set of counters whereas the initial_value parameter specifies the initial value of the counter.
+    /// The bit definitions of the start_flags parameter are shown in the Table  below.
+    ///
+    /// # Parameters
+    /// 
+    /// - The `counter_idx_base` parameter specifies the index base for the pmu counter
+    /// - The `counter_idx_mask` parameter specifies the idx mask for the counter
+    ///
+    /// # Return value
+    ///
+    /// The possible return error codes returned in `SbiRet.error` are shown in the table below:
+    ///
+    /// | Return code               | Description 
+    /// |:--------------------------|:----------------------------------------------
+    /// | SBI_SUCCESS               | counter started successfully.
+    /// | SBI_ERR_INVALID_PARAM     | `start_addr` is not valid possibly due to following reasons: 1. It is not a valid physical address. 2. The address is prohibited by PMP to run in supervisor mode.
+    /// | SBI_ERR_INVALID_PARAM     | `hartid` is not a valid hartid as corresponding hart cannot started in supervisor mode. 
+    /// | SBI_ERR_ALREADY_AVAILABLE | The given hartid is already started.
+    /// | SBI_ERR_FAILED            | The start request failed for unknown reasons.
+    ///
+    /// # Flags
+    /// 
+    ///| Flag Name                    | Bits       | Description
+    /// | SBI_PMU_START_SET_INIT_VALUE | 0:0        | Set the value of counters
+    /// based on the `initial_value`
+    /// parameter
+    /// | SBI_PMU_START_FLAG_INIT_SNAPSHOT | 1:1    | Initialize the given counters
+    /// from shared memory if available
+    /// | *RESERVED*                   | 2:(XLEN-1) | All non-zero values are
+    /// reserved for future use
+    /// 
+    /// *NOTE:* When SBI_PMU_START_SET_INIT_VALUE is not set in `start_flags`,
+    /// the counter value will not be modified and event counting will start
+    /// from current counter value.
+    ///
+    /// Firmware counters take `initial_value` like hardware counters do, as clarified by the SBI 2.0 errata;
+    /// `sbi_pmu_counter_fw_read` then reads the firmware events counted on top of it.
+    ///
+    /// With SBI_PMU_START_FLAG_INIT_SNAPSHOT, counter `counter_idx_base + i` starts from `counter_values[i]`
+    /// of the snapshot shared memory, and `initial_value` is ignored.
+    /// 
+    /// # Errors
+    /// 
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | counter started successfully.
+    /// | SBI_ERR_INVALID_PARAM   | some of the counters specified in parameters
+    ///                             are invalid.
+    /// | SBI_ERR_ALREADY_STARTED | some of the counters specified in parameters
+    ///                             are already started.
+    /// | SBI_ERR_NO_SHMEM        | the snapshot shared memory is not available and
+    ///                             SBI_PMU_START_FLAG_INIT_SNAPSHOT is set.
+    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value:u64) -> SbiRet;
+    /// Stop or disable a set of counters on the calling HART. The `counter_idx_base`
+    ///and `counter_idx_mask` parameters represent the set of counters. The bit
+    ///definitions of the `stop_flags` parameter are shown in the below table.
+    /// 
+    /// # Flags
+    /// | Flag Name               | Bits       | Description
+    /// | SBI_PMU_STOP_FLAG_RESET | 0:0        | Reset the counter to event mapping.
+    /// | SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT | 1:1 | Save a snapshot of the given counters' values in the shared memory.
+    /// | *RESERVED*              | 2:(XLEN-1) | All non-zero values are reserved
+    ///
+    /// With SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT, the final value of counter `counter_idx_base + i` is written into
+    /// `counter_values[i]` of the snapshot shared memory and its overflow into bit `i` of `counter_overflow_bitmap`,
+    /// for counters in the set already stopped as well.
+    ///     
+    /// # Errors
+    /// 
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | counter stopped successfully.
+    /// | SBI_ERR_INVALID_PARAM   | some of the counters specified in parameters
+    ///                             are invalid.
+    /// | SBI_ERR_ALREADY_STOPPED | some of the counters specified in parameters
+    ///                             are already stopped.
+    /// | SBI_ERR_NO_SHMEM        | the snapshot shared memory is not available and
+    ///                             SBI_PMU_STOP_FLAG_TAKE_SNAPSHOT is set.
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet;
+    /// | Function Name                   | SBI Version | FID | EID
+    /// | sbi_pmu_num_counters            | 0.3         | 0   | 0x504D55
+    /// | sbi_pmu_counter_get_info        | 0.3         | 1   | 0x504D55
+    /// | sbi_pmu_counter_config_matching | 0.3         | 2   | 0x504D55
+    /// | sbi_pmu_counter_start           | 0.3         | 3   | 0x504D55
+    /// | sbi_pmu_counter_stop            | 0.3         | 4   | 0x504D55
+    /// | sbi_pmu_counter_fw_read         | 0.3         | 5   | 0x504D55
+    /// | sbi_pmu_counter_fw_read_hi      | 2.0         | 6   | 0x504D55
+    /// Low bits is SBI implementation ID. The firmware specific SBI extensions are
+    /// for SBI implementations. It provides firmware specific SBI functions which
+    /// are defined in the external firmware specification.
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet;
+    /// Provide the upper 32 bits of the current firmware counter value in `SbiRet.value`.
+    ///
+    /// This function always returns zero in `SbiRet.value` for RV64 (or higher) systems.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | firmware counter read successfully.
+    /// | SBI_ERR_INVALID_PARAM   | `counter_idx` points to a hardware counter supervisor can read, or an invalid counter.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        drop(counter_idx);
+        SbiRet::not_supported()
+    }
+    /// Set and enable the PMU snapshot shared memory on the calling hart.
+    ///
+    /// The layout of the snapshot shared memory is described by `SnapshotArea`; it is 4096 bytes long
+    /// and the SBI implementation writes counter overflow status and counter values into it.
+    ///
+    /// # Parameters
+    ///
+    /// - The `shmem_phys_lo` parameter is the lower XLEN bits of the 4096 bytes aligned physical address
+    ///   of the shared memory.
+    /// - The `shmem_phys_hi` parameter is the upper XLEN bits of the physical address of the shared memory.
+    ///
+    /// If both `shmem_phys_lo` and `shmem_phys_hi` are all-ones bitwise, the snapshot shared memory is disabled.
+    /// RustSBI disables it this way when the calling hart stops through the HSM extension, and it is forgotten
+    /// along with other counter state by `pmu_reset`, so that the firmware never writes into memory of a
+    /// previous supervisor.
+    /// - The `flags` parameter is reserved for future use and must be zero.
+    ///
+    /// # Return value
+    ///
+    /// The possible return error codes returned in `SbiRet.error` are shown in the table below:
+    ///
+    /// | Return code             | Description
+    /// |:------------------------|:----------------------------------------------
+    /// | SBI_SUCCESS             | Shared memory was set or cleared successfully.
+    /// | SBI_ERR_NOT_SUPPORTED   | The SBI PMU snapshot functionality is not available in the SBI implementation.
+    /// | SBI_ERR_INVALID_PARAM   | The `flags` parameter is not zero or the `shmem_phys_lo` parameter is not 4096 bytes aligned.
+    /// | SBI_ERR_INVALID_ADDRESS | The shared memory pointed to by the `shmem_phys_lo` and `shmem_phys_hi` parameters is not accessible.
+    /// | SBI_ERR_FAILED          | The request failed for unspecified or unknown other reasons.
+    fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+        drop((shmem_phys_lo, shmem_phys_hi, flags));
+        SbiRet::not_supported()
+    }
+    /// Get details about a list of events, in the event information shared memory of `sbi_pmu_event_get_info`.
+    ///
+    /// Each entry carries an event index and event data given by supervisor; the implementation sets
+    /// bit 0 of its `output` field, `EVENT_INFO_SUPPORTED`, if the event can be counted, and clears it otherwise.
+    /// RustSBI checks the shared memory parameters, then calls this function with copies of a few entries at a time.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | event information written successfully.
+    /// | SBI_ERR_FAILED          | the request failed for unspecified or unknown other reasons.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_event_get_info(&self, entries: &mut [EventInfo]) -> SbiRet {
+        drop(entries);
+        SbiRet::not_supported()
+    }
+    /// Save counter state of the calling hart before it loses its register and CSR values.
+    ///
+    /// RustSBI calls this function before the calling hart is stopped or enters a non-retentive
+    /// suspend state through the HSM extension. The implementation should save counter values,
+    /// event bindings and which counters are started, so that `pmu_restore_context` can bring them back.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_save_context(&mut self) {}
+    /// Restore counter state of the calling hart saved by `pmu_save_context`.
+    ///
+    /// This function is called through `restore_pmu_context` by the platform's HSM implementation
+    /// when the hart resumes from a non-retentive suspend state or is started again.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_restore_context(&mut self) {}
+    /// Detect counter overflow of the calling hart by software, returns the overflow bitmap.
+    ///
+    /// Platforms without Sscofpmf extension have no hardware overflow bits; the implementation may
+    /// detect wrap-around of counter values instead, and report the result the same way as `scountovf`
+    /// and the `counter_overflow_bitmap` field of the snapshot shared memory.
+    ///
+    /// This function is called through `poll_pmu_overflow` by the platform. The default implementation returns 0.
+    fn pmu_poll_overflow(&mut self) -> usize {
+        0
+    }
+    /// Rotate multiplexed events of the calling hart over hardware counters.
+    ///
+    /// An implementation may count more events than the hardware has counters by letting them
+    /// take turns on hardware counters, and scaling the values reported to supervisor.
+    ///
+    /// This function is called through `rotate_pmu_multiplex` by the platform. The default implementation does nothing.
+    fn pmu_rotate_multiplex(&mut self) {}
+    /// Read current values of a set of counters of the calling hart, hardware and firmware counters alike.
+    ///
+    /// For every bit `i` set in `counter_idx_mask`, the value of counter `counter_idx_base + i` is written
+    /// into `values[i]`; other entries are left unchanged. RustSBI calls this function when supervisor makes
+    /// the batch read call of the firmware specific extension of RustSBI (EID `0x0A000004`, FID `2`), so that
+    /// tools sampling many counters need one call instead of one for each counter.
+    ///
+    /// Returns the number of counters read in `SbiRet.value`.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | counters read successfully.
+    /// | SBI_ERR_INVALID_PARAM   | set of counters has an invalid counter.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_counter_read_batch(&self, counter_idx_base: usize, counter_idx_mask: usize, values: &mut [u64; usize::BITS as usize]) -> SbiRet {
+        drop((counter_idx_base, counter_idx_mask, values));
+        SbiRet::not_supported()
+    }
+    /// Print counter bindings, running states and current values of the calling hart to the console.
+    ///
+    /// This function is a debug facility of RustSBI, called when supervisor makes the PMU dump call
+    /// of the firmware specific extension of RustSBI (EID `0x0A000004`, FID `0`).
+    ///
+    /// The default implementation prints nothing.
+    fn pmu_dump(&self) {}
+    /// Record one occurrence of a firmware event on the calling hart.
+    ///
+    /// Started firmware counters of the calling hart bound to `event_code` should be increased by one.
+    /// RustSBI calls this function when supervisor sends IPIs or remote fences and sets the timer;
+    /// platforms report other firmware events, such as received IPIs, through `count_firmware_event`.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_firmware_event(&self, event_code: usize) {
+        drop(event_code);
+    }
+    /// Record cycles spent by RustSBI handling one call of the PMU extension on the calling hart.
+    ///
+    /// Started firmware counters of the calling hart bound to `SBI_PMU_FW_PLATFORM` with `event_data`
+    /// `RUSTSBI_FW_PMU_ECALL_CYCLES` should be increased by `cycles`. RustSBI measures the cycles
+    /// with `mcycle`, so nothing is counted while the `cycle` counter is stopped.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_ecall_cycles(&self, cycles: u64) {
+        drop(cycles);
+    }
+    /// Record a platform specific firmware event on the calling hart.
+    ///
+    /// Started firmware counters of the calling hart bound to `SBI_PMU_FW_PLATFORM` with `event_data`
+    /// `event_data` should be increased by `value`, the cycles spent in a firmware path or the number of
+    /// occurrences. RustSBI reports `RUSTSBI_FW_PMU_CONSOLE_CYCLES` by itself; platforms report trap handling
+    /// through `count_firmware_cycles` and machine interrupts through `count_machine_interrupt`.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_platform_event(&self, event_data: u64, value: u64) {
+        drop((event_data, value));
+    }
+    /// Switch the calling hart to supervisor domain `domain`.
+    ///
+    /// Platforms running several supervisors in separate domains call `enter_pmu_domain` before the
+    /// calling hart starts running a different domain, so that implementations which partition counters
+    /// among domains, such as `DomainPmu`, apply the partition of the new domain.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_enter_domain(&mut self, domain: usize) {
+        drop(domain);
+    }
+    /// Coarsen values of event `event_idx` read by supervisor, against timing side channels.
+    ///
+    /// RustSBI calls this function when supervisor makes the event protection call of the firmware specific
+    /// extension of RustSBI (EID `0x0A000004`, FID `3`). Values of counters bound to the event should be
+    /// rounded down to a multiple of `quantum` and have a random number below `jitter` added; zero leaves
+    /// either out. Implementations never weaken protection set before.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | event protected successfully.
+    /// | SBI_ERR_DENIED          | the platform does not let supervisor protect events.
+    /// | SBI_ERR_INVALID_PARAM   | `event_idx` is not a valid event index.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_protect_event(&mut self, event_idx: usize, quantum: u64, jitter: u64) -> SbiRet {
+        drop((event_idx, quantum, jitter));
+        SbiRet::not_supported()
+    }
+    /// Read the value of firmware counter `counter_idx` and reset it to zero in one step.
+    ///
+    /// RustSBI calls this function when supervisor makes the read and clear call of the firmware specific
+    /// extension of RustSBI (EID `0x0A000004`, FID `6`). Tools measuring intervals would otherwise read the
+    /// counter and then start it again with an initial value of zero, losing events counted in between.
+    /// The returned value is the lower XLEN bits of the counter value, as with `sbi_pmu_counter_fw_read`.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | counter read and cleared successfully.
+    /// | SBI_ERR_INVALID_PARAM   | `counter_idx` does not point to a firmware counter.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_counter_read_clear(&mut self, counter_idx: usize) -> SbiRet {
+        drop(counter_idx);
+        SbiRet::not_supported()
+    }
+    /// Summarize counter state of hart `hartid`, which may be other than the calling hart.
+    ///
+    /// RustSBI calls this function when supervisor makes the hart status call of the firmware specific
+    /// extension of RustSBI (EID `0x0A000004`, FID `7`), after checking `hartid` with `sbi_hart_get_status`
+    /// if the platform has the HSM extension. System-wide profilers use it to coordinate from one hart.
+    /// Implementations only have the state they keep in memory for other harts; values of CSRs, such as
+    /// the overflow bits of Sscofpmf, are only seen on the calling hart.
+    ///
+    /// The default implementation returns `None`, which is reported as `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_hart_summary(&self, hartid: usize) -> Option<HartSummary> {
+        drop(hartid);
+        None
+    }
+    /// Count firmware event `event_idx` across all harts, from their firmware counters.
+    ///
+    /// RustSBI calls this function when supervisor makes the event total call of the firmware specific
+    /// extension of RustSBI (EID `0x0A000004`, FID `9`), so that a system-wide count does not take one call
+    /// on every hart. The value of each hart is the value of its first firmware counter bound to the event,
+    /// or zero if none is; it is written into `per_hart[hartid]` for harts within the slice, and the sum over
+    /// all harts is returned in `SbiRet.value`, lower XLEN bits only.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | event counted successfully.
+    /// | SBI_ERR_INVALID_PARAM   | `event_idx` is not a firmware event.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        drop((event_idx, per_hart));
+        SbiRet::not_supported()
+    }
+    /// Find counters of the calling hart which supervisor configured in its current session and never released.
+    ///
+    /// RustSBI calls this function when supervisor makes the leak check call of the firmware specific extension
+    /// of RustSBI (EID `0x0A000004`, FID `10`). A session starts when supervisor first configures a counter on
+    /// the hart, and again after each call with `end_session`. Counters configured by
+    /// `sbi_pmu_counter_config_matching` in the session which are still bound to an event, never stopped with
+    /// `SBI_PMU_STOP_FLAG_RESET`, are leaked; bit `i` of `SbiRet.value` is set if counter `counter_idx_base + i`
+    /// is. With `end_session`, leaked counters are also printed to the console and a new session starts; they
+    /// stay bound to their events.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | counters checked successfully.
+    /// | SBI_ERR_INVALID_PARAM   | `counter_idx_base` is not a valid counter index.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`; `GenericPmu` keeps sessions with the
+    /// `leak-check` feature.
+    fn pmu_leak_check(&mut self, counter_idx_base: usize, end_session: bool) -> SbiRet {
+        drop((counter_idx_base, end_session));
+        SbiRet::not_supported()
+    }
+    /// Stop all counters of the calling hart and unbind them from their events.
+    ///
+    /// RustSBI calls this function when supervisor makes the reclaim call of the firmware specific extension
+    /// of RustSBI (EID `0x0A000004`, FID `11`), so that a kernel started after a crash frees the counters its
+    /// predecessor left bound, without knowing which they are. Firmware counters are also cleared. The number
+    /// of counters which were bound is returned in `SbiRet.value`.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | counters reclaimed successfully.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_reclaim(&mut self) -> SbiRet {
+        SbiRet::not_supported()
+    }
+    /// Arm a watchpoint on firmware counter `counter_idx` of the calling hart, notifying supervisor once its value
+    /// reaches `threshold`.
+    ///
+    /// RustSBI calls this function when supervisor makes the counter watch call of the firmware specific extension
+    /// of RustSBI (EID `0x0A000004`, FID `13`), which gives event-triggered profiling on platforms without Sscofpmf.
+    /// The first firmware event counted with the counter value at or above `threshold` notifies supervisor as
+    /// `notify` asks, then the watchpoint is disarmed; a counter started with a larger initial value notifies on its
+    /// next event. Arming the counter again replaces its watchpoint, a `threshold` of zero disarms it, and so does
+    /// unbinding the counter from its event.
+    ///
+    /// # Errors
+    ///
+    /// | Error code              | Description
+    /// | SBI_SUCCESS             | watchpoint armed or disarmed successfully.
+    /// | SBI_ERR_INVALID_PARAM   | `counter_idx` does not point to a firmware counter bound to an event.
+    /// | SBI_ERR_DENIED          | values of the counter are not to be seen exactly by supervisor.
+    ///
+    /// The default implementation returns `SBI_ERR_NOT_SUPPORTED`.
+    fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: WatchNotify) -> SbiRet {
+        drop((counter_idx, threshold, notify));
+        SbiRet::not_supported()
+    }
+    /// Forget counter state left by supervisor, so that the next supervisor starts with none.
+    ///
+    /// RustSBI calls this function before a cold or warm reboot through the system reset extension, with
+    /// `all_harts`; platforms call it through `reset_pmu_hart` when a hart is reset on its own. All counters
+    /// of the calling hart are unbound from their events and stop counting, and firmware counters are cleared,
+    /// along with the snapshot shared memory and saved state. With `all_harts`, state of other harts kept in
+    /// memory is cleared as well. Before that, RustSBI has every other started hart call this function without
+    /// `all_harts` through `PmuIpi`, if the platform registered one, so that no counter is left running unbound;
+    /// otherwise counter CSRs of other harts are only reset with the harts themselves.
+    ///
+    /// The default implementation does nothing.
+    fn pmu_reset(&mut self, all_harts: bool) {
+        drop(all_harts);
+    }
+    /// Value of hardware counter `counter_idx` of the calling hart for a read of its counter CSR by supervisor,
+    /// which trapped because the counter's `mcounteren` bit is clear.
+    ///
+    /// RustSBI calls this function from `emulate_counter_csr_read`, which platforms call in their illegal
+    /// instruction handler. Implementations which cleared the `mcounteren` bit themselves, such as `GenericPmu`
+    /// with `StoppedRead::Frozen` for stopped counters, return the value the counter was stopped with; those
+    /// emulating reads of counters the platform keeps away from supervisor, such as `GenericPmu` with
+    /// `HiddenRead::Emulate`, return the value supervisor would read through `sbi_pmu_counter_fw_read`.
+    /// `None` leaves the illegal instruction to supervisor.
+    ///
+    /// The default implementation returns `None`.
+    fn pmu_emulate_counter_read(&self, counter_idx: usize) -> Option<u64> {
+        drop(counter_idx);
+        None
+    }
+    /// Optional capabilities of this implementation on the calling hart, as `RUSTSBI_PMU_CAP_*` bits.
+    ///
+    /// RustSBI returns these bits when supervisor makes the capabilities call of the firmware specific extension
+    /// of RustSBI (EID `0x0A000004`, FID `16`), so that test kernels and tools adapt to the firmware build instead
+    /// of probing each capability by trial and error. `RUSTSBI_PMU_CAP_SAMPLER` is added by RustSBI itself when
+    /// the platform registered a sample timer, as the sampler is not part of the PMU implementation.
+    ///
+    /// The default implementation returns zero, no optional capability.
+    fn pmu_capabilities(&self) -> usize {
+        0
+    }
+}
+
+/// Layout of the PMU snapshot shared memory
+///
+/// | Name                    | Offset | Size | Description
+/// |:------------------------|:-------|:-----|:------------
+/// | counter_overflow_bitmap | 0x0000 | 8    | A bitmap of all logical overflown counters relative to the `counter_idx_base`.
+/// | counter_values          | 0x0008 | 512  | An array of 64-bit logical counters where each index represents the value of each logical counter associated with hardware/firmware relative to the `counter_idx_base`.
+/// | mtime                   | 0x0208 | 8    | RustSBI extension: value of `mtime` when the counter values were written.
+/// | *RESERVED*              | 0x0210 | 3568 | Reserved for future use.
+///
+/// When the platform does not implement Sscofpmf extension, the SBI implementation may
+/// emulate overflow detection and report the result in `counter_overflow_bitmap`.
+///
+/// The `mtime` field takes a word of the reserved space, so that supervisor pairs counter values with the time
+/// they were taken at, and computes rates without reading the `time` counter separately. It is the same clock
+/// as the `time` counter, and zero if the platform does not provide it.
+#[repr(C)]
+pub struct SnapshotArea {
+    /// A bitmap of all logical overflown counters
+    pub counter_overflow_bitmap: u64,
+    /// Values of logical counters
+    pub counter_values: [u64; 64],
+    /// Value of `mtime` when the snapshot was taken
+    pub mtime: u64,
+    reserved: [u64; 446],
+}
+
+/// Entry of the event information shared memory of `sbi_pmu_event_get_info`
+///
+/// | Name       | Offset | Size | Description
+/// |:-----------|:-------|:-----|:------------
+/// | event_idx  | 0x0    | 4    | Event index, written by supervisor.
+/// | output     | 0x4    | 4    | Output of the query; bit 0 is set if the event is supported.
+/// | event_data | 0x8    | 8    | Event data, written by supervisor.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EventInfo {
+    /// Event index of the query
+    pub event_idx: u32,
+    /// Output of the query
+    pub output: u32,
+    /// Event data of the query
+    pub event_data: u64,
+}
+
+/// Bit of `EventInfo::output`, set if the event is supported
+pub const EVENT_INFO_SUPPORTED: u32 = 1;
+
+/// Counter state of one hart, returned by `Pmu::pmu_hart_summary`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HartSummary {
+    /// Number of counters bound to an event
+    pub bound: usize,
+    /// Number of counters started by supervisor
+    pub started: usize,
+    /// Whether some counter has overflowed and the overflow is not yet handled by supervisor
+    pub overflow_pending: bool,
+    /// Number of started counters no SBI call used for longer than the idle timeout, zero if there is none
+    pub idle: usize,
+}
+
+impl HartSummary {
+    /// Encode the summary into `SbiRet.value` of the hart status call
+    ///
+    /// | Bits   | Description
+    /// |:-------|:------------
+    /// | 0..8   | Number of started counters
+    /// | 8..16  | Number of bound counters
+    /// | 16     | Overflow pending
+    /// | 17..25 | Number of idle counters
+    pub fn encode(&self) -> usize {
+        self.started.min(0xff) | self.bound.min(0xff) << 8 | (self.overflow_pending as usize) << 16 | self.idle.min(0xff) << 17
+    }
+}
+
+/// How supervisor is notified when a counter reaches the threshold of its watchpoint, see `Pmu::pmu_counter_watch`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchNotify {
+    /// Raise a supervisor software interrupt on the hart of the counter, by setting `mip.SSIP`
+    SoftwareInterrupt,
+    /// Write the counter value, as a 64-bit value, into supervisor memory at this physical address; RustSBI
+    /// checks the address may be written when the watchpoint is set
+    SharedMemory(usize),
+}
+
+/// Size of the PMU snapshot shared memory in bytes
+pub const SNAPSHOT_AREA_SIZE: usize = 4096;
+
+// `SnapshotArea` is written over the whole shared memory, and its fields are 64-bit words on RV32 as well;
+// these fail to compile if either changes
+const _: [(); SNAPSHOT_AREA_SIZE] = [(); core::mem::size_of::<SnapshotArea>()];
+const _: [(); 8] = [(); core::mem::align_of::<SnapshotArea>()];
+
+/// PMU extension version 0.3, encoded the same way as SBI specification version
+pub const PMU_VERSION_0_3: usize = 3;
+/// PMU extension version 3.0, which adds hardware raw events v2
+pub const PMU_VERSION_3_0: usize = 3 << 24;
+
+/// Bits of `event_data` holding the event selector of hardware raw events
+pub const RAW_EVENT_MASK: u64 = 0xFFFF_FFFF_FFFF;
+
+/// Generalized hardware cache event, decoded from the event code of event type 1
+///
+/// Generalized hardware cache events are `{ L1-D, L1-I, LLC, DTLB, ITLB, BPU, NODE } x
+/// { read, write, prefetch } x { accesses, misses }`, encoded in the event code as follows:
+///
+/// | Bits  | Description
+/// |:------|:------------
+/// | 15:3  | `cache_id`
+/// | 2:1   | `op_id`
+/// | 0:0   | `result_id`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheEvent {
+    /// Cache or buffer being monitored
+    pub id: CacheId,
+    /// Operation on the cache
+    pub op: CacheOp,
+    /// Result of the operation
+    pub result: CacheResult,
+}
+
+/// Cache or buffer of a generalized hardware cache event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheId {
+    /// Level 1 data cache
+    L1d = 0,
+    /// Level 1 instruction cache
+    L1i = 1,
+    /// Last level cache
+    Ll = 2,
+    /// Data TLB
+    Dtlb = 3,
+    /// Instruction TLB
+    Itlb = 4,
+    /// Branch prediction unit
+    Bpu = 5,
+    /// NUMA node cache
+    Node = 6,
+}
+
+/// Operation of a generalized hardware cache event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheOp {
+    /// Read access
+    Read = 0,
+    /// Write access
+    Write = 1,
+    /// Prefetch access
+    Prefetch = 2,
+}
+
+/// Result of a generalized hardware cache event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheResult {
+    /// Access to the cache
+    Access = 0,
+    /// Miss of the cache
+    Miss = 1,
+}
+
+impl CacheEvent {
+    /// Decode a cache event, returns `None` if the event is not a hardware cache event
+    /// or any of its fields is reserved.
+    pub fn decode(event: EventIdx) -> Option<CacheEvent> {
+        if event.event_type() != EVENT_TYPE_HARDWARE_CACHE {
+            return None;
+        }
+        let code = event.code();
+        let id = match code >> 3 {
+            0 => CacheId::L1d,
+            1 => CacheId::L1i,
+            2 => CacheId::Ll,
+            3 => CacheId::Dtlb,
+            4 => CacheId::Itlb,
+            5 => CacheId::Bpu,
+            6 => CacheId::Node,
+            _ => return None,
+        };
+        let op = match (code >> 1) & 0b11 {
+            0 => CacheOp::Read,
+            1 => CacheOp::Write,
+            2 => CacheOp::Prefetch,
+            _ => return None,
+        };
+        let result = match code & 0b1 {
+            0 => CacheResult::Access,
+            _ => CacheResult::Miss,
+        };
+        Some(CacheEvent { id, op, result })
+    }
+    /// Encode the cache event into an event index of event type 1.
+    pub const fn encode(self) -> EventIdx {
+        let code = ((self.id as usize) << 3) | ((self.op as usize) << 1) | self.result as usize;
+        EventIdx::from_parts(EVENT_TYPE_HARDWARE_CACHE, code)
+    }
+}
+
+/// Counter overflow bit of `mhpmeventX` defined by Sscofpmf extension
+pub const MHPMEVENT_OF: u64 = 1 << 63;
+/// Counting inhibited in M-mode, bit of `mhpmeventX` defined by Sscofpmf extension
+pub const MHPMEVENT_MINH: u64 = 1 << 62;
+/// Counting inhibited in S/HS-mode, bit of `mhpmeventX` defined by Sscofpmf extension
+pub const MHPMEVENT_SINH: u64 = 1 << 61;
+/// Counting inhibited in U-mode, bit of `mhpmeventX` defined by Sscofpmf extension
+pub const MHPMEVENT_UINH: u64 = 1 << 60;
+/// Counting inhibited in VS-mode, bit of `mhpmeventX` defined by Sscofpmf extension
+pub const MHPMEVENT_VSINH: u64 = 1 << 59;
+/// Counting inhibited in VU-mode, bit of `mhpmeventX` defined by Sscofpmf extension
+pub const MHPMEVENT_VUINH: u64 = 1 << 58;
+
+/// Convert privilege filter hints in `config_flags` into Sscofpmf `mhpmeventX` inhibit bits.
+///
+/// The returned value should be or-ed into the event selector written to `mhpmeventX`;
+/// platforms without Sscofpmf extension should ignore these hints, and platforms without hypervisor
+/// extension should leave out `MHPMEVENT_VSINH` and `MHPMEVENT_VUINH`.
+#[inline]
+pub fn mhpmevent_inhibit_bits(config_flags: usize) -> u64 {
+    let mut bits = 0;
+    if config_flags & SBI_PMU_CFG_FLAG_SET_MINH != 0 {
+        bits |= MHPMEVENT_MINH;
+    }
+    if config_flags & SBI_PMU_CFG_FLAG_SET_SINH != 0 {
+        bits |= MHPMEVENT_SINH;
+    }
+    if config_flags & SBI_PMU_CFG_FLAG_SET_UINH != 0 {
+        bits |= MHPMEVENT_UINH;
+    }
+    if config_flags & SBI_PMU_CFG_FLAG_SET_VSINH != 0 {
+        bits |= MHPMEVENT_VSINH;
+    }
+    if config_flags & SBI_PMU_CFG_FLAG_SET_VUINH != 0 {
+        bits |= MHPMEVENT_VUINH;
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SnapshotArea, SNAPSHOT_AREA_SIZE};
+    use alloc::boxed::Box;
+
+    // `struct riscv_pmu_snapshot_data` of the Linux `riscv_pmu_sbi` driver: `u64 ctr_overflow_mask`, then
+    // `u64 ctr_values[64]`, then `u64 reserved[447]`, all little-endian like RISC-V itself
+    const LINUX_CTR_OVERFLOW_MASK: usize = 0x0;
+    const LINUX_CTR_VALUES: usize = 0x8;
+    const LINUX_RESERVED: usize = 0x208;
+
+    fn area() -> Box<SnapshotArea> {
+        let mut counter_values = [0; 64];
+        for (i, value) in counter_values.iter_mut().enumerate() {
+            *value = 0x0101_0101_0101_0101 * i as u64 | 0x8000_0000_0000_0000;
+        }
+        Box::new(SnapshotArea {
+            counter_overflow_bitmap: 0x0807_0605_0403_0201,
+            counter_values,
+            mtime: 0x1122_3344_5566_7788,
+            reserved: [0; 446],
+        })
+    }
+
+    fn bytes(area: &SnapshotArea) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(area as *const SnapshotArea as *const u8, SNAPSHOT_AREA_SIZE) }
+    }
+
+    #[test]
+    fn snapshot_area_field_offsets() {
+        let area = area();
+        let base = &*area as *const SnapshotArea as usize;
+        assert_eq!(&area.counter_overflow_bitmap as *const u64 as usize - base, LINUX_CTR_OVERFLOW_MASK);
+        assert_eq!(area.counter_values.as_ptr() as usize - base, LINUX_CTR_VALUES);
+        for i in 0..64 {
+            assert_eq!(&area.counter_values[i] as *const u64 as usize - base, LINUX_CTR_VALUES + 8 * i);
+        }
+        // `mtime` takes the first reserved word, which the Linux driver never reads
+        assert_eq!(&area.mtime as *const u64 as usize - base, LINUX_RESERVED);
+        assert_eq!(area.reserved.as_ptr() as usize - base, LINUX_RESERVED + 8);
+    }
+
+    #[test]
+    fn snapshot_area_bytes_match_linux() {
+        let area = area();
+        let bytes = bytes(&area);
+        // the bitmap is little-endian: counter `counter_idx_base + i` is bit `i % 8` of byte `i / 8`
+        assert_eq!(bytes[..8], [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        assert_eq!(bytes[LINUX_CTR_VALUES..LINUX_CTR_VALUES + 8], [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80]);
+        assert_eq!(bytes[LINUX_CTR_VALUES + 8..LINUX_CTR_VALUES + 16], [0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x81]);
+        let mut golden = [0u8; SNAPSHOT_AREA_SIZE];
+        golden[LINUX_CTR_OVERFLOW_MASK..LINUX_CTR_OVERFLOW_MASK + 8].copy_from_slice(&0x0807_0605_0403_0201u64.to_le_bytes());
+        for i in 0..64 {
+            let value = 0x0101_0101_0101_0101 * i as u64 | 0x8000_0000_0000_0000;
+            golden[LINUX_CTR_VALUES + 8 * i..LINUX_CTR_VALUES + 8 * i + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        golden[LINUX_RESERVED..LINUX_RESERVED + 8].copy_from_slice(&0x1122_3344_5566_7788u64.to_le_bytes());
+        assert_eq!(bytes, &golden[..]);
+    }
+}
@@ -0,0 +1,231 @@
+//! PMU implementations wrapping another one to log calls or restrict events
+
+use super::{EventIdx, EventInfo, HartSummary, Pmu, WatchNotify, EVENT_INFO_SUPPORTED};
+use crate::ecall::SbiRet;
+use core::fmt;
+
+/// PMU implementation which prints every call from supervisor and its result to the console
+///
+/// Each call of the PMU extension, and each batch read call of the firmware specific extension of RustSBI,
+/// is printed as one line after the wrapped implementation returns. Context save and restore and domain switches
+/// made by the platform are printed as well, and so is overflow polling when it finds overflown counters.
+/// Multiplexing rotation, which runs on every timer tick, and firmware events, which are counted on
+/// the fast path and may be counted in interrupt handlers, are not printed.
+///
+/// Unlike the `trace` feature, which traces SBI calls as they arrive at `rustsbi::ecall`, this wrapper
+/// is placed at any layer of composed PMU implementations; wrapped around a `FilteredPmu`, it prints
+/// what supervisor asked for, and wrapped inside it, what the filter let through.
+///
+/// ```no_run
+/// use rustsbi::pmu::{FilteredPmu, GenericPmu, TracedPmu};
+///
+/// rustsbi::init_pmu(TracedPmu::new(FilteredPmu::new(GenericPmu::new(platform)).deny_raw()));
+/// ```
+pub struct TracedPmu<T> {
+    inner: T,
+}
+
+impl<T: Pmu> TracedPmu<T> {
+    /// Print calls to `inner`.
+    #[inline]
+    pub const fn new(inner: T) -> TracedPmu<T> {
+        TracedPmu { inner }
+    }
+    /// The wrapped PMU implementation.
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+fn trace(call: fmt::Arguments, ret: &SbiRet) -> SbiRet {
+    crate::println!("[rustsbi-pmu] {} -> error {}, value {:#x}", call, ret.error as isize, ret.value);
+    SbiRet { error: ret.error, value: ret.value }
+}
+
+impl<T: Pmu> Pmu for TracedPmu<T> {
+    fn pmu_num_counters(&self) -> SbiRet {
+        trace(format_args!("num_counters()"), &self.inner.pmu_num_counters())
+    }
+    fn pmu_counter_get_info(&self, counter_idx: usize) -> SbiRet {
+        let ret = self.inner.pmu_counter_get_info(counter_idx);
+        trace(format_args!("counter_get_info({})", counter_idx), &ret)
+    }
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        let ret = self.inner.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data);
+        trace(
+            format_args!(
+                "counter_config_matching({}, {:#x}, {:#x}, {:#x}, {:#x})",
+                counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data
+            ),
+            &ret,
+        )
+    }
+    fn pmu_counter_start(&mut self, counter_idx_base: usize, counter_idx_mask: usize, start_flags: usize, initial_value: u64) -> SbiRet {
+        let ret = self.inner.pmu_counter_start(counter_idx_base, counter_idx_mask, start_flags, initial_value);
+        trace(
+            format_args!("counter_start({}, {:#x}, {:#x}, {:#x})", counter_idx_base, counter_idx_mask, start_flags, initial_value),
+            &ret,
+        )
+    }
+    fn pmu_counter_stop(&mut self, counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> SbiRet {
+        let ret = self.inner.pmu_counter_stop(counter_idx_base, counter_idx_mask, stop_flags);
+        trace(format_args!("counter_stop({}, {:#x}, {:#x})", counter_idx_base, counter_idx_mask, stop_flags), &ret)
+    }
+    fn pmu_counter_fw_read(&self, counter_idx: usize) -> SbiRet {
+        let ret = self.inner.pmu_counter_fw_read(counter_idx);
+        trace(format_args!("counter_fw_read({})", counter_idx), &ret)
+    }
+    fn pmu_counter_fw_read_hi(&self, counter_idx: usize) -> SbiRet {
+        let ret = self.inner.pmu_counter_fw_read_hi(counter_idx);
+        trace(format_args!("counter_fw_read_hi({})", counter_idx), &ret)
+    }
+    fn pmu_snapshot_set_shm(&mut self, shmem_phys_lo: usize, shmem_phys_hi: usize, flags: usize) -> SbiRet {
+        let ret = self.inner.pmu_snapshot_set_shm(shmem_phys_lo, shmem_phys_hi, flags);
+        trace(format_args!("snapshot_set_shm({:#x}, {:#x}, {:#x})", shmem_phys_lo, shmem_phys_hi, flags), &ret)
+    }
+    fn pmu_event_get_info(&self, entries: &mut [EventInfo]) -> SbiRet {
+        let ret = self.inner.pmu_event_get_info(entries);
+        trace(format_args!("event_get_info({} entries)", entries.len()), &ret)
+    }
+    fn pmu_save_context(&mut self) {
+        self.inner.pmu_save_context();
+        crate::println!("[rustsbi-pmu] save_context()");
+    }
+    fn pmu_restore_context(&mut self) {
+        self.inner.pmu_restore_context();
+        crate::println!("[rustsbi-pmu] restore_context()");
+    }
+    fn pmu_poll_overflow(&mut self) -> usize {
+        let overflow = self.inner.pmu_poll_overflow();
+        if overflow != 0 {
+            crate::println!("[rustsbi-pmu] poll_overflow() -> {:#x}", overflow);
+        }
+        overflow
+    }
+    fn pmu_counter_read_batch(&self, counter_idx_base: usize, counter_idx_mask: usize, values: &mut [u64; usize::BITS as usize]) -> SbiRet {
+        let ret = self.inner.pmu_counter_read_batch(counter_idx_base, counter_idx_mask, values);
+        trace(format_args!("counter_read_batch({}, {:#x})", counter_idx_base, counter_idx_mask), &ret)
+    }
+    fn pmu_enter_domain(&mut self, domain: usize) {
+        self.inner.pmu_enter_domain(domain);
+        crate::println!("[rustsbi-pmu] enter_domain({})", domain);
+    }
+    fn pmu_protect_event(&mut self, event_idx: usize, quantum: u64, jitter: u64) -> SbiRet {
+        let ret = self.inner.pmu_protect_event(event_idx, quantum, jitter);
+        trace(format_args!("protect_event({:#x}, {}, {})", event_idx, quantum, jitter), &ret)
+    }
+    fn pmu_counter_read_clear(&mut self, counter_idx: usize) -> SbiRet {
+        let ret = self.inner.pmu_counter_read_clear(counter_idx);
+        trace(format_args!("counter_read_clear({})", counter_idx), &ret)
+    }
+    fn pmu_hart_summary(&self, hartid: usize) -> Option<HartSummary> {
+        let summary = self.inner.pmu_hart_summary(hartid);
+        crate::println!("[rustsbi-pmu] hart_summary({}) = {:?}", hartid, summary);
+        summary
+    }
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        let ret = self.inner.pmu_firmware_event_total(event_idx, per_hart);
+        trace(format_args!("firmware_event_total({:#x}, {} harts)", event_idx, per_hart.len()), &ret)
+    }
+    fn pmu_leak_check(&mut self, counter_idx_base: usize, end_session: bool) -> SbiRet {
+        let ret = self.inner.pmu_leak_check(counter_idx_base, end_session);
+        trace(format_args!("leak_check({}, {})", counter_idx_base, end_session), &ret)
+    }
+    fn pmu_reclaim(&mut self) -> SbiRet {
+        let ret = self.inner.pmu_reclaim();
+        trace(format_args!("reclaim()"), &ret)
+    }
+    fn pmu_counter_watch(&mut self, counter_idx: usize, threshold: u64, notify: WatchNotify) -> SbiRet {
+        let ret = self.inner.pmu_counter_watch(counter_idx, threshold, notify);
+        trace(format_args!("counter_watch({}, {}, {:?})", counter_idx, threshold, notify), &ret)
+    }
+    crate::delegate_pmu!(inner => is_available, pmu_version, pmu_rotate_multiplex, pmu_dump, pmu_firmware_event,
+        pmu_ecall_cycles, pmu_reset, pmu_platform_event, pmu_emulate_counter_read, pmu_capabilities);
+}
+
+/// PMU implementation which restricts the event types supervisor may configure
+///
+/// Counters are configured for denied event types as if no counter could monitor them:
+/// `pmu_counter_config_matching` returns `SBI_ERR_NOT_SUPPORTED` without calling the wrapped
+/// implementation, and `pmu_event_get_info` reports them unsupported, as does `pmu_firmware_event_total`. Other calls
+/// are forwarded unchanged.
+///
+/// Hardware raw events select any event the hardware counts by its implementation specific encoding,
+/// which may reveal more about other software sharing the hart than generalized events do. Platforms
+/// running mutually distrusting supervisors deny them; raw events are selected by two event types,
+/// `EVENT_TYPE_HARDWARE_RAW` and `EVENT_TYPE_HARDWARE_RAW_V2`, and `deny_raw` denies both.
+pub struct FilteredPmu<T> {
+    inner: T,
+    // bit `i` set if event type `i` is allowed
+    allowed: u16,
+}
+
+impl<T: Pmu> FilteredPmu<T> {
+    /// Allow every event type on `inner`; restrict them with `deny` or `allow_only`.
+    #[inline]
+    pub const fn new(inner: T) -> FilteredPmu<T> {
+        FilteredPmu { inner, allowed: u16::MAX }
+    }
+    /// Deny events of `event_type`, one of the `EVENT_TYPE_*` constants.
+    #[inline]
+    pub const fn deny(mut self, event_type: usize) -> FilteredPmu<T> {
+        self.allowed &= !(1 << (event_type & 0xF));
+        self
+    }
+    /// Deny hardware raw events of both encodings.
+    #[inline]
+    pub const fn deny_raw(self) -> FilteredPmu<T> {
+        self.deny(super::EVENT_TYPE_HARDWARE_RAW).deny(super::EVENT_TYPE_HARDWARE_RAW_V2)
+    }
+    /// Allow only event types whose bits are set in `event_types`; bit `i` stands for event type `i`.
+    #[inline]
+    pub const fn allow_only(mut self, event_types: u16) -> FilteredPmu<T> {
+        self.allowed = event_types;
+        self
+    }
+    /// Check if supervisor may configure counters for `event_idx`.
+    #[inline]
+    pub fn is_allowed(&self, event_idx: usize) -> bool {
+        match EventIdx::new(event_idx) {
+            Some(event) => self.allowed & (1 << event.event_type()) != 0,
+            // rejected by the wrapped implementation
+            None => true,
+        }
+    }
+    /// The wrapped PMU implementation.
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Pmu> Pmu for FilteredPmu<T> {
+    fn pmu_counter_config_matching(&mut self, counter_idx_base: usize, counter_idx_mask: usize, config_flags: usize, event_idx: usize, event_data: u64) -> SbiRet {
+        if !self.is_allowed(event_idx) {
+            return SbiRet::not_supported();
+        }
+        self.inner.pmu_counter_config_matching(counter_idx_base, counter_idx_mask, config_flags, event_idx, event_data)
+    }
+    fn pmu_event_get_info(&self, entries: &mut [EventInfo]) -> SbiRet {
+        let ans = self.inner.pmu_event_get_info(entries);
+        for entry in entries.iter_mut() {
+            if !self.is_allowed(entry.event_idx as usize) {
+                entry.output &= !EVENT_INFO_SUPPORTED;
+            }
+        }
+        ans
+    }
+    fn pmu_firmware_event_total(&self, event_idx: usize, per_hart: &mut [u64]) -> SbiRet {
+        if !self.is_allowed(event_idx) {
+            return SbiRet::not_supported();
+        }
+        self.inner.pmu_firmware_event_total(event_idx, per_hart)
+    }
+    crate::delegate_pmu!(inner => is_available, pmu_version, pmu_num_counters, pmu_counter_get_info, pmu_counter_start,
+        pmu_counter_stop, pmu_counter_fw_read, pmu_counter_fw_read_hi, pmu_snapshot_set_shm, pmu_save_context,
+        pmu_restore_context, pmu_poll_overflow, pmu_rotate_multiplex, pmu_counter_read_batch, pmu_dump,
+        pmu_firmware_event, pmu_ecall_cycles, pmu_enter_domain, pmu_protect_event, pmu_counter_read_clear,
+        pmu_hart_summary, pmu_leak_check, pmu_reclaim, pmu_reset, pmu_platform_event, pmu_counter_watch,
+        pmu_emulate_counter_read, pmu_capabilities);
+}
@@ -81,6 +81,11 @@ pub(crate) fn probe_reset() -> bool {
 
 pub(crate) fn system_reset(reset_type: usize, reset_reason: usize) -> SbiRet {
     if let Some(obj) = &*RESET.lock() {
+        if reset_type == RESET_TYPE_COLD_REBOOT || reset_type == RESET_TYPE_WARM_REBOOT {
+            // counters of this boot must not carry over into the next one; the reset does not return if it succeeds
+            #[cfg(feature = "pmu")]
+            crate::pmu::reset_pmu(true);
+        }
         return obj.system_reset(reset_type, reset_reason);
     }
     SbiRet::not_supported()
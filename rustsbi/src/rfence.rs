@@ -1,5 +1,11 @@
 use crate::hart_mask::HartMask;
 use crate::ecall::SbiRet;
+#[cfg(feature = "pmu")]
+use crate::pmu::{
+    count_sent_event, SBI_PMU_FW_FENCE_I_SENT, SBI_PMU_FW_HFENCE_GVMA_SENT, SBI_PMU_FW_HFENCE_GVMA_VMID_SENT,
+    SBI_PMU_FW_HFENCE_VVMA_ASID_SENT, SBI_PMU_FW_HFENCE_VVMA_SENT, SBI_PMU_FW_SFENCE_VMA_ASID_SENT,
+    SBI_PMU_FW_SFENCE_VMA_SENT,
+};
 
 /// Remote fence support
 ///
@@ -135,57 +141,78 @@ pub(crate) fn probe_rfence() -> bool {
 }
 
 pub(crate) fn remote_fence_i(hart_mask: HartMask) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_fence_i(hart_mask)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_fence_i(hart_mask.clone())
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_FENCE_I_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn remote_sfence_vma(hart_mask: HartMask, start_addr: usize, size: usize) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_sfence_vma(hart_mask, start_addr, size)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_sfence_vma(hart_mask.clone(), start_addr, size)
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_SFENCE_VMA_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn remote_sfence_vma_asid(hart_mask: HartMask, start_addr: usize, size: usize, asid: usize) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_sfence_vma_asid(hart_mask, start_addr, size, asid)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_sfence_vma_asid(hart_mask.clone(), start_addr, size, asid)
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_SFENCE_VMA_ASID_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn remote_hfence_gvma_vmid(hart_mask: HartMask, start_addr: usize, size: usize, vmid: usize) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_hfence_gvma_vmid(hart_mask, start_addr, size, vmid)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_hfence_gvma_vmid(hart_mask.clone(), start_addr, size, vmid)
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_HFENCE_GVMA_VMID_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn remote_hfence_gvma(hart_mask: HartMask, start_addr: usize, size: usize) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_hfence_gvma(hart_mask, start_addr, size)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_hfence_gvma(hart_mask.clone(), start_addr, size)
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_HFENCE_GVMA_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn remote_hfence_vvma_asid(hart_mask: HartMask, start_addr: usize, size: usize, asid: usize) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_hfence_vvma_asid(hart_mask, start_addr, size, asid)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_hfence_vvma_asid(hart_mask.clone(), start_addr, size, asid)
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_HFENCE_VVMA_ASID_SENT, &hart_mask, &ans);
+    ans
 }
 
 pub(crate) fn remote_hfence_vvma(hart_mask: HartMask, start_addr: usize, size: usize) -> SbiRet {
-    if let Some(rfence) = RFENCE.lock().as_mut() {
-        rfence.remote_hfence_vvma(hart_mask, start_addr, size)
+    let ans = if let Some(rfence) = RFENCE.lock().as_mut() {
+        rfence.remote_hfence_vvma(hart_mask.clone(), start_addr, size)
     } else {
         SbiRet::not_supported()
-    }
+    };
+    #[cfg(feature = "pmu")]
+    count_sent_event(SBI_PMU_FW_HFENCE_VVMA_SENT, &hart_mask, &ans);
+    ans
 }
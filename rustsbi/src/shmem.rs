@@ -0,0 +1,146 @@
+//! Supervisor memory written by RustSBI
+//!
+//! Several calls take the physical address of a buffer in supervisor memory and write their results into it
+//! from machine mode, where PMP entries without the lock bit do not restrict accesses. Every such buffer is
+//! checked before anything is written, so that supervisor can not have the firmware overwrite its own text,
+//! data or stacks, or any memory hidden from supervisor.
+
+use crate::ecall::SbiRet;
+use alloc::boxed::Box;
+use core::mem::{align_of, size_of};
+use spin::RwLock;
+
+mod pmp;
+
+pub use pmp::pmp_allows_supervisor;
+
+/// Memory supervisor may share with RustSBI
+pub trait SharedMemory: Send + Sync {
+    /// Whether RustSBI may read and write `size` bytes at physical address `phys` on behalf of supervisor.
+    ///
+    /// Platforms should accept RAM outside the memory of the firmware only, which PMP lets supervisor read
+    /// and write as `pmp_allows_supervisor` checks.
+    fn accessible(&self, phys: usize, size: usize) -> bool;
+}
+
+lazy_static::lazy_static! {
+    static ref SHARED_MEMORY: RwLock<Option<Box<dyn SharedMemory>>> = RwLock::new(None);
+}
+
+/// Register the description of memory supervisor may share with RustSBI.
+pub fn init_shared_memory<T: SharedMemory + 'static>(shmem: T) {
+    *SHARED_MEMORY.write() = Some(Box::new(shmem));
+}
+
+/// Whether RustSBI may read and write `size` bytes at physical address `phys` on behalf of supervisor.
+///
+/// Without a description registered by `init_shared_memory`, only the PMP configuration of the calling hart
+/// is checked.
+pub fn shmem_accessible(phys: usize, size: usize) -> bool {
+    if phys.checked_add(size).is_none() {
+        return false;
+    }
+    match &*SHARED_MEMORY.read() {
+        Some(shmem) => shmem.accessible(phys, size),
+        None => match () {
+            #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+            () => pmp_allows_supervisor(phys, size),
+            // there is no machine mode to protect, as in host tests
+            #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+            () => true,
+        },
+    }
+}
+
+// check a buffer of `count` values of `T` at physical address `phys_lo` and `phys_hi` before writing into it,
+// returns a pointer to its first value; an empty buffer is never written and only checked for alignment
+pub(crate) fn check_shmem<T>(phys_lo: usize, phys_hi: usize, count: usize) -> Result<*mut T, SbiRet> {
+    if phys_lo % align_of::<T>() != 0 {
+        return Err(SbiRet::invalid_param());
+    }
+    if phys_hi != 0 {
+        // memory above XLEN bits is not accessible from machine mode
+        return Err(SbiRet::invalid_address());
+    }
+    let size = match count.checked_mul(size_of::<T>()) {
+        Some(size) => size,
+        None => return Err(SbiRet::invalid_param()),
+    };
+    if size != 0 && !shmem_accessible(phys_lo, size) {
+        return Err(SbiRet::invalid_address());
+    }
+    Ok(phys_lo as *mut T)
+}
+
+// memory not shared in host tests, heap allocations never lie there
+#[cfg(test)]
+pub(crate) const TEST_HIDDEN: core::ops::Range<usize> = 0x1000..0x2000;
+
+// register the memory description of host tests; every test registers the same one, in any order
+#[cfg(test)]
+pub(crate) fn init_test_shmem() {
+    struct TestMemory;
+    impl SharedMemory for TestMemory {
+        fn accessible(&self, phys: usize, size: usize) -> bool {
+            phys + size <= TEST_HIDDEN.start || TEST_HIDDEN.end <= phys
+        }
+    }
+    init_shared_memory(TestMemory);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // error of checking a buffer of `count` 64-bit values, zero if it may be written
+    fn check(phys_lo: usize, phys_hi: usize, count: usize) -> usize {
+        match check_shmem::<u64>(phys_lo, phys_hi, count) {
+            Ok(buf) => {
+                assert_eq!(buf as usize, phys_lo);
+                0
+            }
+            Err(ans) => ans.error,
+        }
+    }
+
+    #[test]
+    fn rejects_hidden_and_oversized_buffers() {
+        init_test_shmem();
+        let mut buf = [0u64; 4];
+        let phys = buf.as_mut_ptr() as usize;
+        assert_eq!(check(phys, 0, 4), 0);
+        assert_eq!(check(phys + 4, 0, 4), SbiRet::invalid_param().error);
+        assert_eq!(check(phys, 1, 4), SbiRet::invalid_address().error);
+        // a buffer reaching into hidden memory with its last value
+        assert_eq!(check(TEST_HIDDEN.start - 16, 0, 2), 0);
+        assert_eq!(check(TEST_HIDDEN.start - 16, 0, 3), SbiRet::invalid_address().error);
+        // the size of the buffer overflows, or the buffer wraps around the address space
+        assert_eq!(check(phys, 0, usize::MAX / 4), SbiRet::invalid_param().error);
+        assert_eq!(check(usize::MAX - 7, 0, 2), SbiRet::invalid_address().error);
+        // nothing is written into an empty buffer
+        assert_eq!(check(TEST_HIDDEN.start, 0, 0), 0);
+    }
+
+    #[test]
+    fn pmp_checks_supervisor_access() {
+        const NAPOT_RWX: u8 = 0x1F;
+        const TOR_RW: u8 = 0x0B;
+        const TOR_R: u8 = 0x09;
+        // 0x8000_0000..0x8020_0000 is read-only, then 0x8020_0000..0x8040_0000 readable and writable
+        let entries = [(TOR_R, 0x8020_0000 >> 2), (TOR_RW, 0x8040_0000 >> 2), (0, 0)];
+        // the first entry starts at address zero
+        assert!(!pmp::supervisor_read_write(&entries, 0x1000, 0x1000));
+        assert!(pmp::supervisor_read_write(&entries, 0x8030_0000, 0x1000));
+        assert!(!pmp::supervisor_read_write(&entries, 0x8010_0000, 0x1000));
+        // partially matching entries fail, and so do accesses no entry matches
+        assert!(!pmp::supervisor_read_write(&entries, 0x803F_F000, 0x2000));
+        assert!(!pmp::supervisor_read_write(&entries, 0x9000_0000, 0x1000));
+        // NAPOT entry of 0x8000_0000..0x8100_0000, taking priority over entries after it
+        let entries = [(NAPOT_RWX, 0x8000_0000 >> 2 | 0x1F_FFFF), (TOR_R, 0x8100_0000 >> 2)];
+        assert!(pmp::supervisor_read_write(&entries, 0x80FF_F000, 0x1000));
+        assert!(!pmp::supervisor_read_write(&entries, 0x8100_0000, 0x1000));
+        // without PMP entries, supervisor accesses all memory
+        assert!(pmp::supervisor_read_write(&[], 0x8000_0000, 0x1000));
+        assert!(!pmp::supervisor_read_write(&entries, u64::MAX, 2));
+    }
+}
@@ -0,0 +1,89 @@
+//! Supervisor access to physical memory under the PMP configuration
+//!
+//! Shared memory registered by supervisor is written by the firmware in machine mode, which PMP entries
+//! without the lock bit do not restrict. Memory supervisor itself cannot read and write must not be accepted,
+//! or the firmware would write into memory hidden from supervisor on its behalf.
+
+// number of PMP entries read on the calling hart, those of RV32 and RV64 both fit in four `pmpcfg` CSRs
+const PMP_ENTRIES: usize = 16;
+
+const PMP_R: u8 = 1 << 0;
+const PMP_W: u8 = 1 << 1;
+const PMP_A_SHIFT: u8 = 3;
+const PMP_A_TOR: u8 = 1;
+const PMP_A_NA4: u8 = 2;
+const PMP_A_NAPOT: u8 = 3;
+
+/// Whether supervisor can read and write `size` bytes at physical address `base` under the PMP configuration
+/// of the calling hart.
+///
+/// The first 16 PMP entries are read; the access must fall into the lowest-numbered entry it overlaps, and
+/// that entry must grant both reads and writes. Memory no entry matches is not accessible from supervisor.
+/// This function must be called in machine mode.
+pub fn pmp_allows_supervisor(base: usize, size: usize) -> bool {
+    supervisor_read_write(&read_pmp(), base as u64, size as u64)
+}
+
+// match the access against `pmpcfg` bytes and `pmpaddr` values of implemented entries, in priority order
+pub(super) fn supervisor_read_write(entries: &[(u8, usize)], base: u64, size: u64) -> bool {
+    let last = match size.checked_sub(1).and_then(|len| base.checked_add(len)) {
+        Some(last) => last,
+        None => return false,
+    };
+    let mut prev = 0;
+    for &(cfg, addr) in entries {
+        let addr = addr as u64;
+        // first and last byte of the entry, `None` if it matches nothing
+        let range = match (cfg >> PMP_A_SHIFT) & 0b11 {
+            PMP_A_TOR if addr > prev => Some((prev << 2, (addr << 2) - 1)),
+            PMP_A_NA4 => Some((addr << 2, (addr << 2) + 3)),
+            PMP_A_NAPOT => {
+                let bits = addr.trailing_ones() + 3;
+                let mask = if bits >= u64::BITS { u64::MAX } else { (1 << bits) - 1 };
+                Some(((addr << 2) & !mask, (addr << 2) | mask))
+            }
+            _ => None,
+        };
+        prev = addr;
+        if let Some((start, end)) = range {
+            if start <= last && base <= end {
+                // an access partially matching an entry fails, whatever the entry grants
+                return start <= base && last <= end && cfg & (PMP_R | PMP_W) == PMP_R | PMP_W;
+            }
+        }
+    }
+    entries.is_empty()
+}
+
+// `pmpcfg` byte and `pmpaddr` value of each entry
+fn read_pmp() -> [(u8, usize); PMP_ENTRIES] {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => {
+            macro_rules! csr {
+                ($csr: literal) => {{
+                    let value: usize;
+                    unsafe { asm!("csrr {}, {csr}", out(reg) value, csr = const $csr) };
+                    value
+                }};
+            }
+            // RV64 has only the even-numbered `pmpcfg` CSRs, each holding eight entries
+            #[cfg(target_pointer_width = "64")]
+            let cfgs = [csr!(0x3A0), csr!(0x3A2)];
+            #[cfg(target_pointer_width = "32")]
+            let cfgs = [csr!(0x3A0), csr!(0x3A1), csr!(0x3A2), csr!(0x3A3)];
+            let addrs = [
+                csr!(0x3B0), csr!(0x3B1), csr!(0x3B2), csr!(0x3B3), csr!(0x3B4), csr!(0x3B5), csr!(0x3B6), csr!(0x3B7),
+                csr!(0x3B8), csr!(0x3B9), csr!(0x3BA), csr!(0x3BB), csr!(0x3BC), csr!(0x3BD), csr!(0x3BE), csr!(0x3BF),
+            ];
+            let per_csr = core::mem::size_of::<usize>();
+            let mut entries = [(0, 0); PMP_ENTRIES];
+            for (i, entry) in entries.iter_mut().enumerate() {
+                *entry = ((cfgs[i / per_csr] >> (i % per_csr * 8)) as u8, addrs[i]);
+            }
+            entries
+        }
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => unimplemented!("not RISC-V instruction set architecture"),
+    }
+}
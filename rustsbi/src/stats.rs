@@ -0,0 +1,122 @@
+//! Statistics of SBI calls
+//!
+//! With the `call-stats` feature, every SBI call handled by `rustsbi::ecall` is counted in a table keyed
+//! by its extension and function IDs, so that users see which SBI traffic their workload generates.
+//! Supervisor copies the table out with the call statistics read call of the firmware specific extension
+//! of RustSBI (EID `0x0A000004`, FID `12`).
+//!
+//! The table holds `STATS_TABLE_SIZE` entries in the order the calls were first seen. Once it is full,
+//! calls of functions not in the table are counted by its last entry, whose extension and function IDs
+//! are both `usize::MAX`.
+use crate::ecall::SbiRet;
+use crate::shmem::check_shmem;
+use core::ptr::write_volatile;
+use spin::Mutex;
+
+/// Number of entries in the call statistics table
+pub const STATS_TABLE_SIZE: usize = 64;
+
+/// Flag of the call statistics read call: clear the table after copying it out
+pub const RUSTSBI_STATS_READ_CLEAR: usize = 1 << 0;
+
+/// Number of calls of one SBI function
+///
+/// Entries are copied into supervisor memory in this layout by the call statistics read call,
+/// as two XLEN-bit words followed by a 64-bit count.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct StatsEntry {
+    /// Extension ID, from `a7` register
+    pub extension: usize,
+    /// Function ID, from `a6` register
+    pub function: usize,
+    /// Calls handled since the table was last cleared
+    pub count: u64,
+}
+
+struct StatsTable {
+    entries: [StatsEntry; STATS_TABLE_SIZE],
+    len: usize,
+}
+
+impl StatsTable {
+    const fn new() -> StatsTable {
+        const EMPTY: StatsEntry = StatsEntry {
+            extension: 0,
+            function: 0,
+            count: 0,
+        };
+        StatsTable {
+            entries: [EMPTY; STATS_TABLE_SIZE],
+            len: 0,
+        }
+    }
+
+    fn count(&mut self, extension: usize, function: usize) {
+        let found = self.entries[..self.len]
+            .iter()
+            .position(|entry| entry.extension == extension && entry.function == function);
+        let idx = match found {
+            Some(idx) => idx,
+            None if self.len < STATS_TABLE_SIZE - 1 => {
+                self.entries[self.len] = StatsEntry { extension, function, count: 0 };
+                self.len += 1;
+                self.len - 1
+            }
+            None => {
+                // every other function shares the last entry
+                if self.len < STATS_TABLE_SIZE {
+                    self.entries[STATS_TABLE_SIZE - 1] = StatsEntry {
+                        extension: usize::MAX,
+                        function: usize::MAX,
+                        count: 0,
+                    };
+                    self.len = STATS_TABLE_SIZE;
+                }
+                STATS_TABLE_SIZE - 1
+            }
+        };
+        self.entries[idx].count += 1;
+    }
+}
+
+static STATS: Mutex<StatsTable> = Mutex::new(StatsTable::new());
+
+/// Copy the entries of the call statistics table into `entries`, returns the number of entries copied.
+pub fn call_stats(entries: &mut [StatsEntry]) -> usize {
+    let table = STATS.lock();
+    let count = entries.len().min(table.len);
+    entries[..count].copy_from_slice(&table.entries[..count]);
+    count
+}
+
+/// Clear the call statistics table.
+pub fn clear_call_stats() {
+    *STATS.lock() = StatsTable::new();
+}
+
+pub(crate) fn count_ecall(extension: usize, function: usize) {
+    STATS.lock().count(extension, function);
+}
+
+// copy at most `count` entries into supervisor memory at physical address `buf`, returns the number of
+// entries copied; the read call itself is counted before the table is copied
+pub(crate) fn stats_read(buf_phys_lo: usize, buf_phys_hi: usize, count: usize, flags: usize) -> SbiRet {
+    if flags & !RUSTSBI_STATS_READ_CLEAR != 0 {
+        return SbiRet::invalid_param();
+    }
+    let mut table = STATS.lock();
+    // entries beyond the table are never written, and the buffer is only checked up to the table's length
+    let copied = count.min(table.len);
+    let buf = match check_shmem::<StatsEntry>(buf_phys_lo, buf_phys_hi, copied) {
+        Ok(buf) => buf,
+        Err(ans) => return ans,
+    };
+    for (idx, entry) in table.entries[..copied].iter().enumerate() {
+        unsafe { write_volatile(buf.add(idx), *entry) };
+    }
+    if flags & RUSTSBI_STATS_READ_CLEAR != 0 {
+        *table = StatsTable::new();
+    }
+    SbiRet::ok(copied)
+}
@@ -31,8 +31,10 @@ pub(crate) fn probe_timer() -> bool {
 pub(crate) fn set_timer(time_value: u64) -> bool {
     if let Some(timer) = TIMER.lock().as_mut() {
         timer.set_timer(time_value);
-        true
     } else {
-        false
+        return false;
     }
+    #[cfg(feature = "pmu")]
+    crate::pmu::count_firmware_event(crate::pmu::SBI_PMU_FW_SET_TIMER);
+    true
 }
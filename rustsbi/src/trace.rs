@@ -0,0 +1,310 @@
+//! Tracing of SBI calls
+//!
+//! With the `trace` feature, every SBI call handled by `rustsbi::ecall` is reported to a trace sink
+//! after it returns, with its extension and function IDs, parameters and the returned `SbiRet`.
+//! Calls are filtered per extension by a bit mask set with `set_trace_mask`; only calls of the
+//! PMU extension are traced by default.
+//!
+//! The default sink prints one line for each call to the legacy console. Platforms may replace it
+//! with `init_trace_sink`. Printing takes long and disturbs the counters being measured; `TraceRing`
+//! keeps the recent records in memory instead, and supervisor reads them out with the trace read call
+//! of the firmware specific extension of RustSBI (EID `0x0A000004`, FID `1`). `TraceRecorder` keeps the
+//! first records instead of the recent ones, so that supervisor replays an exact sequence of calls.
+use crate::ecall::*;
+use crate::shmem::check_shmem;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::ptr::write_volatile;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Trace legacy extensions
+pub const TRACE_LEGACY: usize = 1 << 0;
+/// Trace base extension
+pub const TRACE_BASE: usize = 1 << 1;
+/// Trace timer extension
+pub const TRACE_TIMER: usize = 1 << 2;
+/// Trace IPI extension
+pub const TRACE_IPI: usize = 1 << 3;
+/// Trace RFENCE extension
+pub const TRACE_RFENCE: usize = 1 << 4;
+/// Trace hart state monitor extension
+pub const TRACE_HSM: usize = 1 << 5;
+/// Trace system reset extension
+pub const TRACE_SRST: usize = 1 << 6;
+/// Trace performance monitoring unit extension
+pub const TRACE_PMU: usize = 1 << 7;
+/// Trace firmware specific extension of RustSBI
+pub const TRACE_RUSTSBI: usize = 1 << 8;
+/// Trace calls of all extensions, including unknown ones
+pub const TRACE_ALL: usize = usize::MAX;
+
+// calls of unknown extensions are traced only if every bit is set
+const TRACE_UNKNOWN: usize = 1 << (usize::BITS - 1);
+
+/// A handled SBI call
+///
+/// Records are copied into supervisor memory in this layout by the trace read call,
+/// as ten XLEN-bit words.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TraceRecord {
+    /// Extension ID, from `a7` register
+    pub extension: usize,
+    /// Function ID, from `a6` register
+    pub function: usize,
+    /// Parameters, from `a0` to `a5` registers
+    pub param: [usize; 6],
+    /// Returned error number
+    pub error: usize,
+    /// Returned value
+    pub value: usize,
+}
+
+/// Receiver of SBI call trace records
+pub trait TraceSink: Send {
+    /// Record one handled SBI call.
+    ///
+    /// This function is called on the calling hart before returning to supervisor,
+    /// it should not make SBI calls itself.
+    fn trace(&mut self, record: &TraceRecord);
+    /// Move the oldest kept records into `records`, returns the number of records moved.
+    ///
+    /// Sinks keeping records in memory implement this function for supervisor to read them.
+    /// The default implementation keeps nothing and returns 0.
+    fn drain(&mut self, records: &mut [TraceRecord]) -> usize {
+        drop(records);
+        0
+    }
+}
+
+/// Number of records kept by `TraceRing`
+pub const TRACE_RING_SIZE: usize = 64;
+
+/// Trace sink keeping the most recent records in memory
+///
+/// When the ring is full, the oldest record is overwritten. Supervisor reads records out,
+/// oldest first, with the trace read call of the firmware specific extension of RustSBI.
+pub struct TraceRing {
+    records: [TraceRecord; TRACE_RING_SIZE],
+    // index of the oldest record
+    head: usize,
+    len: usize,
+    // records overwritten before being read
+    lost: usize,
+}
+
+impl TraceRing {
+    /// Create an empty trace ring.
+    pub const fn new() -> TraceRing {
+        const EMPTY: TraceRecord = TraceRecord {
+            extension: 0,
+            function: 0,
+            param: [0; 6],
+            error: 0,
+            value: 0,
+        };
+        TraceRing {
+            records: [EMPTY; TRACE_RING_SIZE],
+            head: 0,
+            len: 0,
+            lost: 0,
+        }
+    }
+
+    /// Number of records overwritten before supervisor read them.
+    pub fn lost(&self) -> usize {
+        self.lost
+    }
+}
+
+impl Default for TraceRing {
+    fn default() -> TraceRing {
+        TraceRing::new()
+    }
+}
+
+impl TraceSink for TraceRing {
+    fn trace(&mut self, record: &TraceRecord) {
+        let tail = (self.head + self.len) % TRACE_RING_SIZE;
+        self.records[tail] = *record;
+        if self.len == TRACE_RING_SIZE {
+            self.head = (self.head + 1) % TRACE_RING_SIZE;
+            self.lost += 1;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn drain(&mut self, records: &mut [TraceRecord]) -> usize {
+        let count = records.len().min(self.len);
+        for record in &mut records[..count] {
+            *record = self.records[self.head];
+            self.head = (self.head + 1) % TRACE_RING_SIZE;
+        }
+        self.len -= count;
+        count
+    }
+}
+
+/// Trace sink recording calls in the order they were made, without overwriting any of them
+///
+/// Supervisor reads records out, oldest first, with the trace read call, and issues the same calls again
+/// to reproduce a run, for example one in which counters were allocated wrongly. Once the recorder is
+/// full, it drops every record until supervisor has read out all records kept, so that records always form
+/// sequences of consecutive calls: the first sequence starts with the first call, and each later one with
+/// the first call after the recorder was emptied. Supervisor knows a recording was cut short when it reads
+/// out fewer records than the calls it made.
+pub struct TraceRecorder {
+    records: VecDeque<TraceRecord>,
+    capacity: usize,
+    // the recorder was full, and records are dropped until all of them are read
+    full: bool,
+    dropped: usize,
+}
+
+impl TraceRecorder {
+    /// Create an empty recorder keeping at most `capacity` records not yet read.
+    pub fn new(capacity: usize) -> TraceRecorder {
+        TraceRecorder {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            full: false,
+            dropped: 0,
+        }
+    }
+
+    /// Number of records dropped while the recorder was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+impl TraceSink for TraceRecorder {
+    fn trace(&mut self, record: &TraceRecord) {
+        if self.full || self.records.len() == self.capacity {
+            self.full = true;
+            self.dropped += 1;
+        } else {
+            self.records.push_back(*record);
+        }
+    }
+
+    fn drain(&mut self, records: &mut [TraceRecord]) -> usize {
+        let count = records.len().min(self.records.len());
+        for (dst, src) in records.iter_mut().zip(self.records.drain(..count)) {
+            *dst = src;
+        }
+        if self.records.is_empty() {
+            self.full = false;
+        }
+        count
+    }
+}
+
+// prints records to legacy console
+struct ConsoleSink;
+
+impl TraceSink for ConsoleSink {
+    fn trace(&mut self, record: &TraceRecord) {
+        let p = &record.param;
+        crate::println!(
+            "[rustsbi-trace] {} eid {:#x} fid {:#x} ({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}) -> error {}, value {:#x}",
+            extension_name(record.extension),
+            record.extension,
+            record.function,
+            p[0],
+            p[1],
+            p[2],
+            p[3],
+            p[4],
+            p[5],
+            record.error as isize,
+            record.value
+        );
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TRACE_SINK: Mutex<Box<dyn TraceSink>> =
+        Mutex::new(Box::new(ConsoleSink));
+}
+
+static TRACE_MASK: AtomicUsize = AtomicUsize::new(TRACE_PMU);
+
+/// Replace the trace sink; records are printed to legacy console before this function is called.
+pub fn init_trace_sink<T: TraceSink + Send + 'static>(sink: T) {
+    *TRACE_SINK.lock() = Box::new(sink);
+}
+
+/// Set extensions to be traced, as a bit mask of `TRACE_*` constants.
+pub fn set_trace_mask(mask: usize) {
+    TRACE_MASK.store(mask, Ordering::Relaxed);
+}
+
+/// Extensions being traced, as a bit mask of `TRACE_*` constants.
+pub fn trace_mask() -> usize {
+    TRACE_MASK.load(Ordering::Relaxed)
+}
+
+pub(crate) fn trace_ecall(extension: usize, function: usize, param: [usize; 6], ans: &SbiRet) {
+    if trace_mask() & extension_bit(extension) == 0 {
+        return;
+    }
+    let record = TraceRecord {
+        extension,
+        function,
+        param,
+        error: ans.error,
+        value: ans.value,
+    };
+    TRACE_SINK.lock().trace(&record);
+}
+
+// copy at most `count` records, oldest first, into supervisor memory at physical address `buf`,
+// returns the number of records copied
+pub(crate) fn trace_read(buf_phys_lo: usize, buf_phys_hi: usize, count: usize) -> SbiRet {
+    // the whole buffer is checked, records are copied as long as there are any
+    let buf = match check_shmem::<TraceRecord>(buf_phys_lo, buf_phys_hi, count) {
+        Ok(buf) => buf,
+        Err(ans) => return ans,
+    };
+    let mut sink = TRACE_SINK.lock();
+    let mut record = [TraceRecord::default(); 1];
+    let mut copied = 0;
+    while copied < count && sink.drain(&mut record) == 1 {
+        unsafe { write_volatile(buf.add(copied), record[0]) };
+        copied += 1;
+    }
+    SbiRet::ok(copied)
+}
+
+fn extension_bit(extension: usize) -> usize {
+    match extension {
+        EXTENSION_BASE => TRACE_BASE,
+        EXTENSION_TIMER => TRACE_TIMER,
+        EXTENSION_IPI => TRACE_IPI,
+        EXTENSION_RFENCE => TRACE_RFENCE,
+        EXTENSION_HSM => TRACE_HSM,
+        EXTENSION_SRST => TRACE_SRST,
+        EXTENSION_PMU => TRACE_PMU,
+        EXTENSION_RUSTSBI => TRACE_RUSTSBI,
+        0x00..=0x08 => TRACE_LEGACY,
+        _ => TRACE_UNKNOWN,
+    }
+}
+
+fn extension_name(extension: usize) -> &'static str {
+    match extension {
+        EXTENSION_BASE => "base",
+        EXTENSION_TIMER => "timer",
+        EXTENSION_IPI => "ipi",
+        EXTENSION_RFENCE => "rfence",
+        EXTENSION_HSM => "hsm",
+        EXTENSION_SRST => "srst",
+        EXTENSION_PMU => "pmu",
+        EXTENSION_RUSTSBI => "rustsbi",
+        0x00..=0x08 => "legacy",
+        _ => "unknown",
+    }
+}